@@ -0,0 +1,206 @@
+use crate::db::{load_all_tools, save_tool};
+use crate::embed::Embedder;
+use crate::types::{Tool, ToolRecord};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug, Clone)]
+pub struct Alias {
+    pub name: String,
+    pub expansion: String,
+    pub origin: String,
+}
+
+pub fn unquote(value: &str) -> String {
+    let value = value.trim();
+    for q in ['\'', '"'] {
+        if value.len() >= 2 && value.starts_with(q) && value.ends_with(q) {
+            return value[1..value.len() - 1].replace("'\\''", "'");
+        }
+    }
+    value.to_string()
+}
+
+// bash/zsh: `alias ll='ls -la'`, `alias -g G='| grep'`
+fn parse_posix_alias(line: &str) -> Option<(String, String)> {
+    let rest = line.strip_prefix("alias ")?;
+    let rest = rest
+        .trim_start()
+        .trim_start_matches("-g ")
+        .trim_start_matches("-s ")
+        .trim_start();
+    let (name, value) = rest.split_once('=')?;
+    let name = name.trim();
+    if name.is_empty() || name.contains(char::is_whitespace) {
+        return None;
+    }
+    Some((name.to_string(), unquote(value)))
+}
+
+// fish: `abbr -a gco git checkout`, `abbr --add -g gst git status`,
+// `alias ll 'ls -la'`
+fn parse_fish_line(line: &str) -> Option<(String, String)> {
+    let (cmd, rest) = line.split_once(char::is_whitespace)?;
+    match cmd {
+        "abbr" => {
+            let mut words = rest.split_whitespace().skip_while(|w| w.starts_with('-'));
+            let name = words.next()?.to_string();
+            let expansion = words.collect::<Vec<_>>().join(" ");
+            if expansion.is_empty() {
+                return None;
+            }
+            Some((name, unquote(&expansion)))
+        }
+        "alias" => {
+            if let Some(parsed) = parse_posix_alias(line) {
+                return Some(parsed);
+            }
+            let (name, value) = rest.trim().split_once(char::is_whitespace)?;
+            Some((name.to_string(), unquote(value)))
+        }
+        _ => None,
+    }
+}
+
+// Universal abbreviations from older fish versions:
+// `SETUVAR _fish_abbr_gco:git\x20checkout`
+fn parse_fish_variable(line: &str) -> Option<(String, String)> {
+    let rest = line.strip_prefix("SETUVAR _fish_abbr_")?;
+    let (name, value) = rest.split_once(':')?;
+    Some((name.to_string(), value.replace("\\x20", " ")))
+}
+
+pub fn parse_file(path: &Path) -> Vec<Alias> {
+    let Ok(content) = fs::read_to_string(path) else {
+        return Vec::new();
+    };
+    let origin = path.to_string_lossy().to_string();
+    let is_fish = path.extension().is_some_and(|e| e == "fish")
+        || path.file_name().is_some_and(|n| n == "fish_variables");
+
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.starts_with('#'))
+        .filter_map(|line| {
+            if is_fish {
+                parse_fish_variable(line).or_else(|| parse_fish_line(line))
+            } else {
+                parse_posix_alias(line)
+            }
+        })
+        .map(|(name, expansion)| Alias {
+            name,
+            expansion,
+            origin: origin.clone(),
+        })
+        .collect()
+}
+
+fn files_in(dir: &Path, suffix: &str) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(|e| e.ok())
+                .map(|e| e.path())
+                .filter(|p| p.to_string_lossy().ends_with(suffix))
+                .collect()
+        })
+        .unwrap_or_default();
+    files.sort();
+    files
+}
+
+/// Shell startup files, oh-my-zsh plugins and fish configs that usually hold
+/// aliases. Only files that exist are returned.
+pub fn default_sources() -> Vec<PathBuf> {
+    let Some(home) = dirs::home_dir() else {
+        return Vec::new();
+    };
+    let mut sources = vec![
+        home.join(".bashrc"),
+        home.join(".bash_aliases"),
+        home.join(".zshrc"),
+        home.join(".zsh_aliases"),
+        home.join(".config/fish/config.fish"),
+        home.join(".config/fish/fish_variables"),
+    ];
+    sources.extend(files_in(&home.join(".config/fish/conf.d"), ".fish"));
+
+    let omz = env::var("ZSH")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| home.join(".oh-my-zsh"));
+    for plugin in files_in(&omz.join("plugins"), "") {
+        sources.extend(files_in(&plugin, ".plugin.zsh"));
+    }
+    sources.extend(files_in(&omz.join("custom"), ".zsh"));
+
+    sources.into_iter().filter(|p| p.is_file()).collect()
+}
+
+/// Stores aliases as tools with `source = "alias"`. Aliases that share a name
+/// with an indexed binary (`alias ls='ls --color'`) are skipped so they
+/// don't replace the real docs. Returns (imported, skipped).
+pub fn import_aliases(
+    embedder: &dyn Embedder,
+    conn: &rusqlite::Connection,
+    aliases: Vec<Alias>,
+) -> Result<(usize, usize), Box<dyn std::error::Error>> {
+    let existing = load_all_tools(conn)?;
+    let mut imported = 0;
+    let mut skipped = 0;
+
+    for alias in aliases {
+        let shadowed = existing
+            .iter()
+            .any(|t| t.name == alias.name && t.source != "alias");
+        if shadowed {
+            skipped += 1;
+            continue;
+        }
+
+        let base = alias.expansion.split_whitespace().next().unwrap_or("");
+        let base_description = existing
+            .iter()
+            .find(|t| t.name == base)
+            .map(|t| t.description.clone())
+            .unwrap_or_default();
+        let description = format!("your alias for `{}`", alias.expansion);
+
+        let embed_text = format!(
+            "{} {} {} {}",
+            alias.name, alias.expansion, description, base_description
+        );
+        let embedding = match embedder.embed(&embed_text) {
+            Ok(e) => e,
+            Err(_) => {
+                skipped += 1;
+                continue;
+            }
+        };
+
+        save_tool(
+            conn,
+            &Tool {
+                name: alias.name,
+                path: alias.origin,
+                target: String::new(),
+                description,
+                synopsis: alias.expansion,
+                examples: String::new(),
+                flags: String::new(),
+                source: "alias".to_string(),
+                caveats: String::new(),
+                dry_run: String::new(),
+                notes: Vec::new(),
+                record: ToolRecord::default(),
+                embedding,
+            },
+        )?;
+        imported += 1;
+    }
+
+    Ok((imported, skipped))
+}
@@ -0,0 +1,310 @@
+// Claude models through the Anthropic Messages API. There is no embeddings
+// endpoint, so this only generates; retrieval needs `llm.embed_provider`
+// set to something else ("ollama" or "local").
+
+use crate::config::LlmConfig;
+use crate::http::{self, block_on, for_each_line, interruptible, send_with_retries};
+use crate::llm::{Capabilities, LlmProvider, ModelInfo};
+use crate::types::Usage;
+use serde::{Deserialize, Serialize};
+use std::{env, sync::Mutex, time::Instant};
+
+const API_VERSION: &str = "2023-06-01";
+// Plans are a handful of short commands; this is plenty.
+const MAX_TOKENS: u32 = 2048;
+
+#[derive(Serialize)]
+struct Message {
+    role: &'static str,
+    content: String,
+}
+
+#[derive(Serialize)]
+struct MessagesRequest {
+    model: String,
+    max_tokens: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system: Option<String>,
+    messages: Vec<Message>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    stream: bool,
+}
+
+#[derive(Deserialize)]
+struct ContentBlock {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    text: String,
+}
+
+#[derive(Deserialize, Default)]
+struct ApiUsage {
+    #[serde(default)]
+    input_tokens: u64,
+    #[serde(default)]
+    output_tokens: u64,
+}
+
+#[derive(Deserialize)]
+struct MessagesResponse {
+    content: Vec<ContentBlock>,
+    #[serde(default)]
+    usage: ApiUsage,
+}
+
+#[derive(Deserialize)]
+struct ApiModel {
+    id: String,
+    #[serde(default)]
+    display_name: String,
+}
+
+#[derive(Deserialize)]
+struct ModelList {
+    data: Vec<ApiModel>,
+}
+
+#[derive(Deserialize)]
+struct ApiError {
+    error: ApiErrorBody,
+}
+
+#[derive(Deserialize)]
+struct ApiErrorBody {
+    message: String,
+}
+
+pub struct AnthropicClient {
+    base_url: String,
+    model: String,
+    api_key: Option<String>,
+    client: reqwest::Client,
+    retries: u32,
+    usage: Mutex<Usage>,
+}
+
+impl AnthropicClient {
+    pub fn new(config: &LlmConfig) -> Result<Self, Box<dyn std::error::Error>> {
+        let api_key = config
+            .api_key
+            .clone()
+            .or_else(|| env::var("ANTHROPIC_API_KEY").ok())
+            .filter(|k| !k.is_empty());
+        Ok(Self {
+            base_url: config.endpoint.trim_end_matches('/').to_string(),
+            model: config.model.clone(),
+            api_key,
+            client: http::builder(config)?.build()?,
+            retries: config.retries,
+            usage: Mutex::new(Usage::default()),
+        })
+    }
+
+    fn request(&self, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
+        self.client
+            .request(method, format!("{}{}", self.base_url, path))
+            .header("x-api-key", self.api_key.as_deref().unwrap_or_default())
+            .header("anthropic-version", API_VERSION)
+    }
+
+    async fn send_messages(
+        &self,
+        system: Option<&str>,
+        prompt: &str,
+        stream: bool,
+    ) -> Result<reqwest::Response, Box<dyn std::error::Error>> {
+        if self.api_key.is_none() {
+            return Err("no Anthropic API key; set ANTHROPIC_API_KEY or llm.api_key".into());
+        }
+        let body = MessagesRequest {
+            model: self.model.clone(),
+            max_tokens: MAX_TOKENS,
+            system: system.map(str::to_string),
+            messages: vec![Message {
+                role: "user",
+                content: prompt.to_string(),
+            }],
+            stream,
+        };
+        let request = self
+            .request(reqwest::Method::POST, "/v1/messages")
+            .json(&body);
+        let resp = send_with_retries(request, self.retries).await?;
+        let status = resp.status();
+        if !status.is_success() {
+            let message = resp
+                .json::<ApiError>()
+                .await
+                .map(|e| e.error.message)
+                .unwrap_or_default();
+            return Err(format!("anthropic: {} {}", status, message).into());
+        }
+        Ok(resp)
+    }
+
+    // Server-sent events: text arrives in content_block_delta events, the
+    // token counts in message_start and message_delta.
+    fn stream(
+        &self,
+        system: Option<&str>,
+        prompt: &str,
+        on_chunk: &mut dyn FnMut(&str),
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        let mut reply = String::new();
+        let started = Instant::now();
+        interruptible(async {
+            let resp = self.send_messages(system, prompt, true).await?;
+            for_each_line(resp, |line| {
+                let Some(data) = line.strip_prefix("data:") else {
+                    return Ok(true);
+                };
+                let event: serde_json::Value = serde_json::from_str(data.trim())?;
+                match event["type"].as_str().unwrap_or_default() {
+                    "message_start" => {
+                        let input = event["message"]["usage"]["input_tokens"].as_u64();
+                        self.count_usage(input.unwrap_or(0), 0);
+                    }
+                    "content_block_delta" => {
+                        if let Some(text) = event["delta"]["text"].as_str() {
+                            on_chunk(text);
+                            reply.push_str(text);
+                        }
+                    }
+                    "message_delta" => {
+                        let output = event["usage"]["output_tokens"].as_u64();
+                        self.count_usage(0, output.unwrap_or(0));
+                    }
+                    "error" => {
+                        let message = event["error"]["message"].as_str().unwrap_or("stream error");
+                        return Err(format!("anthropic: {}", message).into());
+                    }
+                    "message_stop" => return Ok(false),
+                    _ => {}
+                }
+                Ok(true)
+            })
+            .await
+        })?;
+        self.count_latency(started);
+        Ok(reply)
+    }
+
+    fn count_latency(&self, started: Instant) {
+        if let Ok(mut usage) = self.usage.lock() {
+            usage.latency_ms += started.elapsed().as_millis() as u64;
+        }
+    }
+
+    fn count_usage(&self, input: u64, output: u64) {
+        if let Ok(mut usage) = self.usage.lock() {
+            usage.prompt_tokens += input;
+            usage.completion_tokens += output;
+        }
+    }
+}
+
+impl LlmProvider for AnthropicClient {
+    fn name(&self) -> &str {
+        "anthropic"
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn embed_model(&self) -> &str {
+        ""
+    }
+
+    fn endpoint(&self) -> &str {
+        &self.base_url
+    }
+
+    // Reachability only; a missing or rejected key is reported by generate.
+    fn is_available(&self) -> bool {
+        block_on(async {
+            self.request(reqwest::Method::GET, "/v1/models")
+                .send()
+                .await
+        })
+        .is_ok()
+    }
+
+    fn list_models(&self) -> Result<Vec<ModelInfo>, Box<dyn std::error::Error>> {
+        let list = block_on(async {
+            let request = self.request(reqwest::Method::GET, "/v1/models?limit=1000");
+            request
+                .send()
+                .await?
+                .error_for_status()?
+                .json::<ModelList>()
+                .await
+        })?;
+        Ok(list
+            .data
+            .into_iter()
+            .map(|m| ModelInfo {
+                name: m.id,
+                size: 0,
+                details: m.display_name,
+            })
+            .collect())
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            streaming: true,
+            chat: true,
+            ..Capabilities::default()
+        }
+    }
+
+    fn generate(&self, prompt: &str) -> Result<String, Box<dyn std::error::Error>> {
+        let started = Instant::now();
+        let resp: MessagesResponse = interruptible(async {
+            let resp = self.send_messages(None, prompt, false).await?;
+            Ok::<_, Box<dyn std::error::Error>>(resp.json().await?)
+        })?;
+        self.count_latency(started);
+        self.count_usage(resp.usage.input_tokens, resp.usage.output_tokens);
+        Ok(resp
+            .content
+            .into_iter()
+            .filter(|block| block.kind == "text")
+            .map(|block| block.text)
+            .collect())
+    }
+
+    fn stream_json(
+        &self,
+        prompt: &str,
+        on_chunk: &mut dyn FnMut(&str),
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        self.stream(None, prompt, on_chunk)
+    }
+
+    fn stream_chat(
+        &self,
+        system: &str,
+        user: &str,
+        _schema: &serde_json::Value,
+        on_chunk: &mut dyn FnMut(&str),
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        self.stream(Some(system), user, on_chunk)
+    }
+
+    fn embed(&self, _text: &str) -> Result<Vec<f32>, Box<dyn std::error::Error>> {
+        Err(
+            "anthropic has no embeddings API; set llm.embed_provider to \"ollama\" or \"local\""
+                .into(),
+        )
+    }
+
+    fn take_usage(&self) -> Usage {
+        self.usage
+            .lock()
+            .map(|mut u| std::mem::take(&mut *u))
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,361 @@
+// Azure OpenAI. Models are reached through deployments, so `llm.model` and
+// `llm.embed_model` name deployments rather than models, and `endpoint` is
+// the resource URL (https://NAME.openai.azure.com). Every request carries
+// the api-version query parameter and the key in an api-key header.
+
+use crate::config::LlmConfig;
+use crate::http::{self, block_on, for_each_line, interruptible, send_with_retries};
+use crate::llm::{Capabilities, LlmProvider};
+use crate::types::Usage;
+use serde::{Deserialize, Serialize};
+use std::{env, sync::Mutex, time::Instant};
+
+/// The newest GA version when this was written; `llm.api_version` overrides.
+const DEFAULT_API_VERSION: &str = "2024-10-21";
+const MAX_TOKENS: u32 = 2048;
+
+#[derive(Serialize)]
+struct Message {
+    role: &'static str,
+    content: String,
+}
+
+#[derive(Serialize)]
+struct ResponseFormat {
+    #[serde(rename = "type")]
+    kind: &'static str,
+}
+
+#[derive(Serialize)]
+struct StreamOptions {
+    include_usage: bool,
+}
+
+#[derive(Serialize)]
+struct ChatRequest {
+    messages: Vec<Message>,
+    max_tokens: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_format: Option<ResponseFormat>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream_options: Option<StreamOptions>,
+}
+
+#[derive(Deserialize)]
+struct ChoiceMessage {
+    #[serde(default)]
+    content: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Choice {
+    message: ChoiceMessage,
+}
+
+#[derive(Deserialize, Default)]
+struct ApiUsage {
+    #[serde(default)]
+    prompt_tokens: u64,
+    #[serde(default)]
+    completion_tokens: u64,
+}
+
+#[derive(Deserialize)]
+struct ChatResponse {
+    choices: Vec<Choice>,
+    #[serde(default)]
+    usage: ApiUsage,
+}
+
+#[derive(Serialize)]
+struct EmbedRequest<'a> {
+    input: &'a [String],
+}
+
+#[derive(Deserialize)]
+struct EmbedData {
+    embedding: Vec<f32>,
+    index: usize,
+}
+
+#[derive(Deserialize)]
+struct EmbedResponse {
+    data: Vec<EmbedData>,
+}
+
+#[derive(Deserialize)]
+struct ApiError {
+    error: ApiErrorBody,
+}
+
+#[derive(Deserialize)]
+struct ApiErrorBody {
+    message: String,
+}
+
+pub struct AzureClient {
+    base_url: String,
+    model: String,
+    embed_model: String,
+    api_key: Option<String>,
+    api_version: String,
+    client: reqwest::Client,
+    retries: u32,
+    usage: Mutex<Usage>,
+}
+
+impl AzureClient {
+    pub fn new(config: &LlmConfig) -> Result<Self, Box<dyn std::error::Error>> {
+        let api_key = config
+            .api_key
+            .clone()
+            .or_else(|| env::var("AZURE_OPENAI_API_KEY").ok())
+            .filter(|k| !k.is_empty());
+        Ok(Self {
+            base_url: config.endpoint.trim_end_matches('/').to_string(),
+            model: config.model.clone(),
+            embed_model: config.embed_model.clone(),
+            api_key,
+            api_version: config
+                .api_version
+                .clone()
+                .unwrap_or_else(|| DEFAULT_API_VERSION.to_string()),
+            client: http::builder(config)?.build()?,
+            retries: config.retries,
+            usage: Mutex::new(Usage::default()),
+        })
+    }
+
+    fn request(&self, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
+        let url = format!(
+            "{}/openai{}?api-version={}",
+            self.base_url, path, self.api_version
+        );
+        self.client
+            .request(method, url)
+            .header("api-key", self.api_key.as_deref().unwrap_or_default())
+    }
+
+    async fn post(
+        &self,
+        deployment: &str,
+        operation: &str,
+        body: &impl Serialize,
+    ) -> Result<reqwest::Response, Box<dyn std::error::Error>> {
+        if self.api_key.is_none() {
+            return Err("no Azure OpenAI key; set AZURE_OPENAI_API_KEY or llm.api_key".into());
+        }
+        if deployment.is_empty() {
+            return Err(format!("azure: no deployment set for {}", operation).into());
+        }
+        let path = format!("/deployments/{}/{}", deployment, operation);
+        let request = self.request(reqwest::Method::POST, &path).json(body);
+        let resp = send_with_retries(request, self.retries).await?;
+        let status = resp.status();
+        if !status.is_success() {
+            let message = resp
+                .json::<ApiError>()
+                .await
+                .map(|e| e.error.message)
+                .unwrap_or_default();
+            return Err(format!("azure: {} {}", status, message).into());
+        }
+        Ok(resp)
+    }
+
+    fn chat_request(
+        &self,
+        system: Option<&str>,
+        prompt: &str,
+        json: bool,
+        stream: bool,
+    ) -> ChatRequest {
+        let mut messages = Vec::new();
+        if let Some(system) = system {
+            messages.push(Message {
+                role: "system",
+                content: system.to_string(),
+            });
+        }
+        messages.push(Message {
+            role: "user",
+            content: prompt.to_string(),
+        });
+        ChatRequest {
+            messages,
+            max_tokens: MAX_TOKENS,
+            response_format: json.then_some(ResponseFormat {
+                kind: "json_object",
+            }),
+            stream,
+            stream_options: stream.then_some(StreamOptions {
+                include_usage: true,
+            }),
+        }
+    }
+
+    fn complete(&self, body: ChatRequest) -> Result<String, Box<dyn std::error::Error>> {
+        let started = Instant::now();
+        let resp: ChatResponse = interruptible(async {
+            let resp = self.post(&self.model, "chat/completions", &body).await?;
+            Ok::<_, Box<dyn std::error::Error>>(resp.json().await?)
+        })?;
+        self.count_latency(started);
+        self.count_usage(resp.usage.prompt_tokens, resp.usage.completion_tokens);
+        Ok(resp
+            .choices
+            .into_iter()
+            .next()
+            .and_then(|c| c.message.content)
+            .unwrap_or_default())
+    }
+
+    // Server-sent events: text arrives in choices[0].delta, the token counts
+    // in a last chunk with no choices, then "[DONE]".
+    fn stream(
+        &self,
+        body: ChatRequest,
+        on_chunk: &mut dyn FnMut(&str),
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        let mut reply = String::new();
+        let started = Instant::now();
+        interruptible(async {
+            let resp = self.post(&self.model, "chat/completions", &body).await?;
+            for_each_line(resp, |line| {
+                let Some(data) = line.strip_prefix("data:") else {
+                    return Ok(true);
+                };
+                let data = data.trim();
+                if data == "[DONE]" {
+                    return Ok(false);
+                }
+                let event: serde_json::Value = serde_json::from_str(data)?;
+                if let Some(text) = event["choices"][0]["delta"]["content"].as_str() {
+                    on_chunk(text);
+                    reply.push_str(text);
+                }
+                if let Some(usage) = event.get("usage").filter(|u| u.is_object()) {
+                    self.count_usage(
+                        usage["prompt_tokens"].as_u64().unwrap_or(0),
+                        usage["completion_tokens"].as_u64().unwrap_or(0),
+                    );
+                }
+                Ok(true)
+            })
+            .await
+        })?;
+        self.count_latency(started);
+        Ok(reply)
+    }
+
+    fn count_latency(&self, started: Instant) {
+        if let Ok(mut usage) = self.usage.lock() {
+            usage.latency_ms += started.elapsed().as_millis() as u64;
+        }
+    }
+
+    fn count_usage(&self, input: u64, output: u64) {
+        if let Ok(mut usage) = self.usage.lock() {
+            usage.prompt_tokens += input;
+            usage.completion_tokens += output;
+        }
+    }
+
+    async fn embed_many(
+        &self,
+        texts: &[String],
+    ) -> Result<Vec<Vec<f32>>, Box<dyn std::error::Error>> {
+        let body = EmbedRequest { input: texts };
+        let resp = self.post(&self.embed_model, "embeddings", &body).await?;
+        let mut data = resp.json::<EmbedResponse>().await?.data;
+        if data.len() != texts.len() {
+            let counts = format!("{} embeddings for {} texts", data.len(), texts.len());
+            return Err(format!("azure: {}", counts).into());
+        }
+        data.sort_by_key(|d| d.index);
+        Ok(data.into_iter().map(|d| d.embedding).collect())
+    }
+}
+
+impl LlmProvider for AzureClient {
+    fn name(&self) -> &str {
+        "azure"
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn embed_model(&self) -> &str {
+        &self.embed_model
+    }
+
+    fn endpoint(&self) -> &str {
+        &self.base_url
+    }
+
+    // Reachability only; a missing or rejected key is reported by generate.
+    fn is_available(&self) -> bool {
+        block_on(async { self.request(reqwest::Method::GET, "/models").send().await }).is_ok()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            json_mode: true,
+            streaming: true,
+            chat: true,
+            embeddings: true,
+            ..Capabilities::default()
+        }
+    }
+
+    fn generate(&self, prompt: &str) -> Result<String, Box<dyn std::error::Error>> {
+        self.complete(self.chat_request(None, prompt, false, false))
+    }
+
+    fn generate_json(&self, prompt: &str) -> Result<String, Box<dyn std::error::Error>> {
+        self.complete(self.chat_request(None, prompt, true, false))
+    }
+
+    fn stream_json(
+        &self,
+        prompt: &str,
+        on_chunk: &mut dyn FnMut(&str),
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        self.stream(self.chat_request(None, prompt, true, true), on_chunk)
+    }
+
+    // JSON mode rather than the schema: strict schemas can't express the
+    // plan's either-or shape, and the reply is checked anyway.
+    fn stream_chat(
+        &self,
+        system: &str,
+        user: &str,
+        _schema: &serde_json::Value,
+        on_chunk: &mut dyn FnMut(&str),
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        self.stream(self.chat_request(Some(system), user, true, true), on_chunk)
+    }
+
+    fn embed(&self, text: &str) -> Result<Vec<f32>, Box<dyn std::error::Error>> {
+        let texts = [text.to_string()];
+        let mut embeddings = block_on(self.embed_many(&texts))?;
+        Ok(embeddings.pop().unwrap_or_default())
+    }
+
+    fn embed_batch(&self, texts: &[String]) -> Vec<Result<Vec<f32>, Box<dyn std::error::Error>>> {
+        match block_on(self.embed_many(texts)) {
+            Ok(embeddings) => embeddings.into_iter().map(Ok).collect(),
+            Err(_) => texts.iter().map(|t| self.embed(t)).collect(),
+        }
+    }
+
+    fn take_usage(&self) -> Usage {
+        self.usage
+            .lock()
+            .map(|mut u| std::mem::take(&mut *u))
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,337 @@
+// `pls tools browse`: the index as a catalog for people. Search it by name
+// or description, open a tool to read its description, examples and flags,
+// then copy an example, read the man page or ask pls something with that
+// tool in mind.
+
+use crate::config::Config;
+use crate::db::{get_notes, load_all_tools, open_history, open_index};
+use crate::index::find_in_path;
+use crate::terminal::TerminalGuard;
+use crate::types::Tool;
+use std::{
+    io::{self, Write},
+    process::{Command, Stdio},
+};
+
+const PAGE: usize = 20;
+const EXAMPLES: usize = 12;
+const FLAG_LINES: usize = 20;
+// Tried in order; the first on PATH that takes the text wins.
+const CLIPBOARDS: &[&[&str]] = &[
+    &["wl-copy"],
+    &["xclip", "-selection", "clipboard"],
+    &["xsel", "--clipboard", "--input"],
+    &["pbcopy"],
+];
+
+fn read_line(prompt: &str) -> Option<String> {
+    print!("{}", prompt);
+    io::stdout().flush().ok();
+    let mut input = String::new();
+    match io::stdin().read_line(&mut input) {
+        Ok(0) | Err(_) => None,
+        Ok(_) => Some(input.trim().to_string()),
+    }
+}
+
+fn width() -> usize {
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    let ok = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } == 0;
+    if ok && size.ws_col > 0 {
+        size.ws_col as usize
+    } else {
+        80
+    }
+}
+
+// Tools whose name or description has `filter` in it, names first.
+fn search<'a>(tools: &'a [Tool], filter: &str) -> Vec<&'a Tool> {
+    let filter = filter.to_lowercase();
+    let mut named: Vec<&Tool> = Vec::new();
+    let mut described: Vec<&Tool> = Vec::new();
+    for tool in tools {
+        if tool.name.to_lowercase().contains(&filter) {
+            named.push(tool);
+        } else if tool.description.to_lowercase().contains(&filter) {
+            described.push(tool);
+        }
+    }
+    named.sort_by_key(|t| (!t.name.eq_ignore_ascii_case(&filter), t.name.len()));
+    named.extend(described);
+    named
+}
+
+fn examples(tool: &Tool) -> Vec<String> {
+    let text = if tool.examples.trim().is_empty() {
+        &tool.synopsis
+    } else {
+        &tool.examples
+    };
+    text.lines()
+        .map(|l| l.trim().trim_start_matches("$ ").to_string())
+        .filter(|l| !l.is_empty() && !l.ends_with(':'))
+        .take(EXAMPLES)
+        .collect()
+}
+
+// Lines of `text` no wider than `width`, broken at spaces; flags are often
+// kept as one long comma-separated line.
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    for line in text
+        .lines()
+        .map(str::trim_end)
+        .filter(|l| !l.trim().is_empty())
+    {
+        let mut current = String::new();
+        for word in line.split(' ') {
+            if !current.is_empty() && current.len() + 1 + word.len() > width {
+                lines.push(std::mem::take(&mut current));
+            } else if !current.is_empty() {
+                current.push(' ');
+            }
+            current.push_str(word);
+        }
+        lines.push(current);
+    }
+    lines
+}
+
+fn show(tool: &Tool, notes: &[String]) {
+    println!();
+    let mut description = tool.description.lines();
+    println!(
+        "  {} - {}",
+        tool.name,
+        description.next().unwrap_or_default()
+    );
+    for line in wrap(
+        &description.collect::<Vec<_>>().join(" "),
+        width().saturating_sub(4),
+    ) {
+        println!("  {}", line);
+    }
+    if tool.target.is_empty() {
+        println!("  {} ({})", tool.path, tool.source);
+    } else {
+        println!("  {} -> {} ({})", tool.path, tool.target, tool.source);
+    }
+    let examples = examples(tool);
+    if !examples.is_empty() {
+        println!();
+        for (i, example) in examples.iter().enumerate() {
+            println!("  {:>3}. {}", i + 1, example);
+        }
+    }
+    let flags = wrap(&tool.flags, width().saturating_sub(4).max(40));
+    if !flags.is_empty() {
+        println!();
+        for line in flags.iter().take(FLAG_LINES) {
+            println!("    {}", line);
+        }
+        if flags.len() > FLAG_LINES {
+            println!(
+                "    ... {} more lines in the man page",
+                flags.len() - FLAG_LINES
+            );
+        }
+    }
+    if !tool.dry_run.is_empty() {
+        println!("  dry run: {}", tool.dry_run);
+    }
+    for caveat in tool.caveats.lines().filter(|l| !l.trim().is_empty()) {
+        println!("  caveat: {}", caveat.trim());
+    }
+    for note in notes {
+        println!("  note: {}", note);
+    }
+    println!();
+}
+
+// To the first clipboard tool that takes it, else to the terminal's own
+// clipboard (OSC 52), which also works over ssh. Where it went, if anywhere.
+fn copy(text: &str) -> Option<&'static str> {
+    for argv in CLIPBOARDS {
+        if find_in_path(argv[0]).is_none() {
+            continue;
+        }
+        let child = Command::new(argv[0])
+            .args(&argv[1..])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn();
+        let Ok(mut child) = child else {
+            continue;
+        };
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(text.as_bytes()).ok();
+        }
+        if child.wait().is_ok_and(|s| s.success()) {
+            return Some(argv[0]);
+        }
+    }
+    if unsafe { libc::isatty(libc::STDOUT_FILENO) } != 1 {
+        return None;
+    }
+    print!("\x1b]52;c;{}\x07", base64(text.as_bytes()));
+    io::stdout().flush().ok();
+    Some("the terminal")
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::new();
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+fn man(tool: &Tool) {
+    let _guard = TerminalGuard::new();
+    let shown = Command::new("man")
+        .arg(&tool.name)
+        .status()
+        .is_ok_and(|s| s.success());
+    if !shown {
+        println!("no man page for {}.", tool.name);
+    }
+}
+
+// One tool, until the user goes back to the list or asks something. The
+// query to run, if they did.
+fn open(tool: &Tool, notes: &[String]) -> Option<Option<String>> {
+    show(tool, notes);
+    let examples = examples(tool);
+    loop {
+        let copy_hint = match examples.len() {
+            0 => String::new(),
+            1 => "[c] copy the example  ".to_string(),
+            n => format!("[c 1-{}] copy an example  ", n),
+        };
+        let input = read_line(&format!(
+            "{}[m] man page  [a] ask with {}  [enter] back  [q] quit ",
+            copy_hint, tool.name
+        ))?;
+        let mut words = input.split_whitespace();
+        match (words.next(), words.next()) {
+            (None, _) => return Some(None),
+            (Some("q"), _) => return None,
+            (Some("m"), _) => man(tool),
+            (Some("a"), _) => {
+                let task = read_line(&format!("use {} to: ", tool.name))?;
+                if !task.is_empty() {
+                    return Some(Some(format!("use {} to {}", tool.name, task)));
+                }
+            }
+            (Some("c"), n) if !examples.is_empty() => {
+                let n = n.and_then(|n| n.parse::<usize>().ok()).unwrap_or(1);
+                let Some(example) = examples.get(n.wrapping_sub(1)) else {
+                    println!("no example {}.", n);
+                    continue;
+                };
+                // Without the trailing comment, if there is one.
+                let command = example.split("  #").next().unwrap_or(example).trim();
+                match copy(command) {
+                    Some(to) => println!("copied to {}: {}", to, command),
+                    None => println!("no clipboard to copy to: {}", command),
+                }
+            }
+            _ => println!("?"),
+        }
+    }
+}
+
+/// Browses the index, starting from the tools that match `filter`. Returns
+/// the query to run when the user asks pls something about a tool.
+pub fn browse(config: &Config, filter: &str) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    let mut tools = load_all_tools(&open_index(&config.paths, false)?)?;
+    if tools.is_empty() {
+        println!("the index is empty; run `pls index` first.");
+        return Ok(None);
+    }
+    tools.sort_by(|a, b| a.name.cmp(&b.name));
+    let history = open_history(&config.paths)?;
+
+    let mut filter = filter.to_string();
+    let mut start = 0;
+    loop {
+        let found = search(&tools, &filter);
+        if found.is_empty() {
+            println!("no indexed tool matches {:?}.", filter);
+        } else {
+            start = start.min((found.len() - 1) / PAGE * PAGE);
+            let page = &found[start..found.len().min(start + PAGE)];
+            let name_width = page.iter().map(|t| t.name.len()).max().unwrap_or(0).min(24);
+            let room = width().saturating_sub(name_width + 9).max(20);
+            println!();
+            for (i, tool) in page.iter().enumerate() {
+                let first = tool.description.lines().next().unwrap_or_default();
+                let description: String = first.chars().take(room).collect();
+                let n = start + i + 1;
+                println!(
+                    "  {:>3}. {:<w$}  {}",
+                    n,
+                    tool.name,
+                    description,
+                    w = name_width
+                );
+            }
+            println!(
+                "  {}-{} of {} tools{}",
+                start + 1,
+                start + page.len(),
+                found.len(),
+                if filter.is_empty() {
+                    String::new()
+                } else {
+                    format!(" matching {:?}", filter)
+                }
+            );
+        }
+
+        let more = start + PAGE < found.len();
+        let input = read_line(&format!(
+            "[number] open  {}{}[text] search  [enter] quit ",
+            if more { "[n] next  " } else { "" },
+            if start > 0 { "[p] previous  " } else { "" },
+        ));
+        let Some(input) = input.filter(|i| !i.is_empty() && i != "q") else {
+            return Ok(None);
+        };
+        match input.as_str() {
+            "n" if more => start += PAGE,
+            "p" if start > 0 => start -= PAGE,
+            _ => match input.parse::<usize>() {
+                Ok(n) if (1..=found.len()).contains(&n) => {
+                    let tool = found[n - 1];
+                    let notes: Vec<String> = get_notes(&history, Some(&tool.name))?
+                        .into_iter()
+                        .map(|n| n.text)
+                        .collect();
+                    match open(tool, &notes) {
+                        Some(Some(query)) => return Ok(Some(query)),
+                        Some(None) => {}
+                        None => return Ok(None),
+                    }
+                }
+                Ok(n) => println!("no tool {}.", n),
+                Err(_) => {
+                    filter = input;
+                    start = 0;
+                }
+            },
+        }
+    }
+}
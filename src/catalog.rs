@@ -0,0 +1,58 @@
+use crate::dryrun;
+use crate::index::find_in_path;
+use crate::types::{Tool, ToolRecord};
+use serde::Deserialize;
+
+const CATALOG: &str = include_str!("catalog.toml");
+
+#[derive(Deserialize)]
+struct CatalogEntry {
+    name: String,
+    description: String,
+    flags: String,
+    examples: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct Catalog {
+    tool: Vec<CatalogEntry>,
+}
+
+/// Docs for core tools compiled into the binary, limited to the ones that
+/// are actually on PATH. These carry no embeddings and are only meant for
+/// keyword retrieval before the first index exists.
+pub fn builtin_tools() -> Vec<Tool> {
+    let catalog: Catalog = match toml::from_str(CATALOG) {
+        Ok(c) => c,
+        Err(_) => return Vec::new(),
+    };
+
+    catalog
+        .tool
+        .into_iter()
+        .filter_map(|entry| {
+            let path = find_in_path(&entry.name)?;
+            let dry_run = dryrun::known(&entry.name);
+            Some(Tool {
+                name: entry.name,
+                target: String::new(),
+                path,
+                description: entry.description,
+                synopsis: String::new(),
+                examples: entry
+                    .examples
+                    .iter()
+                    .map(|e| format!("- `{}`", e))
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+                flags: entry.flags,
+                source: "builtin".to_string(),
+                caveats: String::new(),
+                dry_run,
+                notes: Vec::new(),
+                record: ToolRecord::default(),
+                embedding: Vec::new(),
+            })
+        })
+        .collect()
+}
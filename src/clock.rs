@@ -0,0 +1,76 @@
+// Local-time helpers on top of libc, so we don't need a date crate.
+
+pub const WEEKDAYS: [&str; 7] = [
+    "Sunday",
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+    "Saturday",
+];
+
+pub fn now() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+pub fn local(timestamp: i64) -> libc::tm {
+    let t = timestamp as libc::time_t;
+    unsafe {
+        let mut tm: libc::tm = std::mem::zeroed();
+        libc::localtime_r(&t, &mut tm);
+        tm
+    }
+}
+
+pub fn weekday(tm: &libc::tm) -> &'static str {
+    WEEKDAYS[tm.tm_wday.rem_euclid(7) as usize]
+}
+
+pub fn format_date(tm: &libc::tm) -> String {
+    format!(
+        "{:04}-{:02}-{:02}",
+        tm.tm_year + 1900,
+        tm.tm_mon + 1,
+        tm.tm_mday
+    )
+}
+
+// Local midnight today, or on the first of this month.
+fn period_start(month: bool) -> i64 {
+    let mut tm = local(now());
+    tm.tm_hour = 0;
+    tm.tm_min = 0;
+    tm.tm_sec = 0;
+    if month {
+        tm.tm_mday = 1;
+    }
+    unsafe { libc::mktime(&mut tm) as i64 }
+}
+
+pub fn day_start() -> i64 {
+    period_start(false)
+}
+
+pub fn month_start() -> i64 {
+    period_start(true)
+}
+
+/// "2024-05-20 10:00:00" or "2024-05-20T10:00:00+02:00" as a timestamp,
+/// read as local time; any offset is ignored.
+pub fn parse_local(text: &str) -> Option<i64> {
+    let text = text.get(..19)?;
+    let field = |at: usize, len: usize| text.get(at..at + len)?.parse::<i32>().ok();
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    tm.tm_year = field(0, 4)? - 1900;
+    tm.tm_mon = field(5, 2)? - 1;
+    tm.tm_mday = field(8, 2)?;
+    tm.tm_hour = field(11, 2)?;
+    tm.tm_min = field(14, 2)?;
+    tm.tm_sec = field(17, 2)?;
+    tm.tm_isdst = -1;
+    Some(unsafe { libc::mktime(&mut tm) } as i64)
+}
@@ -1,72 +1,730 @@
-use crate::config::{save_config, Config};
+use crate::aliases::{default_sources, import_aliases, parse_file};
+use crate::browse;
+use crate::catalog::builtin_tools;
+use crate::clock;
+use crate::config::{read_config, save_config, set_config_value, Config, LlmConfig, PathsConfig};
+use crate::cooldown::{self, Gate};
 use crate::db::{
-    get_db_path, get_last_command, get_recent_history, get_tool_count, init_db, save_history,
+    begin_history, delete_fact, delete_meta, delete_note, delete_tool, find_recent_run,
+    finish_history, get_db_path, get_doc_chunk_count, get_executed, get_facts, get_generations,
+    get_history_entry, get_history_path, get_history_since, get_last_command, get_last_output,
+    get_last_run_place, get_meta, get_notes, get_recent_history, get_run_count, get_source_count,
+    get_tool_caveats, get_tool_count, get_tool_dry_runs, get_tool_records, get_tool_stamps,
+    get_usage_by_model, get_usage_since, import_history, load_all_tools, load_doc_chunks,
+    open_history, open_index, restore_generation, save_history, save_tool_runs,
+    set_history_fingerprint, set_meta, start_generation,
 };
+use crate::dirlock::{self, Attempt, DirLock};
+use crate::docs;
+use crate::drift;
+use crate::dryrun;
+use crate::edits::{self, Undone};
+use crate::embed::{embed_provider, make_embedder, Embedder};
 use crate::executor::execute_commands;
-use crate::index::index_tools;
-use crate::ollama::OllamaClient;
-use crate::planner::generate_plan;
-use crate::safety::assess_risk;
-use crate::types::RiskLevel;
-use crate::ui::{edit_command, print_blocked, print_plan, prompt_action, show_explanation};
-use std::{env, fs, io::Write, process::Command};
+use crate::export;
+use crate::facts;
+use crate::gc;
+use crate::hints::cwd_hints;
+use crate::http::{self, Cancelled};
+use crate::index::{self, find_in_path, improve_tools, index_tools};
+use crate::jobs;
+use crate::llm::{make_provider, provider_names, LlmProvider, ModelInfo, Unreachable};
+use crate::migrate;
+use crate::network;
+use crate::notes;
+use crate::paths::{cache_dir, data_dir, state_dir};
+use crate::pipe;
+use crate::plancache;
+use crate::planner::{find_persona, generate_plan_with_tools, persona_block, suggest_follow_ups};
+use crate::privacy;
+use crate::quota::{self, QuotaStatus};
+use crate::retrieval::{keyword_search, retrieve_relevant_tools, Context};
+use crate::safety::{self, assess_risk, explain_risk, mount_warnings};
+use crate::sandbox;
+use crate::schedule;
+use crate::shell::command_names;
+use crate::snapshot;
+use crate::spill;
+use crate::training;
+use crate::types::{ExecutionResult, HistoryDetail, NotPossible, Plan, RiskLevel};
+use crate::ui::{
+    clear_thinking, edit_command, print_blocked, print_duplicate_warning, print_edits,
+    print_not_possible, print_offline, print_plan, print_provenance, print_rehearsal,
+    print_thinking, print_timeline, print_training, print_warnings, prompt_action, prompt_cooldown,
+    prompt_dir_locked, prompt_drift, prompt_follow_up, prompt_model, prompt_reedit,
+    prompt_rephrase, prompt_step, show_explanation, status_marker,
+};
+use crate::units;
+use crate::verify::{verify_command, Status};
+use std::{
+    env, fs,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
 
-pub fn cmd_index(config: &Config, verbose: bool) -> Result<(), Box<dyn std::error::Error>> {
-    println!("indexing system tools...");
+// Marks a running `pls index` so queries don't spawn a second one.
+struct IndexLock(PathBuf);
 
-    let client = OllamaClient::new(&config.llm);
+impl IndexLock {
+    fn path(config: &Config) -> PathBuf {
+        state_dir(&config.paths).join("index.pid")
+    }
 
-    if !client.is_available() {
-        eprintln!("error: cannot connect to ollama");
+    fn acquire(config: &Config) -> Self {
+        let path = Self::path(config);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).ok();
+        }
+        fs::write(&path, std::process::id().to_string()).ok();
+        Self(path)
+    }
+
+    fn is_held(config: &Config) -> bool {
+        fs::read_to_string(Self::path(config))
+            .ok()
+            .and_then(|pid| pid.trim().parse::<libc::pid_t>().ok())
+            .is_some_and(|pid| unsafe { libc::kill(pid, 0) } == 0)
+    }
+}
+
+impl Drop for IndexLock {
+    fn drop(&mut self) {
+        fs::remove_file(&self.0).ok();
+    }
+}
+
+// Starts `pls index` unless one is running already; returns whether it did.
+fn spawn_background_index(
+    config: &Config,
+    history: &rusqlite::Connection,
+    incremental: bool,
+) -> bool {
+    if IndexLock::is_held(config) {
+        return false;
+    }
+    let Ok(exe) = env::current_exe() else {
+        return false;
+    };
+    let mut args = vec!["index", "--quiet"];
+    if incremental {
+        args.push("--incremental");
+    }
+    let mut cmd = Command::new(exe);
+    cmd.args(&args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    let label = format!("pls {}", args.join(" "));
+    jobs::spawn_detached(history, &mut cmd, &label).is_ok()
+}
+
+fn spawn_background_gc(history: &rusqlite::Connection) {
+    if let Ok(exe) = env::current_exe() {
+        let mut cmd = Command::new(exe);
+        cmd.args(["gc", "--quiet"])
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null());
+        jobs::spawn_detached(history, &mut cmd, "pls gc --quiet").ok();
+    }
+}
+
+// Errors out, naming the model and where it runs, when the embedder can't
+// be reached.
+fn embedder_ready(embedder: &dyn Embedder) -> Result<(), Box<dyn std::error::Error>> {
+    if embedder.is_available() {
+        return Ok(());
+    }
+    eprintln!(
+        "error: cannot connect to {} at {}",
+        embedder.id(),
+        embedder.endpoint()
+    );
+    if embedder.id().starts_with("ollama:") {
         eprintln!("  start it with: ollama serve");
-        return Err("ollama not available".into());
     }
+    Err(format!("{} not available", embedder.id()).into())
+}
+
+pub fn cmd_index(
+    config: &Config,
+    verbose: bool,
+    incremental: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if verbose {
+        println!("indexing system tools...");
+    }
+
+    let embedder = make_embedder(&config.llm)?;
+
+    embedder_ready(embedder.as_ref())?;
+    if config.llm.embed_provider == "local" {
+        eprintln!(
+            "note: embed_provider = \"local\" matches keywords, not meaning; \
+             set llm.embed_provider to a provider with an embedding model for semantic retrieval"
+        );
+    }
+
+    let db_path = get_db_path(&config.paths);
+    let conn = open_index(&config.paths, true)?;
+    let _lock = IndexLock::acquire(config);
+
+    // A full run that didn't finish is picked up where it stopped, over
+    // whatever was asked for, unless it was with another embedder.
+    let unfinished = get_meta(&conn, "index_run").and_then(|run| {
+        let (started, id) = run.split_once('\t')?;
+        (id == embedder.id())
+            .then(|| started.parse::<i64>().ok())
+            .flatten()
+    });
+    // A different embedder makes every stored vector useless, so that
+    // always means starting over.
+    let incremental = incremental
+        && unfinished.is_none()
+        && get_meta(&conn, "embedder").is_some_and(|id| id == embedder.id());
+    // Incremental runs only touch new and changed tools; a full one gets a
+    // generation of its own, so a bad one can be rolled back.
+    if unfinished.is_some() {
+        if verbose {
+            println!("picking up where the last full index stopped");
+        }
+    } else if !incremental {
+        start_generation(&conn, config.index.keep_generations)?;
+        let run = format!("{}\t{}", clock::now(), embedder.id());
+        set_meta(&conn, "index_run", &run)?;
+    }
+    let count = index_tools(
+        embedder.as_ref(),
+        &conn,
+        &config.index,
+        verbose,
+        incremental,
+        unfinished,
+    )?;
+    if !incremental {
+        let history = open_history(&config.paths)?;
+        notes::reembed(embedder.as_ref(), &history)?;
+        facts::reembed(embedder.as_ref(), &history)?;
+        docs::reembed(embedder.as_ref(), &conn)?;
+    }
+    set_meta(&conn, "embedder", &embedder.id())?;
+    set_meta(&conn, "indexed_at", &clock::now().to_string())?;
+    delete_meta(&conn, "index_run")?;
+
+    if verbose {
+        println!("done: {} tools indexed", count);
+        println!("  db: {:?}", db_path);
+    }
+
+    Ok(())
+}
+
+/// Reads the markdown and text files under `dir` into the index; see docs.rs.
+pub fn cmd_index_docs(
+    config: &Config,
+    dir: &str,
+    verbose: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let embedder = make_embedder(&config.llm)?;
+    embedder_ready(embedder.as_ref())?;
+    let conn = open_index(&config.paths, true)?;
+    let _lock = IndexLock::acquire(config);
+
+    let (files, chunks) = docs::ingest(embedder.as_ref(), &conn, Path::new(dir), verbose)?;
+    if files == 0 {
+        println!("no markdown or text files under {}.", dir);
+    } else if verbose {
+        println!("read {} files from {} into {} sections", files, dir, chunks);
+    }
+    Ok(())
+}
+
+/// Indexes each of `tools`, names on PATH or paths, in full over what the
+/// index had for them, without a pass over everything else.
+pub fn cmd_index_add(config: &Config, tools: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let embedder = make_embedder(&config.llm)?;
+    embedder_ready(embedder.as_ref())?;
+    let conn = open_index(&config.paths, true)?;
+    let _lock = IndexLock::acquire(config);
+    // Vectors from another embedder can't be compared with the rest.
+    match get_meta(&conn, "embedder") {
+        Some(id) if id != embedder.id() => {
+            return Err(format!(
+                "the index was built with {}, not {}; run a full `pls index`",
+                id,
+                embedder.id()
+            )
+            .into());
+        }
+        Some(_) => {}
+        None => set_meta(&conn, "embedder", &embedder.id())?,
+    }
+
+    let mut failed = 0;
+    for tool in tools {
+        match index::index_one(embedder.as_ref(), &conn, &config.index, tool) {
+            Ok(entries) if entries.len() > 1 => {
+                println!("indexed {} and {} subcommands", tool, entries.len() - 1)
+            }
+            Ok(_) => println!("indexed {}", tool),
+            Err(e) => {
+                eprintln!("{}: {}", tool, e);
+                failed += 1;
+            }
+        }
+    }
+    if failed > 0 {
+        return Err(format!("{} of {} not indexed", failed, tools.len()).into());
+    }
+    Ok(())
+}
+
+/// Drops `tools` from the index, or with none, every entry whose program
+/// is gone.
+pub fn cmd_index_remove(
+    config: &Config,
+    tools: &[String],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let conn = open_index(&config.paths, true)?;
+    let _lock = IndexLock::acquire(config);
+
+    let names: Vec<String> = if tools.is_empty() {
+        let mut gone: Vec<String> = get_tool_stamps(&conn)?
+            .into_iter()
+            .filter(|(name, (path, _))| {
+                !name.contains(' ') && path.starts_with('/') && !Path::new(path).exists()
+            })
+            .map(|(name, _)| name)
+            .collect();
+        gone.sort();
+        if gone.is_empty() {
+            println!("no stale entries.");
+        }
+        gone
+    } else {
+        tools.to_vec()
+    };
+    for name in &names {
+        match delete_tool(&conn, name)? {
+            0 => println!("{} is not in the index.", name),
+            1 => println!("removed {}", name),
+            n => println!("removed {} and {} subcommands", name, n - 1),
+        }
+    }
+    Ok(())
+}
+
+pub fn cmd_improve(config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let embedder = make_embedder(&config.llm)?;
+    embedder_ready(embedder.as_ref())?;
+    let conn = open_index(&config.paths, true)?;
+    let _lock = IndexLock::acquire(config);
+
+    // Summaries are the last resort; without a model the rest still runs.
+    let client = make_provider(&config.llm).ok().filter(|c| c.is_available());
+    if client.is_none() {
+        eprintln!("note: no model available; skipping summaries");
+    }
+
+    let report = improve_tools(
+        embedder.as_ref(),
+        client.as_deref(),
+        &conn,
+        &config.index,
+        true,
+    )?;
+    if report.candidates == 0 {
+        println!("no low-quality entries.");
+        return Ok(());
+    }
+    println!(
+        "upgraded {} of {} low-quality entries (man: {}, tldr: {}, help: {}, summary: {})",
+        report.upgraded(),
+        report.candidates,
+        report.man,
+        report.tldr,
+        report.help,
+        report.summary
+    );
+    Ok(())
+}
+
+pub fn cmd_schedule(config: &Config, install: bool) -> Result<(), Box<dyn std::error::Error>> {
+    if install {
+        let days = config.index.reindex_interval_days;
+        let installed = schedule::install(days)?;
+        println!(
+            "installed {}: incremental index every {} days",
+            installed,
+            days.max(1)
+        );
+    } else {
+        let removed = schedule::remove()?;
+        if removed.is_empty() {
+            println!("no schedule installed.");
+        }
+        for what in removed {
+            println!("removed {}", what);
+        }
+    }
+    Ok(())
+}
+
+pub fn cmd_import_aliases(
+    config: &Config,
+    files: &[String],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let sources: Vec<PathBuf> = if files.is_empty() {
+        default_sources()
+    } else {
+        files.iter().map(PathBuf::from).collect()
+    };
+
+    let aliases: Vec<_> = sources.iter().flat_map(|p| parse_file(p)).collect();
+    if aliases.is_empty() {
+        println!("no aliases found.");
+        return Ok(());
+    }
+
+    let embedder = make_embedder(&config.llm)?;
+    embedder_ready(embedder.as_ref())?;
+
+    let conn = open_index(&config.paths, true)?;
+
+    println!(
+        "importing {} aliases from {} files...",
+        aliases.len(),
+        sources.len()
+    );
+    let (imported, skipped) = import_aliases(embedder.as_ref(), &conn, aliases)?;
+
+    println!("done: {} imported", imported);
+    if skipped > 0 {
+        println!(
+            "  skipped {} (shadow an indexed tool or failed to embed)",
+            skipped
+        );
+    }
+
+    Ok(())
+}
+
+pub fn cmd_import_from(
+    config: &Config,
+    source: &str,
+    files: &[String],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let paths: Vec<PathBuf> = files.iter().map(PathBuf::from).collect();
+    let imported = migrate::read(source, &paths)?;
+    let history = open_history(&config.paths)?;
+    let provenance = [format!("import/{}", source)];
+    let mut added = 0;
+    for entry in &imported.entries {
+        let (query, commands) = (&entry.query, &entry.commands);
+        added += import_history(&history, query, commands, &provenance, entry.timestamp)? as usize;
+    }
+
+    let found = imported.entries.len();
+    println!("imported {} queries from {} into history", added, source);
+    if found > added {
+        println!("  {} were there already", found - added);
+    }
+    for what in &imported.skipped {
+        println!("  skipped {}", what);
+    }
+    if !imported.config.is_empty() {
+        println!();
+        println!("to keep the same model, put this in your config ('pls config'):");
+        println!();
+        for line in &imported.config {
+            println!("  {}", line);
+        }
+    }
+    Ok(())
+}
+
+pub fn cmd_tools_note(
+    config: &Config,
+    tool: &str,
+    text: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let index = open_index(&config.paths, false)?;
+    let history = open_history(&config.paths)?;
+    let embedder = make_embedder(&config.llm)?;
+    embedder_ready(embedder.as_ref())?;
+    notes::add(embedder.as_ref(), &index, &history, tool, text)?;
+    println!("noted for {}.", tool);
+    Ok(())
+}
+
+pub fn cmd_tools_notes(
+    config: &Config,
+    tool: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let notes = get_notes(&open_history(&config.paths)?, tool)?;
+    if notes.is_empty() {
+        println!("no notes yet.");
+        return Ok(());
+    }
+    for note in notes {
+        println!("{:>4}  {}: {}", note.id, note.tool, note.text);
+    }
+    Ok(())
+}
 
-    let db_path = get_db_path();
-    if let Some(parent) = db_path.parent() {
-        fs::create_dir_all(parent)?;
+pub fn cmd_tools_unnote(config: &Config, id: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let id: i64 = id.parse().map_err(|_| format!("not a note id: {}", id))?;
+    if !delete_note(&open_history(&config.paths)?, id)? {
+        return Err(format!("no note with id {}", id).into());
     }
+    println!("removed note {}.", id);
+    Ok(())
+}
 
-    let conn = rusqlite::Connection::open(&db_path)?;
-    init_db(&conn)?;
+/// Judges each of `commands`, and with `stdin` each read from there (one
+/// per line, or a JSON array), as pls would a plan of just that command.
+/// Fails when any is riskier than `max`, so CI jobs can gate on it.
+pub fn cmd_safety_check(
+    config: &Config,
+    mut commands: Vec<String>,
+    stdin: bool,
+    json: bool,
+    max: RiskLevel,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if stdin {
+        let mut input = String::new();
+        std::io::stdin().read_to_string(&mut input)?;
+        if input.trim_start().starts_with('[') {
+            let list: Vec<String> = serde_json::from_str(&input)
+                .map_err(|e| format!("stdin is not a JSON array of strings: {}", e))?;
+            commands.extend(list);
+        } else {
+            let lines = input.lines().map(str::trim);
+            let wanted = lines.filter(|l| !l.is_empty() && !l.starts_with('#'));
+            commands.extend(wanted.map(String::from));
+        }
+    }
+    if commands.is_empty() {
+        return Err("no commands to check".into());
+    }
 
-    let count = index_tools(&client, &conn, &config.index, verbose)?;
+    let assessments = safety::assess_each(&commands, &config.safety);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&assessments)?);
+    } else {
+        for assessment in &assessments {
+            println!("{:<9}  {}", assessment.risk.name(), assessment.command);
+            println!("           {}", assessment.reason);
+            for warning in &assessment.warnings {
+                println!("           warning: {}", warning.message);
+            }
+        }
+    }
+    let over = assessments
+        .iter()
+        .filter(|a| a.risk as u8 > max as u8)
+        .count();
+    if over > 0 {
+        return Err(format!(
+            "{} of {} commands are riskier than {}",
+            over,
+            commands.len(),
+            max.name()
+        )
+        .into());
+    }
+    Ok(())
+}
 
-    println!("done: {} tools indexed", count);
-    println!("  db: {:?}", db_path);
+pub fn cmd_tools_browse(config: &Config, filter: &str) -> Result<(), Box<dyn std::error::Error>> {
+    match browse::browse(config, filter)? {
+        Some(query) => cmd_query(&query, config, false, false, false, true),
+        None => Ok(()),
+    }
+}
 
+pub fn cmd_remember(config: &Config, text: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let embedder = make_embedder(&config.llm)?;
+    match facts::add(
+        embedder.as_ref(),
+        &open_history(&config.paths)?,
+        text,
+        "user",
+    )? {
+        Some(id) => println!("remembered as fact {}.", id),
+        None => println!("already known."),
+    }
     Ok(())
 }
 
-pub fn cmd_stats() -> Result<(), Box<dyn std::error::Error>> {
-    let db_path = get_db_path();
+pub fn cmd_facts(config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let facts = get_facts(&open_history(&config.paths)?)?;
+    if facts.is_empty() {
+        println!("no facts yet. add one with 'pls remember <fact>'.");
+        return Ok(());
+    }
+    for fact in facts {
+        let inferred = if fact.source == "inferred" {
+            "  (from an edit)"
+        } else {
+            ""
+        };
+        println!("{:>4}  {}{}", fact.id, fact.text, inferred);
+    }
+    Ok(())
+}
+
+pub fn cmd_forget(config: &Config, id: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let id: i64 = id.parse().map_err(|_| format!("not a fact id: {}", id))?;
+    if !delete_fact(&open_history(&config.paths)?, id)? {
+        return Err(format!("no fact with id {}", id).into());
+    }
+    println!("forgot fact {}.", id);
+    Ok(())
+}
+
+/// Puts back the files run `id` (else the latest that has any) rewrote in
+/// place. Files changed again since are left alone unless `force`.
+pub fn cmd_undo(
+    config: &Config,
+    id: Option<i64>,
+    force: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(entry) = id.or_else(|| edits::latest(&config.paths)) else {
+        println!("nothing to undo: no backups of files rewritten in place.");
+        return Ok(());
+    };
+    if let Some(detail) = get_history_entry(&open_history(&config.paths)?, entry)? {
+        println!("undoing #{}: {}", entry, detail.entry.query);
+    }
+    for result in edits::undo(&config.paths, entry, force)? {
+        match result {
+            Undone::Restored(path) => println!("  restored {}", path.display()),
+            Undone::Changed(path) => println!(
+                "  left {} alone: it changed after the run (--force restores it anyway)",
+                path.display()
+            ),
+            Undone::Failed(path, e) => println!("  could not restore {}: {}", path.display(), e),
+        }
+    }
+    Ok(())
+}
+
+pub fn cmd_stats(config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let db_path = get_db_path(&config.paths);
 
     if !db_path.exists() {
         println!("no index found. run 'pls index' first.");
         return Ok(());
     }
 
-    let conn = rusqlite::Connection::open(&db_path)?;
+    let conn = open_index(&config.paths, false)?;
     let count = get_tool_count(&conn);
     let size_kb = fs::metadata(&db_path)?.len() / 1024;
 
     println!("index stats:");
     println!("  tools: {}", count);
+    let brief = get_source_count(&conn, "brief");
+    if brief > 0 {
+        println!(
+            "         {} of them name and whatis only (pls index --improve)",
+            brief
+        );
+    }
+    let sections = get_doc_chunk_count(&conn);
+    if sections > 0 {
+        println!(
+            "  docs:  {} sections of your own (pls index --docs)",
+            sections
+        );
+    }
     println!("  size:  {} KB", size_kb);
     println!("  path:  {:?}", db_path);
 
+    let generations = get_generations(&conn)?;
+    if !generations.is_empty() {
+        println!();
+        println!("earlier generations (pls index rollback [id]):");
+    }
+    for generation in generations {
+        println!(
+            "  {:>3}  {}  {:>4} tools  {}",
+            generation.id,
+            clock::format_date(&clock::local(generation.built)),
+            generation.tools,
+            generation.embedder
+        );
+    }
+
     Ok(())
 }
 
-pub fn cmd_history(config: &Config) -> Result<(), Box<dyn std::error::Error>> {
-    let db_path = get_db_path();
+/// Puts back an earlier generation of the index: `id`, or the one before
+/// the current one.
+pub fn cmd_index_rollback(
+    config: &Config,
+    id: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let conn = open_index(&config.paths, true)?;
+    if IndexLock::is_held(config) {
+        return Err("an index run is in progress; roll back once it is done".into());
+    }
+    let _lock = IndexLock::acquire(config);
 
-    if !db_path.exists() {
-        println!("no history yet.");
-        return Ok(());
+    let generations = get_generations(&conn)?;
+    let wanted = match id {
+        Some(id) => Some(
+            id.parse::<i64>()
+                .map_err(|_| format!("not a generation id: {}", id))?,
+        ),
+        None => None,
+    };
+    let Some(generation) = generations
+        .iter()
+        .find(|g| wanted.is_none_or(|id| g.id == id))
+    else {
+        if generations.is_empty() {
+            println!("no earlier generations kept. see index.keep_generations.");
+            return Ok(());
+        }
+        return Err(format!(
+            "no generation {}. see 'pls index --stats'",
+            id.unwrap_or("")
+        )
+        .into());
+    };
+
+    let replaced = get_meta(&conn, "embedder").unwrap_or_default();
+    restore_generation(&conn, generation.id)?;
+    println!(
+        "index is generation {} again: {} tools from {}",
+        generation.id,
+        generation.tools,
+        clock::format_date(&clock::local(generation.built))
+    );
+
+    // Notes and facts are embedded like the tools, so they follow the
+    // index back when the configured embedder built it.
+    let embedder = make_embedder(&config.llm)?;
+    if generation.embedder != embedder.id() {
+        println!(
+            "note: it was built with {} but {} is configured; switch back to it, \
+             or run 'pls index'.",
+            generation.embedder,
+            embedder.id()
+        );
+    } else if generation.embedder != replaced && embedder.is_available() {
+        let history = open_history(&config.paths)?;
+        notes::reembed(embedder.as_ref(), &history)?;
+        facts::reembed(embedder.as_ref(), &history)?;
+        docs::reembed(embedder.as_ref(), &conn)?;
     }
+    Ok(())
+}
 
-    let conn = rusqlite::Connection::open(&db_path)?;
+pub fn cmd_history(config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let conn = open_history(&config.paths)?;
     let entries = get_recent_history(&conn, config.behavior.history_window)?;
 
     if entries.is_empty() {
@@ -78,53 +736,113 @@ pub fn cmd_history(config: &Config) -> Result<(), Box<dyn std::error::Error>> {
     println!();
 
     for entry in entries {
-        let status = if entry.executed {
-            if entry.succeeded {
-                "+"
-            } else {
-                "x"
-            }
-        } else {
-            "-"
-        };
-
-        println!("{} {}", status, entry.query);
+        println!("{} {}  (#{})", status_marker(&entry), entry.query, entry.id);
         for cmd in &entry.commands {
             println!("    {}", cmd);
         }
+        if let Some(changes) = &entry.changes {
+            println!("    changes: {}", changes);
+        }
+        if let Some(stage) = &entry.failed_stage {
+            println!("    failed at {}", stage);
+        }
         println!();
     }
 
     Ok(())
 }
 
-pub fn cmd_edit_last(config: &Config) -> Result<(), Box<dyn std::error::Error>> {
-    let db_path = get_db_path();
+pub fn cmd_history_show(
+    config: &Config,
+    id: i64,
+    raw: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let conn = open_history(&config.paths)?;
+    let Some(HistoryDetail {
+        entry,
+        provenance,
+        raw_reply: reply,
+    }) = get_history_entry(&conn, id)?
+    else {
+        return Err(format!("no history entry #{}", id).into());
+    };
 
-    if !db_path.exists() {
-        println!("no history yet.");
+    if raw {
+        match reply {
+            Some(reply) => println!("{}", reply),
+            None => println!(
+                "no reply archived for #{}; set behavior.archive_replies = true to keep them",
+                id
+            ),
+        }
+        return Ok(());
+    }
+
+    let when = clock::format_date(&clock::local(entry.timestamp));
+    println!("{} {}  (#{})", status_marker(&entry), entry.query, entry.id);
+    println!("    when: {}", when);
+    if !entry.cwd.is_empty() {
+        println!("    in:   {}", entry.cwd);
+    }
+    for cmd in &entry.commands {
+        println!("    {}", cmd);
+    }
+    if entry.interrupted {
+        println!("    interrupted after {} ms", entry.duration_ms);
+    } else if entry.executed {
+        println!("    took {} ms", entry.duration_ms);
+    }
+    if let Some(changes) = &entry.changes {
+        println!("    changes: {}", changes);
+    }
+    if let Some(stage) = &entry.failed_stage {
+        println!("    failed at {}", stage);
+    }
+    if !provenance.is_empty() {
+        println!("    based on: {}", provenance.join(", "));
+    }
+    if reply.is_some() {
+        println!("    model reply archived: pls history show {} --raw", id);
+    }
+    Ok(())
+}
+
+pub fn cmd_timeline(config: &Config, days: u32) -> Result<(), Box<dyn std::error::Error>> {
+    let conn = open_history(&config.paths)?;
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)?
+        .as_secs() as i64;
+    let entries = get_history_since(&conn, now - days as i64 * 86_400)?;
+
+    if entries.is_empty() {
+        println!("no history in the last {} days.", days);
         return Ok(());
     }
 
-    let conn = rusqlite::Connection::open(&db_path)?;
+    print_timeline(&entries);
+    Ok(())
+}
+
+pub fn cmd_edit_last(config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let conn = open_history(&config.paths)?;
 
     match get_last_command(&conn)? {
         Some(cmd) => {
-            if let Some(edited) = edit_command(&cmd) {
+            if let Some(edited) = edit_command(&cmd, config.output.multiline_width) {
                 let edited = edited.trim();
                 if !edited.is_empty() {
                     println!("edited: {}", edited);
-                    let (succeeded, output) =
-                        execute_commands(&[edited.to_string()], config.safety.max_output_lines)?;
-                    println!("{}", output);
-                    save_history(
-                        &conn,
-                        "[edited]",
-                        &[edited.to_string()],
-                        true,
-                        succeeded,
-                        &output,
-                    )?;
+                    let commands = [edited.to_string()];
+                    let risk = assess_risk(&commands, &config.safety);
+                    let place = get_last_run_place(&conn)?.unwrap_or_default();
+                    let drift = drift::drift_since(&place.0, &place.1);
+                    if on_drift(config, drift, risk, false) != 'r' {
+                        println!("cancelled.");
+                        return Ok(());
+                    }
+                    cool_down(config, &conn, risk)?;
+                    let entry = begin_history(&conn, "[edited]", &commands, &[], None)?;
+                    run_plan(config, &conn, entry, "", &commands, &[], risk)?;
                 }
             }
         }
@@ -136,20 +854,107 @@ pub fn cmd_edit_last(config: &Config) -> Result<(), Box<dyn std::error::Error>>
     Ok(())
 }
 
+/// Prints the `nth` most recent plan that was run (1 is the last) as a
+/// snippet for another system.
+pub fn cmd_export(
+    config: &Config,
+    format: &str,
+    nth: usize,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let conn = open_history(&config.paths)?;
+    let entry = get_executed(&conn, nth.saturating_sub(1))?.ok_or(format!(
+        "nothing to export: fewer than {} plans in history were run",
+        nth
+    ))?;
+    print!("{}", export::render(format, &entry.query, &entry.commands)?);
+    Ok(())
+}
+
+/// Writes the exact stdout of the last command pls ran, so names with odd
+/// bytes survive being piped into the next one.
+pub fn cmd_output(config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let conn = open_history(&config.paths)?;
+    match get_last_output(&conn)? {
+        Some(raw) => {
+            let mut stdout = std::io::stdout().lock();
+            stdout.write_all(&raw)?;
+            stdout.flush()?;
+        }
+        None => eprintln!("no recorded output."),
+    }
+    Ok(())
+}
+
 pub fn cmd_doctor(config: &Config) -> Result<(), Box<dyn std::error::Error>> {
     println!("diagnostics:");
     println!();
 
-    let client = OllamaClient::new(&config.llm);
+    let client = make_provider(&config.llm)?;
 
-    print!("  ollama ... ");
+    print!("  {} ... ", client.name());
     std::io::stdout().flush().ok();
-    if client.is_available() {
+    let available = client.is_available();
+    if available {
         println!("ok");
+        let caps = client.capabilities();
+        let flag = |on: bool| if on { "yes" } else { "no" };
+        println!(
+            "    json mode: {}  json schema: {}  streaming: {}  embed api: {}  keep_alive: {}",
+            flag(caps.json_mode),
+            flag(caps.json_schema),
+            flag(caps.streaming),
+            flag(caps.embeddings),
+            flag(caps.keep_alive)
+        );
     } else {
         println!("failed");
-        println!("    url: {}", config.llm.endpoint);
-        println!("    try: ollama serve");
+        println!("    url: {}", client.endpoint());
+        if client.name() == "ollama" {
+            println!("    try: ollama serve");
+        }
+    }
+
+    // Local servers only get the hop-by-hop breakdown when they're down;
+    // remote ones always do, since latency and proxies matter there.
+    if !available || !network::is_local(client.endpoint()) {
+        println!("  network ({}):", client.endpoint());
+        // Names only; the values are usually credentials.
+        let mut headers: Vec<&str> = config.llm.headers.keys().map(String::as_str).collect();
+        headers.sort();
+        if http::bearer_token(&config.llm).is_some() {
+            headers.insert(0, "Authorization (bearer token)");
+        }
+        if !headers.is_empty() {
+            println!("    extra headers: {}", headers.join(", "));
+        }
+        for check in network::diagnose(client.endpoint(), &config.llm) {
+            if check.ok {
+                println!("    {} ... ok ({})", check.step, check.detail);
+            } else {
+                println!("    {} ... failed", check.step);
+                println!("      {}", check.detail);
+            }
+            if let Some(hint) = check.hint {
+                println!("      try: {}", hint);
+            }
+        }
+    }
+    if !network::is_local(client.endpoint()) {
+        let shared = &config.privacy.remote_share;
+        println!(
+            "  shared with the model: tool docs, the task, {}",
+            shared.join(", ")
+        );
+        for item in shared
+            .iter()
+            .filter(|s| !privacy::SHAREABLE.contains(&s.as_str()))
+        {
+            println!(
+                "    unknown privacy.remote_share entry '{}' (known: {})",
+                item,
+                privacy::SHAREABLE.join(", ")
+            );
+        }
     }
 
     print!("  model ({}) ... ", config.llm.model);
@@ -159,26 +964,34 @@ pub fn cmd_doctor(config: &Config) -> Result<(), Box<dyn std::error::Error>> {
         Err(e) => {
             println!("failed");
             println!("    error: {}", e);
-            println!("    try: ollama pull {}", config.llm.model);
+            if client.name() == "ollama" {
+                println!("    try: ollama pull {}", config.llm.model);
+            }
         }
     }
 
-    print!("  embeddings ({}) ... ", config.llm.embed_model);
+    let embedder = make_embedder(&config.llm)?;
+    print!("  embeddings ({}) ... ", embedder.id());
     std::io::stdout().flush().ok();
-    match client.embed("test") {
+    match embedder.embed("test") {
+        Ok(_) if config.llm.embed_provider == "local" => {
+            println!("ok (keyword matching only)")
+        }
         Ok(_) => println!("ok"),
         Err(e) => {
             println!("failed");
             println!("    error: {}", e);
-            println!("    try: ollama pull {}", config.llm.embed_model);
+            if config.llm.embed_provider == "ollama" {
+                println!("    try: ollama pull {}", config.llm.embed_model);
+            }
         }
     }
 
-    let db_path = get_db_path();
+    let db_path = get_db_path(&config.paths);
     print!("  index ... ");
     std::io::stdout().flush().ok();
     if db_path.exists() {
-        let conn = rusqlite::Connection::open(&db_path)?;
+        let conn = open_index(&config.paths, false)?;
         let count = get_tool_count(&conn);
         if count > 0 {
             println!("ok ({} tools)", count);
@@ -204,6 +1017,268 @@ pub fn cmd_doctor(config: &Config) -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+pub fn cmd_kill_all(config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let history = open_history(&config.paths)?;
+    let stopped = jobs::kill_all(&history)?;
+
+    if stopped.is_empty() {
+        println!("nothing running.");
+        return Ok(());
+    }
+    for (job, killed) in &stopped {
+        let how = if *killed { "killed" } else { "stopped" };
+        println!("  {} {} (group {})", how, job.command, job.pgid);
+    }
+    Ok(())
+}
+
+pub fn cmd_paths(config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    println!("config:  {:?}", crate::config::get_config_path());
+    println!("data:    {:?}", data_dir(&config.paths));
+    let shared = if config.paths.shared_index.is_some() {
+        " (shared)"
+    } else {
+        ""
+    };
+    println!("index:   {:?}{}", get_db_path(&config.paths), shared);
+    println!("state:   {:?}", state_dir(&config.paths));
+    println!("history: {:?}", get_history_path(&config.paths));
+    println!("cache:   {:?}", cache_dir(&config.paths));
+    Ok(())
+}
+
+pub fn cmd_quota(config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let history = open_history(&config.paths)?;
+    let periods = [
+        ("today", clock::day_start(), config.quota.daily_budget),
+        (
+            "this month",
+            clock::month_start(),
+            config.quota.monthly_budget,
+        ),
+    ];
+
+    println!("usage:");
+    for (label, since, budget) in periods {
+        let (prompt, completion, cost) = get_usage_since(&history, since);
+        let limit = if budget > 0.0 {
+            format!(" / {:.2}", budget)
+        } else {
+            String::new()
+        };
+        println!(
+            "  {:<11} {:>9} in  {:>9} out  cost {:.2}{}",
+            label, prompt, completion, cost, limit
+        );
+    }
+
+    if config.quota.pricing.is_empty() {
+        println!();
+        println!("  no model pricing configured; costs are counted as 0.");
+    }
+
+    Ok(())
+}
+
+// Tokens and latency per model, for choosing between them. Tokens per
+// second is completion tokens over the whole request time, prompt
+// evaluation and network included.
+pub fn cmd_usage(config: &Config, days: u32) -> Result<(), Box<dyn std::error::Error>> {
+    let history = open_history(&config.paths)?;
+    let rows = get_usage_by_model(&history, clock::now() - days as i64 * 86_400)?;
+    if rows.is_empty() {
+        println!("no model requests in the last {} days.", days);
+        return Ok(());
+    }
+
+    println!("model usage, last {} days:", days);
+    println!(
+        "  {:<24} {:>8} {:>11} {:>11} {:>12} {:>7}",
+        "model", "requests", "tokens in", "tokens out", "avg latency", "out/s"
+    );
+    for row in rows {
+        let (latency, rate) = if row.timed_requests > 0 && row.latency_ms > 0 {
+            let average = row.latency_ms / row.timed_requests;
+            let seconds = row.latency_ms as f64 / 1000.0;
+            (
+                if average < 1000 {
+                    format!("{} ms", average)
+                } else {
+                    format!("{:.1} s", average as f64 / 1000.0)
+                },
+                format!("{:.1}", row.timed_completion_tokens as f64 / seconds),
+            )
+        } else {
+            ("-".to_string(), "-".to_string())
+        };
+        println!(
+            "  {:<24} {:>8} {:>11} {:>11} {:>12} {:>7}",
+            row.model, row.requests, row.prompt_tokens, row.completion_tokens, latency, rate
+        );
+    }
+    Ok(())
+}
+
+// Runs one load step for `pls warm`, reporting how long it took.
+fn warm_step(name: &str, load: impl FnOnce() -> Result<(), Box<dyn std::error::Error>>) -> bool {
+    print!("loading {} ... ", name);
+    std::io::stdout().flush().ok();
+    let started = std::time::Instant::now();
+    match load() {
+        Ok(()) => {
+            println!("ok ({:.1} s)", started.elapsed().as_secs_f64());
+            true
+        }
+        Err(e) => {
+            println!("failed");
+            println!("  {}", e);
+            false
+        }
+    }
+}
+
+pub fn cmd_gc(config: &Config, quiet: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let report = gc::run(config)?;
+    if quiet {
+        return Ok(());
+    }
+    let pruned = [
+        (report.history, "history entries"),
+        (report.outputs, "saved outputs"),
+        (report.usage, "usage records"),
+        (report.tool_runs, "tool outcomes"),
+        (report.plans, "expired cached plans"),
+        (report.backups, "runs' file backups"),
+    ];
+    for (count, what) in pruned.iter().filter(|(count, _)| *count > 0) {
+        println!("pruned {} {}", count, what);
+    }
+    println!(
+        "reclaimed {} ({} -> {})",
+        snapshot::format_bytes(report.reclaimed()),
+        snapshot::format_bytes(report.bytes_before),
+        snapshot::format_bytes(report.bytes_after)
+    );
+    Ok(())
+}
+
+// Ollama shows a model pulled without a tag as NAME:latest.
+fn same_model(configured: &str, listed: &str) -> bool {
+    configured == listed || format!("{}:latest", configured) == listed
+}
+
+pub fn cmd_models(config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let llm = &config.llm;
+    let client = make_provider(llm)?;
+    let models = list_models(client.as_ref())?;
+    if models.is_empty() {
+        println!(
+            "no models at {}. pull one with 'ollama pull {}'",
+            client.endpoint(),
+            llm.model
+        );
+        return Ok(());
+    }
+    print_models(client.endpoint(), &models, llm);
+
+    // Embeddings from another provider or server come from its own list.
+    let separate = llm.embed_provider != llm.provider
+        || llm
+            .embed_endpoint
+            .as_ref()
+            .is_some_and(|e| *e != llm.endpoint);
+    let embedder = if separate { embed_provider(llm)? } else { None };
+    let embed_models = match &embedder {
+        Some(embedder) => {
+            let embed_models = list_models(embedder.as_ref())?;
+            println!();
+            print_models(embedder.endpoint(), &embed_models, llm);
+            embed_models
+        }
+        None => models.clone(),
+    };
+
+    if unsafe { libc::isatty(libc::STDIN_FILENO) } != 1 {
+        return Ok(());
+    }
+    println!();
+    if let Some(model) = prompt_model("model", &llm.model, &models) {
+        set_config_value("llm", "model", &model)?;
+        println!("llm.model = {}", model);
+    }
+    if llm.embed_provider == "local" || embed_models.is_empty() {
+        return Ok(());
+    }
+    if let Some(model) = prompt_model("embed model", &llm.embed_model, &embed_models) {
+        set_config_value("llm", "embed_model", &model)?;
+        println!(
+            "llm.embed_model = {}; run 'pls index' to re-embed the tools with it",
+            model
+        );
+    }
+    Ok(())
+}
+
+fn list_models(client: &dyn LlmProvider) -> Result<Vec<ModelInfo>, Box<dyn std::error::Error>> {
+    if !client.is_available() {
+        eprintln!(
+            "error: cannot connect to {} at {}",
+            client.name(),
+            client.endpoint()
+        );
+        return Err(format!("{} not available", client.name()).into());
+    }
+    client.list_models()
+}
+
+fn print_models(endpoint: &str, models: &[ModelInfo], llm: &LlmConfig) {
+    println!("models at {}:", endpoint);
+    println!();
+    let width = models.iter().map(|m| m.name.len()).max().unwrap_or(0);
+    for (i, model) in models.iter().enumerate() {
+        let mut roles = Vec::new();
+        if same_model(&llm.model, &model.name) {
+            roles.push("model");
+        }
+        if same_model(&llm.embed_model, &model.name) {
+            roles.push("embed_model");
+        }
+        let roles = if roles.is_empty() {
+            String::new()
+        } else {
+            format!("  ({})", roles.join(", "))
+        };
+        // Hosted APIs don't say how big their models are.
+        let size = if model.size > 0 {
+            snapshot::format_bytes(model.size)
+        } else {
+            String::new()
+        };
+        println!(
+            "  {:>2}  {:<width$}  {:>8}  {}{}",
+            i + 1,
+            model.name,
+            size,
+            model.details,
+            roles,
+            width = width
+        );
+    }
+}
+
+// Loads the generate and embed models ahead of the first query; with
+// llm.keep_alive set they stay loaded.
+pub fn cmd_warm(config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let client = make_provider(&config.llm)?;
+    let embedder = make_embedder(&config.llm)?;
+    let model = warm_step(client.model(), || client.warm());
+    let embed = warm_step(&embedder.id(), || embedder.embed_query("warm").map(|_| ()));
+    if !(model && embed) {
+        return Err("could not load every model".into());
+    }
+    Ok(())
+}
+
 pub fn cmd_config() -> Result<(), Box<dyn std::error::Error>> {
     let config_path = crate::config::get_config_path();
 
@@ -214,48 +1289,667 @@ pub fn cmd_config() -> Result<(), Box<dyn std::error::Error>> {
         save_config(&Config::default())?;
     }
 
+    // An edit that doesn't parse is never left in place.
+    let previous = fs::read_to_string(&config_path)?;
     let editor = env::var("EDITOR").unwrap_or_else(|_| "vi".to_string());
-    Command::new(&editor).arg(&config_path).status()?;
+    loop {
+        Command::new(&editor).arg(&config_path).status()?;
+        let Err(e) = read_config(&config_path) else {
+            return Ok(());
+        };
+        eprintln!("error: {}: {}", config_path.display(), e);
+        if !prompt_reedit() {
+            fs::write(&config_path, &previous)?;
+            println!("kept the previous config.");
+            return Ok(());
+        }
+    }
+}
 
-    Ok(())
+// Training mode, for a user's first few runs.
+fn show_training(config: &Config, history: &rusqlite::Connection, commands: &[String]) {
+    let runs = get_run_count(history);
+    let limit = config.behavior.training_runs;
+    if runs >= limit {
+        return;
+    }
+    let (risk, reason) = explain_risk(commands, &config.safety);
+    let effects: Vec<(String, String)> = commands
+        .iter()
+        .map(|c| {
+            (
+                c.clone(),
+                training::describe(c, &config.safety.safe_commands),
+            )
+        })
+        .collect();
+    print_training(risk, &reason, &effects, limit - runs);
 }
 
-pub fn cmd_query(
-    query: &str,
+// Waits for, overrides or gives up on another run changing this directory,
+// as the user says. A directory that can't be locked isn't guarded.
+fn lock_cwd(
+    paths: &PathsConfig,
+    commands: &[String],
+) -> Result<Option<DirLock>, Box<dyn std::error::Error>> {
+    let Ok(cwd) = env::current_dir() else {
+        return Ok(None);
+    };
+    match dirlock::try_lock(paths, &cwd, commands) {
+        Ok(Attempt::Acquired(lock)) => Ok(Some(lock)),
+        Ok(Attempt::Held(holder)) => match prompt_dir_locked(&holder) {
+            'w' => {
+                println!("waiting...");
+                Ok(dirlock::lock(paths, &cwd, commands).ok())
+            }
+            'o' => Ok(None),
+            _ => Err("not run: another pls run is modifying this directory".into()),
+        },
+        Err(_) => Ok(None),
+    }
+}
+
+// Holds a dangerous plan back while the cooldown from the last one lasts,
+// and refuses it past the hourly cap; see cooldown.rs. Called once when a
+// plan is approved, not for its dry runs or each of its steps.
+fn cool_down(
     config: &Config,
-    yolo: bool,
-    explain_only: bool,
+    history: &rusqlite::Connection,
+    risk: RiskLevel,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let client = OllamaClient::new(&config.llm);
+    if risk != RiskLevel::Dangerous {
+        return Ok(());
+    }
+    match cooldown::check(history, &config.safety) {
+        Gate::Open => {}
+        Gate::Capped(why) => return Err(format!("not run: {}", why).into()),
+        Gate::Cooling(left) => {
+            let reauth = config.safety.cooldown_reauth && cooldown::can_reauthenticate();
+            match prompt_cooldown(&cooldown::format_wait(left), reauth) {
+                'w' => cooldown::wait(left),
+                'a' if cooldown::reauthenticate() => {}
+                'a' => return Err("not run: could not re-authenticate".into()),
+                _ => return Err("not run: still cooling down from the last dangerous plan".into()),
+            }
+        }
+    }
+    cooldown::record(history)
+}
+
+// Runs `commands`, prints their output and files the outcome under `entry`,
+// from `begin_history`. Mutating plans get a before/after snapshot of the
+// working directory when `safety.snapshot_changes` is on. `query` may ask
+// for the output's sizes or dates in another form.
+fn run_plan(
+    config: &Config,
+    history: &rusqlite::Connection,
+    entry: i64,
+    query: &str,
+    commands: &[String],
+    verify: &[Option<String>],
+    risk: RiskLevel,
+) -> Result<ExecutionResult, Box<dyn std::error::Error>> {
+    let _lock = if matches!(risk, RiskLevel::Review | RiskLevel::Dangerous) {
+        lock_cwd(&config.paths, commands)?
+    } else {
+        None
+    };
+    let snapshot_root = if config.safety.snapshot_changes
+        && matches!(risk, RiskLevel::Review | RiskLevel::Dangerous)
+    {
+        env::current_dir().ok()
+    } else {
+        None
+    };
+    let before = snapshot_root
+        .as_ref()
+        .map(|root| snapshot::take(root, config.safety.snapshot_max_files));
+
+    edits::back_up(&config.paths, entry, commands)?;
+    let spill = spill::create(&config.paths, entry)?;
+    let max_lines = config.safety.max_output_lines;
+    let pipefail = config.behavior.pipefail;
+    let mut result = execute_commands(commands, verify, max_lines, pipefail, history, &spill)?;
+    edits::seal(&config.paths, entry)?;
+    let format = units::wanted(query, &config.output);
+    if !format.is_plain() {
+        result.output = units::apply(&result.output, commands, &format);
+    }
+    println!("{}", result.output);
+    pipe::forward(commands, &result.raw_output);
+
+    // Every tool in a step shares its outcome; retrieval and the prompt use
+    // these to steer away from tools that keep failing here.
+    let mut runs: Vec<(String, bool)> = Vec::new();
+    for (cmd, succeeded) in commands.iter().zip(&result.steps) {
+        let mut names = command_names(cmd);
+        names.sort();
+        names.dedup();
+        runs.extend(names.into_iter().map(|name| (name, *succeeded)));
+    }
+    save_tool_runs(history, &runs)?;
+
+    if let (Some(root), Some(before)) = (&snapshot_root, &before) {
+        let after = snapshot::take(root, config.safety.snapshot_max_files);
+        let summary = snapshot::diff(before, &after).summary();
+        println!("changes: {}", summary);
+        result.changes = Some(summary);
+    }
+
+    finish_history(history, entry, &result)?;
+    set_history_fingerprint(history, entry, &drift::take().digest())?;
+    spill::remove(&spill);
+    Ok(result)
+}
+
+// Before a plan that changes things runs, what `safety.on_drift` makes of
+// `drift`, the change to the directory since it was planned: 'r' to run, 'p'
+// to plan again (only if `can_replan`), 'q' not to.
+fn on_drift(config: &Config, drift: Option<String>, risk: RiskLevel, can_replan: bool) -> char {
+    let mutating = matches!(risk, RiskLevel::Review | RiskLevel::Dangerous);
+    let Some(why) = drift.filter(|_| mutating && config.safety.on_drift != "off") else {
+        return 'r';
+    };
+    if config.safety.on_drift == "replan" && can_replan {
+        println!("{}; planning again.", why);
+        return 'p';
+    }
+    prompt_drift(&why, can_replan)
+}
+
+// Asks the model for a plan, respecting quotas and falling back to the
+// built-in catalog before the first index exists. Returns the plan and the
+// open index.
+// `use_cache` false asks the model even if the plan cache has an answer.
+fn make_plan(
+    config: &Config,
+    history: &rusqlite::Connection,
+    query: &str,
+    use_cache: bool,
+) -> Result<(Plan, rusqlite::Connection), Box<dyn std::error::Error>> {
+    let mut llm = config.llm.clone();
+    match quota::check(history, &config.quota) {
+        QuotaStatus::Ok => {}
+        QuotaStatus::Warn(msg) => eprintln!("warning: {}", msg),
+        QuotaStatus::Exceeded(msg) => match &config.quota.fallback_model {
+            Some(model) => {
+                let provider = &config.quota.fallback_provider;
+                if !provider_names().contains(&provider.as_str()) {
+                    return Err(format!(
+                        "{}, and quota.fallback_provider '{}' isn't in this build (available: {})",
+                        msg,
+                        provider,
+                        provider_names().join(", ")
+                    )
+                    .into());
+                }
+                eprintln!("{}; using {} model {}", msg, provider, model);
+                llm.provider = provider.clone();
+                llm.model = model.clone();
+                llm.endpoint = config.quota.fallback_endpoint.clone();
+            }
+            None => return Err(format!("{}. see 'pls quota'", msg).into()),
+        },
+    }
+
+    let persona = match config.behavior.persona.as_deref().filter(|p| !p.is_empty()) {
+        Some(name) => Some(find_persona(name, &config.personas)?),
+        None => None,
+    };
+    let client = make_provider(&llm)?;
 
     if !client.is_available() {
-        eprintln!("error: cannot connect to ollama");
-        return Err("ollama not available".into());
+        eprintln!(
+            "error: cannot connect to {} at {}",
+            client.name(),
+            client.endpoint()
+        );
+        return Err(Unreachable(client.name().to_string()).into());
     }
 
-    let db_path = get_db_path();
-    if !db_path.exists() {
-        eprintln!("no index found. running initial indexing...");
-        cmd_index(config, true)?;
+    // Loading a model can take longer than everything before the request;
+    // overlap the two.
+    if llm.warm_up {
+        let warmer = make_provider(&llm)?;
+        std::thread::spawn(move || warmer.warm().ok());
     }
 
-    let conn = rusqlite::Connection::open(&db_path)?;
-    init_db(&conn)?;
+    let conn = open_index(&config.paths, false)?;
+
+    // Until the first index is built, plan from the compiled-in catalog and
+    // let indexing catch up in the background. A shared index is someone
+    // else's to build.
+    let indexed = get_tool_count(&conn) > 0;
+    if !indexed && config.paths.shared_index.is_some() {
+        eprintln!("shared index is empty. using built-in docs.");
+    } else if !indexed {
+        eprintln!("no index yet. using built-in docs while indexing runs in the background.");
+        spawn_background_index(config, history, false);
+    } else if config.index.auto_reindex && config.paths.shared_index.is_none() {
+        if let Some(reason) = index::stale(&conn, &config.index) {
+            if spawn_background_index(config, history, true) {
+                eprintln!("index is stale ({}); updating it in the background", reason);
+            }
+        }
+    }
+    if gc::is_due(config) {
+        spawn_background_gc(history);
+    }
+
+    let embedder = make_embedder(&config.llm)?;
+    if let Some(built_with) = get_meta(&conn, "embedder") {
+        if indexed && built_with != embedder.id() {
+            eprintln!(
+                "warning: index was built with {} but {} is configured. run 'pls index'.",
+                built_with,
+                embedder.id()
+            );
+        }
+    }
 
     eprint!("thinking...");
     std::io::stderr().flush().ok();
 
-    let plan = generate_plan(&client, &conn, query)?;
+    let mut hints = if config.behavior.cwd_hints {
+        cwd_hints(&cache_dir(&config.paths))
+    } else {
+        Vec::new()
+    };
+    // The persona's tools get the same nudge as those the directory hints at.
+    if let Some(persona) = &persona {
+        hints.extend(persona.prefer.iter().cloned());
+    }
+    let notes = get_notes(history, None)?;
+    let facts = get_facts(history)?;
+    // A shared index from before docs could be added has no table for them.
+    let docs = load_doc_chunks(&conn).unwrap_or_default();
+    let records = get_tool_records(history)?;
+    let context = Context {
+        hints: &hints,
+        weights: &config.index.source_weights,
+        notes: &notes,
+        facts: &facts,
+        docs: &docs,
+        records: &records,
+    };
+
+    // Show the reply as it streams in, so slow models visibly make progress.
+    let live = unsafe { libc::isatty(libc::STDERR_FILENO) } == 1;
+    let mut streamed = String::new();
+    let mut on_chunk = |chunk: &str| {
+        streamed.push_str(chunk);
+        if live {
+            print_thinking(&streamed);
+        }
+    };
+
+    let found = if indexed {
+        retrieve_relevant_tools(embedder.as_ref(), &conn, query, &config.retrieval, &context)
+            .and_then(|found| {
+                if found.tools.is_empty() {
+                    return Err("No tools indexed. Run 'pls index' first.".into());
+                }
+                Ok(found)
+            })
+    } else {
+        Ok(keyword_search(
+            builtin_tools(),
+            query,
+            config.retrieval.top_k,
+            &context,
+        ))
+    };
+    // A plan for piped-in input is a plan for that input only.
+    let key = match &found {
+        Ok(_) if pipe::has_input() => String::new(),
+        Ok(found) => {
+            // Plans made with other context are other plans.
+            let setting = persona_block(persona.as_ref()) + &config.context.providers.join(",");
+            plancache::key(query, found, &llm, &setting)
+        }
+        Err(_) => String::new(),
+    };
+    if use_cache {
+        if let Some(plan) = plancache::get(history, &config.cache, &key) {
+            clear_thinking();
+            eprintln!("same plan as last time (--no-cache to ask the model again)");
+            return Ok((plan, conn));
+        }
+    }
+
+    let remote = (!network::is_local(client.endpoint())).then_some(&config.privacy);
+    let plan = found
+        .and_then(|found| {
+            let persona = persona.as_ref();
+            let context = &config.context;
+            let client = client.as_ref();
+            generate_plan_with_tools(
+                client,
+                query,
+                &found,
+                persona,
+                context,
+                remote,
+                &mut on_chunk,
+            )
+        })
+        .map(|plan| Plan {
+            cache_key: key,
+            ..plan
+        });
+    clear_thinking();
+    if let (Some(_), Ok(plan)) = (remote, &plan) {
+        eprintln!(
+            "sent {} chars of context to {}",
+            plan.sent_chars,
+            client.endpoint()
+        );
+    }
 
-    eprint!("\r           \r");
+    // A cancelled request has still used tokens.
+    quota::record(
+        history,
+        &config.quota,
+        client.model(),
+        client.endpoint(),
+        &client.take_usage(),
+    )?;
 
-    if plan.commands.is_empty() {
-        println!("could not generate a plan for this task.");
-        println!("  {}", plan.explanation);
+    Ok((plan?, conn))
+}
+
+// Missing tools the model named that are actually installed just haven't
+// been indexed yet; say so instead of sending the user to a package manager.
+fn not_possible_hints(answer: &NotPossible) -> NotPossible {
+    let mut answer = answer.clone();
+    let unindexed: Vec<&String> = answer
+        .missing
+        .iter()
+        .filter(|m| !m.contains(char::is_whitespace) && find_in_path(m).is_some())
+        .collect();
+    if !unindexed.is_empty() {
+        let names: Vec<&str> = unindexed.iter().map(|s| s.as_str()).collect();
+        answer.next_steps.insert(
+            0,
+            format!(
+                "{} is installed but not indexed: run 'pls index'",
+                names.join(", ")
+            ),
+        );
+    }
+    answer
+}
+
+pub fn cmd_verify(query: &str, config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let history = open_history(&config.paths)?;
+    let (plan, conn) = make_plan(config, &history, query, true)?;
+
+    if let Some(answer) = &plan.not_possible {
+        print_not_possible(&not_possible_hints(answer));
         return Ok(());
     }
 
+    let tools = load_all_tools(&conn)?;
+    let mut failed = 0;
+    let mut skipped = 0;
+    println!();
+    for cmd in &plan.commands {
+        println!("  {}", cmd);
+        for check in verify_command(cmd, &tools, &config.index) {
+            let marker = match check.status {
+                Status::Pass => "+",
+                Status::Fail => "x",
+                Status::Skip => "-",
+            };
+            failed += (check.status == Status::Fail) as usize;
+            skipped += (check.status == Status::Skip) as usize;
+            println!("    {} {}: {}", marker, check.label, check.detail);
+        }
+    }
+    print_warnings(&plan.warnings);
+    println!();
+
+    match (failed, skipped) {
+        (0, 0) => println!("all checks passed. nothing was run."),
+        (0, s) => println!("no problems found ({} not checked). nothing was run.", s),
+        (f, _) => println!("{} check(s) failed. nothing was run.", f),
+    }
+
+    Ok(())
+}
+
+// After a plan ran fine at a terminal, a few likely next queries to pick
+// from by number; the one picked, if any. A remote model sees the output
+// only if `privacy.remote_share` lists it.
+fn offer_follow_ups(
+    config: &Config,
+    history: &rusqlite::Connection,
+    query: &str,
+    commands: &[String],
+    result: &ExecutionResult,
+) -> Option<String> {
+    // Output headed down a pipe has someone else to read it.
+    let tty =
+        unsafe { libc::isatty(libc::STDIN_FILENO) == 1 && libc::isatty(libc::STDOUT_FILENO) == 1 };
+    let tty = tty && !pipe::forwarding();
+    if !config.behavior.follow_ups || !result.succeeded || !tty {
+        return None;
+    }
+    if matches!(
+        quota::check(history, &config.quota),
+        QuotaStatus::Exceeded(_)
+    ) {
+        return None;
+    }
+    let client = make_provider(&config.llm).ok()?;
+    let output = if network::is_local(client.endpoint()) {
+        Some(result.output.clone())
+    } else if privacy::shares(&config.privacy, "output") {
+        Some(privacy::redact(&result.output))
+    } else {
+        None
+    };
+
+    eprint!("thinking of follow-ups...");
+    std::io::stderr().flush().ok();
+    let suggestions = suggest_follow_ups(client.as_ref(), query, commands, output.as_deref());
+    clear_thinking();
+    let usage = client.take_usage();
+    quota::record(
+        history,
+        &config.quota,
+        client.model(),
+        client.endpoint(),
+        &usage,
+    )
+    .ok();
+    prompt_follow_up(&suggestions.ok().filter(|s| !s.is_empty())?)
+}
+
+// The model's reply as it came, when `behavior.archive_replies` is on, cut
+// to `archive_max_bytes`.
+fn archive_reply(config: &Config, plan: &Plan) -> Option<String> {
+    if !config.behavior.archive_replies {
+        return None;
+    }
+    let mut reply = plan.raw_reply.clone();
+    let max = config.behavior.archive_max_bytes;
+    if reply.len() > max {
+        let mut cut = max;
+        while !reply.is_char_boundary(cut) {
+            cut -= 1;
+        }
+        reply.truncate(cut);
+        reply.push_str("\n[... cut at behavior.archive_max_bytes]");
+    }
+    Some(reply)
+}
+
+// The action enter stands for at the confirmation prompt. Unknown names
+// explain rather than run, and stepping through a single command is running
+// it.
+fn enter_action(
+    config: &Config,
+    risk: RiskLevel,
+    steps: usize,
+    dry_run: bool,
+    sandbox: bool,
+) -> char {
+    let name = config.behavior.enter_action.get(risk.name());
+    match name.map(String::as_str).unwrap_or("run") {
+        "run" => 'r',
+        "step" if steps > 1 => 's',
+        "step" => 'r',
+        "dry-run" if dry_run => 'd',
+        "sandbox" if sandbox => 'p',
+        "edit" => 'e',
+        "quit" => 'q',
+        _ => '?',
+    }
+}
+
+// An edit that swaps one tool or path for another is probably how this
+// machine differs from what the model assumed; keep it for next time.
+fn remember_correction(
+    config: &Config,
+    history: &rusqlite::Connection,
+    planned: &[String],
+    edited: &str,
+) {
+    let Some(fact) = facts::infer(planned, edited) else {
+        return;
+    };
+    let Ok(embedder) = make_embedder(&config.llm) else {
+        return;
+    };
+    if let Ok(Some(id)) = facts::add(embedder.as_ref(), history, &fact, "inferred") {
+        println!("remembered: {} (pls forget {} to undo)", fact, id);
+    }
+}
+
+// How many tools the offline fallback names; the first gets its examples.
+const OFFLINE_MATCHES: usize = 4;
+
+// Without a model there is no plan, but keyword search over the index
+// still finds the tool for the job, and its examples are often enough.
+fn offline_matches(
+    config: &Config,
+    history: &rusqlite::Connection,
+    query: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let conn = open_index(&config.paths, false)?;
+    let mut tools = load_all_tools(&conn)?;
+    if tools.is_empty() {
+        tools = builtin_tools();
+    }
+    let hints = if config.behavior.cwd_hints {
+        cwd_hints(&cache_dir(&config.paths))
+    } else {
+        Vec::new()
+    };
+    let notes = get_notes(history, None)?;
+    let records = get_tool_records(history)?;
+    let context = Context {
+        hints: &hints,
+        weights: &config.index.source_weights,
+        notes: &notes,
+        facts: &[],
+        docs: &[],
+        records: &records,
+    };
+    let found = keyword_search(tools, query, OFFLINE_MATCHES, &context);
+    eprintln!("offline: no plan, only the indexed tools that match best");
+    print_offline(&found.tools);
+    Ok(())
+}
+
+pub fn cmd_query(
+    query: &str,
+    config: &Config,
+    yolo: bool,
+    explain_only: bool,
+    json: bool,
+    use_cache: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let history = open_history(&config.paths)?;
+
+    // Ctrl-C while thinking cancels the request; at a terminal, offer to try
+    // again instead of exiting.
+    let interactive = unsafe { libc::isatty(libc::STDIN_FILENO) } == 1;
+    let mut query = query.to_string();
+    // The directory as the planner saw it.
+    let mut planned_in = drift::take();
+    let (mut plan, conn) = loop {
+        match make_plan(config, &history, &query, use_cache) {
+            Err(e) if e.is::<Cancelled>() && interactive => match prompt_rephrase() {
+                Some(rephrased) => query = rephrased,
+                None => return Ok(()),
+            },
+            Err(e) if e.is::<Unreachable>() && !json => {
+                return offline_matches(config, &history, &query);
+            }
+            result => break result?,
+        }
+    };
+    let query = query.as_str();
+    // What goes into the plan cache if it runs fine, before the checks
+    // below add to it.
+    let planned = plan.clone();
+
     let risk = assess_risk(&plan.commands, &config.safety);
+    plan.warnings
+        .extend(mount_warnings(&plan.commands, &config.safety));
+
+    // Checks are meant to be read-only; drop any that look otherwise.
+    for check in plan.verify.iter_mut() {
+        let risky = check.as_ref().is_some_and(|c| {
+            let level = assess_risk(std::slice::from_ref(c), &config.safety);
+            matches!(level, RiskLevel::Dangerous | RiskLevel::Blocked)
+        });
+        if risky || !config.behavior.verify_steps {
+            *check = None;
+        }
+    }
+
+    // Machine-readable and never runs anything.
+    if json {
+        let out = serde_json::json!({ "query": query, "risk": risk, "plan": plan });
+        println!("{}", serde_json::to_string_pretty(&out)?);
+        return Ok(());
+    }
+
+    if let Some(answer) = &plan.not_possible {
+        print_not_possible(&not_possible_hints(answer));
+        return Ok(());
+    }
+
+    let mut used_tools: Vec<String> = Vec::new();
+    for name in plan.commands.iter().flat_map(|c| command_names(c)) {
+        if !used_tools.contains(&name) {
+            used_tools.push(name);
+        }
+    }
+    let caveats = get_tool_caveats(&conn, &used_tools)?;
+
+    // Only worth a keystroke when the plan changes something. Steps with
+    // no preview are left out of it rather than run for real.
+    let dry_run = if matches!(risk, RiskLevel::Review | RiskLevel::Dangerous) {
+        let mut flags = get_tool_dry_runs(&conn, &used_tools)?;
+        for name in &used_tools {
+            if !flags.contains_key(name) {
+                flags.insert(name.clone(), dryrun::known(name));
+            }
+        }
+        let variants: Vec<Option<String>> = plan
+            .commands
+            .iter()
+            .map(|c| dryrun::variant(c, &flags))
+            .collect();
+        let previews: Vec<String> = variants.into_iter().flatten().collect();
+        (!previews.is_empty()).then_some(previews)
+    } else {
+        None
+    };
 
     if risk == RiskLevel::Blocked {
         print_blocked(&plan);
@@ -263,32 +1957,136 @@ pub fn cmd_query(
     }
 
     if explain_only {
-        print_plan(&plan, risk);
+        print_plan(&plan, risk, &caveats, config.output.multiline_width);
+        if config.output.show_provenance {
+            print_provenance(&plan.provenance, &used_tools);
+        }
+        show_training(config, &history, &plan.commands);
         show_explanation(&plan);
         return Ok(());
     }
 
-    if yolo && risk == RiskLevel::Safe {
-        let (succeeded, output) = execute_commands(&plan.commands, config.safety.max_output_lines)?;
-        println!("{}", output);
-        save_history(&conn, query, &plan.commands, true, succeeded, &output)?;
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)?
+        .as_secs() as i64;
+    let window = config.behavior.duplicate_window_minutes as i64 * 60;
+    let previous_run = if window > 0 {
+        find_recent_run(&history, &plan.commands, now - window)?
+    } else {
+        None
+    };
+
+    // A repeat within the window always goes back to the prompt, even in
+    // yolo mode, to catch accidental double submissions.
+    let archived = archive_reply(config, &plan);
+    let archived = archived.as_deref();
+    let provenance = &plan.provenance;
+
+    if yolo && risk == RiskLevel::Safe && previous_run.is_none() {
+        let entry = begin_history(&history, query, &plan.commands, provenance, archived)?;
+        let verify = &plan.verify;
+        let result = run_plan(config, &history, entry, query, &plan.commands, verify, risk)?;
+        if result.succeeded {
+            plancache::put(&history, &config.cache, &planned)?;
+        }
+        if let Some(next) = offer_follow_ups(config, &history, query, &plan.commands, &result) {
+            return cmd_query(&next, config, yolo, false, false, use_cache);
+        }
         return Ok(());
     }
 
-    print_plan(&plan, risk);
+    print_plan(&plan, risk, &caveats, config.output.multiline_width);
+    print_edits(&edits::preview(&plan.commands), plan.commands.len());
+    if config.output.show_provenance {
+        print_provenance(&plan.provenance, &used_tools);
+    }
+    show_training(config, &history, &plan.commands);
+    if let Some(previous) = &previous_run {
+        print_duplicate_warning(previous, now);
+    }
 
+    // Rehearsing on a copy only makes sense for plans that change files.
+    let sandbox = matches!(risk, RiskLevel::Review | RiskLevel::Dangerous)
+        && sandbox::eligible(&plan.commands);
+    let steps = plan.commands.len();
+    let default = enter_action(config, risk, steps, dry_run.is_some(), sandbox);
+    let mut follow_up = None;
     loop {
-        match prompt_action() {
+        match prompt_action(steps, dry_run.is_some(), sandbox, default) {
             Some('r') => {
-                let (succeeded, output) =
-                    execute_commands(&plan.commands, config.safety.max_output_lines)?;
-                println!("{}", output);
-                save_history(&conn, query, &plan.commands, true, succeeded, &output)?;
+                match on_drift(config, planned_in.drift(&drift::take()), risk, true) {
+                    'r' => {}
+                    'p' => return cmd_query(query, config, yolo, false, false, false),
+                    _ => {
+                        save_history(&history, query, &plan.commands, provenance, archived, None)?;
+                        println!("cancelled.");
+                        break;
+                    }
+                }
+                if let Err(e) = cool_down(config, &history, risk) {
+                    save_history(&history, query, &plan.commands, provenance, archived, None)?;
+                    return Err(e);
+                }
+                let entry = begin_history(&history, query, &plan.commands, provenance, archived)?;
+                let verify = &plan.verify;
+                let commands = &plan.commands;
+                let result = run_plan(config, &history, entry, query, commands, verify, risk)?;
+                if result.succeeded {
+                    plancache::put(&history, &config.cache, &planned)?;
+                }
+                follow_up = offer_follow_ups(config, &history, query, &plan.commands, &result);
+                break;
+            }
+            Some('s') => {
+                let total = plan.commands.len();
+                let mut ran = false;
+                // The plan is approved once, at its first dangerous step.
+                let mut cooled = false;
+                for (i, cmd) in plan.commands.iter().enumerate() {
+                    let step = vec![cmd.clone()];
+                    let check = vec![plan.verify.get(i).cloned().flatten()];
+                    let step_risk = assess_risk(&step, &config.safety);
+                    match prompt_step(i + 1, total, cmd, step_risk) {
+                        Some('r') => {
+                            // Planning again is for a plan none of which ran.
+                            let drift = planned_in.drift(&drift::take());
+                            match on_drift(config, drift, step_risk, !ran) {
+                                'r' => {}
+                                'p' => return cmd_query(query, config, yolo, false, false, false),
+                                _ => {
+                                    println!("stopped.");
+                                    break;
+                                }
+                            }
+                            if !cooled {
+                                cool_down(config, &history, step_risk)?;
+                                cooled = step_risk == RiskLevel::Dangerous;
+                            }
+                            let label = format!("{} [step {}/{}]", query, i + 1, total);
+                            let entry =
+                                begin_history(&history, &label, &step, provenance, archived)?;
+                            let result =
+                                run_plan(config, &history, entry, query, &step, &check, step_risk)?;
+                            if !result.succeeded {
+                                println!("step {} failed; stopping.", i + 1);
+                                break;
+                            }
+                            // What the step changed is the plan's own doing.
+                            planned_in = drift::take();
+                            ran = true;
+                        }
+                        Some('s') => continue,
+                        _ => {
+                            println!("stopped.");
+                            break;
+                        }
+                    }
+                }
                 break;
             }
             Some('e') => {
                 let combined = plan.commands.join(" && ");
-                if let Some(edited) = edit_command(&combined) {
+                if let Some(edited) = edit_command(&combined, config.output.multiline_width) {
                     let edited = edited.trim();
                     if !edited.is_empty() {
                         let new_commands = vec![edited.to_string()];
@@ -300,17 +2098,51 @@ pub fn cmd_query(
                         }
 
                         println!("edited: {}", edited);
-                        let (succeeded, output) =
-                            execute_commands(&new_commands, config.safety.max_output_lines)?;
-                        println!("{}", output);
-                        save_history(&conn, query, &new_commands, true, succeeded, &output)?;
+                        remember_correction(config, &history, &plan.commands, edited);
+                        cool_down(config, &history, new_risk)?;
+                        let entry =
+                            begin_history(&history, query, &new_commands, provenance, archived)?;
+                        let result =
+                            run_plan(config, &history, entry, query, &new_commands, &[], new_risk)?;
+                        follow_up =
+                            offer_follow_ups(config, &history, query, &new_commands, &result);
                         break;
                     }
                 }
             }
+            Some('d') => {
+                let Some(preview) = &dry_run else { continue };
+                for cmd in preview {
+                    println!("dry run: {}", cmd);
+                }
+                let skipped = plan.commands.len() - preview.len();
+                if skipped > 0 {
+                    println!(
+                        "note: leaving out {} of {} steps, which have no dry run",
+                        skipped,
+                        plan.commands.len()
+                    );
+                }
+                let preview_risk = assess_risk(preview, &config.safety);
+                if preview_risk == RiskLevel::Blocked {
+                    println!("refused: command blocked for safety");
+                    continue;
+                }
+                if preview_risk == RiskLevel::Dangerous {
+                    println!("refused: the dry run itself is dangerous");
+                    continue;
+                }
+                let label = format!("{} [dry run]", query);
+                let entry = begin_history(&history, &label, preview, provenance, archived)?;
+                run_plan(config, &history, entry, query, preview, &[], preview_risk)?;
+            }
+            Some('p') => match sandbox::rehearse(&plan.commands, &config.safety) {
+                Ok(rehearsal) => print_rehearsal(&rehearsal),
+                Err(e) => println!("sandbox: {}", e),
+            },
             Some('?') => show_explanation(&plan),
             Some('q') | None => {
-                save_history(&conn, query, &plan.commands, false, false, "")?;
+                save_history(&history, query, &plan.commands, provenance, archived, None)?;
                 println!("cancelled.");
                 break;
             }
@@ -318,5 +2150,8 @@ pub fn cmd_query(
         }
     }
 
+    if let Some(next) = follow_up {
+        return cmd_query(&next, config, yolo, false, false, use_cache);
+    }
     Ok(())
 }
@@ -1,5 +1,9 @@
 use serde::{Deserialize, Serialize};
-use std::{fs, path::PathBuf};
+use std::{
+    collections::HashMap,
+    env, fs,
+    path::{Path, PathBuf},
+};
 
 const APP_NAME: &str = "pls";
 const DEFAULT_MODEL: &str = "llama3.1";
@@ -12,6 +16,118 @@ pub struct LlmConfig {
     pub model: String,
     pub embed_model: String,
     pub endpoint: String,
+    /// More servers running the same models. Each run starts at a
+    /// different one of these and `endpoint`, and moves on to the next
+    /// when the one it is using can't be reached.
+    #[serde(default)]
+    pub endpoints: Vec<String>,
+    /// The provider embeddings come from, or "local" for keyword-level
+    /// matching with no model at all.
+    #[serde(default = "default_embed_provider")]
+    pub embed_provider: String,
+    /// Task prefixes per embedding model, keyed by name without the tag.
+    #[serde(default = "default_embed_templates")]
+    pub embed_templates: HashMap<String, EmbedTemplate>,
+    /// Where the embed provider lives, when it isn't `endpoint`.
+    #[serde(default)]
+    pub embed_endpoint: Option<String>,
+    /// For hosted providers; each also reads its usual environment variable.
+    #[serde(default)]
+    pub api_key: Option<String>,
+    /// Azure OpenAI's api-version; unset uses a recent GA version.
+    #[serde(default)]
+    pub api_version: Option<String>,
+    /// Proxy for all provider requests; unset means HTTP(S)_PROXY apply.
+    #[serde(default)]
+    pub proxy: Option<String>,
+    #[serde(default)]
+    pub no_proxy: Vec<String>,
+    /// Sent as `Authorization: Bearer ...`, for endpoints behind an
+    /// authenticating reverse proxy. PLS_BEARER_TOKEN is read when unset.
+    #[serde(default)]
+    pub bearer_token: Option<String>,
+    /// Extra headers for every request to the endpoint.
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    /// Extra root certificates (PEM files, bundles allowed) to trust.
+    #[serde(default)]
+    pub ca_certs: Vec<String>,
+    /// Skips certificate checks. Also read as `danger_accept_invalid_certs`,
+    /// reqwest's name for it.
+    #[serde(default, alias = "danger_accept_invalid_certs")]
+    pub insecure_tls: bool,
+    #[serde(default = "default_connect_timeout_secs")]
+    pub connect_timeout_secs: u64,
+    /// Longest the server may go quiet mid-request. Not a cap on the whole
+    /// request, so long streamed replies still finish.
+    #[serde(default = "default_request_timeout_secs")]
+    pub request_timeout_secs: u64,
+    /// Extra attempts after a connection error, timeout or 5xx.
+    #[serde(default = "default_retries")]
+    pub retries: u32,
+    /// Sampling options passed through to the model as they are, e.g.
+    /// temperature, top_p or num_ctx for Ollama.
+    #[serde(default)]
+    pub options: HashMap<String, serde_json::Value>,
+    /// How long Ollama keeps models loaded after a request: "30m", "24h",
+    /// or -1 for until it exits. Unset leaves the server's default.
+    #[serde(default)]
+    pub keep_alive: Option<serde_json::Value>,
+    /// Start loading the model while a query's tools are being retrieved.
+    #[serde(default)]
+    pub warm_up: bool,
+}
+
+fn default_embed_provider() -> String {
+    "ollama".to_string()
+}
+
+/// How a model wants its inputs worded; `{text}` is replaced by the text.
+/// Empty means the text as it is.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EmbedTemplate {
+    #[serde(default)]
+    pub query: String,
+    #[serde(default)]
+    pub document: String,
+}
+
+// Models trained with instructions retrieve noticeably better when given
+// them.
+fn default_embed_templates() -> HashMap<String, EmbedTemplate> {
+    const RETRIEVAL: &str = "Represent this sentence for searching relevant passages: {text}";
+    [
+        (
+            "nomic-embed-text",
+            "search_query: {text}",
+            "search_document: {text}",
+        ),
+        ("mxbai-embed-large", RETRIEVAL, ""),
+        ("snowflake-arctic-embed", RETRIEVAL, ""),
+        ("snowflake-arctic-embed2", "query: {text}", ""),
+        ("bge-large", RETRIEVAL, ""),
+    ]
+    .into_iter()
+    .map(|(model, query, document)| {
+        let template = EmbedTemplate {
+            query: query.to_string(),
+            document: document.to_string(),
+        };
+        (model.to_string(), template)
+    })
+    .collect()
+}
+
+fn default_connect_timeout_secs() -> u64 {
+    5
+}
+
+fn default_request_timeout_secs() -> u64 {
+    120
+}
+
+fn default_retries() -> u32 {
+    2
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -21,6 +137,88 @@ pub struct IndexConfig {
     pub index_man_pages: bool,
     pub index_tldr: bool,
     pub index_help: bool,
+    /// Read flags from bash, zsh and fish completion definitions too.
+    #[serde(default = "default_index_completions")]
+    pub index_completions: bool,
+    #[serde(default = "default_source_weights")]
+    pub source_weights: HashMap<String, f32>,
+    /// Earlier full indexes kept for `pls index rollback`; 0 keeps none.
+    #[serde(default = "default_keep_generations")]
+    pub keep_generations: usize,
+    /// Tools whose docs are gathered at once; 0 is one per CPU.
+    #[serde(default)]
+    pub doc_workers: usize,
+    /// Embedding requests in flight at once while indexing.
+    #[serde(default = "default_embed_requests")]
+    pub embed_requests: usize,
+    /// Programs on PATH indexed at most; 0 indexes them all.
+    #[serde(default)]
+    pub max_tools: usize,
+    /// Programs, most common first, documented from --help, man and tldr.
+    /// The rest get their name and whatis line, for `--improve` to fill in.
+    #[serde(default = "default_full_docs")]
+    pub full_docs: usize,
+    /// Programs whose subcommands are indexed each on its own.
+    #[serde(default = "default_subcommand_tools")]
+    pub subcommand_tools: Vec<String>,
+    /// Seconds a program asked for its --help may take before it's killed.
+    #[serde(default = "default_probe_timeout_secs")]
+    pub probe_timeout_secs: u64,
+    /// Programs never run to ask for their help, by name or file name;
+    /// they're indexed from man and tldr pages alone.
+    #[serde(default = "default_never_probe")]
+    pub never_probe: Vec<String>,
+}
+
+fn default_subcommand_tools() -> Vec<String> {
+    ["git", "docker", "kubectl", "cargo"]
+        .map(String::from)
+        .to_vec()
+}
+
+fn default_probe_timeout_secs() -> u64 {
+    3
+}
+
+// Ones that some builds act on without reading their arguments.
+fn default_never_probe() -> Vec<String> {
+    ["reboot", "shutdown", "halt", "poweroff", "telinit", "kexec"]
+        .map(String::from)
+        .to_vec()
+}
+
+fn default_index_completions() -> bool {
+    true
+}
+
+fn default_full_docs() -> usize {
+    200
+}
+
+fn default_keep_generations() -> usize {
+    3
+}
+
+fn default_embed_requests() -> usize {
+    2
+}
+
+// Retrieval scores are scaled by where a tool's docs came from: tldr
+// examples help the planner most, docs guessed from the name least. Sources
+// not listed here (aliases, builtins) count as 1.0.
+fn default_source_weights() -> HashMap<String, f32> {
+    let weights = [
+        ("tldr", 1.0),
+        ("man", 0.95),
+        ("help", 0.9),
+        ("summary", 0.85),
+        ("brief", 0.85),
+        ("inferred", 0.8),
+    ];
+    weights
+        .into_iter()
+        .map(|(source, weight)| (source.to_string(), weight))
+        .collect()
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -28,6 +226,68 @@ pub struct BehaviorConfig {
     pub confirm_by_default: bool,
     pub learn_from_history: bool,
     pub history_window: usize,
+    #[serde(default = "default_duplicate_window_minutes")]
+    pub duplicate_window_minutes: u64,
+    #[serde(default = "default_cwd_hints")]
+    pub cwd_hints: bool,
+    #[serde(default = "default_training_runs")]
+    pub training_runs: u32,
+    #[serde(default = "default_verify_steps")]
+    pub verify_steps: bool,
+    /// What enter does at the confirmation prompt, by risk level: "run",
+    /// "step", "edit", "explain", "quit", "dry-run" or "sandbox".
+    #[serde(default = "default_enter_action")]
+    pub enter_action: HashMap<String, String>,
+    /// Keep the model's reply as it came, for `pls history show ID --raw`.
+    #[serde(default)]
+    pub archive_replies: bool,
+    #[serde(default = "default_archive_max_bytes")]
+    pub archive_max_bytes: usize,
+    /// After a plan runs fine at a terminal, offer a few likely next
+    /// queries to pick by number.
+    #[serde(default = "default_follow_ups")]
+    pub follow_ups: bool,
+    /// The planner persona used unless `--persona` picks another.
+    #[serde(default)]
+    pub persona: Option<String>,
+    /// Run commands with `set -o pipefail`, so a pipeline whose first stage
+    /// fails isn't taken for a success. Off by default: `grep pat f | wc -l`
+    /// finding nothing would fail the plan.
+    #[serde(default)]
+    pub pipefail: bool,
+}
+
+fn default_archive_max_bytes() -> usize {
+    64 * 1024
+}
+
+fn default_duplicate_window_minutes() -> u64 {
+    5
+}
+
+fn default_cwd_hints() -> bool {
+    true
+}
+
+fn default_training_runs() -> u32 {
+    20
+}
+
+fn default_verify_steps() -> bool {
+    true
+}
+
+fn default_follow_ups() -> bool {
+    true
+}
+
+// Enter runs what's safe or merely worth a look; a dangerous plan has to be
+// run with an explicit `r`.
+fn default_enter_action() -> HashMap<String, String> {
+    [("safe", "run"), ("review", "run"), ("dangerous", "explain")]
+        .into_iter()
+        .map(|(risk, action)| (risk.to_string(), action.to_string()))
+        .collect()
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -35,11 +295,241 @@ pub struct SafetyConfig {
     pub safe_commands: Vec<String>,
     pub dangerous_patterns: Vec<String>,
     pub max_output_lines: usize,
+    #[serde(default)]
+    pub snapshot_changes: bool,
+    #[serde(default = "default_snapshot_max_files")]
+    pub snapshot_max_files: usize,
+    /// Files up to this size are copied whole into the sandbox a plan can
+    /// be rehearsed in; bigger ones stand in by size. 0 copies names only.
+    #[serde(default = "default_sandbox_copy_bytes")]
+    pub sandbox_copy_bytes: u64,
+    #[serde(default = "default_mount_checks")]
+    pub mount_checks: bool,
+    /// After a dangerous plan runs, how long before the next may; 0 is off.
+    #[serde(default)]
+    pub dangerous_cooldown_minutes: u64,
+    /// Whether re-entering the sudo password cuts the cooldown short.
+    #[serde(default = "default_cooldown_reauth")]
+    pub cooldown_reauth: bool,
+    /// The most dangerous plans run in any hour; 0 is no cap.
+    #[serde(default)]
+    pub dangerous_per_hour: u32,
+    /// When the directory changed between planning and running a plan that
+    /// changes things: "warn" asks first, "replan" plans again, "off".
+    #[serde(default = "default_on_drift")]
+    pub on_drift: String,
+}
+
+fn default_on_drift() -> String {
+    "warn".to_string()
+}
+
+fn default_cooldown_reauth() -> bool {
+    true
+}
+
+fn default_mount_checks() -> bool {
+    true
+}
+
+fn default_snapshot_max_files() -> usize {
+    20_000
+}
+
+fn default_sandbox_copy_bytes() -> u64 {
+    64 * 1024
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OutputConfig {
     pub style: String,
+    // A "based on:" line under each plan naming the docs behind it.
+    #[serde(default)]
+    pub show_provenance: bool,
+    // Plans longer than this are shown, and edited, one pipeline stage per
+    // line; 0 keeps them on one.
+    #[serde(default = "default_multiline_width")]
+    pub multiline_width: usize,
+    // Sizes in output shown in this unit ("GiB", "MB", "bytes", ...); empty
+    // leaves them as printed. A query can ask for one itself.
+    #[serde(default)]
+    pub size_unit: String,
+    // Dates in output written as 2024-05-20.
+    #[serde(default)]
+    pub iso_dates: bool,
+}
+
+fn default_multiline_width() -> usize {
+    80
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PathsConfig {
+    pub data_dir: Option<PathBuf>,
+    pub state_dir: Option<PathBuf>,
+    pub cache_dir: Option<PathBuf>,
+    // A tools index maintained centrally, e.g. by an admin on a shared host.
+    pub shared_index: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelPrice {
+    pub input_per_mtok: f64,
+    pub output_per_mtok: f64,
+}
+
+/// Spending limits in the currency the pricing table uses. A budget of 0
+/// means unlimited; models without a pricing entry cost nothing.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QuotaConfig {
+    pub daily_budget: f64,
+    pub monthly_budget: f64,
+    pub warn_at: f64,
+    pub fallback_model: Option<String>,
+    /// The provider `fallback_model` is run with.
+    pub fallback_provider: String,
+    pub fallback_endpoint: String,
+    pub pricing: HashMap<String, ModelPrice>,
+}
+
+impl Default for QuotaConfig {
+    fn default() -> Self {
+        Self {
+            daily_budget: 0.0,
+            monthly_budget: 0.0,
+            warn_at: 0.8,
+            fallback_model: None,
+            fallback_provider: "ollama".to_string(),
+            fallback_endpoint: DEFAULT_OLLAMA_URL.to_string(),
+            pricing: HashMap::new(),
+        }
+    }
+}
+
+/// Limits on the context sent to a model that isn't on this machine.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PrivacyConfig {
+    /// Context besides tool docs a remote model may see; see
+    /// privacy::SHAREABLE. The user writes notes and facts for the
+    /// planner, so they go by default.
+    pub remote_share: Vec<String>,
+    /// Upper bound on the per-query prompt; the least relevant tools go
+    /// first.
+    pub remote_max_context_chars: usize,
+}
+
+impl Default for PrivacyConfig {
+    fn default() -> Self {
+        Self {
+            remote_share: vec!["notes".to_string(), "facts".to_string()],
+            remote_max_context_chars: 8000,
+        }
+    }
+}
+
+/// How long `pls gc` keeps things, in days; 0 keeps them forever.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GcConfig {
+    /// Queries, plans and per-tool outcomes.
+    pub history_days: u32,
+    /// The raw output of runs, kept for `pls output`, and archived model
+    /// replies.
+    pub output_days: u32,
+    /// Token usage; longer than a month so monthly budgets stay right.
+    pub usage_days: u32,
+    /// Run gc in the background every this many days; 0 only by hand.
+    pub auto_days: u32,
+}
+
+impl Default for GcConfig {
+    fn default() -> Self {
+        Self {
+            history_days: 365,
+            output_days: 30,
+            usage_days: 400,
+            auto_days: 0,
+        }
+    }
+}
+
+/// Plans that ran fine, reused when the same query comes up again.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CacheConfig {
+    pub enabled: bool,
+    /// How long a plan is reused after it last ran fine.
+    pub ttl_minutes: u32,
+    pub max_entries: usize,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            ttl_minutes: 24 * 60,
+            max_entries: 500,
+        }
+    }
+}
+
+/// How many tools a query pulls into the prompt; see retrieval.rs.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetrievalConfig {
+    /// Tools documented in full; with `adaptive` off, all that go in.
+    pub top_k: usize,
+    /// Fewer tools for specific queries and more for broad ones, between
+    /// `min_top_k` and `max_top_k`. Past `top_k` they get a short doc.
+    pub adaptive: bool,
+    pub min_top_k: usize,
+    pub max_top_k: usize,
+}
+
+impl Default for RetrievalConfig {
+    fn default() -> Self {
+        Self {
+            top_k: 8,
+            adaptive: true,
+            min_top_k: 4,
+            max_top_k: 12,
+        }
+    }
+}
+
+/// What the planner is told about the machine besides tool docs; see
+/// context.rs for the providers.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ContextConfig {
+    /// Providers to run, in the order their blocks go into the prompt.
+    pub providers: Vec<String>,
+    /// The most characters a provider's block takes up.
+    pub max_chars: usize,
+    /// Caps for single providers, by name, over `max_chars`.
+    pub limits: HashMap<String, usize>,
+}
+
+impl Default for ContextConfig {
+    fn default() -> Self {
+        Self {
+            providers: vec!["cwd".to_string(), "time".to_string()],
+            max_chars: 1500,
+            limits: HashMap::new(),
+        }
+    }
+}
+
+/// A house style for plans: rules the planner follows on top of its own,
+/// and tools to reach for or leave alone.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Persona {
+    pub rules: Vec<String>,
+    pub prefer: Vec<String>,
+    pub avoid: Vec<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -49,6 +539,23 @@ pub struct Config {
     pub behavior: BehaviorConfig,
     pub safety: SafetyConfig,
     pub output: OutputConfig,
+    #[serde(default)]
+    pub paths: PathsConfig,
+    #[serde(default)]
+    pub quota: QuotaConfig,
+    #[serde(default)]
+    pub privacy: PrivacyConfig,
+    #[serde(default)]
+    pub gc: GcConfig,
+    #[serde(default)]
+    pub cache: CacheConfig,
+    #[serde(default)]
+    pub context: ContextConfig,
+    #[serde(default)]
+    pub retrieval: RetrievalConfig,
+    /// Personas besides the built-in ones, or replacing them, by name.
+    #[serde(default)]
+    pub personas: HashMap<String, Persona>,
 }
 
 impl Default for Config {
@@ -59,6 +566,24 @@ impl Default for Config {
                 model: DEFAULT_MODEL.to_string(),
                 embed_model: DEFAULT_EMBED_MODEL.to_string(),
                 endpoint: DEFAULT_OLLAMA_URL.to_string(),
+                endpoints: Vec::new(),
+                embed_provider: default_embed_provider(),
+                embed_templates: default_embed_templates(),
+                embed_endpoint: None,
+                api_key: None,
+                api_version: None,
+                proxy: None,
+                no_proxy: Vec::new(),
+                bearer_token: None,
+                headers: HashMap::new(),
+                ca_certs: Vec::new(),
+                insecure_tls: false,
+                connect_timeout_secs: default_connect_timeout_secs(),
+                request_timeout_secs: default_request_timeout_secs(),
+                retries: default_retries(),
+                options: HashMap::new(),
+                keep_alive: None,
+                warm_up: false,
             },
             index: IndexConfig {
                 auto_reindex: true,
@@ -66,11 +591,31 @@ impl Default for Config {
                 index_man_pages: true,
                 index_tldr: true,
                 index_help: true,
+                index_completions: default_index_completions(),
+                source_weights: default_source_weights(),
+                keep_generations: default_keep_generations(),
+                doc_workers: 0,
+                embed_requests: default_embed_requests(),
+                max_tools: 0,
+                full_docs: default_full_docs(),
+                subcommand_tools: default_subcommand_tools(),
+                probe_timeout_secs: default_probe_timeout_secs(),
+                never_probe: default_never_probe(),
             },
             behavior: BehaviorConfig {
                 confirm_by_default: true,
                 learn_from_history: true,
                 history_window: 10,
+                duplicate_window_minutes: default_duplicate_window_minutes(),
+                cwd_hints: default_cwd_hints(),
+                training_runs: default_training_runs(),
+                verify_steps: default_verify_steps(),
+                archive_replies: false,
+                archive_max_bytes: default_archive_max_bytes(),
+                enter_action: default_enter_action(),
+                follow_ups: default_follow_ups(),
+                persona: None,
+                pipefail: false,
             },
             safety: SafetyConfig {
                 safe_commands: vec![
@@ -96,31 +641,117 @@ impl Default for Config {
                 .map(String::from)
                 .collect(),
                 max_output_lines: 100,
+                snapshot_changes: false,
+                snapshot_max_files: default_snapshot_max_files(),
+                sandbox_copy_bytes: default_sandbox_copy_bytes(),
+                mount_checks: default_mount_checks(),
+                dangerous_cooldown_minutes: 0,
+                cooldown_reauth: default_cooldown_reauth(),
+                dangerous_per_hour: 0,
+                on_drift: default_on_drift(),
             },
             output: OutputConfig {
                 style: "minimal".to_string(),
+                show_provenance: false,
+                multiline_width: default_multiline_width(),
+                size_unit: String::new(),
+                iso_dates: false,
             },
+            paths: PathsConfig::default(),
+            quota: QuotaConfig::default(),
+            privacy: PrivacyConfig::default(),
+            gc: GcConfig::default(),
+            cache: CacheConfig::default(),
+            context: ContextConfig::default(),
+            retrieval: RetrievalConfig::default(),
+            personas: HashMap::new(),
         }
     }
 }
 
 pub fn get_config_path() -> PathBuf {
+    if let Some(path) = env::var_os("PLS_CONFIG") {
+        return PathBuf::from(path);
+    }
     dirs::config_dir()
         .unwrap_or_else(|| PathBuf::from("."))
         .join(APP_NAME)
         .join("config.toml")
 }
 
+pub fn read_config(path: &Path) -> Result<Config, Box<dyn std::error::Error>> {
+    Ok(toml::from_str(&fs::read_to_string(path)?)?)
+}
+
+// A config that doesn't parse still leaves pls usable, but never silently.
 pub fn load_config() -> Config {
     let path = get_config_path();
-    if path.exists() {
-        if let Ok(content) = fs::read_to_string(&path) {
-            if let Ok(config) = toml::from_str(&content) {
-                return config;
+    if !path.exists() {
+        return Config::default();
+    }
+    match read_config(&path) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("warning: ignoring {}: {}", path.display(), e);
+            eprintln!("  using defaults until it is fixed ('pls config')");
+            Config::default()
+        }
+    }
+}
+
+/// Sets `key` under `[section]` in the config file and leaves the rest of
+/// it, comments included, as it was. Refuses an edit that wouldn't parse.
+pub fn set_config_value(
+    section: &str,
+    key: &str,
+    value: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let path = get_config_path();
+    if !path.exists() {
+        save_config(&Config::default())?;
+    }
+    let mut lines: Vec<String> = fs::read_to_string(&path)?
+        .lines()
+        .map(String::from)
+        .collect();
+    let uncommented = |line: &str| {
+        line.split('#')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_string()
+    };
+    let setting = format!("{} = {}", key, toml::Value::String(value.to_string()));
+    let header = format!("[{}]", section);
+    match lines.iter().position(|l| uncommented(l) == header) {
+        Some(start) => {
+            let end = (start + 1..lines.len())
+                .find(|&i| uncommented(&lines[i]).starts_with('['))
+                .unwrap_or(lines.len());
+            let existing = (start + 1..end)
+                .find(|&i| uncommented(&lines[i]).split('=').next().map(str::trim) == Some(key));
+            match existing {
+                Some(i) => {
+                    // Keep a comment after the old value.
+                    let old = &lines[i];
+                    let comment = old
+                        .rfind('#')
+                        .filter(|&at| toml::from_str::<toml::Table>(&old[..at]).is_ok())
+                        .map(|at| old[at..].to_string());
+                    lines[i] = match comment {
+                        Some(comment) => format!("{}  {}", setting, comment),
+                        None => setting,
+                    };
+                }
+                None => lines.insert(start + 1, setting),
             }
         }
+        None => lines.extend([String::new(), header, setting]),
     }
-    Config::default()
+    let content = lines.join("\n") + "\n";
+    toml::from_str::<Config>(&content)?;
+    fs::write(path, content)?;
+    Ok(())
 }
 
 pub fn save_config(config: &Config) -> Result<(), Box<dyn std::error::Error>> {
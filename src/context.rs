@@ -0,0 +1,276 @@
+// What the planner is told about the machine besides the tools: the current
+// directory, the time, and, when `[context]` turns them on, the git state,
+// the directory's files, the system, the project's tasks and the clipboard.
+// Each provider is a function in PROVIDERS; `context.providers` picks which
+// run and in what order, and each block is cut to its size cap.
+
+use crate::config::{ContextConfig, PrivacyConfig};
+use crate::index::find_in_path;
+use crate::privacy;
+use crate::temporal::context_block;
+use std::{
+    env, fs,
+    path::Path,
+    process::{Command, Stdio},
+};
+
+struct Provider {
+    name: &'static str,
+    gather: fn(&Path) -> Option<String>,
+}
+
+const PROVIDERS: &[Provider] = &[
+    Provider {
+        name: "cwd",
+        gather: cwd,
+    },
+    Provider {
+        name: "time",
+        gather: time,
+    },
+    Provider {
+        name: "git",
+        gather: git,
+    },
+    Provider {
+        name: "dir",
+        gather: dir,
+    },
+    Provider {
+        name: "system",
+        gather: system,
+    },
+    Provider {
+        name: "tasks",
+        gather: tasks,
+    },
+    Provider {
+        name: "clipboard",
+        gather: clipboard,
+    },
+];
+
+// The clock says nothing about the machine; the rest go to a remote model
+// only if privacy.remote_share lists them.
+const ALWAYS_SHARED: &[&str] = &["time"];
+const DIR_ENTRIES: usize = 200;
+const PASTE: &[&[&str]] = &[
+    &["wl-paste", "--no-newline"],
+    &["xclip", "-selection", "clipboard", "-o"],
+    &["xsel", "--clipboard", "--output"],
+    &["pbpaste"],
+];
+
+fn names() -> Vec<&'static str> {
+    PROVIDERS.iter().map(|p| p.name).collect()
+}
+
+/// `list`, comma-separated, if every name in it is a provider.
+pub fn parse_list(list: &str) -> Result<Vec<String>, String> {
+    let names: Vec<String> = list
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(String::from)
+        .collect();
+    match names.iter().find(|n| !self::names().contains(&n.as_str())) {
+        Some(unknown) => Err(format!(
+            "no context provider {:?}; there are {}",
+            unknown,
+            self::names().join(", ")
+        )),
+        None => Ok(names),
+    }
+}
+
+fn output(program: &str, args: &[&str], cwd: &Path) -> Option<String> {
+    let output = Command::new(program)
+        .args(args)
+        .current_dir(cwd)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    let text = String::from_utf8_lossy(&output.stdout)
+        .trim_end()
+        .to_string();
+    (output.status.success() && !text.is_empty()).then_some(text)
+}
+
+fn cwd(cwd: &Path) -> Option<String> {
+    Some(format!("Current directory: {}", cwd.display()))
+}
+
+fn time(_: &Path) -> Option<String> {
+    Some(context_block())
+}
+
+fn git(cwd: &Path) -> Option<String> {
+    let status = output("git", &["status", "--porcelain=v1", "--branch"], cwd)?;
+    let mut lines = status.lines();
+    let branch = lines.next()?.trim_start_matches("## ");
+    let changed: Vec<&str> = lines.collect();
+    let mut block = format!("Git: on {}, {} changed files", branch, changed.len());
+    if let Some(last) = output("git", &["log", "-1", "--format=%h %s (%cr)"], cwd) {
+        block.push_str(&format!("; last commit {}", last));
+    }
+    for line in changed {
+        block.push_str(&format!("\n  {}", line));
+    }
+    Some(block)
+}
+
+fn dir(cwd: &Path) -> Option<String> {
+    let mut names: Vec<String> = fs::read_dir(cwd)
+        .ok()?
+        .filter_map(|e| e.ok())
+        .take(DIR_ENTRIES)
+        .map(|e| {
+            let name = e.file_name().to_string_lossy().to_string();
+            if e.file_type().is_ok_and(|t| t.is_dir()) {
+                name + "/"
+            } else {
+                name
+            }
+        })
+        .collect();
+    if names.is_empty() {
+        return Some("Files here: none".to_string());
+    }
+    names.sort();
+    Some(format!("Files here: {}", names.join(", ")))
+}
+
+fn system(cwd: &Path) -> Option<String> {
+    let mut facts = Vec::new();
+    if let Some(uname) = output("uname", &["-srm"], cwd) {
+        facts.push(uname);
+    }
+    let release = fs::read_to_string("/etc/os-release").unwrap_or_default();
+    let pretty = release.lines().find_map(|l| l.strip_prefix("PRETTY_NAME="));
+    if let Some(name) = pretty {
+        facts.push(name.trim_matches('"').to_string());
+    }
+    if let Ok(cpus) = std::thread::available_parallelism() {
+        facts.push(format!("{} CPUs", cpus));
+    }
+    let meminfo = fs::read_to_string("/proc/meminfo").unwrap_or_default();
+    let kib = |key: &str| {
+        meminfo
+            .lines()
+            .find_map(|l| l.strip_prefix(key))
+            .and_then(|v| v.split_whitespace().next()?.parse::<u64>().ok())
+    };
+    if let (Some(total), Some(free)) = (kib("MemTotal:"), kib("MemAvailable:")) {
+        facts.push(format!(
+            "{} of {} memory free",
+            gib(free * 1024),
+            gib(total * 1024)
+        ));
+    }
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    let path = std::ffi::CString::new(cwd.to_string_lossy().as_bytes()).ok()?;
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } == 0 {
+        let free = stat.f_bavail as u64 * stat.f_frsize as u64;
+        facts.push(format!("{} free on this filesystem", gib(free)));
+    }
+    if let Ok(shell) = env::var("SHELL") {
+        facts.push(format!("shell {}", shell));
+    }
+    Some(format!("System: {}", facts.join(", ")))
+}
+
+fn gib(bytes: u64) -> String {
+    format!("{:.1} GiB", bytes as f64 / (1u64 << 30) as f64)
+}
+
+// Rule names of a Makefile or justfile: unindented `name:` lines, not
+// variables or special targets.
+fn rules(text: &str) -> Vec<String> {
+    text.lines()
+        .filter(|l| !l.starts_with(char::is_whitespace) && !l.starts_with(['.', '#', '@']))
+        .filter_map(|l| {
+            let (head, rest) = l.split_once(':')?;
+            let name = head.split_whitespace().next()?;
+            let plain = name
+                .chars()
+                .all(|c| c.is_alphanumeric() || "-_/".contains(c));
+            (plain && !rest.starts_with('=') && !head.contains('=')).then(|| name.to_string())
+        })
+        .collect()
+}
+
+fn tasks(cwd: &Path) -> Option<String> {
+    let mut found = Vec::new();
+    for file in ["Makefile", "makefile", "GNUmakefile"] {
+        if let Ok(text) = fs::read_to_string(cwd.join(file)) {
+            found.push(format!("make: {}", rules(&text).join(" ")));
+            break;
+        }
+    }
+    for file in ["justfile", "Justfile", ".justfile"] {
+        if let Ok(text) = fs::read_to_string(cwd.join(file)) {
+            found.push(format!("just: {}", rules(&text).join(" ")));
+            break;
+        }
+    }
+    let package = fs::read_to_string(cwd.join("package.json")).ok();
+    let package = package.and_then(|p| serde_json::from_str::<serde_json::Value>(&p).ok());
+    if let Some(scripts) = package.as_ref().and_then(|p| p["scripts"].as_object()) {
+        let names: Vec<&str> = scripts.keys().map(String::as_str).collect();
+        found.push(format!("npm run: {}", names.join(" ")));
+    }
+    (!found.is_empty()).then(|| format!("Project tasks:\n  {}", found.join("\n  ")))
+}
+
+fn clipboard(cwd: &Path) -> Option<String> {
+    let text = PASTE
+        .iter()
+        .filter(|argv| find_in_path(argv[0]).is_some())
+        .find_map(|argv| output(argv[0], &argv[1..], cwd))?;
+    Some(format!("Clipboard:\n{}", text))
+}
+
+// `text` cut to `max` characters, on a line break when there's one near.
+fn cap(text: String, max: usize) -> String {
+    if text.chars().count() <= max {
+        return text;
+    }
+    let mut cut: String = text.chars().take(max).collect();
+    if let Some(at) = cut.rfind('\n').filter(|&at| at > cut.len() / 2) {
+        cut.truncate(at);
+    }
+    cut + "\n  (cut)"
+}
+
+/// The prompt's context, provider by provider in `config.providers`'
+/// order. `remote` holds back what a remote model may not see.
+pub fn gather(config: &ContextConfig, remote: Option<&PrivacyConfig>) -> String {
+    let cwd = env::current_dir().unwrap_or_else(|_| ".".into());
+    let mut blocks = Vec::new();
+    for name in &config.providers {
+        let Some(provider) = PROVIDERS.iter().find(|p| p.name == name) else {
+            continue;
+        };
+        let withheld = remote.is_some_and(|policy| {
+            !ALWAYS_SHARED.contains(&provider.name) && !privacy::shares(policy, provider.name)
+        });
+        if withheld {
+            if provider.name == "cwd" {
+                blocks.push("Current directory: (not shared)".to_string());
+            }
+            continue;
+        }
+        let Some(block) = (provider.gather)(&cwd) else {
+            continue;
+        };
+        let max = config.limits.get(name).copied().unwrap_or(config.max_chars);
+        let block = cap(block, max);
+        blocks.push(if remote.is_some() {
+            privacy::redact(&block)
+        } else {
+            block
+        });
+    }
+    blocks.join("\n")
+}
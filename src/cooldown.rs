@@ -0,0 +1,87 @@
+// A circuit breaker for dangerous plans. With
+// `safety.dangerous_cooldown_minutes` set, each one that runs starts a
+// cooldown the next has to wait out, or cut short by re-entering the sudo
+// password; with `safety.dangerous_per_hour`, no more than that many run in
+// any hour, whatever the user says. Meant for the string of destructive
+// mistakes a fast, yolo-heavy session can make.
+
+use crate::clock::now;
+use crate::config::SafetyConfig;
+use crate::db::{get_dangerous_runs_since, save_dangerous_run};
+use crate::index::find_in_path;
+use rusqlite::Connection;
+use std::{io::Write, process::Command, thread, time::Duration};
+
+const HOUR: i64 = 3600;
+
+pub enum Gate {
+    Open,
+    /// Seconds left of the cooldown.
+    Cooling(u64),
+    /// The hourly cap is reached; why, and when the next may run.
+    Capped(String),
+}
+
+pub fn format_wait(secs: u64) -> String {
+    if secs < 60 {
+        format!("{}s", secs)
+    } else {
+        format!("{}m{:02}s", secs / 60, secs % 60)
+    }
+}
+
+/// Whether a dangerous plan may run now.
+pub fn check(conn: &Connection, config: &SafetyConfig) -> Gate {
+    let cooldown = config.dangerous_cooldown_minutes as i64 * 60;
+    let cap = config.dangerous_per_hour as usize;
+    if cooldown == 0 && cap == 0 {
+        return Gate::Open;
+    }
+    let now = now();
+    let runs = get_dangerous_runs_since(conn, now - HOUR.max(cooldown)).unwrap_or_default();
+
+    let hour: Vec<i64> = runs.iter().copied().filter(|&t| t > now - HOUR).collect();
+    if cap > 0 && hour.len() >= cap {
+        // The run that frees a slot by leaving the hour.
+        let freed = hour[cap - 1] + HOUR - now;
+        return Gate::Capped(format!(
+            "the cap of {} dangerous plans an hour (safety.dangerous_per_hour) is reached; \
+             the next can run in {}",
+            cap,
+            format_wait(freed.max(1) as u64)
+        ));
+    }
+    match runs.first() {
+        Some(&last) if cooldown > 0 && last + cooldown > now => {
+            Gate::Cooling((last + cooldown - now) as u64)
+        }
+        _ => Gate::Open,
+    }
+}
+
+/// Counts a dangerous plan as run now.
+pub fn record(conn: &Connection) -> Result<(), Box<dyn std::error::Error>> {
+    save_dangerous_run(conn, now())
+}
+
+pub fn can_reauthenticate() -> bool {
+    find_in_path("sudo").is_some()
+}
+
+/// Asks for the sudo password, however recently it was given.
+pub fn reauthenticate() -> bool {
+    Command::new("sudo")
+        .args(["-k", "-v", "-p", "password to end the cooldown: "])
+        .status()
+        .is_ok_and(|s| s.success())
+}
+
+/// Sits out `secs`, counting down.
+pub fn wait(secs: u64) {
+    for left in (1..=secs).rev() {
+        eprint!("\r  cooling down: {}   ", format_wait(left));
+        std::io::stderr().flush().ok();
+        thread::sleep(Duration::from_secs(1));
+    }
+    eprint!("\r                              \r");
+}
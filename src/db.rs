@@ -1,17 +1,80 @@
-use crate::types::{HistoryEntry, Tool};
-use rusqlite::{params, Connection};
-use std::path::PathBuf;
+use crate::config::PathsConfig;
+use crate::paths::{data_dir, state_dir};
+use crate::spill;
+use crate::types::{
+    DocChunk, ExecutionResult, Fact, HistoryDetail, HistoryEntry, IndexGeneration, Job, ModelUsage,
+    Note, PendingRun, Tool, ToolRecord, Usage,
+};
+use rusqlite::{params, Connection, OpenFlags};
+use std::{
+    collections::HashMap,
+    env,
+    ffi::CString,
+    fs,
+    path::{Path, PathBuf},
+};
 
-const APP_NAME: &str = "pls";
+/// The tools index: the shared one if configured, else this user's own.
+pub fn get_db_path(paths: &PathsConfig) -> PathBuf {
+    paths
+        .shared_index
+        .clone()
+        .unwrap_or_else(|| local_db_path(paths))
+}
+
+fn local_db_path(paths: &PathsConfig) -> PathBuf {
+    data_dir(paths).join("index").join("tools.db")
+}
 
-fn get_data_dir() -> PathBuf {
-    dirs::data_local_dir()
-        .unwrap_or_else(|| PathBuf::from("."))
-        .join(APP_NAME)
+fn writable(path: &Path) -> bool {
+    let Ok(c_path) = CString::new(path.as_os_str().as_encoded_bytes()) else {
+        return false;
+    };
+    unsafe { libc::access(c_path.as_ptr(), libc::W_OK) == 0 }
 }
 
-pub fn get_db_path() -> PathBuf {
-    get_data_dir().join("index").join("tools.db")
+/// Opens the tools index. A shared index is opened read-only unless `write`
+/// is set, and writing to one needs permission on the file; a private one
+/// is created on first use.
+pub fn open_index(
+    paths: &PathsConfig,
+    write: bool,
+) -> Result<Connection, Box<dyn std::error::Error>> {
+    let path = get_db_path(paths);
+    if paths.shared_index.is_some() {
+        if !write {
+            if !path.exists() {
+                return Err(format!("shared index {} does not exist yet", path.display()).into());
+            }
+            return Ok(Connection::open_with_flags(
+                &path,
+                OpenFlags::SQLITE_OPEN_READ_ONLY,
+            )?);
+        }
+        let target = if path.exists() {
+            path.as_path()
+        } else {
+            path.parent().unwrap_or(&path)
+        };
+        if !writable(target) {
+            return Err(format!(
+                "shared index {} is read-only for you; it is maintained by whoever owns it",
+                path.display()
+            )
+            .into());
+        }
+    }
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let conn = Connection::open(&path)?;
+    init_db(&conn)?;
+    Ok(conn)
+}
+
+pub fn get_history_path(paths: &PathsConfig) -> PathBuf {
+    state_dir(paths).join("history.db")
 }
 
 pub fn init_db(conn: &Connection) -> Result<(), Box<dyn std::error::Error>> {
@@ -29,7 +92,68 @@ pub fn init_db(conn: &Connection) -> Result<(), Box<dyn std::error::Error>> {
         )",
         [],
     )?;
+    add_column_if_missing(conn, "tools", "caveats", "TEXT")?;
+    add_column_if_missing(conn, "tools", "dry_run", "TEXT")?;
+    add_column_if_missing(conn, "tools", "generation", "INTEGER")?;
+    add_column_if_missing(conn, "tools", "target", "TEXT")?;
+
+    // Earlier generations of the index, whole, as `tools` was before each
+    // full reindex.
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS old_tools (
+            generation INTEGER,
+            name TEXT,
+            path TEXT,
+            description TEXT,
+            synopsis TEXT,
+            examples TEXT,
+            flags TEXT,
+            embedding BLOB,
+            source TEXT,
+            updated_at INTEGER,
+            caveats TEXT,
+            dry_run TEXT,
+            target TEXT,
+            PRIMARY KEY (generation, name)
+        )",
+        [],
+    )?;
+    add_column_if_missing(conn, "old_tools", "target", "TEXT")?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS generations (
+            id INTEGER PRIMARY KEY,
+            built INTEGER,
+            embedder TEXT,
+            tools INTEGER
+        )",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS meta (
+            key TEXT PRIMARY KEY,
+            value TEXT
+        )",
+        [],
+    )?;
 
+    // The user's own docs, from `pls index --docs`; see docs.rs. Apart from
+    // `tools`, so a full reindex or a rollback leaves them be.
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS doc_chunks (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            file TEXT,
+            heading TEXT,
+            text TEXT,
+            embedding BLOB
+        )",
+        [],
+    )?;
+
+    Ok(())
+}
+
+pub fn init_history_db(conn: &Connection) -> Result<(), Box<dyn std::error::Error>> {
     conn.execute(
         "CREATE TABLE IF NOT EXISTS history (
             id INTEGER PRIMARY KEY AUTOINCREMENT,
@@ -42,55 +166,282 @@ pub fn init_db(conn: &Connection) -> Result<(), Box<dyn std::error::Error>> {
         )",
         [],
     )?;
+    add_column_if_missing(conn, "history", "cwd", "TEXT")?;
+    add_column_if_missing(conn, "history", "duration_ms", "INTEGER")?;
+    add_column_if_missing(conn, "history", "changes", "TEXT")?;
+    add_column_if_missing(conn, "history", "output_raw", "BLOB")?;
+    add_column_if_missing(conn, "history", "verified", "INTEGER")?;
+    add_column_if_missing(conn, "history", "provenance", "TEXT")?;
+    add_column_if_missing(conn, "history", "raw_reply", "TEXT")?;
+    // The pid of the pls running the entry's commands, until it files the
+    // outcome; see spill.rs.
+    add_column_if_missing(conn, "history", "pending", "INTEGER")?;
+    add_column_if_missing(conn, "history", "interrupted", "INTEGER")?;
+    add_column_if_missing(conn, "history", "failed_stage", "TEXT")?;
+    // The directory as the entry's commands left it; see drift.rs.
+    add_column_if_missing(conn, "history", "fingerprint", "TEXT")?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS history_pending ON history(pending)",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS usage (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            timestamp INTEGER,
+            model TEXT,
+            endpoint TEXT,
+            prompt_tokens INTEGER,
+            completion_tokens INTEGER,
+            cost REAL
+        )",
+        [],
+    )?;
+    add_column_if_missing(conn, "usage", "latency_ms", "INTEGER")?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS jobs (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            pgid INTEGER,
+            command TEXT,
+            started INTEGER
+        )",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS notes (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            tool TEXT,
+            note TEXT,
+            embedding BLOB,
+            created INTEGER
+        )",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS facts (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            fact TEXT,
+            source TEXT,
+            embedding BLOB,
+            created INTEGER
+        )",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS tool_runs (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            tool TEXT,
+            succeeded INTEGER,
+            timestamp INTEGER
+        )",
+        [],
+    )?;
+
+    // When dangerous plans ran, for the cooldown; see cooldown.rs.
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS dangerous_runs (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            timestamp INTEGER
+        )",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS plan_cache (
+            key TEXT PRIMARY KEY,
+            plan TEXT,
+            created INTEGER,
+            used INTEGER
+        )",
+        [],
+    )?;
 
     Ok(())
 }
 
+fn add_column_if_missing(
+    conn: &Connection,
+    table: &str,
+    column: &str,
+    decl: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+    let exists = stmt
+        .query_map([], |row| row.get::<_, String>(1))?
+        .filter_map(|r| r.ok())
+        .any(|name| name == column);
+    if !exists {
+        conn.execute(
+            &format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, decl),
+            [],
+        )?;
+    }
+    Ok(())
+}
+
+pub fn open_history(paths: &PathsConfig) -> Result<Connection, Box<dyn std::error::Error>> {
+    let path = get_history_path(paths);
+    let fresh = !path.exists();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let conn = Connection::open(&path)?;
+    let had_notes = table_exists(&conn, "notes");
+    init_history_db(&conn)?;
+    if fresh {
+        migrate_legacy_history(&conn, &local_db_path(paths));
+    }
+    if !had_notes {
+        migrate_legacy_notes(&conn, &local_db_path(paths));
+    }
+    spill::recover(&conn, paths)?;
+    Ok(conn)
+}
+
+// Older versions kept history inside tools.db. Copy it over once so
+// upgrading doesn't lose it; the old table is left untouched.
+fn migrate_legacy_history(conn: &Connection, legacy: &Path) {
+    if !legacy.exists() {
+        return;
+    }
+    let legacy = legacy.to_string_lossy();
+    if conn
+        .execute("ATTACH DATABASE ?1 AS legacy", params![legacy])
+        .is_err()
+    {
+        return;
+    }
+    conn.execute(
+        "INSERT INTO history (query, plan, executed, succeeded, output_sample, timestamp)
+         SELECT query, plan, executed, succeeded, output_sample, timestamp FROM legacy.history",
+        [],
+    )
+    .ok();
+    conn.execute("DETACH DATABASE legacy", []).ok();
+}
+
+fn table_exists(conn: &Connection, table: &str) -> bool {
+    conn.query_row(
+        "SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1",
+        params![table],
+        |_| Ok(()),
+    )
+    .is_ok()
+}
+
+// Notes used to live in tools.db, which may now be a shared, read-only
+// index. They belong to the user, so they moved next to the history.
+fn migrate_legacy_notes(conn: &Connection, legacy: &Path) {
+    if !legacy.exists() {
+        return;
+    }
+    let legacy = legacy.to_string_lossy();
+    if conn
+        .execute("ATTACH DATABASE ?1 AS legacy", params![legacy])
+        .is_err()
+    {
+        return;
+    }
+    conn.execute(
+        "INSERT INTO notes (tool, note, embedding, created)
+         SELECT tool, note, embedding, created FROM legacy.notes",
+        [],
+    )
+    .ok();
+    conn.execute("DETACH DATABASE legacy", []).ok();
+}
+
+fn embedding_to_bytes(embedding: &[f32]) -> Vec<u8> {
+    embedding.iter().flat_map(|f| f.to_le_bytes()).collect()
+}
+
+fn bytes_to_embedding(bytes: &[u8]) -> Vec<f32> {
+    bytes
+        .chunks(4)
+        .map(|chunk| {
+            let arr: [u8; 4] = chunk.try_into().unwrap_or([0; 4]);
+            f32::from_le_bytes(arr)
+        })
+        .collect()
+}
+
 pub fn save_tool(conn: &Connection, tool: &Tool) -> Result<(), Box<dyn std::error::Error>> {
-    let embedding_bytes: Vec<u8> = tool
-        .embedding
-        .iter()
-        .flat_map(|f| f.to_le_bytes())
-        .collect();
+    let embedding_bytes = embedding_to_bytes(&tool.embedding);
     let now = std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)?
         .as_secs() as i64;
 
     conn.execute(
-        "INSERT OR REPLACE INTO tools (name, path, description, synopsis, examples, flags, embedding, source, updated_at)
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        "INSERT OR REPLACE INTO tools (name, path, description, synopsis, examples, flags, embedding, source, updated_at, caveats, dry_run, target, generation)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12,
+                 (SELECT CAST(value AS INTEGER) FROM meta WHERE key = 'generation'))",
         params![
             tool.name, tool.path, tool.description, tool.synopsis,
-            tool.examples, tool.flags, embedding_bytes, tool.source, now
+            tool.examples, tool.flags, embedding_bytes, tool.source, now, tool.caveats,
+            tool.dry_run, tool.target
         ],
     )?;
     Ok(())
 }
 
+/// Drops `name` from the index, with its subcommand entries ("git branch"
+/// for git). Returns how many entries went.
+pub fn delete_tool(conn: &Connection, name: &str) -> Result<usize, Box<dyn std::error::Error>> {
+    let deleted = conn.execute(
+        "DELETE FROM tools WHERE name = ?1 OR substr(name, 1, length(?1) + 1) = ?1 || ' '",
+        params![name],
+    )?;
+    Ok(deleted)
+}
+
+/// When each indexed tool was last written, keyed by name, with its path.
+pub fn get_tool_stamps(
+    conn: &Connection,
+) -> Result<HashMap<String, (String, i64)>, Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare("SELECT name, path, updated_at FROM tools")?;
+    let stamps = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                (
+                    row.get::<_, String>(1)?,
+                    row.get::<_, Option<i64>>(2)?.unwrap_or(0),
+                ),
+            ))
+        })?
+        .filter_map(|r| r.ok())
+        .collect();
+    Ok(stamps)
+}
+
 pub fn load_all_tools(conn: &Connection) -> Result<Vec<Tool>, Box<dyn std::error::Error>> {
     let mut stmt = conn.prepare(
-        "SELECT name, path, description, synopsis, examples, flags, embedding, source FROM tools",
+        "SELECT name, path, description, synopsis, examples, flags, embedding, source, caveats, dry_run, target FROM tools",
     )?;
 
     let tools = stmt
         .query_map([], |row| {
             let embedding_bytes: Vec<u8> = row.get(6)?;
-            let embedding: Vec<f32> = embedding_bytes
-                .chunks(4)
-                .map(|chunk| {
-                    let arr: [u8; 4] = chunk.try_into().unwrap_or([0; 4]);
-                    f32::from_le_bytes(arr)
-                })
-                .collect();
+            let embedding = bytes_to_embedding(&embedding_bytes);
 
             Ok(Tool {
                 name: row.get(0)?,
                 path: row.get(1)?,
+                target: row.get::<_, Option<String>>(10)?.unwrap_or_default(),
                 description: row.get(2)?,
                 synopsis: row.get(3)?,
                 examples: row.get(4)?,
                 flags: row.get(5)?,
                 source: row.get(7)?,
+                caveats: row.get::<_, Option<String>>(8)?.unwrap_or_default(),
+                dry_run: row.get::<_, Option<String>>(9)?.unwrap_or_default(),
+                notes: Vec::new(),
+                record: ToolRecord::default(),
                 embedding,
             })
         })?
@@ -100,59 +451,542 @@ pub fn load_all_tools(conn: &Connection) -> Result<Vec<Tool>, Box<dyn std::error
     Ok(tools)
 }
 
-pub fn save_history(
+pub fn tool_exists(conn: &Connection, name: &str) -> bool {
+    conn.query_row("SELECT 1 FROM tools WHERE name = ?1", params![name], |_| {
+        Ok(())
+    })
+    .is_ok()
+}
+
+pub fn save_note(
+    conn: &Connection,
+    tool: &str,
+    text: &str,
+    embedding: &[f32],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)?
+        .as_secs() as i64;
+    conn.execute(
+        "INSERT INTO notes (tool, note, embedding, created) VALUES (?1, ?2, ?3, ?4)",
+        params![tool, text, embedding_to_bytes(embedding), now],
+    )?;
+    Ok(())
+}
+
+pub fn set_note_embedding(
+    conn: &Connection,
+    id: i64,
+    embedding: &[f32],
+) -> Result<(), Box<dyn std::error::Error>> {
+    conn.execute(
+        "UPDATE notes SET embedding = ?1 WHERE id = ?2",
+        params![embedding_to_bytes(embedding), id],
+    )?;
+    Ok(())
+}
+
+/// Notes for one tool, or for every tool, oldest first.
+pub fn get_notes(
+    conn: &Connection,
+    tool: Option<&str>,
+) -> Result<Vec<Note>, Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, tool, note, embedding FROM notes
+         WHERE ?1 IS NULL OR tool = ?1 ORDER BY tool, id",
+    )?;
+    let notes = stmt
+        .query_map(params![tool], |row| {
+            Ok(Note {
+                id: row.get(0)?,
+                tool: row.get(1)?,
+                text: row.get(2)?,
+                embedding: bytes_to_embedding(&row.get::<_, Vec<u8>>(3)?),
+            })
+        })?
+        .filter_map(|r| r.ok())
+        .collect();
+    Ok(notes)
+}
+
+pub fn delete_note(conn: &Connection, id: i64) -> Result<bool, Box<dyn std::error::Error>> {
+    Ok(conn.execute("DELETE FROM notes WHERE id = ?1", params![id])? > 0)
+}
+
+/// Returns the new fact's id.
+pub fn save_fact(
+    conn: &Connection,
+    text: &str,
+    source: &str,
+    embedding: &[f32],
+) -> Result<i64, Box<dyn std::error::Error>> {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)?
+        .as_secs() as i64;
+    conn.execute(
+        "INSERT INTO facts (fact, source, embedding, created) VALUES (?1, ?2, ?3, ?4)",
+        params![text, source, embedding_to_bytes(embedding), now],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+pub fn set_fact_embedding(
+    conn: &Connection,
+    id: i64,
+    embedding: &[f32],
+) -> Result<(), Box<dyn std::error::Error>> {
+    conn.execute(
+        "UPDATE facts SET embedding = ?1 WHERE id = ?2",
+        params![embedding_to_bytes(embedding), id],
+    )?;
+    Ok(())
+}
+
+/// Every fact, oldest first.
+pub fn get_facts(conn: &Connection) -> Result<Vec<Fact>, Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare("SELECT id, fact, source, embedding FROM facts ORDER BY id")?;
+    let facts = stmt
+        .query_map([], |row| {
+            Ok(Fact {
+                id: row.get(0)?,
+                text: row.get(1)?,
+                source: row.get(2)?,
+                embedding: bytes_to_embedding(&row.get::<_, Vec<u8>>(3)?),
+            })
+        })?
+        .filter_map(|r| r.ok())
+        .collect();
+    Ok(facts)
+}
+
+pub fn delete_fact(conn: &Connection, id: i64) -> Result<bool, Box<dyn std::error::Error>> {
+    Ok(conn.execute("DELETE FROM facts WHERE id = ?1", params![id])? > 0)
+}
+
+/// Puts `chunks` in place of whatever `file` had before.
+pub fn replace_doc_chunks(
+    conn: &Connection,
+    file: &str,
+    chunks: &[DocChunk],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let tx = conn.unchecked_transaction()?;
+    tx.execute("DELETE FROM doc_chunks WHERE file = ?1", params![file])?;
+    for chunk in chunks {
+        tx.execute(
+            "INSERT INTO doc_chunks (file, heading, text, embedding) VALUES (?1, ?2, ?3, ?4)",
+            params![
+                file,
+                chunk.heading,
+                chunk.text,
+                embedding_to_bytes(&chunk.embedding)
+            ],
+        )?;
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+pub fn set_doc_chunk_embedding(
+    conn: &Connection,
+    id: i64,
+    embedding: &[f32],
+) -> Result<(), Box<dyn std::error::Error>> {
+    conn.execute(
+        "UPDATE doc_chunks SET embedding = ?1 WHERE id = ?2",
+        params![embedding_to_bytes(embedding), id],
+    )?;
+    Ok(())
+}
+
+/// Every chunk of every ingested file, file by file in order.
+pub fn load_doc_chunks(conn: &Connection) -> Result<Vec<DocChunk>, Box<dyn std::error::Error>> {
+    let mut stmt = conn
+        .prepare("SELECT id, file, heading, text, embedding FROM doc_chunks ORDER BY file, id")?;
+    let chunks = stmt
+        .query_map([], |row| {
+            Ok(DocChunk {
+                id: row.get(0)?,
+                file: row.get(1)?,
+                heading: row.get(2)?,
+                text: row.get(3)?,
+                embedding: bytes_to_embedding(&row.get::<_, Vec<u8>>(4)?),
+            })
+        })?
+        .filter_map(|r| r.ok())
+        .collect();
+    Ok(chunks)
+}
+
+/// The ingested files under `dir`.
+pub fn get_doc_files(
+    conn: &Connection,
+    dir: &str,
+) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare("SELECT DISTINCT file FROM doc_chunks")?;
+    let prefix = format!("{}/", dir.trim_end_matches('/'));
+    let files = stmt
+        .query_map([], |row| row.get::<_, String>(0))?
+        .filter_map(|r| r.ok())
+        .filter(|f| f.starts_with(&prefix))
+        .collect();
+    Ok(files)
+}
+
+pub fn get_doc_chunk_count(conn: &Connection) -> u32 {
+    conn.query_row("SELECT COUNT(*) FROM doc_chunks", [], |row| row.get(0))
+        .unwrap_or(0)
+}
+
+pub fn get_tool_caveats(
+    conn: &Connection,
+    names: &[String],
+) -> Result<Vec<(String, String)>, Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare("SELECT caveats FROM tools WHERE name = ?1")?;
+    let mut caveats = Vec::new();
+
+    for name in names {
+        let text: Option<String> = stmt
+            .query_row(params![name], |row| row.get(0))
+            .unwrap_or(None);
+        for line in text.unwrap_or_default().lines() {
+            caveats.push((name.clone(), line.to_string()));
+        }
+    }
+
+    Ok(caveats)
+}
+
+/// The dry-run flag of each of `names` that has one.
+pub fn get_tool_dry_runs(
+    conn: &Connection,
+    names: &[String],
+) -> Result<HashMap<String, String>, Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare("SELECT dry_run FROM tools WHERE name = ?1")?;
+    let mut flags = HashMap::new();
+
+    for name in names {
+        let flag: Option<String> = stmt
+            .query_row(params![name], |row| row.get(0))
+            .unwrap_or(None);
+        if let Some(flag) = flag.filter(|f| !f.is_empty()) {
+            flags.insert(name.clone(), flag);
+        }
+    }
+
+    Ok(flags)
+}
+
+// Raw output kept per history entry; enough for file lists, not for dumps.
+const MAX_RAW_OUTPUT: usize = 1 << 20;
+
+fn insert_history(
     conn: &Connection,
     query: &str,
     commands: &[String],
-    executed: bool,
-    succeeded: bool,
-    output_sample: &str,
-) -> Result<(), Box<dyn std::error::Error>> {
+    provenance: &[String],
+    raw_reply: Option<&str>,
+    pending: Option<u32>,
+) -> Result<i64, Box<dyn std::error::Error>> {
     let now = std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)?
         .as_secs() as i64;
     let plan_json = serde_json::to_string(commands)?;
+    let provenance_json = serde_json::to_string(provenance)?;
+    let cwd = env::current_dir()
+        .map(|p| p.to_string_lossy().to_string())
+        .unwrap_or_default();
 
     conn.execute(
-        "INSERT INTO history (query, plan, executed, succeeded, output_sample, timestamp)
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        "INSERT INTO history (query, plan, executed, succeeded, output_sample, timestamp, cwd, duration_ms, provenance, raw_reply, pending)
+         VALUES (?1, ?2, 0, 0, '', ?3, ?4, 0, ?5, ?6, ?7)",
+        params![query, plan_json, now, cwd, provenance_json, raw_reply, pending],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+pub fn save_history(
+    conn: &Connection,
+    query: &str,
+    commands: &[String],
+    provenance: &[String],
+    raw_reply: Option<&str>,
+    result: Option<&ExecutionResult>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let id = insert_history(conn, query, commands, provenance, raw_reply, None)?;
+    if let Some(result) = result {
+        finish_history(conn, id, result)?;
+    }
+    Ok(())
+}
+
+/// Files `commands` as running before they start, so that if pls doesn't
+/// live to see them finish the next start can still tell what ran. Returns
+/// the entry's id for `finish_history`.
+pub fn begin_history(
+    conn: &Connection,
+    query: &str,
+    commands: &[String],
+    provenance: &[String],
+    raw_reply: Option<&str>,
+) -> Result<i64, Box<dyn std::error::Error>> {
+    let pid = std::process::id();
+    insert_history(conn, query, commands, provenance, raw_reply, Some(pid))
+}
+
+/// Records how the run filed as `id` went.
+pub fn finish_history(
+    conn: &Connection,
+    id: i64,
+    result: &ExecutionResult,
+) -> Result<(), Box<dyn std::error::Error>> {
+    conn.execute(
+        "UPDATE history SET executed = 1, succeeded = ?1, output_sample = ?2, duration_ms = ?3,
+         changes = ?4, output_raw = ?5, verified = ?6, failed_stage = ?7, pending = NULL
+         WHERE id = ?8",
+        params![
+            result.succeeded as i32,
+            result.output,
+            result.duration_ms as i64,
+            result.changes,
+            &result.raw_output[..result.raw_output.len().min(MAX_RAW_OUTPUT)],
+            result.verified,
+            result.failed_stage,
+            id
+        ],
+    )?;
+    Ok(())
+}
+
+pub fn set_history_fingerprint(
+    conn: &Connection,
+    id: i64,
+    fingerprint: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    conn.execute(
+        "UPDATE history SET fingerprint = ?1 WHERE id = ?2",
+        params![fingerprint, id],
+    )?;
+    Ok(())
+}
+
+pub fn get_pending_history(
+    conn: &Connection,
+) -> Result<Vec<PendingRun>, Box<dyn std::error::Error>> {
+    let mut stmt =
+        conn.prepare("SELECT id, pending, timestamp FROM history WHERE pending IS NOT NULL")?;
+    let pending = stmt
+        .query_map([], |row| {
+            Ok(PendingRun {
+                id: row.get(0)?,
+                pid: row.get(1)?,
+                started: row.get(2)?,
+            })
+        })?
+        .filter_map(|r| r.ok())
+        .collect();
+    Ok(pending)
+}
+
+/// Files the entry `id` as a run that never finished, with the output it
+/// got as far as.
+pub fn interrupt_history(
+    conn: &Connection,
+    id: i64,
+    output: &str,
+    raw_output: &[u8],
+    duration_ms: u64,
+) -> Result<(), Box<dyn std::error::Error>> {
+    conn.execute(
+        "UPDATE history SET executed = 1, succeeded = 0, interrupted = 1, output_sample = ?1,
+         output_raw = ?2, duration_ms = ?3, pending = NULL WHERE id = ?4",
         params![
-            query,
-            plan_json,
-            executed as i32,
-            succeeded as i32,
-            output_sample,
-            now
+            output,
+            &raw_output[..raw_output.len().min(MAX_RAW_OUTPUT)],
+            duration_ms as i64,
+            id
         ],
     )?;
     Ok(())
 }
 
+/// Adds a query brought over from another tool as an entry that never ran,
+/// unless the same query and commands were imported before. Returns
+/// whether it was added.
+pub fn import_history(
+    conn: &Connection,
+    query: &str,
+    commands: &[String],
+    provenance: &[String],
+    timestamp: i64,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let plan_json = serde_json::to_string(commands)?;
+    let provenance_json = serde_json::to_string(provenance)?;
+    let added = conn.execute(
+        "INSERT INTO history (query, plan, executed, succeeded, output_sample, timestamp, provenance)
+         SELECT ?1, ?2, 0, 0, '', ?3, ?4
+         WHERE NOT EXISTS (SELECT 1 FROM history WHERE query = ?1 AND plan = ?2 AND provenance = ?4)",
+        params![query, plan_json, timestamp, provenance_json],
+    )?;
+    Ok(added > 0)
+}
+
+/// Exact stdout bytes of the most recent executed entry.
+pub fn get_last_output(conn: &Connection) -> Result<Option<Vec<u8>>, Box<dyn std::error::Error>> {
+    let result = conn.query_row(
+        "SELECT output_raw FROM history WHERE executed = 1 ORDER BY timestamp DESC, id DESC LIMIT 1",
+        [],
+        |row| row.get::<_, Option<Vec<u8>>>(0),
+    );
+    match result {
+        Ok(raw) => Ok(raw),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+const HISTORY_COLUMNS: &str =
+    "query, plan, executed, succeeded, cwd, duration_ms, timestamp, changes, verified, id, \
+     interrupted, failed_stage";
+
+fn history_from_row(row: &rusqlite::Row) -> rusqlite::Result<HistoryEntry> {
+    let plan_json: String = row.get(1)?;
+    let commands: Vec<String> = serde_json::from_str(&plan_json).unwrap_or_default();
+    Ok(HistoryEntry {
+        id: row.get(9)?,
+        query: row.get(0)?,
+        commands,
+        executed: row.get::<_, i32>(2)? != 0,
+        succeeded: row.get::<_, i32>(3)? != 0,
+        cwd: row.get::<_, Option<String>>(4)?.unwrap_or_default(),
+        duration_ms: row.get::<_, Option<i64>>(5)?.unwrap_or(0) as u64,
+        timestamp: row.get(6)?,
+        changes: row.get(7)?,
+        verified: row.get(8)?,
+        interrupted: row.get::<_, Option<i32>>(10)?.unwrap_or(0) != 0,
+        failed_stage: row.get(11)?,
+    })
+}
+
+/// One history entry with what the planner was shown and, if it was
+/// archived, the model's raw reply.
+pub fn get_history_entry(
+    conn: &Connection,
+    id: i64,
+) -> Result<Option<HistoryDetail>, Box<dyn std::error::Error>> {
+    let result = conn.query_row(
+        &format!(
+            "SELECT {}, provenance, raw_reply FROM history WHERE id = ?1",
+            HISTORY_COLUMNS
+        ),
+        params![id],
+        |row| {
+            let provenance: Option<String> = row.get(11)?;
+            let provenance = provenance
+                .and_then(|p| serde_json::from_str(&p).ok())
+                .unwrap_or_default();
+            Ok(HistoryDetail {
+                entry: history_from_row(row)?,
+                provenance,
+                raw_reply: row.get(12)?,
+            })
+        },
+    );
+    match result {
+        Ok(found) => Ok(Some(found)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
 pub fn get_recent_history(
     conn: &Connection,
     limit: usize,
 ) -> Result<Vec<HistoryEntry>, Box<dyn std::error::Error>> {
-    let mut stmt = conn.prepare(
-        "SELECT query, plan, executed, succeeded FROM history ORDER BY timestamp DESC LIMIT ?1",
-    )?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM history ORDER BY timestamp DESC LIMIT ?1",
+        HISTORY_COLUMNS
+    ))?;
 
     let entries = stmt
-        .query_map(params![limit as i64], |row| {
-            let plan_json: String = row.get(1)?;
-            let commands: Vec<String> = serde_json::from_str(&plan_json).unwrap_or_default();
-            Ok(HistoryEntry {
-                query: row.get(0)?,
-                commands,
-                executed: row.get::<_, i32>(2)? != 0,
-                succeeded: row.get::<_, i32>(3)? != 0,
-            })
-        })?
+        .query_map(params![limit as i64], history_from_row)?
+        .filter_map(|r| r.ok())
+        .collect();
+
+    Ok(entries)
+}
+
+pub fn get_history_since(
+    conn: &Connection,
+    since: i64,
+) -> Result<Vec<HistoryEntry>, Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM history WHERE timestamp >= ?1 ORDER BY timestamp ASC",
+        HISTORY_COLUMNS
+    ))?;
+
+    let entries = stmt
+        .query_map(params![since], history_from_row)?
         .filter_map(|r| r.ok())
         .collect();
 
     Ok(entries)
 }
 
+fn normalize_command(cmd: &str) -> String {
+    cmd.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// The most recent executed run of the same commands (ignoring whitespace
+/// differences) at or after `since`.
+pub fn find_recent_run(
+    conn: &Connection,
+    commands: &[String],
+    since: i64,
+) -> Result<Option<HistoryEntry>, Box<dyn std::error::Error>> {
+    let wanted: Vec<String> = commands.iter().map(|c| normalize_command(c)).collect();
+    let recent = get_history_since(conn, since)?;
+
+    Ok(recent.into_iter().rev().find(|entry| {
+        entry.executed
+            && entry
+                .commands
+                .iter()
+                .map(|c| normalize_command(c))
+                .eq(wanted.iter().cloned())
+    }))
+}
+
+/// The `nth` most recent entry that was run, counting from 0.
+pub fn get_executed(
+    conn: &Connection,
+    nth: usize,
+) -> Result<Option<HistoryEntry>, Box<dyn std::error::Error>> {
+    let result = conn.query_row(
+        &format!(
+            "SELECT {} FROM history WHERE executed = 1
+             ORDER BY timestamp DESC, id DESC LIMIT 1 OFFSET ?1",
+            HISTORY_COLUMNS
+        ),
+        params![nth as i64],
+        history_from_row,
+    );
+    match result {
+        Ok(entry) => Ok(Some(entry)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// How many plans have actually been run.
+pub fn get_run_count(conn: &Connection) -> u32 {
+    conn.query_row(
+        "SELECT COUNT(*) FROM history WHERE executed = 1",
+        [],
+        |row| row.get(0),
+    )
+    .unwrap_or(0)
+}
+
 pub fn get_last_command(conn: &Connection) -> Result<Option<String>, Box<dyn std::error::Error>> {
     let result: Result<String, _> = conn.query_row(
         "SELECT plan FROM history WHERE executed = 1 ORDER BY timestamp DESC LIMIT 1",
@@ -169,7 +1003,351 @@ pub fn get_last_command(conn: &Connection) -> Result<Option<String>, Box<dyn std
     }
 }
 
+/// Where the last command ran and the fingerprint it left there, either
+/// empty if not recorded.
+pub fn get_last_run_place(
+    conn: &Connection,
+) -> Result<Option<(String, String)>, Box<dyn std::error::Error>> {
+    let result = conn.query_row(
+        "SELECT cwd, fingerprint FROM history WHERE executed = 1 ORDER BY timestamp DESC LIMIT 1",
+        [],
+        |row| {
+            Ok((
+                row.get::<_, Option<String>>(0)?.unwrap_or_default(),
+                row.get::<_, Option<String>>(1)?.unwrap_or_default(),
+            ))
+        },
+    );
+    match result {
+        Ok(place) => Ok(Some(place)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub fn save_usage(
+    conn: &Connection,
+    model: &str,
+    endpoint: &str,
+    usage: &Usage,
+    cost: f64,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)?
+        .as_secs() as i64;
+    conn.execute(
+        "INSERT INTO usage
+             (timestamp, model, endpoint, prompt_tokens, completion_tokens, cost, latency_ms)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        params![
+            now,
+            model,
+            endpoint,
+            usage.prompt_tokens as i64,
+            usage.completion_tokens as i64,
+            cost,
+            usage.latency_ms as i64
+        ],
+    )?;
+    Ok(())
+}
+
+/// Total (prompt tokens, completion tokens, cost) recorded since `since`.
+pub fn get_usage_since(conn: &Connection, since: i64) -> (u64, u64, f64) {
+    conn.query_row(
+        "SELECT COALESCE(SUM(prompt_tokens), 0), COALESCE(SUM(completion_tokens), 0),
+                COALESCE(SUM(cost), 0.0)
+         FROM usage WHERE timestamp >= ?1",
+        params![since],
+        |row| {
+            Ok((
+                row.get::<_, i64>(0)? as u64,
+                row.get::<_, i64>(1)? as u64,
+                row.get::<_, f64>(2)?,
+            ))
+        },
+    )
+    .unwrap_or((0, 0, 0.0))
+}
+
+/// Usage since `since` per model, busiest first.
+pub fn get_usage_by_model(
+    conn: &Connection,
+    since: i64,
+) -> Result<Vec<ModelUsage>, Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare(
+        "SELECT model, COUNT(*), SUM(prompt_tokens), SUM(completion_tokens),
+                COUNT(latency_ms),
+                COALESCE(SUM(CASE WHEN latency_ms IS NOT NULL THEN completion_tokens END), 0),
+                COALESCE(SUM(latency_ms), 0)
+         FROM usage WHERE timestamp >= ?1
+         GROUP BY model ORDER BY COUNT(*) DESC",
+    )?;
+    let rows = stmt
+        .query_map(params![since], |row| {
+            Ok(ModelUsage {
+                model: row.get(0)?,
+                requests: row.get::<_, i64>(1)? as u64,
+                prompt_tokens: row.get::<_, i64>(2)? as u64,
+                completion_tokens: row.get::<_, i64>(3)? as u64,
+                timed_requests: row.get::<_, i64>(4)? as u64,
+                timed_completion_tokens: row.get::<_, i64>(5)? as u64,
+                latency_ms: row.get::<_, i64>(6)? as u64,
+            })
+        })?
+        .filter_map(|r| r.ok())
+        .collect();
+    Ok(rows)
+}
+
+// Only this many of a tool's latest runs count, so an upgrade that fixes it
+// is noticed after a while.
+const RECENT_TOOL_RUNS: u32 = 20;
+
+/// Records whether each (tool, succeeded) run worked.
+pub fn save_tool_runs(
+    conn: &Connection,
+    runs: &[(String, bool)],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)?
+        .as_secs() as i64;
+    let mut stmt =
+        conn.prepare("INSERT INTO tool_runs (tool, succeeded, timestamp) VALUES (?1, ?2, ?3)")?;
+    for (tool, succeeded) in runs {
+        stmt.execute(params![tool, *succeeded as i32, now])?;
+    }
+    Ok(())
+}
+
+/// Each tool's record over its most recent runs.
+pub fn get_tool_records(
+    conn: &Connection,
+) -> Result<HashMap<String, ToolRecord>, Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare("SELECT tool, succeeded FROM tool_runs ORDER BY id DESC")?;
+    let mut records: HashMap<String, ToolRecord> = HashMap::new();
+    let rows = stmt.query_map([], |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, i32>(1)?))
+    })?;
+    for (tool, succeeded) in rows.filter_map(|r| r.ok()) {
+        let record = records.entry(tool).or_default();
+        if record.runs < RECENT_TOOL_RUNS {
+            record.runs += 1;
+            record.failures += (succeeded == 0) as u32;
+        }
+    }
+    Ok(records)
+}
+
+/// Notes that a dangerous plan ran at `at`, forgetting those too old to
+/// count against the cooldown or the hourly cap.
+pub fn save_dangerous_run(conn: &Connection, at: i64) -> Result<(), Box<dyn std::error::Error>> {
+    conn.execute(
+        "DELETE FROM dangerous_runs WHERE timestamp < ?1",
+        params![at - 86_400],
+    )?;
+    conn.execute(
+        "INSERT INTO dangerous_runs (timestamp) VALUES (?1)",
+        params![at],
+    )?;
+    Ok(())
+}
+
+/// When dangerous plans ran since `since`, newest first.
+pub fn get_dangerous_runs_since(
+    conn: &Connection,
+    since: i64,
+) -> Result<Vec<i64>, Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare(
+        "SELECT timestamp FROM dangerous_runs WHERE timestamp >= ?1 ORDER BY timestamp DESC",
+    )?;
+    let runs = stmt
+        .query_map(params![since], |row| row.get(0))?
+        .filter_map(|r| r.ok())
+        .collect();
+    Ok(runs)
+}
+
+pub fn save_job(
+    conn: &Connection,
+    pgid: i32,
+    command: &str,
+) -> Result<i64, Box<dyn std::error::Error>> {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)?
+        .as_secs() as i64;
+    conn.execute(
+        "INSERT INTO jobs (pgid, command, started) VALUES (?1, ?2, ?3)",
+        params![pgid, command, now],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+/// Every recorded job. Rows stay until their process group is gone, since
+/// background children can outlive the command that started them.
+pub fn get_jobs(conn: &Connection) -> Result<Vec<Job>, Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare("SELECT id, pgid, command, started FROM jobs ORDER BY id")?;
+    let jobs = stmt
+        .query_map([], |row| {
+            Ok(Job {
+                id: row.get(0)?,
+                pgid: row.get(1)?,
+                command: row.get(2)?,
+                started: row.get(3)?,
+            })
+        })?
+        .filter_map(|r| r.ok())
+        .collect();
+    Ok(jobs)
+}
+
+pub fn delete_job(conn: &Connection, id: i64) -> Result<(), Box<dyn std::error::Error>> {
+    conn.execute("DELETE FROM jobs WHERE id = ?1", params![id])?;
+    Ok(())
+}
+
+pub fn get_meta(conn: &Connection, key: &str) -> Option<String> {
+    conn.query_row(
+        "SELECT value FROM meta WHERE key = ?1",
+        params![key],
+        |row| row.get(0),
+    )
+    .ok()
+}
+
+pub fn set_meta(
+    conn: &Connection,
+    key: &str,
+    value: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    conn.execute(
+        "INSERT OR REPLACE INTO meta (key, value) VALUES (?1, ?2)",
+        params![key, value],
+    )?;
+    Ok(())
+}
+
+pub fn delete_meta(conn: &Connection, key: &str) -> Result<(), Box<dyn std::error::Error>> {
+    conn.execute("DELETE FROM meta WHERE key = ?1", params![key])?;
+    Ok(())
+}
+
+const TOOL_COLUMNS: &str =
+    "name, path, description, synopsis, examples, flags, embedding, source, \
+                            updated_at, caveats, dry_run, target";
+
+fn current_generation(conn: &Connection) -> i64 {
+    get_meta(conn, "generation")
+        .and_then(|g| g.parse().ok())
+        .unwrap_or(0)
+}
+
+// Copies the live index into the archive as `generation`.
+fn archive_live(conn: &Connection, generation: i64) -> Result<(), Box<dyn std::error::Error>> {
+    if get_tool_count(conn) == 0 {
+        return Ok(());
+    }
+    conn.execute(
+        &format!(
+            "INSERT OR REPLACE INTO old_tools (generation, {0}) SELECT ?1, {0} FROM tools",
+            TOOL_COLUMNS
+        ),
+        params![generation],
+    )?;
+    conn.execute(
+        "INSERT OR REPLACE INTO generations (id, built, embedder, tools)
+         SELECT ?1, MAX(updated_at), ?2, COUNT(*) FROM tools",
+        params![generation, get_meta(conn, "embedder").unwrap_or_default()],
+    )?;
+    Ok(())
+}
+
+/// Starts a new generation before a full reindex: the live index is
+/// archived, and only the newest `keep` archived generations stay.
+pub fn start_generation(conn: &Connection, keep: usize) -> Result<i64, Box<dyn std::error::Error>> {
+    let tx = conn.unchecked_transaction()?;
+    let current = current_generation(conn);
+    if keep > 0 {
+        archive_live(conn, current)?;
+    }
+    conn.execute(
+        "DELETE FROM generations WHERE id NOT IN
+         (SELECT id FROM generations ORDER BY id DESC LIMIT ?1)",
+        params![keep as i64],
+    )?;
+    conn.execute(
+        "DELETE FROM old_tools WHERE generation NOT IN (SELECT id FROM generations)",
+        [],
+    )?;
+    let newest: i64 =
+        conn.query_row("SELECT COALESCE(MAX(id), 0) FROM generations", [], |row| {
+            row.get(0)
+        })?;
+    let next = current.max(newest) + 1;
+    conn.execute("UPDATE tools SET generation = ?1", params![next])?;
+    set_meta(conn, "generation", &next.to_string())?;
+    tx.commit()?;
+    Ok(next)
+}
+
+/// The archived generations, newest first.
+pub fn get_generations(
+    conn: &Connection,
+) -> Result<Vec<IndexGeneration>, Box<dyn std::error::Error>> {
+    let mut stmt =
+        conn.prepare("SELECT id, built, embedder, tools FROM generations ORDER BY id DESC")?;
+    let generations = stmt
+        .query_map([], |row| {
+            Ok(IndexGeneration {
+                id: row.get(0)?,
+                built: row.get::<_, Option<i64>>(1)?.unwrap_or(0),
+                embedder: row.get::<_, Option<String>>(2)?.unwrap_or_default(),
+                tools: row.get(3)?,
+            })
+        })?
+        .filter_map(|r| r.ok())
+        .collect();
+    Ok(generations)
+}
+
+/// Makes archived generation `id` the live index again. The one it
+/// replaces is archived in its place, so a rollback can be undone.
+pub fn restore_generation(conn: &Connection, id: i64) -> Result<(), Box<dyn std::error::Error>> {
+    let tx = conn.unchecked_transaction()?;
+    archive_live(conn, current_generation(conn))?;
+    conn.execute("DELETE FROM tools", [])?;
+    conn.execute(
+        &format!(
+            "INSERT INTO tools ({0}, generation) SELECT {0}, generation FROM old_tools
+             WHERE generation = ?1",
+            TOOL_COLUMNS
+        ),
+        params![id],
+    )?;
+    let embedder: String = conn.query_row(
+        "SELECT embedder FROM generations WHERE id = ?1",
+        params![id],
+        |row| row.get(0),
+    )?;
+    conn.execute("DELETE FROM old_tools WHERE generation = ?1", params![id])?;
+    conn.execute("DELETE FROM generations WHERE id = ?1", params![id])?;
+    set_meta(conn, "generation", &id.to_string())?;
+    set_meta(conn, "embedder", &embedder)?;
+    tx.commit()?;
+    Ok(())
+}
+
 pub fn get_tool_count(conn: &Connection) -> u32 {
     conn.query_row("SELECT COUNT(*) FROM tools", [], |row| row.get(0))
         .unwrap_or(0)
 }
+
+/// How many tools have their docs from `source`.
+pub fn get_source_count(conn: &Connection, source: &str) -> u32 {
+    conn.query_row(
+        "SELECT COUNT(*) FROM tools WHERE source = ?1",
+        params![source],
+        |row| row.get(0),
+    )
+    .unwrap_or(0)
+}
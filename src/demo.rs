@@ -0,0 +1,254 @@
+// `pls demo`: a guided tour that can't hurt anything. Generated files stand
+// in for a project, the selftest's mock server stands in for the model, and
+// a script of queries shows planning, confirmation, checks and refusals with
+// no model, no setup and nothing real at stake.
+
+use crate::config::Config;
+use crate::index::find_in_path;
+use crate::selftest::{start_mock, task};
+use std::{
+    env, fs,
+    io::{self, Write},
+    os::unix::fs::symlink,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+// The only programs on the sandbox's PATH, linked from the real ones.
+const DEMO_TOOLS: &[&str] = &[
+    "sh", "ls", "cat", "head", "tail", "find", "du", "sort", "uniq", "cut", "grep", "wc", "xargs",
+    "tar", "gzip", "mkdir",
+];
+
+struct Step {
+    query: &'static str,
+    commands: &'static [&'static str],
+    verify: &'static [&'static str],
+    explanation: &'static str,
+}
+
+const SCRIPT: &[Step] = &[
+    Step {
+        query: "find the 5 biggest files here",
+        commands: &["find . -type f -exec du -k {} + | sort -rn | head -5"],
+        verify: &[],
+        explanation: "sizes every file in KiB and keeps the five largest",
+    },
+    Step {
+        query: "count errors in the app log by day",
+        commands: &["grep ERROR logs/app.log | cut -d' ' -f1 | sort | uniq -c"],
+        verify: &[],
+        explanation: "keeps the ERROR lines, takes their date and counts each one",
+    },
+    Step {
+        query: "how many lines of python and rust are there",
+        commands: &["find src -name '*.py' -o -name '*.rs' | xargs wc -l"],
+        verify: &[],
+        explanation: "finds the source files and counts their lines",
+    },
+    Step {
+        query: "archive the logs folder into logs.tgz",
+        commands: &["tar -czf logs.tgz logs"],
+        verify: &["tar -tzf logs.tgz | head"],
+        explanation: "compresses logs/ into logs.tgz, then lists the archive to check it",
+    },
+    Step {
+        query: "wipe the whole disk",
+        commands: &["rm -rf /"],
+        verify: &[],
+        explanation: "deletes everything",
+    },
+];
+
+fn answer(prompt: &str) -> serde_json::Value {
+    let task = task(prompt);
+    match SCRIPT.iter().find(|s| s.query == task) {
+        Some(step) => serde_json::json!({
+            "commands": step.commands,
+            "verify": step.verify,
+            "explanation": step.explanation,
+            "warnings": [],
+            "needs_confirmation": true,
+        }),
+        None => serde_json::json!({
+            "possible": false,
+            "reason": "the demo's stand-in model only knows the scripted queries",
+            "missing": [],
+            "next_steps": SCRIPT.iter().map(|s| format!("try: {}", s.query)).collect::<Vec<_>>(),
+        }),
+    }
+}
+
+// Same numbers on every run, so a recorded demo matches a live one.
+struct Lcg(u64);
+
+impl Lcg {
+    fn next(&mut self, below: u64) -> u64 {
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (self.0 >> 33) % below
+    }
+}
+
+const MESSAGES: &[(&str, &str)] = &[
+    ("INFO", "request served in 12ms"),
+    ("INFO", "cache warmed"),
+    ("INFO", "user signed in"),
+    ("WARN", "slow query on orders (840ms)"),
+    ("WARN", "retrying upstream call"),
+    ("ERROR", "upstream timed out after 30s"),
+    ("ERROR", "could not write session: disk quota exceeded"),
+];
+
+fn seed(work: &Path) -> io::Result<()> {
+    let mut rng = Lcg(1507);
+    let day = 86400;
+    let today = crate::clock::now() / day * day;
+
+    fs::create_dir_all(work.join("logs"))?;
+    let mut log = String::new();
+    for days_ago in (0..5).rev() {
+        let date = crate::clock::format_date(&crate::clock::local(today - days_ago * day));
+        for _ in 0..80 + rng.next(40) {
+            let (level, message) = MESSAGES[rng.next(MESSAGES.len() as u64) as usize];
+            let (h, m, s) = (rng.next(24), rng.next(60), rng.next(60));
+            log.push_str(&format!(
+                "{} {:02}:{:02}:{:02} {} {}\n",
+                date, h, m, s, level, message
+            ));
+        }
+    }
+    fs::write(work.join("logs/app.log"), log)?;
+    fs::write(
+        work.join("logs/access.log"),
+        "GET /health 200\n".repeat(500),
+    )?;
+
+    fs::create_dir_all(work.join("src"))?;
+    for (name, line) in [
+        ("main.py", "print(\"hello\")\n"),
+        ("util.py", "def helper(x):\n    return x * 2\n"),
+        ("main.rs", "fn main() {\n    println!(\"hello\");\n}\n"),
+        ("lib.rs", "pub fn helper(x: u32) -> u32 {\n    x * 2\n}\n"),
+    ] {
+        fs::write(
+            work.join("src").join(name),
+            line.repeat(5 + rng.next(30) as usize),
+        )?;
+    }
+
+    fs::create_dir_all(work.join("data"))?;
+    for (name, kib) in [
+        ("export.csv", 2048),
+        ("backup.sql", 1024),
+        ("thumbs.db", 512),
+    ] {
+        fs::write(work.join("data").join(name), vec![b'x'; kib * 1024])?;
+    }
+    fs::create_dir_all(work.join("photos"))?;
+    for i in 1..=6 {
+        fs::write(
+            work.join(format!("photos/IMG_{:04}.jpg", i)),
+            vec![0; 4096 * i],
+        )?;
+    }
+    Ok(())
+}
+
+struct Sandbox {
+    root: PathBuf,
+    exe: PathBuf,
+}
+
+impl Sandbox {
+    fn create(endpoint: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let root = env::temp_dir().join(format!("pls-demo-{}", std::process::id()));
+        let bin = root.join("bin");
+        fs::create_dir_all(&bin)?;
+        fs::create_dir_all(root.join("work"))?;
+
+        for tool in DEMO_TOOLS {
+            if let Some(path) = find_in_path(tool) {
+                symlink(path, bin.join(tool))?;
+            }
+        }
+        seed(&root.join("work"))?;
+
+        let mut config = Config::default();
+        config.llm.endpoint = endpoint.to_string();
+        config.llm.embed_provider = "local".to_string();
+        config.paths.data_dir = Some(root.join("data"));
+        config.paths.state_dir = Some(root.join("state"));
+        config.paths.cache_dir = Some(root.join("cache"));
+        fs::write(root.join("config.toml"), toml::to_string(&config)?)?;
+
+        Ok(Self {
+            root,
+            exe: env::current_exe()?,
+        })
+    }
+
+    // Runs pls in the sandbox on the user's terminal.
+    fn pls(&self, args: &[&str]) -> Result<(), Box<dyn std::error::Error>> {
+        Command::new(&self.exe)
+            .args(args)
+            .current_dir(self.root.join("work"))
+            .env("PATH", self.root.join("bin"))
+            .env("HOME", &self.root)
+            .env("PLS_CONFIG", self.root.join("config.toml"))
+            .status()?;
+        Ok(())
+    }
+}
+
+impl Drop for Sandbox {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.root).ok();
+    }
+}
+
+// None at end of input.
+fn read_line(prompt: &str) -> Option<String> {
+    print!("{}", prompt);
+    io::stdout().flush().ok();
+    let mut input = String::new();
+    match io::stdin().read_line(&mut input) {
+        Ok(0) | Err(_) => None,
+        Ok(_) => Some(input.trim().to_string()),
+    }
+}
+
+pub fn run() -> Result<(), Box<dyn std::error::Error>> {
+    let endpoint = start_mock(answer)?;
+    let sandbox = Sandbox::create(&endpoint)?;
+    let interactive = unsafe { libc::isatty(libc::STDIN_FILENO) } == 1;
+
+    println!("pls demo: a sandbox at {}", sandbox.root.display());
+    println!("  files are generated, the model is a stand-in, and the PATH only has");
+    println!("  {}", DEMO_TOOLS.join(" "));
+    println!("  everything is deleted when the demo ends.");
+    println!();
+    sandbox.pls(&["index", "--quiet"])?;
+
+    for (i, step) in SCRIPT.iter().enumerate() {
+        println!();
+        println!("--- {}/{} ---", i + 1, SCRIPT.len());
+        println!("$ pls {}", step.query);
+        if interactive && read_line("[enter] ask  [q] stop ").is_none_or(|a| a == "q") {
+            return Ok(());
+        }
+        sandbox.pls(&[step.query])?;
+    }
+
+    if interactive {
+        println!();
+        println!("your turn: ask one of those again, or anything else to see how pls says no");
+        println!("(enter on its own ends the demo)");
+        while let Some(query) = read_line("$ pls ").filter(|q| !q.is_empty()) {
+            sandbox.pls(&[&query])?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,94 @@
+// One plan at a time changing a directory. Runs that need review take an
+// advisory lock on the working directory, so two terminals don't interleave
+// destructive steps. Lock files hold the commands being run, so they live in
+// the user's own state directory, which only they can enter, and the kernel
+// drops the lock when its holder exits, however that happens.
+
+use crate::config::PathsConfig;
+use crate::embed::fnv1a;
+use crate::paths::state_dir;
+use std::{
+    fs::{DirBuilder, File, OpenOptions},
+    io::{self, Read, Seek, Write},
+    os::unix::{
+        fs::{DirBuilderExt, OpenOptionsExt},
+        io::AsRawFd,
+    },
+    path::{Path, PathBuf},
+};
+
+const DIR: &str = "dirlocks";
+
+/// Held until dropped.
+pub struct DirLock(File);
+
+impl Drop for DirLock {
+    fn drop(&mut self) {
+        unsafe { libc::flock(self.0.as_raw_fd(), libc::LOCK_UN) };
+    }
+}
+
+pub enum Attempt {
+    Acquired(DirLock),
+    /// Who has it: pid and the commands it is running.
+    Held(String),
+}
+
+fn lock_path(paths: &PathsConfig, dir: &Path) -> io::Result<PathBuf> {
+    let locks = state_dir(paths).join(DIR);
+    DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(&locks)?;
+    let dir = dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
+    let hash = fnv1a(dir.as_os_str().as_encoded_bytes());
+    Ok(locks.join(format!("{:016x}.lock", hash)))
+}
+
+fn open(path: &Path) -> io::Result<File> {
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .mode(0o600)
+        .open(path)
+}
+
+fn flock(file: &File, blocking: bool) -> bool {
+    let op = if blocking {
+        libc::LOCK_EX
+    } else {
+        libc::LOCK_EX | libc::LOCK_NB
+    };
+    unsafe { libc::flock(file.as_raw_fd(), op) == 0 }
+}
+
+// What a waiting run shows; written once the lock is ours.
+fn claim(mut file: File, commands: &[String]) -> DirLock {
+    let holder = format!("pid {}: {}", std::process::id(), commands.join(" && "));
+    if file.set_len(0).is_ok() && file.rewind().is_ok() {
+        file.write_all(holder.as_bytes()).ok();
+    }
+    DirLock(file)
+}
+
+/// Takes the lock on `dir` for `commands` if nobody has it.
+pub fn try_lock(paths: &PathsConfig, dir: &Path, commands: &[String]) -> io::Result<Attempt> {
+    let mut file = open(&lock_path(paths, dir)?)?;
+    if flock(&file, false) {
+        return Ok(Attempt::Acquired(claim(file, commands)));
+    }
+    let mut holder = String::new();
+    file.read_to_string(&mut holder).ok();
+    Ok(Attempt::Held(holder))
+}
+
+/// Waits for the lock on `dir`.
+pub fn lock(paths: &PathsConfig, dir: &Path, commands: &[String]) -> io::Result<DirLock> {
+    let file = open(&lock_path(paths, dir)?)?;
+    if !flock(&file, true) {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(claim(file, commands))
+}
@@ -0,0 +1,207 @@
+// The user's own docs in the index. `pls index --docs <dir>` reads the
+// markdown and text files under a directory (runbooks, a team's notes on its
+// internal CLIs), cuts them into sections, embeds each and keeps them beside
+// the tool docs. Retrieval puts the few closest to a query in the prompt, so
+// plans can use tools and conventions no man page knows of.
+
+use crate::db::{get_doc_files, load_doc_chunks, replace_doc_chunks, set_doc_chunk_embedding};
+use crate::embed::Embedder;
+use crate::progress::Progress;
+use crate::types::DocChunk;
+use rusqlite::Connection;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+const EXTENSIONS: &[&str] = &["md", "markdown", "txt", "text", "rst", "adoc"];
+// About a screenful; longer sections are cut between paragraphs.
+const CHUNK_CHARS: usize = 1200;
+// Bigger files are more likely logs or dumps than anything written to read.
+const MAX_FILE_BYTES: u64 = 1 << 20;
+const EMBED_BATCH: usize = 32;
+
+fn files(dir: &Path, found: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.filter_map(|e| e.ok()) {
+        let path = entry.path();
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        // Symlinked directories aren't followed; one could lead back here.
+        if entry.file_type().is_ok_and(|t| t.is_dir()) {
+            files(&path, found);
+            continue;
+        }
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or_default();
+        let small = fs::metadata(&path).is_ok_and(|m| m.is_file() && m.len() <= MAX_FILE_BYTES);
+        if small && EXTENSIONS.contains(&extension.to_lowercase().as_str()) {
+            found.push(path);
+        }
+    }
+}
+
+// A markdown file's sections, each with the headings it sits under; a text
+// file is one section with none. `#` lines in fenced code aren't headings.
+fn sections(text: &str, markdown: bool) -> Vec<(String, String)> {
+    let mut sections = Vec::new();
+    let mut headings: Vec<(usize, String)> = Vec::new();
+    let mut body = String::new();
+    let mut fenced = false;
+    for line in text.lines() {
+        if line.trim_start().starts_with("```") {
+            fenced = !fenced;
+        }
+        let level = line.chars().take_while(|&c| c == '#').count();
+        if markdown && !fenced && level > 0 && line[level..].starts_with(' ') {
+            let trail: Vec<&str> = headings.iter().map(|(_, h)| h.as_str()).collect();
+            sections.push((trail.join(" > "), std::mem::take(&mut body)));
+            headings.retain(|(l, _)| *l < level);
+            headings.push((level, line[level..].trim().to_string()));
+        } else {
+            body.push_str(line);
+            body.push('\n');
+        }
+    }
+    let trail: Vec<&str> = headings.iter().map(|(_, h)| h.as_str()).collect();
+    sections.push((trail.join(" > "), body));
+    sections.retain(|(_, body)| !body.trim().is_empty());
+    sections
+}
+
+// `body` in pieces of at most CHUNK_CHARS, whole paragraphs where they fit.
+fn pieces(body: &str) -> Vec<String> {
+    let mut pieces = Vec::new();
+    let mut current = String::new();
+    for paragraph in body.split("\n\n").map(str::trim).filter(|p| !p.is_empty()) {
+        if !current.is_empty() && current.len() + 2 + paragraph.len() > CHUNK_CHARS {
+            pieces.push(std::mem::take(&mut current));
+        }
+        if !current.is_empty() {
+            current.push_str("\n\n");
+        }
+        current.push_str(paragraph);
+    }
+    if !current.is_empty() {
+        pieces.push(current);
+    }
+    pieces
+        .into_iter()
+        .flat_map(|piece| {
+            let chars: Vec<char> = piece.chars().collect();
+            chars
+                .chunks(CHUNK_CHARS)
+                .map(|c| c.iter().collect())
+                .collect::<Vec<String>>()
+        })
+        .collect()
+}
+
+fn chunks(path: &Path, text: &str) -> Vec<DocChunk> {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default();
+    let markdown = matches!(extension.to_lowercase().as_str(), "md" | "markdown");
+    let file = path.to_string_lossy().to_string();
+    sections(text, markdown)
+        .into_iter()
+        .flat_map(|(heading, body)| {
+            let file = file.clone();
+            pieces(&body).into_iter().map(move |text| DocChunk {
+                id: 0,
+                file: file.clone(),
+                heading: heading.clone(),
+                text,
+                embedding: Vec::new(),
+            })
+        })
+        .collect()
+}
+
+/// Where a chunk came from, as the prompt and `--stats` name it.
+pub fn label(chunk: &DocChunk) -> String {
+    let name = Path::new(&chunk.file)
+        .file_name()
+        .map_or(chunk.file.clone(), |n| n.to_string_lossy().to_string());
+    if chunk.heading.is_empty() {
+        name
+    } else {
+        format!("{} > {}", name, chunk.heading)
+    }
+}
+
+fn embed_text(chunk: &DocChunk) -> String {
+    format!("{}\n{}", label(chunk), chunk.text)
+}
+
+/// Reads the docs under `dir` into the index in place of what an earlier
+/// run read from there, dropping files that have gone since. Returns how
+/// many files and chunks were read.
+pub fn ingest(
+    embedder: &dyn Embedder,
+    conn: &Connection,
+    dir: &Path,
+    verbose: bool,
+) -> Result<(usize, usize), Box<dyn std::error::Error>> {
+    let dir = fs::canonicalize(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+    if !dir.is_dir() {
+        return Err(format!("{} is not a directory", dir.display()).into());
+    }
+    let mut found = Vec::new();
+    files(&dir, &mut found);
+    found.sort();
+
+    let mut read = 0;
+    let mut total = 0;
+    let mut progress = Progress::new(found.len(), verbose);
+    for path in &found {
+        progress.advance(&path.strip_prefix(&dir).unwrap_or(path).to_string_lossy());
+        // Not text, or not UTF-8: not docs either.
+        let Ok(text) = fs::read_to_string(path) else {
+            continue;
+        };
+        let mut chunks = chunks(path, &text);
+        for batch in chunks.chunks_mut(EMBED_BATCH) {
+            let texts: Vec<String> = batch.iter().map(embed_text).collect();
+            for (chunk, embedding) in batch.iter_mut().zip(embedder.embed_batch(&texts)) {
+                chunk.embedding = embedding?;
+            }
+        }
+        replace_doc_chunks(conn, &path.to_string_lossy(), &chunks)?;
+        read += 1;
+        total += chunks.len();
+    }
+    let kept: Vec<String> = found
+        .iter()
+        .map(|p| p.to_string_lossy().to_string())
+        .collect();
+    for gone in get_doc_files(conn, &dir.to_string_lossy())? {
+        if !kept.contains(&gone) {
+            replace_doc_chunks(conn, &gone, &[])?;
+        }
+    }
+    progress.finish();
+    Ok((read, total))
+}
+
+/// Re-embeds every chunk, for when the index was rebuilt with a different
+/// embedder. Returns how many were updated.
+pub fn reembed(
+    embedder: &dyn Embedder,
+    conn: &Connection,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let mut updated = 0;
+    for chunk in load_doc_chunks(conn)? {
+        if let Ok(embedding) = embedder.embed(&embed_text(&chunk)) {
+            set_doc_chunk_embedding(conn, chunk.id, &embedding)?;
+            updated += 1;
+        }
+    }
+    Ok(updated)
+}
@@ -0,0 +1,111 @@
+// Whether a plan still meets the directory it was made for. The planner
+// was told the current directory and, often, what's in it; a plan confirmed
+// minutes later, or re-run from history, may find itself somewhere else or
+// among other files. A fingerprint is the directory and the names, sizes
+// and mtimes of what's directly in it, taken when the plan is made and
+// again before a plan that changes things runs.
+
+use crate::embed::fnv1a;
+use std::{collections::BTreeMap, env, fs, time::UNIX_EPOCH};
+
+// Enough for any directory a plan is read against; past this, later names
+// (in sorted order) go unwatched.
+const MAX_ENTRIES: usize = 2000;
+// Names a drift message spells out per kind of change.
+const NAMED: usize = 3;
+
+pub struct Fingerprint {
+    pub cwd: String,
+    // Name to (size, mtime in ns).
+    entries: BTreeMap<String, (u64, u128)>,
+}
+
+/// The current directory as it is now.
+pub fn take() -> Fingerprint {
+    let cwd = env::current_dir()
+        .map(|p| p.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let mut entries: Vec<(String, (u64, u128))> = fs::read_dir(&cwd)
+        .into_iter()
+        .flatten()
+        .filter_map(|e| e.ok())
+        .map(|e| {
+            let meta = e.metadata().ok();
+            let mtime = meta
+                .as_ref()
+                .and_then(|m| m.modified().ok())
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map_or(0, |d| d.as_nanos());
+            let size = meta.map_or(0, |m| m.len());
+            (e.file_name().to_string_lossy().to_string(), (size, mtime))
+        })
+        .collect();
+    entries.sort();
+    entries.truncate(MAX_ENTRIES);
+    Fingerprint {
+        cwd,
+        entries: entries.into_iter().collect(),
+    }
+}
+
+impl Fingerprint {
+    /// A short hash of the whole fingerprint, for the history.
+    pub fn digest(&self) -> String {
+        let mut text = self.cwd.clone();
+        for (name, (size, mtime)) in &self.entries {
+            text.push_str(&format!("\n{}\t{}\t{}", name, size, mtime));
+        }
+        format!("{:016x}", fnv1a(text.as_bytes()))
+    }
+
+    /// What changed between this fingerprint and `now`, if anything.
+    pub fn drift(&self, now: &Fingerprint) -> Option<String> {
+        if self.cwd != now.cwd {
+            return Some(format!("now in {}, planned in {}", now.cwd, self.cwd));
+        }
+        let added: Vec<&str> = now
+            .entries
+            .keys()
+            .filter(|n| !self.entries.contains_key(*n))
+            .map(String::as_str)
+            .collect();
+        let removed: Vec<&str> = self
+            .entries
+            .keys()
+            .filter(|n| !now.entries.contains_key(*n))
+            .map(String::as_str)
+            .collect();
+        let changed: Vec<&str> = self
+            .entries
+            .iter()
+            .filter(|(n, was)| now.entries.get(*n).is_some_and(|is| is != *was))
+            .map(|(n, _)| n.as_str())
+            .collect();
+        let parts: Vec<String> = [("added", added), ("removed", removed), ("changed", changed)]
+            .into_iter()
+            .filter(|(_, names)| !names.is_empty())
+            .map(|(what, names)| format!("{} {} ({})", names.len(), what, list(&names)))
+            .collect();
+        let summary = parts.join(", ");
+        (!parts.is_empty()).then(|| format!("files here changed since planning: {}", summary))
+    }
+}
+
+fn list(names: &[&str]) -> String {
+    let mut shown = names[..names.len().min(NAMED)].join(", ");
+    if names.len() > NAMED {
+        shown.push_str(", ...");
+    }
+    shown
+}
+
+/// What changed since a plan last ran in `cwd`, leaving the directory as
+/// `digest` has it. Only a digest is kept, so the files can't be named.
+pub fn drift_since(cwd: &str, digest: &str) -> Option<String> {
+    let now = take();
+    if !cwd.is_empty() && now.cwd != cwd {
+        return Some(format!("now in {}, it last ran in {}", now.cwd, cwd));
+    }
+    (!digest.is_empty() && now.digest() != digest)
+        .then(|| "files here changed since it last ran".to_string())
+}
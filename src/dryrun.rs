@@ -0,0 +1,203 @@
+// Previews of a plan through the tools' own dry-run modes: `rsync -n`,
+// `make -n`, `terraform plan` for `terraform apply`. Indexing records the
+// flag each tool takes; a review or dangerous plan can then be run once
+// with the flags put in before it is run for real.
+
+use crate::shell::WRAPPERS;
+use std::collections::HashMap;
+
+// Tools whose preview flag their help doesn't call a dry run, or names in
+// a way detection would get wrong.
+const KNOWN: &[(&str, &str)] = &[
+    ("rsync", "--dry-run"),
+    ("make", "-n"),
+    ("apt-get", "--simulate"),
+    ("apt", "--simulate"),
+    ("ansible-playbook", "--check"),
+    ("rename", "-n"),
+    ("prename", "-n"),
+    ("dnf", "--assumeno"),
+];
+
+// Previews that belong to a subcommand. A flag starting with '-' goes
+// after the subcommand; anything else replaces it.
+const SUBCOMMANDS: &[(&str, &str, &str)] = &[
+    ("terraform", "apply", "plan"),
+    ("tofu", "apply", "plan"),
+    ("git", "clean", "-n"),
+    ("git", "push", "--dry-run"),
+    ("git", "add", "--dry-run"),
+    ("git", "rm", "--dry-run"),
+    ("git", "mv", "--dry-run"),
+    ("kubectl", "apply", "--dry-run=client"),
+    ("kubectl", "create", "--dry-run=client"),
+    ("kubectl", "delete", "--dry-run=client"),
+    ("helm", "install", "--dry-run"),
+    ("helm", "upgrade", "--dry-run"),
+    ("npm", "publish", "--dry-run"),
+    ("cargo", "publish", "--dry-run"),
+];
+
+// Tools with no mode that only reports, previewed by listing what they
+// would act on instead: the program and its options give way to this.
+// Whatever the index has for them is passed over; `rm -i` once stood in
+// for rm, and it deletes everything it gets a `y` for.
+const LISTED: &[(&str, &str)] = &[("rm", "ls -d --")];
+
+const DRY_RUN_WORDS: &[&str] = &["dry-run", "dryrun", "simulate", "no-act", "just-print"];
+
+/// The flag that makes `name` only report what it would do, from the known
+/// list or else its `--help`; empty if there is none.
+pub fn detect(name: &str, help: &Option<String>) -> String {
+    if let Some((_, flag)) = KNOWN.iter().find(|(tool, _)| *tool == name) {
+        return flag.to_string();
+    }
+    let Some(help) = help else {
+        return String::new();
+    };
+    let mut found: Vec<String> = Vec::new();
+    for line in help.lines().map(str::trim).filter(|l| l.starts_with('-')) {
+        // "-n, --dry-run[=WHEN]  perform a trial run"
+        let options = line.split("  ").next().unwrap_or(line);
+        for option in options.split([',', ' ']).filter(|o| o.starts_with("--")) {
+            let flag = option.split(['=', '[']).next().unwrap_or(option);
+            if DRY_RUN_WORDS
+                .iter()
+                .any(|w| flag[2..].eq_ignore_ascii_case(w))
+            {
+                found.push(flag.to_string());
+            }
+        }
+    }
+    found
+        .iter()
+        .find(|f| *f == "--dry-run")
+        .or(found.first())
+        .cloned()
+        .unwrap_or_default()
+}
+
+pub fn known(name: &str) -> String {
+    detect(name, &None)
+}
+
+// A word of a command line and where it sits in it.
+struct Word {
+    text: String,
+    start: usize,
+    end: usize,
+}
+
+// Words grouped into simple commands, split at top-level `|`, `&`, `;`
+// and newlines. Quotes are kept in `text`, which is only compared with
+// plain words.
+fn simple_commands(cmd: &str) -> Vec<Vec<Word>> {
+    let mut commands: Vec<Vec<Word>> = vec![Vec::new()];
+    let mut word: Option<Word> = None;
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    for (i, c) in cmd.char_indices() {
+        // `2>&1` and `&>` are redirections, not separators.
+        let redirect = c == '&' && (cmd[..i].ends_with('>') || cmd[i + 1..].starts_with('>'));
+        let separator =
+            quote.is_none() && !escaped && !redirect && matches!(c, '|' | '&' | ';' | '\n');
+        let blank = quote.is_none() && !escaped && c.is_whitespace();
+        if separator || blank {
+            if let Some(w) = word.take() {
+                commands.last_mut().unwrap().push(w);
+            }
+            if separator && !commands.last().unwrap().is_empty() {
+                commands.push(Vec::new());
+            }
+            continue;
+        }
+        match (quote, c) {
+            _ if escaped => escaped = false,
+            (q, '\\') if q != Some('\'') => escaped = true,
+            (Some(q), c) if c == q => quote = None,
+            (None, '\'' | '"') => quote = Some(c),
+            _ => {}
+        }
+        let w = word.get_or_insert(Word {
+            text: String::new(),
+            start: i,
+            end: i,
+        });
+        w.text.push(c);
+        w.end = i + c.len_utf8();
+    }
+    if let Some(w) = word {
+        commands.last_mut().unwrap().push(w);
+    }
+    commands
+}
+
+// The edit that previews one simple command: text to insert at an offset,
+// or a word to replace. An empty edit when it already is a dry run.
+fn preview(words: &[Word], flags: &HashMap<String, String>) -> Option<(usize, usize, String)> {
+    let mut rest = words
+        .iter()
+        .skip_while(|w| w.text.contains('=') && !w.text.starts_with('-'))
+        .peekable();
+    let program = loop {
+        let word = rest.next()?;
+        let name = word.text.rsplit('/').next().unwrap_or(&word.text);
+        if !WRAPPERS.contains(&name) {
+            break word;
+        }
+        // Wrapper options and `timeout 10s`.
+        while rest.peek().is_some_and(|w| w.text.starts_with('-')) {
+            rest.next();
+        }
+        if name == "timeout" {
+            rest.next();
+        }
+    };
+    let name = program.text.rsplit('/').next().unwrap_or(&program.text);
+    let args: Vec<&Word> = rest.collect();
+
+    if let Some(sub) = args.iter().find(|w| !w.text.starts_with('-')) {
+        let rule = SUBCOMMANDS
+            .iter()
+            .find(|(tool, s, _)| *tool == name && *s == sub.text);
+        if let Some((_, _, preview)) = rule {
+            if preview.starts_with('-') {
+                if args.iter().any(|w| w.text == *preview) {
+                    return Some((sub.end, sub.end, String::new()));
+                }
+                return Some((sub.end, sub.end, format!(" {}", preview)));
+            }
+            return Some((sub.start, sub.end, preview.to_string()));
+        }
+    }
+    if let Some((_, listing)) = LISTED.iter().find(|(tool, _)| *tool == name) {
+        let options = args.iter().take_while(|w| w.text.starts_with('-'));
+        let end = options.last().map_or(program.end, |w| w.end);
+        return Some((program.start, end, listing.to_string()));
+    }
+    let flag = flags.get(name).filter(|f| !f.is_empty())?;
+    if args.iter().any(|w| w.text == *flag) {
+        return Some((program.end, program.end, String::new()));
+    }
+    Some((program.end, program.end, format!(" {}", flag)))
+}
+
+/// `cmd` with every program switched to its dry-run flag in `flags` (keyed
+/// by tool name), or None unless every simple command in it has a preview:
+/// one left as it is would really run.
+pub fn variant(cmd: &str, flags: &HashMap<String, String>) -> Option<String> {
+    let mut edits: Vec<(usize, usize, String)> = simple_commands(cmd)
+        .iter()
+        .filter(|words| !words.is_empty())
+        .map(|words| preview(words, flags))
+        .collect::<Option<_>>()?;
+    if edits.is_empty() {
+        return None;
+    }
+    let mut out = cmd.to_string();
+    edits.sort_by_key(|(start, _, _)| std::cmp::Reverse(*start));
+    for (start, end, text) in edits {
+        out.replace_range(start..end, &text);
+    }
+    Some(out)
+}
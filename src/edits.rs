@@ -0,0 +1,586 @@
+// In-place edits in a plan: `sed -i`, `awk -i inplace`, and a pipeline of
+// filters redirected over a file that's already there. Before the plan
+// runs, each edit is worked out on a copy so the prompt can show it as a
+// diff. When the plan does run, the files it edits are backed up first,
+// under the state directory and keyed by the run's history entry, and
+// `pls undo` puts them back.
+
+use crate::config::PathsConfig;
+use crate::embed::fnv1a;
+use crate::index::find_in_path;
+use crate::paths::state_dir;
+use crate::pipe;
+use crate::quoting::quote;
+use crate::shell::{split_pipelines, split_segments, words};
+use serde::{Deserialize, Serialize};
+use std::{
+    env, fs,
+    path::{Component, Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+const DIR: &str = "undo";
+const MANIFEST: &str = "manifest.json";
+// Files bigger than this are backed up but not diffed.
+const PREVIEW_BYTES: u64 = 1024 * 1024;
+// What the copy of an edited file is called in a preview's directory.
+const EDITED: &str = "pls-edited";
+// Programs that only read their input and write their output.
+const FILTERS: &[&str] = &[
+    "cat", "sort", "uniq", "cut", "tr", "sed", "awk", "gawk", "jq", "yq", "grep", "egrep", "head",
+    "tail", "tac", "column", "paste", "fmt", "fold", "expand", "unexpand", "iconv", "envsubst",
+    "nl", "rev", "comm", "join",
+];
+
+/// A file one step of a plan rewrites, and what it would hold afterwards
+/// as things stand.
+pub struct Edit {
+    pub step: usize,
+    pub path: PathBuf,
+    /// The diff against the file as it is; None when it couldn't be worked
+    /// out, with `note` saying why.
+    pub diff: Option<String>,
+    pub note: Option<String>,
+}
+
+// How a step rewrites its files.
+enum Rewrite {
+    // `sed`/`awk` with these arguments, minus the in-place ones, prints
+    // the new contents of each file.
+    Program(String, Vec<String>),
+    // The pipeline, written to another file instead, makes the new contents.
+    Redirect(String, String),
+}
+
+#[derive(Serialize, Deserialize)]
+struct Backup {
+    path: PathBuf,
+    backup: PathBuf,
+    // The file's hash once the run was over; undo leaves it alone if it
+    // has changed since.
+    after: Option<u64>,
+}
+
+// `sed -i`: the arguments without the in-place flag, and the files.
+fn sed(args: &[String]) -> Option<(Vec<String>, Vec<String>)> {
+    let (mut kept, mut files) = (Vec::new(), Vec::new());
+    let (mut in_place, mut script) = (false, false);
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if files.is_empty() && arg == "--" {
+            files.extend(args.by_ref().cloned());
+        } else if arg == "--in-place" || arg.starts_with("--in-place=") {
+            in_place = true;
+        } else if arg.starts_with("--expression=") || arg.starts_with("--file=") {
+            script = true;
+            kept.push(arg.clone());
+        } else if arg.starts_with("--") {
+            kept.push(arg.clone());
+        } else if let Some(cluster) = arg.strip_prefix('-').filter(|c| !c.is_empty()) {
+            // `-Ei` is -E and -i; `-ie` is -i with the suffix "e".
+            let mut flags = String::new();
+            for (i, c) in cluster.char_indices() {
+                match c {
+                    'i' => {
+                        in_place = true;
+                        break;
+                    }
+                    'e' | 'f' | 'l' => {
+                        script |= c != 'l';
+                        flags.push_str(&cluster[i..]);
+                        if i + 1 == cluster.len() {
+                            flags.push(' ');
+                        }
+                        break;
+                    }
+                    c => flags.push(c),
+                }
+            }
+            match flags.strip_suffix(' ') {
+                Some(flags) => {
+                    kept.push(format!("-{}", flags));
+                    kept.push(args.next()?.clone());
+                }
+                None if !flags.is_empty() => kept.push(format!("-{}", flags)),
+                None => {}
+            }
+        } else if !script {
+            script = true;
+            kept.push(arg.clone());
+        } else {
+            files.push(arg.clone());
+        }
+    }
+    (in_place && !files.is_empty()).then_some((kept, files))
+}
+
+// `awk -i inplace` (gawk): the arguments without it, and the files.
+fn awk(args: &[String]) -> Option<(Vec<String>, Vec<String>)> {
+    let (mut kept, mut files) = (Vec::new(), Vec::new());
+    let (mut in_place, mut program) = (false, false);
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-i" => {
+                let library = args.next()?;
+                if library == "inplace" {
+                    in_place = true;
+                } else {
+                    kept.extend([arg.clone(), library.clone()]);
+                }
+            }
+            "-iinplace" | "--include=inplace" => in_place = true,
+            "-v" | "-F" => kept.extend([arg.clone(), args.next()?.clone()]),
+            "-f" => {
+                program = true;
+                kept.extend([arg.clone(), args.next()?.clone()]);
+            }
+            "--" => files.extend(args.by_ref().cloned()),
+            _ if arg.starts_with('-') => kept.push(arg.clone()),
+            _ if !program => {
+                program = true;
+                kept.push(arg.clone());
+            }
+            // `var=value` operands are assignments, not files.
+            _ if arg.contains('=') => kept.push(arg.clone()),
+            _ => files.push(arg.clone()),
+        }
+    }
+    (in_place && !files.is_empty()).then_some((kept, files))
+}
+
+// The target of the pipeline's last `>`, as written, when every program in
+// it is a filter and the target is a file that's already there.
+fn redirect(pipeline: &str) -> Option<String> {
+    let segments = split_segments(pipeline);
+    let filters = segments.iter().all(|segment| {
+        let words = words(segment);
+        let start = words
+            .iter()
+            .position(|w| !w.contains('=') || w.starts_with('-'));
+        start
+            .is_some_and(|i| FILTERS.contains(&words[i].as_str()) && sed(&words[i + 1..]).is_none())
+    });
+    if !filters {
+        return None;
+    }
+    let words = words(segments.last()?);
+    let target = words
+        .iter()
+        .enumerate()
+        .rev()
+        .find_map(|(i, w)| match w.as_str() {
+            ">" | "1>" | ">|" => words.get(i + 1).cloned(),
+            w if w.starts_with('>') && !w.starts_with(">>") && !w.starts_with(">&") => {
+                Some(w.trim_start_matches(['>', '|']).to_string())
+            }
+            _ => None,
+        })?;
+    fs::metadata(&target)
+        .is_ok_and(|m| m.is_file())
+        .then_some(target)
+}
+
+// What `cmd` rewrites, with the files it rewrites.
+fn rewrites(cmd: &str) -> Vec<(Rewrite, Vec<String>)> {
+    let mut found = Vec::new();
+    for pipeline in split_pipelines(cmd) {
+        let all = words(&pipeline);
+        // `sudo sed -i ...` previews as the user; reading is usually enough.
+        let skip = all
+            .iter()
+            .take_while(|w| matches!(w.as_str(), "sudo" | "doas"))
+            .count();
+        let Some(program) = all.get(skip) else {
+            continue;
+        };
+        let single = split_segments(&pipeline).len() == 1;
+        let args = &all[skip + 1..];
+        let parsed = match program.as_str() {
+            "sed" if single => sed(args),
+            "awk" | "gawk" if single => awk(args),
+            _ => None,
+        };
+        if let Some((kept, files)) = parsed {
+            found.push((Rewrite::Program(program.clone(), kept), files));
+        } else if let Some(target) = redirect(&pipeline) {
+            found.push((
+                Rewrite::Redirect(pipeline.clone(), target.clone()),
+                vec![target],
+            ));
+        }
+    }
+    found
+}
+
+/// Every file `commands` rewrite in place.
+pub fn targets(commands: &[String]) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = Vec::new();
+    for (_, files) in commands.iter().flat_map(|c| rewrites(c)) {
+        for file in files {
+            let path = absolute(&file);
+            if path.is_file() && !paths.contains(&path) {
+                paths.push(path);
+            }
+        }
+    }
+    paths
+}
+
+fn absolute(file: &str) -> PathBuf {
+    let path = PathBuf::from(file);
+    if path.is_absolute() {
+        path
+    } else {
+        env::current_dir().unwrap_or_default().join(path)
+    }
+}
+
+fn unified_diff(path: &str, before: &Path, after: &Path) -> Result<String, String> {
+    let output = Command::new("diff")
+        .arg("-u")
+        .args([
+            "--label",
+            &format!("a/{}", path),
+            "--label",
+            &format!("b/{}", path),
+        ])
+        .arg(before)
+        .arg(after)
+        .output()
+        .map_err(|_| "no diff program to show it with".to_string())?;
+    match output.status.code() {
+        Some(0 | 1) => Ok(String::from_utf8_lossy(&output.stdout).into_owned()),
+        _ => Err(String::from_utf8_lossy(&output.stderr).trim().to_string()),
+    }
+}
+
+// Words the shell would act on before any program ran; a preview runs
+// programs itself, never through a shell, so it can't show them.
+const SHELL_ONLY: &[&str] = &["$(", "`", "<(", ">(", ";"];
+// Filters' own options for writing a file besides stdout.
+const WRITES: &[(&str, &[&str])] = &[
+    ("sort", &["-o", "--output"]),
+    ("iconv", &["-o", "--output"]),
+    ("yq", &["-i", "--inplace"]),
+];
+
+// Whether `word` is one of `flags`, or a short one among clustered flags.
+fn has_flag(word: &str, flags: &[&str]) -> bool {
+    flags.iter().any(|flag| match flag.strip_prefix("--") {
+        Some(_) => word.starts_with(flag),
+        None => {
+            let letter = &flag[1..];
+            word.starts_with('-') && !word.starts_with("--") && word[1..].contains(letter)
+        }
+    })
+}
+
+// One filter of a preview as a command to run in `dir`: `words` as the
+// shell would split them, with the relative files it names copied into
+// `dir` first and `file` standing for the copy made of the edited one.
+// sed and gawk run with --sandbox, so their scripts can't run commands or
+// write files; awk that isn't gawk has no such mode and isn't run at all.
+fn filter(words: &[String], dir: &Path) -> Result<Command, String> {
+    if let Some(word) = words
+        .iter()
+        .find(|w| SHELL_ONLY.iter().any(|s| w.contains(s)))
+    {
+        return Err(format!("not previewed: {:?} needs a shell", word));
+    }
+    let assignments = words
+        .iter()
+        .take_while(|w| w.contains('=') && !w.starts_with('-'));
+    let env: Vec<(&str, &str)> = assignments.filter_map(|w| w.split_once('=')).collect();
+    let (program, args) = words[env.len()..].split_first().ok_or("nothing to run")?;
+    if !FILTERS.contains(&program.as_str()) {
+        return Err(format!("not previewed: {} is not a filter", program));
+    }
+    if let Some(word) = args
+        .iter()
+        .find(|w| w.starts_with(['<', '>']) || w.contains(">&"))
+    {
+        return Err(format!("not previewed: {:?} redirects", word));
+    }
+    let writes = WRITES.iter().find(|(p, _)| p == program);
+    let operands = args.iter().filter(|a| !a.starts_with('-')).count();
+    if writes.is_some_and(|(_, flags)| args.iter().any(|a| has_flag(a, flags)))
+        || (program == "uniq" && operands > 1)
+    {
+        return Err(format!(
+            "not previewed: this {} writes a file of its own",
+            program
+        ));
+    }
+    let mut cmd = match program.as_str() {
+        "sed" => Command::new("sed"),
+        "awk" | "gawk" if find_in_path("gawk").is_some() => Command::new("gawk"),
+        "awk" | "gawk" => return Err("not previewed: that needs gawk's --sandbox".to_string()),
+        _ => Command::new(program),
+    };
+    if matches!(program.as_str(), "sed" | "awk" | "gawk") {
+        cmd.arg("--sandbox");
+    }
+    for arg in args {
+        let path = Path::new(arg);
+        let inside =
+            path.is_relative() && path.components().all(|c| matches!(c, Component::Normal(_)));
+        if inside && !arg.starts_with('-') && path.is_file() && !dir.join(path).exists() {
+            if fs::metadata(path).map_or(0, |m| m.len()) > PREVIEW_BYTES {
+                return Err("too big to show".to_string());
+            }
+            if let Some(parent) = dir.join(path).parent() {
+                fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+            }
+            fs::copy(path, dir.join(path)).map_err(|e| e.to_string())?;
+        }
+    }
+    cmd.args(args).envs(env).current_dir(dir);
+    Ok(cmd)
+}
+
+// Runs `stages` as a pipeline, each one's output the next one's input, and
+// returns what the last one printed. Only input piped into pls reaches the
+// first; a filter waiting on the terminal would hang the prompt.
+fn run_pipeline(stages: Vec<Command>) -> Result<Vec<u8>, String> {
+    let count = stages.len();
+    let mut children = Vec::new();
+    let mut input = Some(if pipe::has_input() {
+        pipe::stdin()
+    } else {
+        Stdio::null()
+    });
+    for (i, mut cmd) in stages.into_iter().enumerate() {
+        let last = i + 1 == count;
+        cmd.stdin(input.take().unwrap_or_else(Stdio::null))
+            .stdout(Stdio::piped())
+            .stderr(if last { Stdio::piped() } else { Stdio::null() });
+        let mut child = cmd.spawn().map_err(|e| e.to_string())?;
+        if !last {
+            input = child.stdout.take().map(Stdio::from);
+        }
+        children.push(child);
+    }
+    let last = children.pop().ok_or("nothing to run")?;
+    let output = last.wait_with_output().map_err(|e| e.to_string())?;
+    for mut child in children {
+        child.wait().ok();
+    }
+    if output.status.success() {
+        Ok(output.stdout)
+    } else {
+        Err(String::from_utf8_lossy(&output.stderr).trim().to_string())
+    }
+}
+
+// The new contents of `file`, worked out in the scratch directory `dir`
+// on a copy of it, without touching it or anything else.
+fn rewritten(rewrite: &Rewrite, file: &str, dir: &Path) -> Result<Vec<u8>, String> {
+    let copy = dir.join(EDITED);
+    fs::copy(absolute(file), &copy).map_err(|e| e.to_string())?;
+    // The edited file's name, as each program is given it, is the copy's.
+    let swap = |w: &String| {
+        if w == file {
+            EDITED.to_string()
+        } else {
+            w.clone()
+        }
+    };
+    let stages = match rewrite {
+        Rewrite::Program(program, args) => {
+            let mut words = vec![program.clone()];
+            words.extend(args.iter().cloned());
+            words.push(EDITED.to_string());
+            vec![filter(&words, dir)?]
+        }
+        Rewrite::Redirect(pipeline, target) => {
+            // The shell empties the file before anything reads it.
+            let named = |w: &&String| *w == target || w.trim_start_matches(['>', '|']) == target;
+            let reads = words(pipeline).iter().filter(named).count();
+            if reads > 1 {
+                return Ok(Vec::new());
+            }
+            split_segments(&strip_redirect(pipeline, target))
+                .iter()
+                .map(|segment| filter(&words(segment).iter().map(swap).collect::<Vec<_>>(), dir))
+                .collect::<Result<Vec<_>, _>>()?
+        }
+    };
+    run_pipeline(stages)
+}
+
+// `pipeline` without its last `> target`, as written.
+fn strip_redirect(pipeline: &str, target: &str) -> String {
+    let Some(at) = pipeline.rfind('>') else {
+        return pipeline.to_string();
+    };
+    let rest = pipeline[at + 1..].trim_start_matches('|').trim_start();
+    let written = [quote(target), format!("\"{}\"", target), target.to_string()]
+        .into_iter()
+        .find(|w| rest.starts_with(w.as_str()));
+    let end = pipeline.len() - rest.len() + written.map_or(0, |w| w.len());
+    format!("{}{}", &pipeline[..at], &pipeline[end..])
+        .trim_end()
+        .to_string()
+}
+
+/// The in-place edits in `commands`, each worked out on a copy of the file
+/// as it is now. Steps after the first run after the ones before them, so
+/// their diffs may not be what happens.
+pub fn preview(commands: &[String]) -> Vec<Edit> {
+    let dir = env::temp_dir().join(format!("pls-edit-{}", std::process::id()));
+    let scratch = env::temp_dir().join(format!("pls-edit-{}.after", std::process::id()));
+    let mut edits = Vec::new();
+    for (step, cmd) in commands.iter().enumerate() {
+        for (rewrite, files) in rewrites(cmd) {
+            for file in files {
+                let path = absolute(&file);
+                let size = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+                let result = if size > PREVIEW_BYTES {
+                    Err("too big to show".to_string())
+                } else {
+                    // Each edit starts from an empty directory of its own.
+                    fs::remove_dir_all(&dir).ok();
+                    fs::create_dir(&dir)
+                        .map_err(|e| e.to_string())
+                        .and_then(|_| rewritten(&rewrite, &file, &dir))
+                        .and_then(|after| {
+                            fs::write(&scratch, after).map_err(|e| e.to_string())?;
+                            unified_diff(&file, &path, &scratch)
+                        })
+                };
+                let (diff, note) = match result {
+                    Ok(diff) => (Some(diff), None),
+                    Err(e) => (None, Some(e)),
+                };
+                edits.push(Edit {
+                    step: step + 1,
+                    path,
+                    diff,
+                    note,
+                });
+            }
+        }
+    }
+    fs::remove_dir_all(&dir).ok();
+    fs::remove_file(&scratch).ok();
+    edits
+}
+
+fn entry_dir(paths: &PathsConfig, entry: i64) -> PathBuf {
+    state_dir(paths).join(DIR).join(entry.to_string())
+}
+
+fn hash(path: &Path) -> Option<u64> {
+    fs::read(path).ok().map(|bytes| fnv1a(&bytes))
+}
+
+/// Copies what `commands` are about to rewrite aside, for `pls undo` to
+/// put back. Nothing is kept for a plan that rewrites nothing.
+pub fn back_up(
+    paths: &PathsConfig,
+    entry: i64,
+    commands: &[String],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let targets = targets(commands);
+    if targets.is_empty() {
+        return Ok(());
+    }
+    let dir = entry_dir(paths, entry);
+    fs::create_dir_all(&dir)?;
+    let mut backups = Vec::new();
+    for (i, path) in targets.into_iter().enumerate() {
+        let backup = dir.join(i.to_string());
+        if fs::copy(&path, &backup).is_ok() {
+            backups.push(Backup {
+                path,
+                backup,
+                after: None,
+            });
+        }
+    }
+    fs::write(dir.join(MANIFEST), serde_json::to_string(&backups)?)?;
+    Ok(())
+}
+
+fn read_manifest(dir: &Path) -> Option<Vec<Backup>> {
+    serde_json::from_str(&fs::read_to_string(dir.join(MANIFEST)).ok()?).ok()
+}
+
+/// Notes what the backed-up files hold now the run is over.
+pub fn seal(paths: &PathsConfig, entry: i64) -> Result<(), Box<dyn std::error::Error>> {
+    let dir = entry_dir(paths, entry);
+    let Some(mut backups) = read_manifest(&dir) else {
+        return Ok(());
+    };
+    for backup in &mut backups {
+        backup.after = hash(&backup.path);
+    }
+    fs::write(dir.join(MANIFEST), serde_json::to_string(&backups)?)?;
+    Ok(())
+}
+
+/// The newest run with files to put back.
+pub fn latest(paths: &PathsConfig) -> Option<i64> {
+    fs::read_dir(state_dir(paths).join(DIR))
+        .ok()?
+        .filter_map(|e| e.ok()?.file_name().to_str()?.parse::<i64>().ok())
+        .max()
+}
+
+/// What undoing a run did to each file it had rewritten.
+pub enum Undone {
+    Restored(PathBuf),
+    // Changed again since the run; left alone without `force`.
+    Changed(PathBuf),
+    Failed(PathBuf, String),
+}
+
+/// Puts back the files run `entry` rewrote. The backups go once every file
+/// is back.
+pub fn undo(
+    paths: &PathsConfig,
+    entry: i64,
+    force: bool,
+) -> Result<Vec<Undone>, Box<dyn std::error::Error>> {
+    let dir = entry_dir(paths, entry);
+    let backups = read_manifest(&dir).ok_or(format!("nothing to undo for run {}", entry))?;
+    let mut results = Vec::new();
+    for backup in backups {
+        let changed = backup
+            .after
+            .is_some_and(|after| hash(&backup.path) != Some(after));
+        if changed && !force {
+            results.push(Undone::Changed(backup.path));
+            continue;
+        }
+        match fs::copy(&backup.backup, &backup.path) {
+            Ok(_) => results.push(Undone::Restored(backup.path)),
+            Err(e) => results.push(Undone::Failed(backup.path, e.to_string())),
+        }
+    }
+    if results.iter().all(|r| matches!(r, Undone::Restored(_))) {
+        fs::remove_dir_all(&dir)?;
+    }
+    Ok(results)
+}
+
+/// Drops backups older than `days`; 0 keeps them all. Returns how many
+/// runs' worth went.
+pub fn prune(paths: &PathsConfig, days: u32) -> usize {
+    let Ok(entries) = fs::read_dir(state_dir(paths).join(DIR)) else {
+        return 0;
+    };
+    let limit = std::time::Duration::from_secs(days as u64 * 24 * 60 * 60);
+    let mut pruned = 0;
+    for dir in entries.filter_map(|e| e.ok()).map(|e| e.path()) {
+        let age = fs::metadata(&dir)
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|t| t.elapsed().ok());
+        if days > 0 && age.is_some_and(|age| age > limit) && fs::remove_dir_all(&dir).is_ok() {
+            pruned += 1;
+        }
+    }
+    pruned
+}
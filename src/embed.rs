@@ -0,0 +1,182 @@
+use crate::config::{EmbedTemplate, LlmConfig};
+use crate::llm::{make_named, LlmProvider};
+
+/// `embed` and `embed_batch` are for what gets searched (tool docs, notes,
+/// facts), `embed_query` for what searches it.
+pub trait Embedder: Sync {
+    fn embed(&self, text: &str) -> Result<Vec<f32>, Box<dyn std::error::Error>>;
+    fn embed_batch(&self, texts: &[String]) -> Vec<Result<Vec<f32>, Box<dyn std::error::Error>>> {
+        texts.iter().map(|t| self.embed(t)).collect()
+    }
+    fn embed_query(&self, text: &str) -> Result<Vec<f32>, Box<dyn std::error::Error>> {
+        self.embed(text)
+    }
+    fn is_available(&self) -> bool;
+    /// Where the model runs, for messages.
+    fn endpoint(&self) -> &str;
+    /// Identifies the model. Vectors from different ids aren't comparable,
+    /// so the index records which one built it.
+    fn id(&self) -> String;
+}
+
+/// A provider's embedding model, with the model's task prefixes applied.
+pub struct ModelEmbedder {
+    provider: Box<dyn LlmProvider>,
+    template: EmbedTemplate,
+}
+
+fn apply(template: &str, text: &str) -> String {
+    if template.is_empty() {
+        text.to_string()
+    } else {
+        template.replace("{text}", text)
+    }
+}
+
+impl Embedder for ModelEmbedder {
+    fn embed(&self, text: &str) -> Result<Vec<f32>, Box<dyn std::error::Error>> {
+        self.provider.embed(&apply(&self.template.document, text))
+    }
+
+    fn embed_batch(&self, texts: &[String]) -> Vec<Result<Vec<f32>, Box<dyn std::error::Error>>> {
+        let texts: Vec<String> = texts
+            .iter()
+            .map(|t| apply(&self.template.document, t))
+            .collect();
+        self.provider.embed_batch(&texts)
+    }
+
+    fn embed_query(&self, text: &str) -> Result<Vec<f32>, Box<dyn std::error::Error>> {
+        self.provider.embed(&apply(&self.template.query, text))
+    }
+
+    fn is_available(&self) -> bool {
+        self.provider.is_available()
+    }
+
+    fn endpoint(&self) -> &str {
+        self.provider.endpoint()
+    }
+
+    // Prefixes change the vectors, so they're part of the id.
+    fn id(&self) -> String {
+        let id = format!("{}:{}", self.provider.name(), self.provider.embed_model());
+        if self.template.query.is_empty() && self.template.document.is_empty() {
+            return id;
+        }
+        let both = format!("{}\0{}", self.template.query, self.template.document);
+        format!("{}+{:08x}", id, fnv1a(both.as_bytes()) as u32)
+    }
+}
+
+const HASH_DIMS: usize = 512;
+
+/// In-process embedder that needs no model or daemon: words and character
+/// trigrams are hashed into a fixed-size vector. This is keyword matching,
+/// not a trained model: it ranks tool docs by the words they share with the
+/// query and knows nothing of synonyms ("delete" won't find `rm`). A
+/// fallback for when no embedding model is to hand.
+pub struct HashingEmbedder;
+
+/// A hash that stays the same across builds and platforms.
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for b in bytes {
+        hash ^= *b as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+impl HashingEmbedder {
+    fn add(vector: &mut [f32], feature: &str, weight: f32) {
+        let hash = fnv1a(feature.as_bytes());
+        let slot = (hash % HASH_DIMS as u64) as usize;
+        let sign = if hash >> 63 == 0 { 1.0 } else { -1.0 };
+        vector[slot] += sign * weight;
+    }
+}
+
+impl Embedder for HashingEmbedder {
+    fn embed(&self, text: &str) -> Result<Vec<f32>, Box<dyn std::error::Error>> {
+        let mut vector = vec![0.0f32; HASH_DIMS];
+        let lower = text.to_lowercase();
+
+        for word in lower.split(|c: char| !c.is_alphanumeric() && c != '-') {
+            if word.len() < 2 {
+                continue;
+            }
+            Self::add(&mut vector, word, 1.0);
+            let padded: Vec<char> = format!("^{}$", word).chars().collect();
+            for gram in padded.windows(3) {
+                Self::add(&mut vector, &gram.iter().collect::<String>(), 0.3);
+            }
+        }
+
+        let norm: f32 = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+        if norm > 0.0 {
+            vector.iter_mut().for_each(|x| *x /= norm);
+        }
+        Ok(vector)
+    }
+
+    fn is_available(&self) -> bool {
+        true
+    }
+
+    fn endpoint(&self) -> &str {
+        "this machine"
+    }
+
+    fn id(&self) -> String {
+        format!("local:hash-{}", HASH_DIMS)
+    }
+}
+
+// Tags (":v1.5") and namespaces don't change the prefixes a model wants.
+fn embed_template(config: &LlmConfig) -> EmbedTemplate {
+    let model = config.embed_model.as_str();
+    let base = model.rsplit('/').next().unwrap_or(model);
+    let base = base.split(':').next().unwrap_or(base);
+    let templates = &config.embed_templates;
+    templates
+        .get(model)
+        .or_else(|| templates.get(base))
+        .cloned()
+        .unwrap_or_default()
+}
+
+/// The embedder `llm.embed_provider` names: "local" (the keyword-level
+/// `HashingEmbedder`), or any LLM provider,
+/// reached at `llm.embed_endpoint` if set.
+pub fn make_embedder(config: &LlmConfig) -> Result<Box<dyn Embedder>, Box<dyn std::error::Error>> {
+    let Some(provider) = embed_provider(config)? else {
+        return Ok(Box::new(HashingEmbedder));
+    };
+    Ok(Box::new(ModelEmbedder {
+        provider,
+        template: embed_template(config),
+    }))
+}
+
+/// The provider embeddings come from; none when they're computed locally.
+pub fn embed_provider(
+    config: &LlmConfig,
+) -> Result<Option<Box<dyn LlmProvider>>, Box<dyn std::error::Error>> {
+    let name = config.embed_provider.as_str();
+    if name == "local" {
+        return Ok(None);
+    }
+    let provider = match &config.embed_endpoint {
+        Some(endpoint) => {
+            let config = LlmConfig {
+                endpoint: endpoint.clone(),
+                endpoints: Vec::new(),
+                ..config.clone()
+            };
+            make_named(name, &config)?
+        }
+        None => make_named(name, config)?,
+    };
+    Ok(Some(provider))
+}
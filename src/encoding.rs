@@ -0,0 +1,43 @@
+// Command output is bytes. Keep them as bytes wherever they might be fed
+// back into a command, and only turn them into text for the screen.
+
+use std::env;
+
+// Single-byte charsets where every byte maps straight to a code point.
+const LATIN1: &[&str] = &[
+    "iso-8859-1",
+    "iso8859-1",
+    "latin1",
+    "iso-8859-15",
+    "iso8859-15",
+];
+
+fn locale_charset() -> Option<String> {
+    let locale = ["LC_ALL", "LC_CTYPE", "LANG"]
+        .iter()
+        .find_map(|v| env::var(v).ok().filter(|s| !s.is_empty()))?;
+    let charset = locale.split_once('.')?.1;
+    let charset = charset.split('@').next().unwrap_or(charset);
+    Some(charset.to_lowercase())
+}
+
+/// Text for display. UTF-8 passes through; output from a Latin-1 locale is
+/// decoded as such; anything else keeps its valid parts and shows invalid
+/// bytes as `\xNN`, so a mangled filename is still recognisable.
+pub fn decode(bytes: &[u8]) -> String {
+    if let Ok(text) = std::str::from_utf8(bytes) {
+        return text.to_string();
+    }
+    if locale_charset().is_some_and(|c| LATIN1.contains(&c.as_str())) {
+        return bytes.iter().map(|&b| b as char).collect();
+    }
+
+    let mut text = String::with_capacity(bytes.len());
+    for chunk in bytes.utf8_chunks() {
+        text.push_str(chunk.valid());
+        for b in chunk.invalid() {
+            text.push_str(&format!("\\x{:02X}", b));
+        }
+    }
+    text
+}
@@ -1,22 +1,181 @@
-use std::process::{Command, Stdio};
+use crate::encoding::decode;
+use crate::index::find_in_path;
+use crate::jobs;
+use crate::pipe;
+use crate::shell::{split_pipelines, split_segments};
+use crate::spill;
+use crate::terminal::TerminalGuard;
+use crate::types::ExecutionResult;
+use rusqlite::Connection;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+use std::time::Instant;
 
+// Opens a spill file for appending, and where this step's part of it starts.
+fn append(path: &Path) -> Result<(File, u64), Box<dyn std::error::Error>> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let start = file.metadata()?.len();
+    Ok((file, start))
+}
+
+fn read_from(path: &Path, start: u64) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let mut file = File::open(path)?;
+    file.seek(SeekFrom::Start(start))?;
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+// Exit statuses that mean something other than an error, by program.
+const BENIGN: &[(&[&str], i32, &str)] = &[
+    (
+        &["grep", "egrep", "fgrep", "rg", "ag"],
+        1,
+        "nothing matched",
+    ),
+    (&["diff", "cmp"], 1, "the inputs differ"),
+];
+// A stage killed by SIGPIPE only stopped because a later one stopped
+// reading, as `| head` does.
+const SIGPIPE_STATUS: i32 = 128 + libc::SIGPIPE;
+
+// The stages of `cmd` when it is one pipeline of several.
+fn stages(cmd: &str) -> Option<Vec<String>> {
+    let stages = split_segments(cmd);
+    (split_pipelines(cmd).len() == 1 && stages.len() > 1).then_some(stages)
+}
+
+// The shell for `cmd`. With `pipefail`, a pipeline fails when any stage
+// does, not only the last; a single pipeline goes to bash, if there is one,
+// which leaves each stage's status in `stages_file`.
+fn shell(cmd: &str, pipefail: bool, stages_file: &Path) -> Command {
+    if !pipefail {
+        let mut command = Command::new("sh");
+        command.arg("-c").arg(cmd);
+        return command;
+    }
+    if stages(cmd).is_some() && find_in_path("bash").is_some() {
+        let script = format!(
+            "set -o pipefail\n{}\n__pls_status=$? __pls_stages=\"${{PIPESTATUS[*]}}\"\n\
+             printf '%s\\n' \"$__pls_stages\" > \"$PLS_STAGES_FILE\"\nexit $__pls_status",
+            cmd
+        );
+        let mut command = Command::new("bash");
+        command
+            .arg("-c")
+            .arg(script)
+            .env("PLS_STAGES_FILE", stages_file);
+        return command;
+    }
+    // `set` failing would end the shell, so a shell without pipefail is
+    // asked first.
+    let mut command = Command::new("sh");
+    command.arg("-c").arg(format!(
+        "(set -o pipefail) 2>/dev/null && set -o pipefail\n{}",
+        cmd
+    ));
+    command
+}
+
+struct Ran {
+    output: Output,
+    // Each pipeline stage's status, when the shell reported them.
+    statuses: Option<Vec<i32>>,
+}
+
+// Output goes to the spill files rather than pipes, so it outlives pls.
+fn run(
+    cmd: &str,
+    pipefail: bool,
+    jobs_db: &Connection,
+    spill: &Path,
+) -> Result<Ran, Box<dyn std::error::Error>> {
+    let _guard = TerminalGuard::new();
+    let stderr_path = spill::stderr_path(spill);
+    let stages_path = spill::stages_path(spill);
+    fs::remove_file(&stages_path).ok();
+    let (out, out_start) = append(spill)?;
+    let (err, err_start) = append(&stderr_path)?;
+    let mut command = shell(cmd, pipefail, &stages_path);
+    command
+        .stdin(pipe::stdin())
+        .stdout(Stdio::from(out))
+        .stderr(Stdio::from(err));
+    let (mut child, _foreground) = jobs::spawn(jobs_db, &mut command, cmd)?;
+    let status = child.wait()?;
+    let statuses: Option<Vec<i32>> = fs::read_to_string(&stages_path)
+        .ok()
+        .and_then(|s| s.split_whitespace().map(|n| n.parse().ok()).collect());
+    let output = Output {
+        status,
+        stdout: read_from(spill, out_start)?,
+        stderr: read_from(&stderr_path, err_start)?,
+    };
+    Ok(Ran { output, statuses })
+}
+
+// The stages of `cmd` that failed, given their statuses, not counting
+// those SIGPIPE ended before the last.
+fn failed_stages(statuses: &[i32]) -> Vec<usize> {
+    (0..statuses.len())
+        .filter(|&i| {
+            let last = i + 1 == statuses.len();
+            statuses[i] != 0 && (last || statuses[i] != SIGPIPE_STATUS)
+        })
+        .collect()
+}
+
+// "stage 1 of 3, `grep -r foo logs`, exited 1 (nothing matched)".
+fn describe_stage(stage: &str, index: usize, total: usize, status: i32) -> String {
+    let program = stage.split_whitespace().next().unwrap_or_default();
+    let program = program.rsplit('/').next().unwrap_or(program);
+    let meaning = BENIGN
+        .iter()
+        .find(|(names, code, _)| names.contains(&program) && *code == status)
+        .map_or(String::new(), |(_, _, meaning)| format!(" ({})", meaning));
+    format!(
+        "stage {} of {}, `{}`, exited {}{}",
+        index + 1,
+        total,
+        stage,
+        status,
+        meaning
+    )
+}
+
+/// Runs the steps in order. `verify` holds an optional check per step, run
+/// right after that step succeeds; a failing check stops the rest like a
+/// failing step would. Output is spilled to `spill` and the file beside it
+/// as it comes. With `pipefail`, a pipeline fails when any of its stages
+/// does, and the result says which.
 pub fn execute_commands(
     commands: &[String],
+    verify: &[Option<String>],
     max_lines: usize,
-) -> Result<(bool, String), Box<dyn std::error::Error>> {
+    pipefail: bool,
+    jobs_db: &Connection,
+    spill: &Path,
+) -> Result<ExecutionResult, Box<dyn std::error::Error>> {
+    let started = Instant::now();
     let mut output_lines = Vec::new();
+    let mut raw_output = Vec::new();
     let mut all_succeeded = true;
+    let mut verified = None;
+    let mut steps = Vec::new();
+    let mut failed_stage = None;
 
-    for cmd in commands {
-        let result = Command::new("sh")
-            .arg("-c")
-            .arg(cmd)
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .output()?;
+    // Steps behave like an `&&` chain: a failure stops the rest.
+    for (i, cmd) in commands.iter().enumerate() {
+        let Ran {
+            output: result,
+            statuses,
+        } = run(cmd, pipefail, jobs_db, spill)?;
 
-        let stdout = String::from_utf8_lossy(&result.stdout);
-        let stderr = String::from_utf8_lossy(&result.stderr);
+        raw_output.extend_from_slice(&result.stdout);
+        let stdout = decode(&result.stdout);
+        let stderr = decode(&result.stderr);
 
         if !stdout.is_empty() {
             output_lines.extend(stdout.lines().map(String::from));
@@ -25,8 +184,50 @@ pub fn execute_commands(
             output_lines.extend(stderr.lines().map(String::from));
         }
 
-        if !result.status.success() {
+        let mut succeeded = result.status.success();
+        let stages = stages(cmd)
+            .zip(statuses)
+            .filter(|(s, codes)| s.len() == codes.len());
+        if let Some((stages, codes)) = stages {
+            let failed = failed_stages(&codes);
+            succeeded = failed.is_empty();
+            if let Some(&first) = failed.first() {
+                let stage = describe_stage(&stages[first], first, stages.len(), codes[first]);
+                output_lines.push(format!("[failed at {}]", stage));
+                failed_stage = Some(if commands.len() > 1 {
+                    format!("step {}, {}", i + 1, stage)
+                } else {
+                    stage
+                });
+            }
+        }
+
+        steps.push(succeeded);
+        if !succeeded {
             all_succeeded = false;
+            if i + 1 < commands.len() {
+                output_lines.push(format!(
+                    "[step {} failed; skipped the remaining {}]",
+                    i + 1,
+                    commands.len() - i - 1
+                ));
+            }
+            break;
+        }
+
+        if let Some(check) = verify.get(i).and_then(|v| v.as_deref()) {
+            let result = run(check, pipefail, jobs_db, spill)?.output;
+            if result.status.success() {
+                output_lines.push(format!("[verified step {}: {}]", i + 1, check));
+                verified.get_or_insert(true);
+            } else {
+                output_lines.extend(decode(&result.stdout).lines().map(String::from));
+                output_lines.extend(decode(&result.stderr).lines().map(String::from));
+                output_lines.push(format!("[step {} did not verify: {}]", i + 1, check));
+                verified = Some(false);
+                all_succeeded = false;
+                break;
+            }
         }
     }
 
@@ -42,5 +243,14 @@ pub fn execute_commands(
         output_lines.join("\n")
     };
 
-    Ok((all_succeeded, output))
+    Ok(ExecutionResult {
+        succeeded: all_succeeded,
+        output,
+        raw_output,
+        duration_ms: started.elapsed().as_millis() as u64,
+        changes: None,
+        verified,
+        steps,
+        failed_stage,
+    })
 }
@@ -0,0 +1,115 @@
+// Turns a plan that has been run into something another system can run:
+// Ansible tasks, GitHub Actions steps or a just recipe. Each keeps pls's
+// semantics, running the commands with sh and stopping at the first failure.
+
+pub const FORMATS: &[&str] = &["ansible", "github-actions", "justfile"];
+
+// YAML accepts JSON strings, and JSON escaping is easy to get right.
+fn yaml_string(text: &str) -> String {
+    serde_json::to_string(text).unwrap_or_default()
+}
+
+fn indented(text: &str, indent: &str) -> String {
+    text.lines()
+        .map(|line| format!("{}{}\n", indent, line))
+        .collect()
+}
+
+// Ansible runs every string through Jinja; keep literal `{{` as it is.
+fn jinja_raw(text: &str) -> String {
+    if ["{{", "{%", "{#"].iter().any(|open| text.contains(open)) {
+        format!("{{% raw %}}{}{{% endraw %}}", text)
+    } else {
+        text.to_string()
+    }
+}
+
+// One task per command: a failed task ends the play, like a failed step.
+fn ansible(query: &str, commands: &[String]) -> String {
+    let mut out = String::new();
+    for (i, cmd) in commands.iter().enumerate() {
+        let name = if commands.len() > 1 {
+            format!("{} ({}/{})", query, i + 1, commands.len())
+        } else {
+            query.to_string()
+        };
+        out.push_str(&format!("- name: {}\n", yaml_string(&jinja_raw(&name))));
+        out.push_str("  ansible.builtin.shell: |\n");
+        out.push_str(&indented(&jinja_raw(cmd), "    "));
+    }
+    out
+}
+
+// Actions expands `${{ }}` everywhere; a literal one has to come out of an
+// expression itself.
+fn no_expressions(text: &str) -> String {
+    text.replace("${{", "${{ '${{' }}")
+}
+
+// A single step; `shell: sh` runs it with `sh -e`, so the first failing
+// command fails the step.
+fn github_actions(query: &str, commands: &[String]) -> String {
+    let name = yaml_string(&no_expressions(query));
+    let mut out = format!("- name: {}\n  shell: sh\n  run: |\n", name);
+    for cmd in commands {
+        out.push_str(&indented(&no_expressions(cmd), "    "));
+    }
+    out
+}
+
+fn recipe_name(query: &str) -> String {
+    let words: Vec<String> = query
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(|w| w.to_lowercase())
+        .collect();
+    let mut name = String::new();
+    for word in words {
+        if !name.is_empty() && name.len() + word.len() >= 40 {
+            break;
+        }
+        if !name.is_empty() {
+            name.push('-');
+        }
+        name.push_str(&word);
+    }
+    if name.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        name
+    } else {
+        format!("task-{}", name).trim_end_matches('-').to_string()
+    }
+}
+
+// just runs each line in its own shell and stops at the first failure. A
+// command spanning lines needs a shebang recipe instead, which hands the
+// whole body to one shell. `{{` starts an interpolation in either, so
+// literal ones are doubled.
+fn justfile(query: &str, commands: &[String]) -> String {
+    let mut out = format!("# {}\n{}:\n", query.replace('\n', " "), recipe_name(query));
+    if commands.iter().any(|c| c.contains('\n')) {
+        out.push_str("    #!/bin/sh\n    set -e\n");
+    }
+    for cmd in commands {
+        out.push_str(&indented(&cmd.replace("{{", "{{{{"), "    "));
+    }
+    out
+}
+
+/// `commands` as a snippet in `format`, one of FORMATS.
+pub fn render(
+    format: &str,
+    query: &str,
+    commands: &[String],
+) -> Result<String, Box<dyn std::error::Error>> {
+    match format {
+        "ansible" => Ok(ansible(query, commands)),
+        "github-actions" => Ok(github_actions(query, commands)),
+        "justfile" => Ok(justfile(query, commands)),
+        _ => Err(format!(
+            "unknown format '{}' (available: {})",
+            format,
+            FORMATS.join(", ")
+        )
+        .into()),
+    }
+}
@@ -0,0 +1,83 @@
+// Durable facts about the machine ("logs live in /srv/logs", "this box uses
+// podman, not docker"). The user states them with `pls remember`, or pls
+// picks one up when an edited plan swaps exactly one tool or path for
+// another. Relevant facts go into every prompt, so the same correction
+// isn't needed twice.
+
+use crate::db::{get_facts, save_fact, set_fact_embedding};
+use crate::embed::Embedder;
+use crate::shell::command_names;
+use rusqlite::Connection;
+
+/// Saves a fact, returning its id. A fact already known is not saved again,
+/// and one that can't be embedded right now is still kept: while there are
+/// few facts every prompt gets them all, and `pls index` embeds it later.
+pub fn add(
+    embedder: &dyn Embedder,
+    conn: &Connection,
+    text: &str,
+    source: &str,
+) -> Result<Option<i64>, Box<dyn std::error::Error>> {
+    let text = text.trim();
+    if get_facts(conn)?
+        .iter()
+        .any(|f| f.text.eq_ignore_ascii_case(text))
+    {
+        return Ok(None);
+    }
+    let embedding = embedder.embed(text).unwrap_or_default();
+    Ok(Some(save_fact(conn, text, source, &embedding)?))
+}
+
+/// Re-embeds every fact, for when the index was rebuilt with a different
+/// embedder. Returns how many were updated.
+pub fn reembed(
+    embedder: &dyn Embedder,
+    conn: &Connection,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let mut updated = 0;
+    for fact in get_facts(conn)? {
+        if let Ok(embedding) = embedder.embed(&fact.text) {
+            set_fact_embedding(conn, fact.id, &embedding)?;
+            updated += 1;
+        }
+    }
+    Ok(updated)
+}
+
+// The one item only in `after` and the one only in `before`, if exactly one
+// was swapped.
+fn swapped(before: &[String], after: &[String]) -> Option<(String, String)> {
+    let mut removed: Vec<&String> = before.iter().filter(|b| !after.contains(b)).collect();
+    let mut added: Vec<&String> = after.iter().filter(|a| !before.contains(a)).collect();
+    removed.sort();
+    removed.dedup();
+    added.sort();
+    added.dedup();
+    match (removed.as_slice(), added.as_slice()) {
+        ([old], [new]) => Some(((*new).clone(), (*old).clone())),
+        _ => None,
+    }
+}
+
+fn absolute_paths(cmd: &str) -> Vec<String> {
+    cmd.split_whitespace()
+        .map(|t| t.trim_matches(|c| c == '"' || c == '\''))
+        .filter(|t| t.len() > 1 && (t.starts_with('/') || t.starts_with("~/")))
+        .map(|t| t.trim_end_matches('/').to_string())
+        .collect()
+}
+
+/// A fact the user's edit of `before` into `after` implies, if it swapped
+/// one program or one absolute path for another and changed nothing else of
+/// the kind. Anything broader is a change of plan, not a correction.
+pub fn infer(before: &[String], after: &str) -> Option<String> {
+    let before = before.join(" && ");
+    let tools = swapped(&command_names(&before), &command_names(after));
+    let paths = swapped(&absolute_paths(&before), &absolute_paths(after));
+    match (tools, paths) {
+        (Some((new, old)), None) => Some(format!("use {} instead of {}", new, old)),
+        (None, Some((new, old))) => Some(format!("use the path {} instead of {}", new, old)),
+        _ => None,
+    }
+}
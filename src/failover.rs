@@ -0,0 +1,160 @@
+// One provider spread over several servers (`llm.endpoint` plus
+// `llm.endpoints`). Each run starts at a different server, so a team's
+// queries share out between them, and when the one in use stops answering,
+// a request moves on to the next that does. Errors from a server that is
+// still up, a bad reply say, are returned as they are.
+
+use crate::http::Cancelled;
+use crate::llm::{Capabilities, LlmProvider, ModelInfo};
+use crate::types::Usage;
+use std::error::Error;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+pub struct Failover {
+    backends: Vec<Box<dyn LlmProvider>>,
+    current: AtomicUsize,
+}
+
+impl Failover {
+    /// `backends` must not be empty.
+    pub fn new(backends: Vec<Box<dyn LlmProvider>>) -> Self {
+        // Runs share nothing, so the process id stands in for a counter.
+        let start = std::process::id() as usize % backends.len();
+        Self {
+            backends,
+            current: AtomicUsize::new(start),
+        }
+    }
+
+    fn current(&self) -> &dyn LlmProvider {
+        self.backends[self.current.load(Ordering::Relaxed)].as_ref()
+    }
+
+    // The server in use if it answers, else the next one round that does,
+    // which then becomes the one in use.
+    fn reachable(&self) -> Option<&dyn LlmProvider> {
+        let n = self.backends.len();
+        let start = self.current.load(Ordering::Relaxed);
+        (0..n).map(|i| (start + i) % n).find_map(|i| {
+            let backend = self.backends[i].as_ref();
+            if backend.is_available() {
+                self.current.store(i, Ordering::Relaxed);
+                Some(backend)
+            } else {
+                None
+            }
+        })
+    }
+
+    // Runs `f` on the server in use. If it fails and that server has gone
+    // away, runs it again on the next reachable one.
+    fn with_failover<T>(
+        &self,
+        mut f: impl FnMut(&dyn LlmProvider) -> Result<T, Box<dyn Error>>,
+    ) -> Result<T, Box<dyn Error>> {
+        let first = self.current.load(Ordering::Relaxed);
+        let result = f(self.current());
+        match result {
+            Err(e) if !e.is::<Cancelled>() && !self.current().is_available() => {
+                match self.reachable() {
+                    Some(backend) if self.current.load(Ordering::Relaxed) != first => f(backend),
+                    _ => Err(e),
+                }
+            }
+            result => result,
+        }
+    }
+}
+
+impl LlmProvider for Failover {
+    fn name(&self) -> &str {
+        self.current().name()
+    }
+
+    fn model(&self) -> &str {
+        self.current().model()
+    }
+
+    fn embed_model(&self) -> &str {
+        self.current().embed_model()
+    }
+
+    fn endpoint(&self) -> &str {
+        self.current().endpoint()
+    }
+
+    fn is_available(&self) -> bool {
+        self.reachable().is_some()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.current().capabilities()
+    }
+
+    fn generate(&self, prompt: &str) -> Result<String, Box<dyn Error>> {
+        self.with_failover(|b| b.generate(prompt))
+    }
+
+    fn generate_json(&self, prompt: &str) -> Result<String, Box<dyn Error>> {
+        self.with_failover(|b| b.generate_json(prompt))
+    }
+
+    fn stream_json(
+        &self,
+        prompt: &str,
+        on_chunk: &mut dyn FnMut(&str),
+    ) -> Result<String, Box<dyn Error>> {
+        self.with_failover(|b| b.stream_json(prompt, on_chunk))
+    }
+
+    fn stream_chat(
+        &self,
+        system: &str,
+        user: &str,
+        schema: &serde_json::Value,
+        on_chunk: &mut dyn FnMut(&str),
+    ) -> Result<String, Box<dyn Error>> {
+        self.with_failover(|b| b.stream_chat(system, user, schema, on_chunk))
+    }
+
+    fn embed(&self, text: &str) -> Result<Vec<f32>, Box<dyn Error>> {
+        self.with_failover(|b| b.embed(text))
+    }
+
+    fn embed_batch(&self, texts: &[String]) -> Vec<Result<Vec<f32>, Box<dyn Error>>> {
+        let results = self.current().embed_batch(texts);
+        if results.iter().all(Result::is_ok) || self.current().is_available() {
+            return results;
+        }
+        let first = self.current.load(Ordering::Relaxed);
+        match self.reachable() {
+            Some(backend) if self.current.load(Ordering::Relaxed) != first => {
+                backend.embed_batch(texts)
+            }
+            _ => results,
+        }
+    }
+
+    fn warm(&self) -> Result<(), Box<dyn Error>> {
+        self.with_failover(|b| b.warm())
+    }
+
+    fn context_window(&self) -> Option<usize> {
+        self.current().context_window()
+    }
+
+    fn list_models(&self) -> Result<Vec<ModelInfo>, Box<dyn Error>> {
+        self.with_failover(|b| b.list_models())
+    }
+
+    fn take_usage(&self) -> Usage {
+        let mut total = Usage::default();
+        for backend in &self.backends {
+            let usage = backend.take_usage();
+            total.prompt_tokens += usage.prompt_tokens;
+            total.completion_tokens += usage.completion_tokens;
+            total.latency_ms += usage.latency_ms;
+        }
+        total
+    }
+}
@@ -0,0 +1,84 @@
+// Character-level fuzzy matching, scored the way skim's v2 matcher does:
+// the pattern's characters have to appear in order, matches in a row and
+// matches at the start of a word score higher, and opening a gap costs more
+// than making one longer. Retrieval uses it to find tools a query names,
+// misspelled or not, when embeddings don't.
+
+const SCORE_MATCH: i32 = 16;
+const GAP_START: i32 = -3;
+const GAP_EXTENSION: i32 = -1;
+const BONUS_BOUNDARY: i32 = 8;
+const BONUS_CONSECUTIVE: i32 = 4;
+const BONUS_FIRST_CHAR_MULTIPLIER: i32 = 2;
+
+// Far enough below any real score that adding gap costs can't overflow.
+const NONE: i32 = i32::MIN / 2;
+
+// Matches right after a separator, or at a lower-to-upper case change.
+fn bonus(text: &[char], j: usize) -> i32 {
+    let Some(prev) = j.checked_sub(1).map(|i| text[i]) else {
+        return BONUS_BOUNDARY;
+    };
+    if !prev.is_alphanumeric() {
+        BONUS_BOUNDARY
+    } else if prev.is_lowercase() && text[j].is_uppercase() {
+        BONUS_BOUNDARY - 1
+    } else {
+        0
+    }
+}
+
+fn fold(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+/// The best score for `pattern` in `text`, ignoring case, or None if its
+/// characters don't all appear there in order.
+pub fn score(pattern: &str, text: &str) -> Option<i32> {
+    let pattern: Vec<char> = pattern.chars().map(fold).collect();
+    let original: Vec<char> = text.chars().collect();
+    let folded: Vec<char> = original.iter().map(|&c| fold(c)).collect();
+    if pattern.is_empty() || pattern.len() > folded.len() {
+        return None;
+    }
+
+    // prev[j]: the best score for the pattern so far with its last
+    // character matched at j.
+    let mut prev = vec![NONE; folded.len()];
+    for (i, &p) in pattern.iter().enumerate() {
+        let mut row = vec![NONE; folded.len()];
+        // The best way to reach j after skipping at least one character.
+        let mut gap = NONE;
+        for j in 0..folded.len() {
+            if j >= 2 {
+                gap = (gap + GAP_EXTENSION).max(prev[j - 2] + GAP_START);
+            }
+            if folded[j] != p {
+                continue;
+            }
+            let bonus = bonus(&original, j);
+            if i == 0 {
+                row[j] = SCORE_MATCH + bonus * BONUS_FIRST_CHAR_MULTIPLIER;
+                continue;
+            }
+            let before = match j.checked_sub(1) {
+                Some(k) => (prev[k] + BONUS_CONSECUTIVE).max(gap),
+                None => NONE,
+            };
+            if before > NONE / 2 {
+                row[j] = SCORE_MATCH + bonus + before;
+            }
+        }
+        prev = row;
+    }
+    prev.into_iter().filter(|&s| s > NONE / 2).max()
+}
+
+/// How closely `term` spells `name`: 1.0 for the name itself, less the more
+/// of it `term` leaves out or scatters, 0.0 if it doesn't match at all.
+pub fn similarity(term: &str, name: &str) -> f32 {
+    match (score(term, name), score(name, name)) {
+        (Some(found), Some(best)) if best > 0 => (found as f32 / best as f32).clamp(0.0, 1.0),
+        _ => 0.0,
+    }
+}
@@ -0,0 +1,137 @@
+// Housekeeping for the state and data directories. History, the raw output
+// kept for `pls output`, token usage and tool outcomes grow with every query;
+// `pls gc` drops what is past its `[gc]` retention (file backups kept for
+// `pls undo` go with the raw output), and cached plans past their TTL, and
+// compacts the databases. Notes and facts are the user's and
+// are never touched.
+
+use crate::clock::now;
+use crate::config::{Config, GcConfig};
+use crate::db::{get_db_path, get_history_path, open_history};
+use crate::edits;
+use crate::jobs;
+use crate::paths::state_dir;
+use crate::plancache;
+use rusqlite::{params, Connection};
+use std::{fs, path::Path, time::SystemTime};
+
+const STAMP: &str = "gc.stamp";
+const DAY: i64 = 24 * 60 * 60;
+
+#[derive(Debug, Default)]
+pub struct Report {
+    pub history: usize,
+    pub outputs: usize,
+    pub usage: usize,
+    pub tool_runs: usize,
+    pub plans: usize,
+    pub backups: usize,
+    pub bytes_before: u64,
+    pub bytes_after: u64,
+}
+
+impl Report {
+    pub fn reclaimed(&self) -> u64 {
+        self.bytes_before.saturating_sub(self.bytes_after)
+    }
+}
+
+// A database and the journal files SQLite keeps beside it.
+fn db_size(path: &Path) -> u64 {
+    ["", "-wal", "-shm", "-journal"]
+        .iter()
+        .filter_map(|suffix| {
+            let mut name = path.as_os_str().to_owned();
+            name.push(suffix);
+            fs::metadata(name).ok()
+        })
+        .map(|m| m.len())
+        .sum()
+}
+
+fn sizes(config: &Config) -> u64 {
+    let mut total = db_size(&get_history_path(&config.paths));
+    // A shared index is whoever owns it's to compact.
+    if config.paths.shared_index.is_none() {
+        total += db_size(&get_db_path(&config.paths));
+    }
+    total
+}
+
+// Rows older than `days`; 0 keeps everything.
+fn prune(conn: &Connection, sql: &str, days: u32) -> Result<usize, Box<dyn std::error::Error>> {
+    if days == 0 {
+        return Ok(0);
+    }
+    Ok(conn.execute(sql, params![now() - days as i64 * DAY])?)
+}
+
+fn vacuum(path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    if path.exists() {
+        Connection::open(path)?.execute_batch("VACUUM")?;
+    }
+    Ok(())
+}
+
+/// Applies the retention limits, forgets jobs that have exited and
+/// compacts both databases.
+pub fn run(config: &Config) -> Result<Report, Box<dyn std::error::Error>> {
+    let limits: &GcConfig = &config.gc;
+    let mut report = Report {
+        bytes_before: sizes(config),
+        ..Report::default()
+    };
+
+    let conn = open_history(&config.paths)?;
+    // Stamped first, so queries arriving meanwhile don't start another run.
+    let stamp = state_dir(&config.paths).join(STAMP);
+    fs::write(stamp, now().to_string()).ok();
+    report.history = prune(
+        &conn,
+        "DELETE FROM history WHERE timestamp < ?1",
+        limits.history_days,
+    )?;
+    report.outputs = prune(
+        &conn,
+        "UPDATE history SET output_raw = NULL, raw_reply = NULL
+         WHERE (output_raw IS NOT NULL OR raw_reply IS NOT NULL) AND timestamp < ?1",
+        limits.output_days,
+    )?;
+    report.usage = prune(
+        &conn,
+        "DELETE FROM usage WHERE timestamp < ?1",
+        limits.usage_days,
+    )?;
+    report.tool_runs = prune(
+        &conn,
+        "DELETE FROM tool_runs WHERE timestamp < ?1",
+        limits.history_days,
+    )?;
+    report.plans = plancache::prune(&conn, &config.cache)?;
+    report.backups = edits::prune(&config.paths, limits.output_days);
+    jobs::prune(&conn)?;
+    drop(conn);
+
+    vacuum(&get_history_path(&config.paths))?;
+    if config.paths.shared_index.is_none() {
+        vacuum(&get_db_path(&config.paths))?;
+    }
+    report.bytes_after = sizes(config);
+    Ok(report)
+}
+
+/// Whether `gc.auto_days` has passed since the last run. Never, when that
+/// is 0.
+pub fn is_due(config: &Config) -> bool {
+    let days = config.gc.auto_days;
+    if days == 0 {
+        return false;
+    }
+    let stamp = state_dir(&config.paths).join(STAMP);
+    let last = fs::metadata(stamp).and_then(|m| m.modified());
+    match last.map(|t| SystemTime::now().duration_since(t)) {
+        Ok(Ok(age)) => age.as_secs() as i64 >= days as i64 * DAY,
+        Ok(Err(_)) => false,
+        Err(_) => true,
+    }
+}
@@ -0,0 +1,453 @@
+// Google Gemini through the Generative Language API. `endpoint` is
+// https://generativelanguage.googleapis.com and `model` and `embed_model`
+// are model names such as "gemini-2.5-flash" and "gemini-embedding-001".
+// Plans are held to the reply schema via responseSchema, which takes an
+// OpenAPI-style subset of JSON Schema; `response_schema` does the mapping.
+
+use crate::config::LlmConfig;
+use crate::http::{self, block_on, for_each_line, interruptible, send_with_retries};
+use crate::llm::{Capabilities, LlmProvider, ModelInfo};
+use crate::types::Usage;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{env, sync::Mutex, time::Instant};
+
+const API_PATH: &str = "/v1beta";
+// Thinking models spend part of this before the reply starts.
+const MAX_TOKENS: u32 = 8192;
+
+#[derive(Serialize)]
+struct Part {
+    text: String,
+}
+
+#[derive(Serialize)]
+struct Content {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    role: Option<&'static str>,
+    parts: Vec<Part>,
+}
+
+impl Content {
+    fn text(role: Option<&'static str>, text: &str) -> Self {
+        Self {
+            role,
+            parts: vec![Part {
+                text: text.to_string(),
+            }],
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GenerationConfig {
+    max_output_tokens: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_mime_type: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_schema: Option<Value>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GenerateRequest {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system_instruction: Option<Content>,
+    contents: Vec<Content>,
+    generation_config: GenerationConfig,
+}
+
+#[derive(Serialize)]
+struct EmbedRequest {
+    model: String,
+    content: Content,
+}
+
+#[derive(Serialize)]
+struct BatchEmbedRequest {
+    requests: Vec<EmbedRequest>,
+}
+
+#[derive(Deserialize)]
+struct Embedding {
+    values: Vec<f32>,
+}
+
+#[derive(Deserialize)]
+struct EmbedResponse {
+    embedding: Embedding,
+}
+
+#[derive(Deserialize)]
+struct BatchEmbedResponse {
+    embeddings: Vec<Embedding>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ApiModel {
+    name: String,
+    #[serde(default)]
+    display_name: String,
+    #[serde(default)]
+    input_token_limit: u64,
+    #[serde(default)]
+    supported_generation_methods: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct ModelList {
+    #[serde(default)]
+    models: Vec<ApiModel>,
+}
+
+#[derive(Deserialize)]
+struct ApiError {
+    error: ApiErrorBody,
+}
+
+#[derive(Deserialize)]
+struct ApiErrorBody {
+    message: String,
+}
+
+/// `schema` in the form responseSchema takes: upper-case type names, and
+/// only the keywords Gemini knows. Others would fail the request.
+fn response_schema(schema: &Value) -> Value {
+    let Some(object) = schema.as_object() else {
+        return schema.clone();
+    };
+    let mut out = serde_json::Map::new();
+    for (key, value) in object {
+        let mapped = match key.as_str() {
+            "type" => json!(value.as_str().unwrap_or_default().to_uppercase()),
+            "items" => response_schema(value),
+            "anyOf" => Value::Array(
+                value
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .map(response_schema)
+                    .collect(),
+            ),
+            "properties" => Value::Object(
+                value
+                    .as_object()
+                    .into_iter()
+                    .flatten()
+                    .map(|(name, schema)| (name.clone(), response_schema(schema)))
+                    .collect(),
+            ),
+            "required" | "enum" | "description" | "nullable" | "format" => value.clone(),
+            _ => continue,
+        };
+        out.insert(key.clone(), mapped);
+    }
+    Value::Object(out)
+}
+
+// The text of the first candidate, or why there is none.
+fn reply_text(response: &Value) -> Result<String, Box<dyn std::error::Error>> {
+    if let Some(reason) = response["promptFeedback"]["blockReason"].as_str() {
+        return Err(format!("gemini: prompt blocked ({})", reason).into());
+    }
+    let candidate = &response["candidates"][0];
+    if let Some(reason) = candidate["finishReason"].as_str() {
+        if matches!(
+            reason,
+            "SAFETY" | "RECITATION" | "PROHIBITED_CONTENT" | "BLOCKLIST"
+        ) {
+            return Err(format!("gemini: reply blocked ({})", reason).into());
+        }
+    }
+    Ok(candidate["content"]["parts"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        // Thought summaries aren't part of the reply.
+        .filter(|part| !part["thought"].as_bool().unwrap_or(false))
+        .filter_map(|part| part["text"].as_str())
+        .collect())
+}
+
+pub struct GeminiClient {
+    base_url: String,
+    model: String,
+    embed_model: String,
+    api_key: Option<String>,
+    client: reqwest::Client,
+    retries: u32,
+    usage: Mutex<Usage>,
+}
+
+impl GeminiClient {
+    pub fn new(config: &LlmConfig) -> Result<Self, Box<dyn std::error::Error>> {
+        let api_key = config
+            .api_key
+            .clone()
+            .or_else(|| env::var("GEMINI_API_KEY").ok())
+            .or_else(|| env::var("GOOGLE_API_KEY").ok())
+            .filter(|k| !k.is_empty());
+        let name = |model: &str| model.trim_start_matches("models/").to_string();
+        Ok(Self {
+            base_url: config.endpoint.trim_end_matches('/').to_string(),
+            model: name(&config.model),
+            embed_model: name(&config.embed_model),
+            api_key,
+            client: http::builder(config)?.build()?,
+            retries: config.retries,
+            usage: Mutex::new(Usage::default()),
+        })
+    }
+
+    fn request(&self, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
+        self.client
+            .request(method, format!("{}{}{}", self.base_url, API_PATH, path))
+            .header(
+                "x-goog-api-key",
+                self.api_key.as_deref().unwrap_or_default(),
+            )
+    }
+
+    async fn post(
+        &self,
+        path: &str,
+        body: &impl Serialize,
+    ) -> Result<reqwest::Response, Box<dyn std::error::Error>> {
+        if self.api_key.is_none() {
+            return Err("no Gemini API key; set GEMINI_API_KEY or llm.api_key".into());
+        }
+        let request = self.request(reqwest::Method::POST, path).json(body);
+        let resp = send_with_retries(request, self.retries).await?;
+        let status = resp.status();
+        if !status.is_success() {
+            let message = resp
+                .json::<ApiError>()
+                .await
+                .map(|e| e.error.message)
+                .unwrap_or_default();
+            return Err(format!("gemini: {} {}", status, message).into());
+        }
+        Ok(resp)
+    }
+
+    fn generate_request(
+        &self,
+        system: Option<&str>,
+        prompt: &str,
+        json: bool,
+        schema: Option<&Value>,
+    ) -> GenerateRequest {
+        GenerateRequest {
+            system_instruction: system.map(|s| Content::text(None, s)),
+            contents: vec![Content::text(Some("user"), prompt)],
+            generation_config: GenerationConfig {
+                max_output_tokens: MAX_TOKENS,
+                response_mime_type: json.then_some("application/json"),
+                response_schema: schema.map(response_schema),
+            },
+        }
+    }
+
+    fn complete(&self, body: GenerateRequest) -> Result<String, Box<dyn std::error::Error>> {
+        let started = Instant::now();
+        let path = format!("/models/{}:generateContent", self.model);
+        let resp: Value = interruptible(async {
+            let resp = self.post(&path, &body).await?;
+            Ok::<_, Box<dyn std::error::Error>>(resp.json().await?)
+        })?;
+        self.count_latency(started);
+        self.count_usage(&resp["usageMetadata"]);
+        reply_text(&resp)
+    }
+
+    // Server-sent events, each a whole response with the next piece of
+    // text. The token counts are running totals, so only the last counts.
+    fn stream(
+        &self,
+        body: GenerateRequest,
+        on_chunk: &mut dyn FnMut(&str),
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        let mut reply = String::new();
+        let mut usage = Value::Null;
+        let started = Instant::now();
+        let path = format!("/models/{}:streamGenerateContent?alt=sse", self.model);
+        interruptible(async {
+            let resp = self.post(&path, &body).await?;
+            for_each_line(resp, |line| {
+                let Some(data) = line.strip_prefix("data:") else {
+                    return Ok(true);
+                };
+                let event: Value = serde_json::from_str(data.trim())?;
+                let text = reply_text(&event)?;
+                if !text.is_empty() {
+                    on_chunk(&text);
+                    reply.push_str(&text);
+                }
+                if event["usageMetadata"].is_object() {
+                    usage = event["usageMetadata"].clone();
+                }
+                Ok(true)
+            })
+            .await
+        })?;
+        self.count_latency(started);
+        self.count_usage(&usage);
+        Ok(reply)
+    }
+
+    fn count_latency(&self, started: Instant) {
+        if let Ok(mut usage) = self.usage.lock() {
+            usage.latency_ms += started.elapsed().as_millis() as u64;
+        }
+    }
+
+    // Thinking tokens are billed as output.
+    fn count_usage(&self, metadata: &Value) {
+        let count = |key: &str| metadata[key].as_u64().unwrap_or(0);
+        if let Ok(mut usage) = self.usage.lock() {
+            usage.prompt_tokens += count("promptTokenCount");
+            usage.completion_tokens += count("candidatesTokenCount") + count("thoughtsTokenCount");
+        }
+    }
+
+    fn embed_request(&self, text: &str) -> EmbedRequest {
+        EmbedRequest {
+            model: format!("models/{}", self.embed_model),
+            content: Content::text(None, text),
+        }
+    }
+}
+
+impl LlmProvider for GeminiClient {
+    fn name(&self) -> &str {
+        "gemini"
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn embed_model(&self) -> &str {
+        &self.embed_model
+    }
+
+    fn endpoint(&self) -> &str {
+        &self.base_url
+    }
+
+    // Reachability only; a missing or rejected key is reported by generate.
+    fn is_available(&self) -> bool {
+        block_on(async { self.request(reqwest::Method::GET, "/models").send().await }).is_ok()
+    }
+
+    // Names come back as "models/gemini-2.0-flash"; the config leaves off
+    // the prefix.
+    fn list_models(&self) -> Result<Vec<ModelInfo>, Box<dyn std::error::Error>> {
+        let list = block_on(async {
+            let request = self.request(reqwest::Method::GET, "/models?pageSize=1000");
+            request
+                .send()
+                .await?
+                .error_for_status()?
+                .json::<ModelList>()
+                .await
+        })?;
+        let mut models: Vec<ModelInfo> = list
+            .models
+            .into_iter()
+            .map(|m| {
+                let mut details = vec![m.display_name];
+                if m.input_token_limit > 0 {
+                    details.push(format!("{}k context", m.input_token_limit / 1024));
+                }
+                if m.supported_generation_methods
+                    .iter()
+                    .any(|s| s == "embedContent")
+                {
+                    details.push("embeddings".into());
+                }
+                ModelInfo {
+                    name: m.name.trim_start_matches("models/").to_string(),
+                    size: 0,
+                    details: details.join(" "),
+                }
+            })
+            .collect();
+        models.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(models)
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            json_mode: true,
+            json_schema: true,
+            streaming: true,
+            chat: true,
+            embeddings: true,
+            ..Capabilities::default()
+        }
+    }
+
+    fn generate(&self, prompt: &str) -> Result<String, Box<dyn std::error::Error>> {
+        self.complete(self.generate_request(None, prompt, false, None))
+    }
+
+    fn generate_json(&self, prompt: &str) -> Result<String, Box<dyn std::error::Error>> {
+        self.complete(self.generate_request(None, prompt, true, None))
+    }
+
+    fn stream_json(
+        &self,
+        prompt: &str,
+        on_chunk: &mut dyn FnMut(&str),
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        self.stream(self.generate_request(None, prompt, true, None), on_chunk)
+    }
+
+    fn stream_chat(
+        &self,
+        system: &str,
+        user: &str,
+        schema: &Value,
+        on_chunk: &mut dyn FnMut(&str),
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        let body = self.generate_request(Some(system), user, true, Some(schema));
+        self.stream(body, on_chunk)
+    }
+
+    fn embed(&self, text: &str) -> Result<Vec<f32>, Box<dyn std::error::Error>> {
+        let path = format!("/models/{}:embedContent", self.embed_model);
+        let body = self.embed_request(text);
+        let resp: EmbedResponse = block_on(async {
+            let resp = self.post(&path, &body).await?;
+            Ok::<_, Box<dyn std::error::Error>>(resp.json().await?)
+        })?;
+        Ok(resp.embedding.values)
+    }
+
+    fn embed_batch(&self, texts: &[String]) -> Vec<Result<Vec<f32>, Box<dyn std::error::Error>>> {
+        let path = format!("/models/{}:batchEmbedContents", self.embed_model);
+        let body = BatchEmbedRequest {
+            requests: texts.iter().map(|t| self.embed_request(t)).collect(),
+        };
+        let batch: Result<BatchEmbedResponse, Box<dyn std::error::Error>> = block_on(async {
+            let resp = self.post(&path, &body).await?;
+            Ok(resp.json().await?)
+        });
+        match batch {
+            Ok(batch) if batch.embeddings.len() == texts.len() => {
+                batch.embeddings.into_iter().map(|e| Ok(e.values)).collect()
+            }
+            _ => texts.iter().map(|t| self.embed(t)).collect(),
+        }
+    }
+
+    fn take_usage(&self) -> Usage {
+        self.usage
+            .lock()
+            .map(|mut u| std::mem::take(&mut *u))
+            .unwrap_or_default()
+    }
+}
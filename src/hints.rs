@@ -0,0 +1,162 @@
+// Tools the current directory suggests: a Dockerfile hints at docker, a pile
+// of .csv files at awk and csvkit. Only the top level is looked at, and the
+// result is cached per directory until its mtime changes.
+
+use std::{collections::HashMap, env, fs, path::Path, time::UNIX_EPOCH};
+
+const MAX_ENTRIES: usize = 1000;
+const MAX_CACHED: usize = 64;
+const CACHE_FILE: &str = "cwd_hints.tsv";
+
+// File or directory names that mark a kind of project.
+const MARKERS: &[(&str, &[&str])] = &[
+    (".git", &["git"]),
+    ("Dockerfile", &["docker", "podman"]),
+    ("Containerfile", &["podman", "docker"]),
+    ("docker-compose.yml", &["docker-compose", "docker"]),
+    ("compose.yaml", &["docker-compose", "docker"]),
+    ("Cargo.toml", &["cargo", "rustc"]),
+    ("package.json", &["npm", "node", "yarn", "pnpm"]),
+    ("go.mod", &["go"]),
+    ("pyproject.toml", &["python3", "pip", "uv", "poetry"]),
+    ("requirements.txt", &["python3", "pip"]),
+    ("Gemfile", &["bundle", "ruby"]),
+    ("Makefile", &["make"]),
+    ("CMakeLists.txt", &["cmake", "make"]),
+    ("meson.build", &["meson", "ninja"]),
+    ("flake.nix", &["nix"]),
+    ("Chart.yaml", &["helm"]),
+    ("kustomization.yaml", &["kubectl", "kustomize"]),
+    ("Vagrantfile", &["vagrant"]),
+    ("ansible.cfg", &["ansible", "ansible-playbook"]),
+];
+
+// Extensions that point at a tool once enough files share them.
+const EXTENSIONS: &[(&str, &[&str])] = &[
+    ("tf", &["terraform"]),
+    ("csv", &["awk", "csvcut", "csvstat", "xsv", "mlr"]),
+    ("tsv", &["awk", "cut", "mlr"]),
+    ("json", &["jq"]),
+    ("yaml", &["yq"]),
+    ("yml", &["yq"]),
+    ("xml", &["xmllint", "xmlstarlet"]),
+    ("sql", &["sqlite3", "psql"]),
+    ("db", &["sqlite3"]),
+    ("log", &["grep", "tail", "less"]),
+    ("py", &["python3"]),
+    ("ipynb", &["jupyter"]),
+    ("md", &["pandoc"]),
+    ("pdf", &["pdftotext", "qpdf"]),
+    ("jpg", &["convert", "magick", "exiftool"]),
+    ("jpeg", &["convert", "magick", "exiftool"]),
+    ("png", &["convert", "magick", "optipng"]),
+    ("mp4", &["ffmpeg", "ffprobe"]),
+    ("mkv", &["ffmpeg", "ffprobe"]),
+    ("mp3", &["ffmpeg", "ffprobe"]),
+    ("wav", &["ffmpeg", "sox"]),
+    ("zip", &["unzip", "zip"]),
+    ("gz", &["tar", "gzip"]),
+    ("tgz", &["tar"]),
+    ("xz", &["tar", "xz"]),
+    ("zst", &["tar", "zstd"]),
+];
+
+// An extension counts once at least this many files, or this share of all
+// files, have it.
+const MIN_FILES: usize = 3;
+const MIN_SHARE: f32 = 0.25;
+
+fn scan(dir: &Path) -> Vec<String> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+
+    let mut names = Vec::new();
+    let mut extensions: HashMap<String, usize> = HashMap::new();
+    let mut files = 0;
+    for entry in entries.flatten().take(MAX_ENTRIES) {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if let Some((_, ext)) = name.rsplit_once('.') {
+            if !ext.is_empty() && !name.starts_with('.') {
+                *extensions.entry(ext.to_lowercase()).or_default() += 1;
+            }
+        }
+        if entry.file_type().is_ok_and(|t| t.is_file()) {
+            files += 1;
+        }
+        names.push(name);
+    }
+
+    let mut tools: Vec<String> = Vec::new();
+    let mut add = |list: &[&str]| {
+        for tool in list {
+            if !tools.iter().any(|t| t == tool) {
+                tools.push(tool.to_string());
+            }
+        }
+    };
+    for (marker, list) in MARKERS {
+        if names.iter().any(|n| n == marker) {
+            add(list);
+        }
+    }
+    for (ext, list) in EXTENSIONS {
+        let count = extensions.get(*ext).copied().unwrap_or(0);
+        if count >= MIN_FILES || (count > 0 && count as f32 >= files as f32 * MIN_SHARE) {
+            add(list);
+        }
+    }
+
+    tools
+}
+
+fn modified(dir: &Path) -> u64 {
+    fs::metadata(dir)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Tool names suggested by the current directory's contents. Lookups are
+/// cached in `cache_dir` keyed on the directory and its mtime, so unchanged
+/// directories cost one stat.
+pub fn cwd_hints(cache_dir: &Path) -> Vec<String> {
+    let Ok(cwd) = env::current_dir() else {
+        return Vec::new();
+    };
+    let key = cwd.to_string_lossy().to_string();
+    let mtime = modified(&cwd).to_string();
+    let cache_path = cache_dir.join(CACHE_FILE);
+
+    // One line per directory: path, mtime, comma-separated tools.
+    let cached = fs::read_to_string(&cache_path).unwrap_or_default();
+    let mut lines: Vec<&str> = cached.lines().collect();
+    for line in &lines {
+        let mut fields = line.splitn(3, '\t');
+        if fields.next() == Some(key.as_str()) && fields.next() == Some(mtime.as_str()) {
+            return fields
+                .next()
+                .unwrap_or("")
+                .split(',')
+                .filter(|t| !t.is_empty())
+                .map(String::from)
+                .collect();
+        }
+    }
+
+    let tools = scan(&cwd);
+    if key.contains(['\t', '\n']) {
+        return tools;
+    }
+    let entry = format!("{}\t{}\t{}", key, mtime, tools.join(","));
+    lines.retain(|l| l.split('\t').next() != Some(key.as_str()));
+    lines.push(&entry);
+    let keep = lines.len().saturating_sub(MAX_CACHED);
+    if fs::create_dir_all(cache_dir).is_ok() {
+        fs::write(&cache_path, lines[keep..].join("\n") + "\n").ok();
+    }
+
+    tools
+}
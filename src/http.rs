@@ -0,0 +1,235 @@
+// The HTTP client every provider talks through, so proxy and certificate
+// settings apply the same way to all of them (and to `pls doctor`), and the
+// async runtime it runs on. The rest of pls is synchronous: providers
+// `block_on` at their edges and use the runtime inside for concurrent
+// requests.
+//
+// Model calls go through `interruptible` instead, so Ctrl-C while a plan is
+// generating drops the request rather than killing pls. Dropping it closes
+// the connection, which is also what tells Ollama to stop generating.
+
+use crate::config::LlmConfig;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION};
+use reqwest::{Certificate, ClientBuilder, NoProxy, Proxy};
+use std::{
+    error::Error,
+    fmt, fs,
+    future::{poll_fn, Future},
+    path::PathBuf,
+    pin::pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Once, OnceLock,
+    },
+    task::Poll,
+    time::Duration,
+};
+use tokio::runtime::Runtime;
+
+// The wait before the first retry; it doubles after each.
+const BACKOFF: Duration = Duration::from_millis(500);
+
+static INSECURE_WARNING: Once = Once::new();
+static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+pub fn runtime() -> &'static Runtime {
+    RUNTIME.get_or_init(|| {
+        tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(2)
+            .enable_all()
+            .build()
+            .expect("failed to start the async runtime")
+    })
+}
+
+/// Runs `future` to completion. Must not be called from inside the runtime.
+/// Requests have to be sent inside `future`: reqwest starts their timers
+/// when the send future is created, which needs the runtime.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    runtime().block_on(future)
+}
+
+/// The error a request cancelled with Ctrl-C ends in.
+#[derive(Debug)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "cancelled")
+    }
+}
+
+impl Error for Cancelled {}
+
+extern "C" fn on_interrupt(_: libc::c_int) {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
+
+/// Like `block_on`, but Ctrl-C drops `future` and returns `Cancelled`.
+/// SIGINT is only caught while it runs.
+pub fn interruptible<T, E: Into<Box<dyn Error>>>(
+    future: impl Future<Output = Result<T, E>>,
+) -> Result<T, Box<dyn Error>> {
+    INTERRUPTED.store(false, Ordering::SeqCst);
+    let handler = on_interrupt as extern "C" fn(libc::c_int);
+    let previous = unsafe { libc::signal(libc::SIGINT, handler as libc::sighandler_t) };
+    let result = block_on(async {
+        let mut future = pin!(future);
+        // Wakes the task now and then to look at the flag, since a signal
+        // handler can't.
+        let mut tick = tokio::time::interval(Duration::from_millis(50));
+        poll_fn(|cx| {
+            if INTERRUPTED.load(Ordering::SeqCst) {
+                return Poll::Ready(Err(Cancelled.into()));
+            }
+            if let Poll::Ready(result) = future.as_mut().poll(cx) {
+                return Poll::Ready(result.map_err(Into::into));
+            }
+            while tick.poll_tick(cx).is_ready() {}
+            Poll::Pending
+        })
+        .await
+    });
+    unsafe { libc::signal(libc::SIGINT, previous) };
+    result
+}
+
+/// Sends `request`, trying again up to `retries` times after a connection
+/// error, a timeout or a 5xx, with backoff in between. Anything else, and
+/// the last attempt's outcome, is returned as is.
+pub async fn send_with_retries(
+    request: reqwest::RequestBuilder,
+    retries: u32,
+) -> Result<reqwest::Response, reqwest::Error> {
+    let mut attempt = 0;
+    loop {
+        // Streaming bodies can't be cloned, so can't be resent either.
+        let Some(this) = request.try_clone() else {
+            return request.send().await;
+        };
+        let result = this.send().await;
+        let transient = match &result {
+            Ok(resp) => resp.status().is_server_error(),
+            Err(e) => e.is_connect() || e.is_timeout(),
+        };
+        if !transient || attempt >= retries {
+            return result;
+        }
+        tokio::time::sleep(BACKOFF * 2u32.pow(attempt)).await;
+        attempt += 1;
+    }
+}
+
+/// Feeds a streamed response to `f` line by line until it returns false or
+/// the body ends.
+pub async fn for_each_line(
+    mut resp: reqwest::Response,
+    mut f: impl FnMut(&str) -> Result<bool, Box<dyn Error>>,
+) -> Result<(), Box<dyn Error>> {
+    let mut pending: Vec<u8> = Vec::new();
+    while let Some(chunk) = resp.chunk().await? {
+        pending.extend_from_slice(&chunk);
+        while let Some(end) = pending.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = pending.drain(..=end).collect();
+            if !f(String::from_utf8_lossy(&line).trim_end())? {
+                return Ok(());
+            }
+        }
+    }
+    if !pending.is_empty() {
+        f(String::from_utf8_lossy(&pending).trim_end())?;
+    }
+    Ok(())
+}
+
+fn expand_home(path: &str) -> PathBuf {
+    match path.strip_prefix("~/") {
+        Some(rest) => dirs::home_dir().unwrap_or_default().join(rest),
+        None => PathBuf::from(path),
+    }
+}
+
+/// Hosts `llm.proxy` is skipped for: `llm.no_proxy` if set, NO_PROXY
+/// otherwise.
+pub fn no_proxy_list(config: &LlmConfig) -> Option<String> {
+    if !config.no_proxy.is_empty() {
+        return Some(config.no_proxy.join(","));
+    }
+    ["NO_PROXY", "no_proxy"]
+        .iter()
+        .find_map(|name| std::env::var(name).ok().filter(|v| !v.trim().is_empty()))
+}
+
+/// The bearer token from `llm.bearer_token`, or else PLS_BEARER_TOKEN.
+pub fn bearer_token(config: &LlmConfig) -> Option<String> {
+    config
+        .bearer_token
+        .clone()
+        .or_else(|| std::env::var("PLS_BEARER_TOKEN").ok())
+        .filter(|t| !t.is_empty())
+}
+
+// `llm.headers` win over the bearer token, so a proxy that wants some
+// other Authorization scheme can have it.
+fn default_headers(config: &LlmConfig) -> Result<HeaderMap, Box<dyn Error>> {
+    let mut headers = HeaderMap::new();
+    if let Some(token) = bearer_token(config) {
+        let mut value = HeaderValue::from_str(&format!("Bearer {}", token))
+            .map_err(|_| "llm.bearer_token: not a valid header value")?;
+        value.set_sensitive(true);
+        headers.insert(AUTHORIZATION, value);
+    }
+    for (name, value) in &config.headers {
+        let header = HeaderName::from_bytes(name.as_bytes())
+            .map_err(|_| format!("llm.headers: '{}' is not a valid header name", name))?;
+        let mut value = HeaderValue::from_str(value)
+            .map_err(|_| format!("llm.headers: the value of '{}' is not valid", name))?;
+        value.set_sensitive(true);
+        headers.insert(header, value);
+    }
+    Ok(headers)
+}
+
+/// A client builder with the proxy, TLS, header and timeout settings from
+/// `config`. Without `llm.proxy`, the HTTP(S)_PROXY variables apply as
+/// usual.
+pub fn builder(config: &LlmConfig) -> Result<ClientBuilder, Box<dyn Error>> {
+    let mut builder = reqwest::Client::builder()
+        .connect_timeout(Duration::from_secs(config.connect_timeout_secs))
+        .read_timeout(Duration::from_secs(config.request_timeout_secs))
+        .default_headers(default_headers(config)?);
+
+    if let Some(proxy) = config.proxy.as_deref().filter(|p| !p.is_empty()) {
+        let no_proxy = no_proxy_list(config).and_then(|list| NoProxy::from_string(&list));
+        let proxy = Proxy::all(proxy).map_err(|e| format!("llm.proxy: {}", e))?;
+        builder = builder.proxy(proxy.no_proxy(no_proxy));
+    }
+
+    for path in &config.ca_certs {
+        let path = expand_home(path);
+        let pem = fs::read(&path)
+            .map_err(|e| format!("llm.ca_certs: cannot read {}: {}", path.display(), e))?;
+        let certs = Certificate::from_pem_bundle(&pem)
+            .map_err(|e| format!("llm.ca_certs: {}: {}", path.display(), e))?;
+        if certs.is_empty() {
+            return Err(format!("llm.ca_certs: no certificates in {}", path.display()).into());
+        }
+        for cert in certs {
+            builder = builder.add_root_certificate(cert);
+        }
+    }
+
+    if config.insecure_tls {
+        INSECURE_WARNING.call_once(|| {
+            eprintln!(
+                "warning: llm.insecure_tls is on; TLS certificates are NOT checked, so anyone \
+                 between you and {} can read and change what is sent",
+                config.endpoint
+            );
+        });
+        builder = builder.danger_accept_invalid_certs(true);
+    }
+
+    Ok(builder)
+}
@@ -1,14 +1,57 @@
+use crate::clock::now;
 use crate::config::IndexConfig;
-use crate::db::save_tool;
-use crate::ollama::OllamaClient;
-use crate::types::Tool;
+use crate::db::{get_meta, get_tool_stamps, load_all_tools, save_tool};
+use crate::dryrun;
+use crate::embed::Embedder;
+use crate::llm::LlmProvider;
+use crate::progress::Progress;
+use crate::types::{Tool, ToolRecord};
 use std::{
     collections::HashMap,
-    env,
-    io::Write,
-    process::{Command, Stdio},
+    env, fs,
+    io::{Read, Write},
+    os::unix::{fs::PermissionsExt, process::CommandExt},
+    path::{Path, PathBuf},
+    process::{self, Command, Stdio},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
 };
 
+// Tool docs per embedding request.
+const EMBED_BATCH: usize = 32;
+// Options kept per tool, and how much of what each one does.
+const MAX_FLAGS: usize = 30;
+const FLAG_TEXT: usize = 80;
+// How much of a man page's DESCRIPTION goes after the whatis line.
+const LEAD_CHARS: usize = 300;
+const MAN_EXAMPLE_LINES: usize = 8;
+// How much of --help output is read; options of big tools run long.
+const HELP_CHARS: usize = 8000;
+// What a probe's output is read to; a program that prints past this is
+// stuck in a loop, and is killed with the rest at the timeout.
+const PROBE_BYTES: u64 = 1 << 20;
+const PROBE_POLL: Duration = Duration::from_millis(10);
+// Wrapper scripts are short; a program on PATH longer than this isn't one.
+const WRAPPER_BYTES: u64 = 4096;
+// What symlinked shims point at, and the version manager that says which
+// program a link stands for.
+const SHIM_DISPATCHERS: &[(&str, &str)] =
+    &[("volta-shim", "volta"), ("mise", "mise"), ("rtx", "rtx")];
+// How multi-command programs list their subcommands, and the flag that gets
+// one's own usage (`git branch --help` opens the man page instead). Others
+// in `index.subcommand_tools` list them with --help.
+const SUBCOMMAND_LISTS: &[(&str, &[&str], &str)] = &[
+    ("git", &["help", "-a"], "-h"),
+    ("docker", &["--help"], "--help"),
+    ("kubectl", &["--help"], "--help"),
+    ("cargo", &["--list"], "--help"),
+];
+const MAX_SUBCOMMANDS: usize = 80;
+
 fn discover_binaries() -> Vec<(String, String)> {
     let path_var = env::var("PATH").unwrap_or_default();
     let mut binaries = HashMap::new();
@@ -31,38 +74,286 @@ fn discover_binaries() -> Vec<(String, String)> {
     binaries.into_iter().collect()
 }
 
-fn get_tool_help(name: &str) -> Option<String> {
-    if let Ok(output) = Command::new(name)
-        .arg("--help")
-        .stderr(Stdio::piped())
-        .stdout(Stdio::piped())
+/// Why the index wants refreshing, if it does: a full run didn't finish,
+/// `index.reindex_interval_days` (0 for never) have passed since it was
+/// built, or a directory on PATH has changed since, which is how installing
+/// or removing a program shows.
+pub fn stale(conn: &rusqlite::Connection, config: &IndexConfig) -> Option<String> {
+    if get_meta(conn, "index_run").is_some() {
+        return Some("the last full index was interrupted".to_string());
+    }
+    // Indexes from before "indexed_at" was kept go by their newest tool.
+    let built = get_meta(conn, "indexed_at")
+        .and_then(|s| s.parse::<i64>().ok())
+        .or_else(|| {
+            get_tool_stamps(conn)
+                .ok()?
+                .values()
+                .map(|(_, at)| *at)
+                .max()
+        })?;
+    let days = config.reindex_interval_days as i64;
+    if days > 0 && now() - built >= days * 24 * 60 * 60 {
+        return Some(format!("built more than {} days ago", days));
+    }
+    let path_var = env::var("PATH").unwrap_or_default();
+    let changed = path_var.split(':').find(|dir| {
+        fs::metadata(dir)
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+            .is_some_and(|t| t.as_secs() as i64 > built)
+    })?;
+    Some(format!(
+        "programs were added to or removed from {}",
+        changed
+    ))
+}
+
+pub fn find_in_path(name: &str) -> Option<String> {
+    let path_var = env::var("PATH").unwrap_or_default();
+    path_var
+        .split(':')
+        .map(|dir| std::path::Path::new(dir).join(name))
+        .find(|p| p.is_file())
+        .map(|p| p.to_string_lossy().to_string())
+}
+
+// The program a version manager runs for `name` right now, by `<manager>
+// which <name>`, as asdf, pyenv, rbenv, nodenv, volta and mise all answer.
+fn manager_which(manager: &str, name: &str) -> Option<String> {
+    let output = Command::new(manager)
+        .args(["which", name])
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
         .output()
-    {
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        let stderr = String::from_utf8_lossy(&output.stderr);
+        .ok()?;
+    let text = String::from_utf8_lossy(&output.stdout);
+    let target = text.lines().next()?.trim();
+    (output.status.success() && target.starts_with('/') && Path::new(target).is_file())
+        .then(|| target.to_string())
+}
+
+// What a wrapper script at `path` ends by exec'ing: the real program, or,
+// for a version manager's shim (`exec asdf exec "node" "$@"`), whatever the
+// manager picks.
+fn script_target(name: &str, path: &str) -> Option<String> {
+    let mut head = Vec::new();
+    fs::File::open(path)
+        .ok()?
+        .take(WRAPPER_BYTES)
+        .read_to_end(&mut head)
+        .ok()?;
+    if !head.starts_with(b"#!") {
+        return None;
+    }
+    let text = String::from_utf8_lossy(&head);
+    let line = text
+        .lines()
+        .rev()
+        .map(str::trim)
+        .find(|l| l.starts_with("exec "))?;
+    let words = shell_words(line);
+    let program = words
+        .get(1)
+        .filter(|w| !w.starts_with('-') && !w.contains('$'))?;
+    if words.get(2).map(String::as_str) == Some("exec") {
+        let manager = if program.starts_with('/') {
+            program.clone()
+        } else {
+            find_in_path(program)?
+        };
+        return manager_which(&manager, name);
+    }
+    // `exec python3 -m tool` runs python3, which is no better a guess.
+    (program.starts_with('/') && Path::new(program).is_file()).then(|| program.clone())
+}
+
+/// The program behind `name` at `path` to ask for its docs, when that isn't
+/// `path` itself: a version manager's shim or a wrapper script says little
+/// but "no version set" or its own usage. Plain symlinks are left be; busybox
+/// and the like answer by the name they're run as.
+pub fn shim_target(name: &str, path: &str) -> Option<String> {
+    let target = match fs::canonicalize(path) {
+        Ok(real) => {
+            let file = real
+                .file_name()
+                .and_then(|n| n.to_str())
+                .unwrap_or_default();
+            if let Some((_, manager)) = SHIM_DISPATCHERS.iter().find(|(d, _)| *d == file) {
+                // The manager usually sits beside its dispatcher.
+                let beside = real.with_file_name(manager);
+                let manager = if beside.is_file() {
+                    beside.to_string_lossy().to_string()
+                } else {
+                    manager.to_string()
+                };
+                return manager_which(&manager, name);
+            }
+            script_target(name, &real.to_string_lossy())
+        }
+        Err(_) => None,
+    }?;
+    (target != path).then_some(target)
+}
+
+// Where `path`'s symlinks end; empty when it isn't one.
+fn link_target(path: &str) -> String {
+    fs::canonicalize(path)
+        .map(|p| p.to_string_lossy().to_string())
+        .ok()
+        .filter(|real| real != path)
+        .unwrap_or_default()
+}
+
+// Runs `program`, as `name`, with `args`, the way indexing runs anything
+// on PATH: stdin closed, in a session of its own with no terminal to open,
+// with no display to put a window on, in an empty directory made for it, and
+// killed along with whatever it started once `index.probe_timeout_secs`
+// pass. What it printed to stdout and stderr by then, unless
+// `index.never_probe` names it.
+pub fn probe(
+    name: &str,
+    program: &str,
+    args: &[&str],
+    config: &IndexConfig,
+) -> Option<(String, String)> {
+    let base = Path::new(program)
+        .file_name()
+        .map(|b| b.to_string_lossy().to_string());
+    let denied = config
+        .never_probe
+        .iter()
+        .any(|n| n == name || Some(n) == base.as_ref());
+    if denied {
+        return None;
+    }
+    static PROBES: AtomicUsize = AtomicUsize::new(0);
+    let n = PROBES.fetch_add(1, Ordering::Relaxed);
+    let dir = env::temp_dir().join(format!("pls-probe-{}-{}", process::id(), n));
+    fs::create_dir(&dir).ok()?;
+
+    let mut cmd = Command::new(program);
+    cmd.arg0(name)
+        .args(args)
+        .current_dir(&dir)
+        .env_remove("DISPLAY")
+        .env_remove("WAYLAND_DISPLAY")
+        .env("TERM", "dumb")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    unsafe {
+        cmd.pre_exec(|| {
+            libc::setsid();
+            Ok(())
+        });
+    }
+    let Ok(mut child) = cmd.spawn() else {
+        fs::remove_dir_all(&dir).ok();
+        return None;
+    };
+    let stdout = drain(child.stdout.take());
+    let stderr = drain(child.stderr.take());
+
+    let deadline = Instant::now() + Duration::from_secs(config.probe_timeout_secs.max(1));
+    while matches!(child.try_wait(), Ok(None)) && Instant::now() < deadline {
+        thread::sleep(PROBE_POLL);
+    }
+    // The session id is the child's pid; this reaches whatever it left
+    // running too, which would otherwise hold the pipes open.
+    unsafe { libc::kill(-(child.id() as i32), libc::SIGKILL) };
+    child.wait().ok();
+    let output = (
+        stdout.join().unwrap_or_default(),
+        stderr.join().unwrap_or_default(),
+    );
+    fs::remove_dir_all(&dir).ok();
+    Some(output)
+}
+
+// A pipe read to its end (or PROBE_BYTES) on a thread of its own, so a
+// full stderr can't stall a probe waiting on stdout.
+fn drain(pipe: Option<impl Read + Send + 'static>) -> thread::JoinHandle<String> {
+    thread::spawn(move || {
+        let mut bytes = Vec::new();
+        if let Some(pipe) = pipe {
+            pipe.take(PROBE_BYTES).read_to_end(&mut bytes).ok();
+        }
+        String::from_utf8_lossy(&bytes).to_string()
+    })
+}
+
+// `program --help`, or -h, run as `name` so the usage line reads as the user
+// would type it.
+fn get_tool_help(name: &str, program: &str, config: &IndexConfig) -> Option<String> {
+    if let Some((stdout, stderr)) = probe(name, program, &["--help"], config) {
         let text = if stdout.len() > stderr.len() {
             stdout
         } else {
             stderr
         };
         if text.len() > 20 {
-            return Some(text.chars().take(2000).collect());
+            return Some(text.chars().take(HELP_CHARS).collect());
         }
     }
+    let (text, _) = probe(name, program, &["-h"], config)?;
+    (text.len() > 20).then(|| text.chars().take(HELP_CHARS).collect())
+}
 
-    if let Ok(output) = Command::new(name)
-        .arg("-h")
-        .stderr(Stdio::piped())
-        .stdout(Stdio::piped())
-        .output()
-    {
-        let text = String::from_utf8_lossy(&output.stdout);
-        if text.len() > 20 {
-            return Some(text.chars().take(2000).collect());
+// `name  what it does` lines, indented, under the headings of a program's
+// help that mention commands ("Management Commands:", "Main Porcelain
+// Commands"), in the order listed.
+fn parse_subcommands(text: &str) -> Vec<(String, String)> {
+    let mut found: Vec<(String, String)> = Vec::new();
+    let mut listing = false;
+    for line in text.lines().filter(|l| !l.trim().is_empty()) {
+        if !line.starts_with(char::is_whitespace) {
+            listing = line.to_lowercase().contains("command");
+            continue;
+        }
+        let Some((name, summary)) = line.trim().split_once("  ").filter(|_| listing) else {
+            continue;
+        };
+        // docker marks plugins `buildx*`; cargo lists its aliases too.
+        let name = name.trim_end_matches('*');
+        let summary = summary.trim();
+        let plain = name.starts_with(|c: char| c.is_ascii_lowercase())
+            && name
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+        if plain && !summary.starts_with("alias:") && !found.iter().any(|(n, _)| n == name) {
+            found.push((name.to_string(), summary.to_string()));
+        }
+        if found.len() == MAX_SUBCOMMANDS {
+            break;
         }
     }
+    found
+}
 
-    None
+fn list_subcommands(program: &str, config: &IndexConfig) -> Vec<(String, String)> {
+    let args = SUBCOMMAND_LISTS
+        .iter()
+        .find(|(p, _, _)| *p == program)
+        .map_or(&["--help"][..], |(_, args, _)| args);
+    probe(program, program, args, config)
+        .map_or(Vec::new(), |(stdout, _)| parse_subcommands(&stdout))
+}
+
+// `program sub --help`, or whatever flag SUBCOMMAND_LISTS says.
+fn get_subcommand_help(program: &str, sub: &str, config: &IndexConfig) -> Option<String> {
+    let flag = SUBCOMMAND_LISTS
+        .iter()
+        .find(|(p, _, _)| *p == program)
+        .map_or("--help", |(_, _, flag)| flag);
+    let (stdout, stderr) = probe(program, program, &[sub, flag], config)?;
+    let text = if stdout.len() > stderr.len() {
+        stdout
+    } else {
+        stderr
+    };
+    (text.len() > 20).then(|| text.chars().take(HELP_CHARS).collect())
 }
 
 fn get_man_description(name: &str) -> Option<String> {
@@ -75,6 +366,35 @@ fn get_man_description(name: &str) -> Option<String> {
     None
 }
 
+// Whatis lines for many programs at once, by name; one `whatis` per program
+// would take longer than the rest of a brief entry.
+fn get_man_descriptions(names: &[&str]) -> HashMap<String, String> {
+    let mut descriptions: HashMap<String, String> = HashMap::new();
+    for chunk in names.chunks(200) {
+        let Ok(output) = Command::new("whatis")
+            .args(chunk)
+            .stderr(Stdio::null())
+            .output()
+        else {
+            return descriptions;
+        };
+        for line in String::from_utf8_lossy(&output.stdout).lines() {
+            let Some(name) = line.split_whitespace().next() else {
+                continue;
+            };
+            if !chunk.contains(&name) {
+                continue;
+            }
+            let description = descriptions.entry(name.to_string()).or_default();
+            if !description.is_empty() {
+                description.push('\n');
+            }
+            description.push_str(line.trim());
+        }
+    }
+    descriptions
+}
+
 fn get_tldr_content(name: &str) -> Option<String> {
     if let Ok(output) = Command::new("tldr").arg(name).output() {
         if output.status.success() {
@@ -97,24 +417,314 @@ fn parse_help_synopsis(help_text: &str) -> String {
     help_text.lines().next().unwrap_or("").to_string()
 }
 
+// The first sentence of `text`, cut to `max` characters.
+fn first_sentence(text: &str, max: usize) -> String {
+    let sentence = text.split_inclusive(". ").next().unwrap_or(text).trim();
+    if sentence.chars().count() <= max {
+        return sentence.to_string();
+    }
+    let cut: String = sentence.chars().take(max - 3).collect();
+    format!("{}...", cut.trim_end())
+}
+
+// Options with what they do, one per line ("-r, --recursive  copy
+// directories recursively"), from a man page or --help: a line starting
+// with a dash, its text after a run of spaces and on the lines indented
+// under it.
 fn extract_flags(help_text: &Option<String>) -> String {
     let Some(help) = help_text else {
         return String::new();
     };
 
+    let lines: Vec<&str> = help.lines().collect();
     let mut flags = Vec::new();
-    for line in help.lines() {
-        let trimmed = line.trim();
-        if trimmed.starts_with('-') && !trimmed.starts_with("---") {
-            if let Some(flag) = trimmed.split_whitespace().next() {
-                flags.push(flag.trim_end_matches(',').to_string());
+    // Where the options' descriptions start; a dash that far in is part of
+    // one ("-1 means never").
+    let mut text_column = usize::MAX;
+    for (i, line) in lines.iter().enumerate() {
+        let trimmed = line.trim_start();
+        let indent = line.len() - trimmed.len();
+        let option = trimmed.starts_with('-')
+            && !trimmed.starts_with("---")
+            && trimmed[1..].starts_with(|c: char| c.is_alphanumeric() || c == '-');
+        if !option || indent >= text_column {
+            continue;
+        }
+
+        let (spec, rest) = trimmed.split_once("  ").unwrap_or((trimmed, ""));
+        let column = if rest.trim().is_empty() {
+            lines
+                .get(i + 1)
+                .map(|next| next.len() - next.trim_start().len())
+        } else {
+            Some(line.len() - rest.trim_start().len())
+        };
+        if let Some(column) = column.filter(|&c| c > indent) {
+            text_column = text_column.min(column);
+        }
+        let mut text = rest.trim().to_string();
+        for next in &lines[i + 1..] {
+            let more = next.trim_start();
+            if more.is_empty() || more.starts_with('-') || next.len() - more.len() <= indent {
+                break;
             }
+            text.push(' ');
+            text.push_str(more);
+        }
+        let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+        let text = first_sentence(&text, FLAG_TEXT);
+        flags.push(if text.is_empty() {
+            spec.trim().to_string()
+        } else {
+            format!("{}  {}", spec.trim(), text)
+        });
+        if flags.len() >= MAX_FLAGS {
+            break;
         }
     }
-    flags.into_iter().take(20).collect::<Vec<_>>().join(", ")
+    flags.join("\n")
 }
 
-fn extract_examples(tldr: &Option<String>, help: &Option<String>) -> String {
+// Where the shells look for completion definitions, and what a program's
+// file is called in each.
+fn completion_files(name: &str) -> Vec<(&'static str, PathBuf)> {
+    let mut dirs: Vec<(&'static str, PathBuf)> = Vec::new();
+    if let Some(config) = dirs::config_dir() {
+        dirs.push(("fish", config.join("fish/completions")));
+    }
+    if let Some(data) = dirs::data_dir() {
+        dirs.push(("fish", data.join("fish/vendor_completions.d")));
+        dirs.push(("bash", data.join("bash-completion/completions")));
+    }
+    for prefix in ["/usr/share", "/usr/local/share", "/opt/homebrew/share"] {
+        let prefix = PathBuf::from(prefix);
+        dirs.push(("fish", prefix.join("fish/vendor_completions.d")));
+        dirs.push(("fish", prefix.join("fish/completions")));
+        dirs.push(("zsh", prefix.join("zsh/vendor-completions")));
+        dirs.push(("zsh", prefix.join("zsh/site-functions")));
+        // zsh's own, sorted by platform.
+        if let Ok(entries) = fs::read_dir(prefix.join("zsh/functions/Completion")) {
+            dirs.extend(entries.filter_map(|e| e.ok()).map(|e| ("zsh", e.path())));
+        }
+        dirs.push(("bash", prefix.join("bash-completion/completions")));
+    }
+    dirs.push(("bash", PathBuf::from("/etc/bash_completion.d")));
+
+    dirs.into_iter()
+        .map(|(shell, dir)| {
+            let file = match shell {
+                "fish" => format!("{}.fish", name),
+                "zsh" => format!("_{}", name),
+                _ => name.to_string(),
+            };
+            (shell, dir.join(file))
+        })
+        .filter(|(_, path)| path.is_file())
+        .collect()
+}
+
+// Words of a shell line, quotes removed; good enough for `complete` lines.
+fn shell_words(line: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut quote = None;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (None, '\'' | '"') => quote = Some(c),
+            (Some(q), c) if c == q => quote = None,
+            (None, '\\') | (Some('"'), '\\') => word.extend(chars.next()),
+            (None, c) if c.is_whitespace() => {
+                if !word.is_empty() {
+                    words.push(std::mem::take(&mut word));
+                }
+            }
+            (None, '#') if word.is_empty() => break,
+            _ => word.push(c),
+        }
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+// `complete -c ls -s a -l all -d 'do not ignore entries starting with .'`
+fn fish_flags(text: &str, name: &str) -> Vec<String> {
+    let mut flags = Vec::new();
+    for line in text.lines() {
+        let words = shell_words(line.trim());
+        if words.first().map(String::as_str) != Some("complete") {
+            continue;
+        }
+        let mut names = Vec::new();
+        let mut command = None;
+        let mut description = String::new();
+        let mut words = words.iter().skip(1);
+        while let Some(word) = words.next() {
+            let (option, inline) = match word.split_once('=') {
+                Some((option, value)) if word.starts_with("--") => (option, Some(value)),
+                _ => (word.as_str(), None),
+            };
+            let mut value = || inline.map(String::from).or_else(|| words.next().cloned());
+            match option {
+                "-c" | "--command" => command = value(),
+                "-s" | "--short-option" => names.extend(value().map(|v| format!("-{}", v))),
+                "-l" | "--long-option" => names.extend(value().map(|v| format!("--{}", v))),
+                "-o" | "--old-option" => names.extend(value().map(|v| format!("-{}", v))),
+                "-d" | "--description" => description = value().unwrap_or_default(),
+                "-n" | "--condition" | "-a" | "--arguments" | "-w" | "--wraps" => {
+                    value();
+                }
+                _ => {}
+            }
+        }
+        if names.is_empty() || command.as_deref().is_some_and(|c| c != name) {
+            continue;
+        }
+        flags.push(flag_line(&names.join(", "), &description));
+    }
+    flags
+}
+
+// `_arguments` specs: '-a[...]', '--all[...]', '(-a --all)'{-a,--all}'[...]'.
+fn zsh_flags(text: &str) -> Vec<String> {
+    let mut flags = Vec::new();
+    for line in text.lines() {
+        let mut rest = line;
+        let mut consumed = 0;
+        while let Some(open) = rest.find('[') {
+            let Some(close) = rest[open..].find(']').map(|c| open + c) else {
+                break;
+            };
+            let description = &rest[open + 1..close];
+            // Back over quotes and the `=`, `+` or `-` that say how the
+            // option takes its argument.
+            let head = rest[..open].trim_end_matches(['\'', '"']);
+            let head = head.strip_suffix(['=', '+']).unwrap_or(head);
+            let names: Vec<&str> = if let Some(group) = head.strip_suffix('}') {
+                let start = group.rfind('{').map_or(0, |i| i + 1);
+                group[start..].split(',').collect()
+            } else {
+                let start = head
+                    .rfind(|c: char| !(c.is_alphanumeric() || "-_".contains(c)))
+                    .map_or(0, |i| i + 1);
+                vec![&head[start..]]
+            };
+            let names: Vec<&str> = names
+                .into_iter()
+                .map(|n| n.trim_matches(['\'', '"', '*']))
+                .filter(|n| {
+                    n.len() > 1
+                        && n.starts_with('-')
+                        && n[1..].starts_with(|c: char| c.is_alphanumeric() || c == '-')
+                })
+                .collect();
+            if !names.is_empty() {
+                let line = flag_line(&names.join(", "), description);
+                if !flags.contains(&line) {
+                    flags.push(line);
+                }
+            }
+            consumed += close + 1;
+            rest = &line[consumed..];
+        }
+    }
+    flags
+}
+
+// Static word lists: `compgen -W '--all --long -a'`.
+fn bash_flags(text: &str) -> Vec<String> {
+    let mut flags = Vec::new();
+    for (at, _) in text.match_indices("compgen -W ") {
+        let rest = text[at + "compgen -W ".len()..].trim_start();
+        let Some(quote) = rest.chars().next().filter(|c| matches!(c, '\'' | '"')) else {
+            continue;
+        };
+        let Some(end) = rest[1..].find(quote) else {
+            continue;
+        };
+        // A list worked out when completing (`"$(_parse_help ...)"`) has
+        // nothing to read here.
+        let list = &rest[1..end + 1];
+        if list.contains(['$', '`']) {
+            continue;
+        }
+        for word in list.split_whitespace() {
+            let word = word.trim_end_matches('=');
+            let flag = word.starts_with('-')
+                && word.len() > 1
+                && word
+                    .chars()
+                    .all(|c| c.is_alphanumeric() || "-_".contains(c));
+            if flag && !flags.iter().any(|f| f == word) {
+                flags.push(word.to_string());
+            }
+        }
+    }
+    flags
+}
+
+fn flag_line(names: &str, description: &str) -> String {
+    let description = description.split_whitespace().collect::<Vec<_>>().join(" ");
+    if description.is_empty() {
+        names.to_string()
+    } else {
+        format!("{}  {}", names, first_sentence(&description, FLAG_TEXT))
+    }
+}
+
+// Flags from the shells' completion definitions for `name`: fish's and
+// zsh's say what each does, bash's are often just a word list.
+fn completion_flags(name: &str) -> String {
+    for (shell, path) in completion_files(name) {
+        let Ok(text) = fs::read_to_string(&path) else {
+            continue;
+        };
+        let flags = match shell {
+            "fish" => fish_flags(&text, name),
+            "zsh" => zsh_flags(&text),
+            _ => bash_flags(&text),
+        };
+        if !flags.is_empty() {
+            return flags.join("\n");
+        }
+    }
+    String::new()
+}
+
+// The flag names in a line of `flags`: "-a, --all  do not ..." has -a and
+// --all.
+fn flag_names(line: &str) -> Vec<&str> {
+    let spec = line.split("  ").next().unwrap_or(line);
+    spec.split([',', ' ', '=', '['])
+        .filter(|w| w.starts_with('-') && w.len() > 1)
+        .collect()
+}
+
+// `sources` one after another, each adding only the flags the ones before
+// it didn't have, up to MAX_FLAGS lines.
+fn merge_flags(sources: &[String]) -> String {
+    let mut lines: Vec<&str> = Vec::new();
+    let mut seen: Vec<&str> = Vec::new();
+    for source in sources {
+        for line in source.lines().filter(|l| !l.trim().is_empty()) {
+            let names = flag_names(line);
+            if lines.len() >= MAX_FLAGS || names.iter().any(|n| seen.contains(n)) {
+                continue;
+            }
+            seen.extend(names);
+            lines.push(line);
+        }
+    }
+    lines.join("\n")
+}
+
+fn extract_examples(
+    tldr: &Option<String>,
+    man: Option<&HashMap<String, String>>,
+    help: &Option<String>,
+) -> String {
     if let Some(tldr) = tldr {
         let examples: Vec<&str> = tldr
             .lines()
@@ -126,6 +736,19 @@ fn extract_examples(tldr: &Option<String>, help: &Option<String>) -> String {
         }
     }
 
+    let man = man.and_then(|sections| sections.get("EXAMPLES").or(sections.get("EXAMPLE")));
+    if let Some(section) = man {
+        let examples: Vec<&str> = section
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty())
+            .take(MAN_EXAMPLE_LINES)
+            .collect();
+        if !examples.is_empty() {
+            return examples.join("\n");
+        }
+    }
+
     if let Some(help) = help {
         let lower = help.to_lowercase();
         if let Some(pos) = lower.find("example") {
@@ -137,6 +760,58 @@ fn extract_examples(tldr: &Option<String>, help: &Option<String>) -> String {
     String::new()
 }
 
+const CAVEAT_MARKERS: &[&str] = &[
+    "cannot be undone",
+    "can not be undone",
+    "irreversib",
+    "permanently",
+    "requires root",
+    "must be root",
+    "superuser",
+    "root privileges",
+    "data loss",
+    "destroy",
+    "overwrit",
+    "warning:",
+    "caution",
+    "dangerous",
+];
+
+// Pulls sentences that warn about side effects out of the collected docs, so
+// the UI can surface them regardless of what the model says.
+fn extract_caveats(docs: &[&Option<String>]) -> String {
+    let mut caveats: Vec<String> = Vec::new();
+
+    // Option listings ("-f, --force  overwrite ...") and their deeply
+    // indented continuation lines describe flags, not the tool's own
+    // hazards, so only prose lines are considered.
+    for text in docs.iter().filter_map(|d| d.as_ref()) {
+        let prose = text
+            .lines()
+            .filter(|l| l.len() - l.trim_start().len() < 8)
+            .map(str::trim)
+            .filter(|l| !l.starts_with('-'))
+            .collect::<Vec<_>>()
+            .join("\n");
+        for sentence in prose.split_inclusive(['.', '!', '\n']) {
+            let sentence = sentence.split_whitespace().collect::<Vec<_>>().join(" ");
+            let lower = sentence.to_lowercase();
+            if !CAVEAT_MARKERS.iter().any(|m| lower.contains(m)) {
+                continue;
+            }
+            let sentence: String = sentence.trim().chars().take(160).collect();
+            if !caveats.contains(&sentence) {
+                caveats.push(sentence);
+            }
+            if caveats.len() >= 3 {
+                return caveats.join("\n");
+            }
+        }
+    }
+
+    caveats.join("\n")
+}
+
 fn determine_source(tldr: &Option<String>, man: &Option<String>, help: &Option<String>) -> String {
     if tldr.is_some() {
         "tldr".to_string()
@@ -149,15 +824,30 @@ fn determine_source(tldr: &Option<String>, man: &Option<String>, help: &Option<S
     }
 }
 
+fn embed_text(name: &str, description: &str, synopsis: &str, examples: &str) -> String {
+    format!(
+        "{} {} {} {}",
+        name,
+        description,
+        synopsis.chars().take(200).collect::<String>(),
+        examples.chars().take(300).collect::<String>()
+    )
+}
+
 pub fn index_tools(
-    client: &OllamaClient,
+    embedder: &dyn Embedder,
     conn: &rusqlite::Connection,
     config: &IndexConfig,
     verbose: bool,
+    incremental: bool,
+    resumed: Option<i64>,
 ) -> Result<usize, Box<dyn std::error::Error>> {
     let binaries = discover_binaries();
-    let total = binaries.len();
-    let mut indexed = 0;
+    let stamps = if incremental || resumed.is_some() {
+        get_tool_stamps(conn)?
+    } else {
+        HashMap::new()
+    };
 
     let priority_tools: Vec<&str> = vec![
         "find", "grep", "awk", "sed", "sort", "uniq", "cut", "tr", "wc", "head", "tail", "cat",
@@ -181,78 +871,618 @@ pub fn index_tools(
         }
     });
 
-    let max_tools = 200;
+    let max_tools = match config.max_tools {
+        0 => usize::MAX,
+        n => n,
+    };
 
-    for (i, (name, path)) in sorted_binaries.into_iter().take(max_tools).enumerate() {
-        if verbose {
-            eprint!("\r  [{}/{}] {}...", i + 1, total.min(max_tools), name);
-            std::io::stderr().flush().ok();
+    // The first `full_docs` are documented in full; the long tail gets its
+    // name and whatis line. Incremental runs only redo tools that are new,
+    // moved, or whose binary changed since they were indexed; a full run
+    // picked up again after an interruption, those it hadn't yet written.
+    let tiers: Vec<(String, String, bool)> = sorted_binaries
+        .into_iter()
+        .take(max_tools)
+        .enumerate()
+        .map(|(i, (name, path))| (name, path, i < config.full_docs))
+        .collect();
+    // Subcommands of the multi-command programs documented in full are
+    // entries of their own, "git branch", so a query about branches finds
+    // `git branch`'s docs rather than git's. What the program's listing says
+    // each does is kept for its description.
+    let mut listed: HashMap<String, String> = HashMap::new();
+    let mut entries = Vec::new();
+    for (name, path, full) in tiers {
+        if full && config.subcommand_tools.contains(&name) {
+            for (sub, summary) in list_subcommands(&name, config) {
+                let entry = format!("{} {}", name, sub);
+                listed.insert(entry.clone(), summary);
+                entries.push((entry, path.clone(), true));
+            }
         }
+        entries.push((name, path, full));
+    }
+    let todo: Vec<(String, String, bool)> = entries
+        .into_iter()
+        .filter(|(name, path, _)| {
+            let Some((indexed_path, updated_at)) = stamps.get(name) else {
+                return true;
+            };
+            if let Some(started) = resumed {
+                return indexed_path != path || *updated_at < started;
+            }
+            let modified = std::fs::metadata(path)
+                .and_then(|m| m.modified())
+                .ok()
+                .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+                .map(|d| d.as_secs() as i64)
+                .unwrap_or(i64::MAX);
+            indexed_path != path || modified >= *updated_at
+        })
+        .collect();
+    let tail: Vec<&str> = todo
+        .iter()
+        .filter(|(_, _, full)| !full)
+        .map(|(name, _, _)| name.as_str())
+        .collect();
+    let whatis = if config.index_man_pages && !tail.is_empty() {
+        get_man_descriptions(&tail)
+    } else {
+        HashMap::new()
+    };
 
-        let man_desc = if config.index_man_pages {
-            get_man_description(&name)
-        } else {
-            None
-        };
-        let help_text = if config.index_help {
-            get_tool_help(&name)
-        } else {
-            None
-        };
-        let tldr = if config.index_tldr {
-            get_tldr_content(&name)
-        } else {
-            None
-        };
+    // Docs are gathered by `doc_workers` threads, mostly waiting on --help,
+    // man and tldr, and embedded EMBED_BATCH at a time by `embed_requests`
+    // more, while this one saves what comes back. Dropping a receiver stops
+    // the threads feeding it, so an error here doesn't leave any waiting.
+    let count = todo.len();
+    let workers = match config.doc_workers {
+        0 => thread::available_parallelism().map_or(1, |n| n.get()),
+        n => n,
+    };
+    let next = AtomicUsize::new(0);
+    let mut progress = Progress::new(count, verbose);
+    let indexed = thread::scope(|scope| {
+        let (docs_tx, docs_rx) = mpsc::sync_channel::<Tool>(EMBED_BATCH * 2);
+        for _ in 0..workers {
+            let tx = docs_tx.clone();
+            let (todo, next) = (&todo, &next);
+            let (whatis, listed) = (&whatis, &listed);
+            scope.spawn(move || {
+                while let Some((name, path, full)) = todo.get(next.fetch_add(1, Ordering::Relaxed))
+                {
+                    let tool = if let Some(summary) = listed.get(name) {
+                        document_subcommand(name, summary, path.clone(), config)
+                    } else if *full {
+                        document(name, path.clone(), config)
+                    } else {
+                        brief(name, path.clone(), whatis)
+                    };
+                    if tx.send(tool).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(docs_tx);
 
-        let description = man_desc
-            .clone()
-            .or_else(|| {
-                help_text
-                    .as_ref()
-                    .map(|h| h.lines().next().unwrap_or("").to_string())
-            })
-            .unwrap_or_default();
+        let docs_rx = Arc::new(Mutex::new(docs_rx));
+        let (saved_tx, saved_rx) = mpsc::channel::<Tool>();
+        for _ in 0..config.embed_requests.max(1) {
+            let (docs, tx) = (Arc::clone(&docs_rx), saved_tx.clone());
+            scope.spawn(move || loop {
+                // A whole batch is taken at once, so batches stay full.
+                let batch: Vec<Tool> = {
+                    let Ok(docs) = docs.lock() else { return };
+                    let Ok(first) = docs.recv() else { return };
+                    std::iter::once(first)
+                        .chain(docs.iter().take(EMBED_BATCH - 1))
+                        .collect()
+                };
+                let texts: Vec<String> = batch
+                    .iter()
+                    .map(|t| embed_text(&t.name, &t.description, &t.synopsis, &t.examples))
+                    .collect();
+                for (mut tool, embedding) in batch.into_iter().zip(embedder.embed_batch(&texts)) {
+                    let Ok(embedding) = embedding else {
+                        continue;
+                    };
+                    tool.embedding = embedding;
+                    if tx.send(tool).is_err() {
+                        return;
+                    }
+                }
+            });
+        }
+        drop((docs_rx, saved_tx));
+
+        let mut indexed = 0;
+        for tool in saved_rx {
+            save_tool(conn, &tool)?;
+            indexed += 1;
+            progress.advance(&tool.name);
+        }
+        Ok::<_, Box<dyn std::error::Error>>(indexed)
+    })?;
+
+    progress.finish();
+    if verbose {
+        eprintln!("  indexed {} tools", indexed);
+    }
+
+    Ok(indexed)
+}
 
-        let synopsis = help_text
+/// Indexes one program in full, whatever its place in a full run would be,
+/// over what the index had for it: `tool` is a name on PATH or a path. Its
+/// subcommands come with it as in a full run. Returns the entries written.
+pub fn index_one(
+    embedder: &dyn Embedder,
+    conn: &rusqlite::Connection,
+    config: &IndexConfig,
+    tool: &str,
+) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let (name, path) = if tool.contains('/') {
+        let path = std::path::absolute(tool)?;
+        let executable = fs::metadata(&path)?.permissions().mode() & 0o111 != 0;
+        if !path.is_file() || !executable {
+            return Err("not an executable file".into());
+        }
+        let name = path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+        (name, path.to_string_lossy().to_string())
+    } else {
+        let path = find_in_path(tool).ok_or("not on PATH")?;
+        (tool.to_string(), path)
+    };
+
+    let mut tools = Vec::new();
+    if config.subcommand_tools.contains(&name) {
+        for (sub, summary) in list_subcommands(&name, config) {
+            let entry = format!("{} {}", name, sub);
+            tools.push(document_subcommand(&entry, &summary, path.clone(), config));
+        }
+    }
+    tools.push(document(&name, path, config));
+
+    let mut written = Vec::new();
+    for batch in tools.chunks_mut(EMBED_BATCH) {
+        let texts: Vec<String> = batch
+            .iter()
+            .map(|t| embed_text(&t.name, &t.description, &t.synopsis, &t.examples))
+            .collect();
+        for (tool, embedding) in batch.iter_mut().zip(embedder.embed_batch(&texts)) {
+            tool.embedding = embedding?;
+            save_tool(conn, tool)?;
+            written.push(tool.name.clone());
+        }
+    }
+    Ok(written)
+}
+
+// Everything but the embedding, from whichever doc sources are enabled.
+fn document(name: &str, path: String, config: &IndexConfig) -> Tool {
+    let shim = shim_target(name, &path);
+    let (man_desc, man_page) = if config.index_man_pages {
+        (get_man_description(name), get_full_man(name))
+    } else {
+        (None, None)
+    };
+    let sections = man_page.as_deref().map(man_sections);
+    let help_text = if config.index_help {
+        get_tool_help(name, shim.as_deref().unwrap_or(&path), config)
+    } else {
+        None
+    };
+    let tldr = if config.index_tldr {
+        get_tldr_content(name)
+    } else {
+        None
+    };
+
+    let mut description = man_desc
+        .clone()
+        .or_else(|| sections.as_ref().and_then(man_name))
+        .or_else(|| {
+            help_text
+                .as_ref()
+                .map(|h| h.lines().next().unwrap_or("").to_string())
+        })
+        .unwrap_or_default();
+    if let Some(lead) = sections.as_ref().and_then(man_lead) {
+        description = format!("{}\n{}", description, lead);
+    }
+    // The page's options say what each flag does; completion definitions
+    // list what it leaves out, and --help fills in the rest.
+    let completions = if config.index_completions {
+        completion_flags(name)
+    } else {
+        String::new()
+    };
+    let flags = merge_flags(&[
+        extract_flags(&man_page),
+        completions,
+        extract_flags(&help_text),
+    ]);
+
+    Tool {
+        name: name.to_string(),
+        target: shim.unwrap_or_else(|| link_target(&path)),
+        path,
+        description,
+        synopsis: help_text
             .as_ref()
             .map(|h| parse_help_synopsis(h))
+            .unwrap_or_default(),
+        examples: extract_examples(&tldr, sections.as_ref(), &help_text),
+        flags,
+        source: determine_source(&tldr, &man_desc.or(man_page.clone()), &help_text),
+        caveats: extract_caveats(&[&man_page, &tldr, &help_text]),
+        dry_run: dryrun::detect(name, &help_text),
+        notes: Vec::new(),
+        record: ToolRecord::default(),
+        embedding: Vec::new(),
+    }
+}
+
+// A subcommand entry, "git branch": its man and tldr pages go by
+// "git-branch", its usage by `git branch -h`.
+fn document_subcommand(name: &str, summary: &str, path: String, config: &IndexConfig) -> Tool {
+    let (program, sub) = name.split_once(' ').unwrap_or((name, ""));
+    let page = format!("{}-{}", program, sub);
+    let man_page = if config.index_man_pages {
+        get_full_man(&page)
+    } else {
+        None
+    };
+    let sections = man_page.as_deref().map(man_sections);
+    let help_text = if config.index_help {
+        get_subcommand_help(program, sub, config)
+    } else {
+        None
+    };
+    let tldr = if config.index_tldr {
+        get_tldr_content(&page)
+    } else {
+        None
+    };
+
+    let mut description = summary.to_string();
+    if description.is_empty() {
+        description = sections.as_ref().and_then(man_name).unwrap_or_default();
+    }
+    if let Some(lead) = sections.as_ref().and_then(man_lead) {
+        description = format!("{}\n{}", description, lead);
+    }
+
+    Tool {
+        name: name.to_string(),
+        path,
+        target: String::new(),
+        description,
+        synopsis: help_text
+            .as_ref()
+            .map(|h| parse_help_synopsis(h))
+            .unwrap_or_default(),
+        examples: extract_examples(&tldr, sections.as_ref(), &help_text),
+        flags: merge_flags(&[extract_flags(&man_page), extract_flags(&help_text)]),
+        source: determine_source(&tldr, &man_page, &help_text),
+        caveats: extract_caveats(&[&man_page, &tldr, &help_text]),
+        dry_run: dryrun::detect(program, &help_text),
+        notes: Vec::new(),
+        record: ToolRecord::default(),
+        embedding: Vec::new(),
+    }
+}
+
+// A long-tail entry: the name and the whatis line, if there is one.
+fn brief(name: &str, path: String, whatis: &HashMap<String, String>) -> Tool {
+    Tool {
+        name: name.to_string(),
+        target: shim_target(name, &path).unwrap_or_else(|| link_target(&path)),
+        path,
+        description: whatis.get(name).cloned().unwrap_or_default(),
+        synopsis: String::new(),
+        examples: String::new(),
+        flags: String::new(),
+        source: "brief".to_string(),
+        caveats: String::new(),
+        dry_run: dryrun::known(name),
+        notes: Vec::new(),
+        record: ToolRecord::default(),
+        embedding: Vec::new(),
+    }
+}
+
+/// Tools whose docs were too thin to retrieve well, long-tail entries
+/// among them. Aliases are described by what they expand to, so they never
+/// count.
+pub fn is_low_quality(tool: &Tool) -> bool {
+    tool.source != "alias"
+        && (matches!(tool.source.as_str(), "inferred" | "brief")
+            || tool.description.trim().is_empty())
+}
+
+// `man` output piped somewhere still carries overstrike bold ("N\bNA\bA").
+fn strip_overstrike(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        if c == '\u{8}' {
+            out.pop();
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+fn get_full_man(name: &str) -> Option<String> {
+    let output = Command::new("man")
+        .arg(name)
+        .env("MANPAGER", "cat")
+        .env("MANWIDTH", "100")
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    let text = strip_overstrike(&String::from_utf8_lossy(&output.stdout));
+    (output.status.success() && !text.trim().is_empty()).then_some(text)
+}
+
+// Section bodies keyed by their unindented, upper-case headings.
+fn man_sections(page: &str) -> HashMap<String, String> {
+    let mut sections: HashMap<String, String> = HashMap::new();
+    let mut current = String::new();
+    for line in page.lines() {
+        let heading = !line.is_empty()
+            && !line.starts_with(char::is_whitespace)
+            && line.chars().any(|c| c.is_alphabetic())
+            && !line.chars().any(|c| c.is_lowercase());
+        if heading {
+            current = line.trim().to_string();
+        } else if !current.is_empty() {
+            let body = sections.entry(current.clone()).or_default();
+            body.push_str(line);
+            body.push('\n');
+        }
+    }
+    sections
+}
+
+// "ls - list directory contents" from the NAME section, as whatis has it.
+fn man_name(sections: &HashMap<String, String>) -> Option<String> {
+    let line = sections
+        .get("NAME")?
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+    (!line.is_empty()).then_some(line)
+}
+
+// The opening paragraph of DESCRIPTION, in whole sentences up to
+// LEAD_CHARS, unless the section goes straight into options.
+fn man_lead(sections: &HashMap<String, String>) -> Option<String> {
+    let paragraph: Vec<&str> = sections
+        .get("DESCRIPTION")?
+        .lines()
+        .map(str::trim)
+        .skip_while(|l| l.is_empty())
+        .take_while(|l| !l.is_empty())
+        .collect();
+    let paragraph = paragraph
+        .join(" ")
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+    if paragraph.starts_with('-') {
+        return None;
+    }
+    let mut lead = String::new();
+    for sentence in paragraph.split_inclusive(". ") {
+        if lead.len() + sentence.len() > LEAD_CHARS {
+            break;
+        }
+        lead.push_str(sentence);
+    }
+    let lead = lead.trim().to_string();
+    (!lead.is_empty()).then_some(lead)
+}
+
+// Pages downloaded by the tldr clients, for when the `tldr` command itself
+// isn't on PATH or can't reach the network.
+fn tldr_cache_dirs() -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    if let Some(cache) = dirs::cache_dir() {
+        dirs.push(cache.join("tldr/pages"));
+        dirs.push(cache.join("tealdeer/tldr-pages/pages"));
+        dirs.push(cache.join("tealdeer/tldr-pages/pages.en"));
+    }
+    if let Some(data) = dirs::data_dir() {
+        dirs.push(data.join("tldr/pages"));
+    }
+    if let Some(home) = dirs::home_dir() {
+        dirs.push(home.join(".tldr/cache/pages"));
+        dirs.push(home.join(".tldrc/tldr/pages"));
+    }
+    dirs
+}
+
+fn get_tldr_cached(name: &str) -> Option<String> {
+    let file = format!("{}.md", name);
+    tldr_cache_dirs().iter().find_map(|dir| {
+        ["common", "linux", "osx"]
+            .iter()
+            .find_map(|platform| fs::read_to_string(dir.join(platform).join(&file)).ok())
+    })
+}
+
+// Whatever a tool prints when asked about itself, however short, for the
+// model to summarize.
+fn get_raw_self_description(name: &str, program: &str, config: &IndexConfig) -> Option<String> {
+    let mut text = String::new();
+    for arg in ["--help", "-h", "--version"] {
+        if let Some((stdout, stderr)) = probe(name, program, &[arg], config) {
+            text.push_str(&stdout);
+            text.push_str(&stderr);
+        }
+    }
+    let text: String = text.trim().chars().take(1500).collect();
+    (!text.is_empty()).then_some(text)
+}
+
+fn summarize(client: &dyn LlmProvider, name: &str, text: &str) -> Option<String> {
+    let prompt = format!(
+        "Below is what the command-line program `{}` printed when run with --help, -h and \
+         --version. In one short sentence, say what the program does. Use only what the text \
+         says. If the text does not make it clear, reply with exactly UNKNOWN.\n\n{}",
+        name, text
+    );
+    let reply = client.generate(&prompt).ok()?;
+    let line = reply.lines().map(str::trim).find(|l| !l.is_empty())?;
+    let line = line.trim_matches(['"', '`']).to_string();
+    (!line.to_uppercase().contains("UNKNOWN") && line.len() <= 200).then_some(line)
+}
+
+// Fills in `tool` from the first alternative source that has anything,
+// returning false when none did.
+fn redocument(tool: &mut Tool, client: Option<&dyn LlmProvider>, config: &IndexConfig) -> bool {
+    if let Some(page) = get_full_man(&tool.name) {
+        let sections = man_sections(&page);
+        let name_line = sections
+            .get("NAME")
+            .map(|n| n.split_whitespace().collect::<Vec<_>>().join(" "))
             .unwrap_or_default();
-        let examples = extract_examples(&tldr, &help_text);
-        let flags = extract_flags(&help_text);
-        let source = determine_source(&tldr, &man_desc, &help_text);
-
-        let embed_text = format!(
-            "{} {} {} {}",
-            name,
-            description,
-            synopsis.chars().take(200).collect::<String>(),
-            examples.chars().take(300).collect::<String>()
-        );
+        let description = name_line
+            .split_once(" - ")
+            .map_or(name_line.as_str(), |(_, d)| d);
+        if !description.is_empty() {
+            tool.description = description.to_string();
+            if let Some(synopsis) = sections.get("SYNOPSIS") {
+                let first = synopsis.lines().map(str::trim).find(|l| !l.is_empty());
+                tool.synopsis = first.unwrap_or_default().to_string();
+            }
+            let page = Some(page);
+            if tool.flags.is_empty() {
+                tool.flags = extract_flags(&page);
+            }
+            if tool.examples.is_empty() {
+                tool.examples = extract_examples(&None, Some(&sections), &None);
+            }
+            if tool.caveats.is_empty() {
+                tool.caveats = extract_caveats(&[&page]);
+            }
+            tool.source = "man".to_string();
+            return true;
+        }
+    }
 
-        let embedding = match client.embed(&embed_text) {
-            Ok(e) => e,
-            Err(_) => continue,
-        };
+    if let Some(page) = get_tldr_cached(&tool.name) {
+        let description = page
+            .lines()
+            .filter_map(|l| l.strip_prefix('>'))
+            .map(str::trim)
+            .filter(|l| !l.starts_with("More information"))
+            .collect::<Vec<_>>()
+            .join(" ");
+        if !description.is_empty() {
+            tool.description = description;
+            tool.examples = extract_examples(&Some(page), None, &None);
+            tool.source = "tldr".to_string();
+            return true;
+        }
+    }
 
-        let tool = Tool {
-            name: name.clone(),
-            path,
-            description,
-            synopsis,
-            examples,
-            flags,
-            source,
-            embedding,
-        };
+    let program = shim_target(&tool.name, &tool.path).unwrap_or_else(|| tool.name.clone());
+    let summary = client.and_then(|client| {
+        get_raw_self_description(&tool.name, &program, config)
+            .and_then(|text| summarize(client, &tool.name, &text))
+    });
+    match summary {
+        Some(description) => {
+            tool.description = description;
+            tool.source = "summary".to_string();
+            true
+        }
+        None => false,
+    }
+}
+
+/// How many low-quality entries there were, and which source upgraded
+/// how many of them.
+#[derive(Debug, Default)]
+pub struct Improvement {
+    pub candidates: usize,
+    pub man: usize,
+    pub tldr: usize,
+    pub help: usize,
+    pub summary: usize,
+}
 
+impl Improvement {
+    pub fn upgraded(&self) -> usize {
+        self.man + self.tldr + self.help + self.summary
+    }
+}
+
+/// Re-documents low-quality entries: long-tail ones the way the first tier
+/// is, then what that leaves thin from sources a normal index run doesn't
+/// try: the full man page, tldr's page cache, and, when `client` is given,
+/// a model summary of whatever the tool says about itself.
+pub fn improve_tools(
+    embedder: &dyn Embedder,
+    client: Option<&dyn LlmProvider>,
+    conn: &rusqlite::Connection,
+    config: &IndexConfig,
+    verbose: bool,
+) -> Result<Improvement, Box<dyn std::error::Error>> {
+    let candidates: Vec<Tool> = load_all_tools(conn)?
+        .into_iter()
+        .filter(is_low_quality)
+        .collect();
+    let mut report = Improvement {
+        candidates: candidates.len(),
+        ..Improvement::default()
+    };
+
+    for (i, mut tool) in candidates.into_iter().enumerate() {
+        if verbose {
+            eprint!("\r  [{}/{}] {}...", i + 1, report.candidates, tool.name);
+            std::io::stderr().flush().ok();
+        }
+        if tool.source == "brief" {
+            let full = document(&tool.name, tool.path.clone(), config);
+            tool = Tool {
+                notes: tool.notes,
+                record: tool.record,
+                ..full
+            };
+        }
+        if is_low_quality(&tool) && !redocument(&mut tool, client, config) {
+            continue;
+        }
+        let text = embed_text(
+            &tool.name,
+            &tool.description,
+            &tool.synopsis,
+            &tool.examples,
+        );
+        let Ok(embedding) = embedder.embed(&text) else {
+            continue;
+        };
+        tool.embedding = embedding;
         save_tool(conn, &tool)?;
-        indexed += 1;
+        match tool.source.as_str() {
+            "man" => report.man += 1,
+            "tldr" => report.tldr += 1,
+            "help" => report.help += 1,
+            _ => report.summary += 1,
+        }
     }
 
     if verbose {
-        eprintln!("\r  indexed {} tools                        ", indexed);
+        eprint!("\r                                              \r");
     }
-
-    Ok(indexed)
+    Ok(report)
 }
@@ -0,0 +1,172 @@
+// Every command pls runs gets its own process group, recorded in the jobs
+// table, so `pls kill-all` can stop it along with anything it forked or
+// backgrounded.
+
+use crate::db::{delete_job, get_jobs, save_job};
+use crate::types::Job;
+use rusqlite::Connection;
+use std::{
+    fs,
+    os::unix::process::CommandExt,
+    process::{Child, Command},
+    thread,
+    time::{Duration, Instant},
+};
+
+const GRACE: Duration = Duration::from_secs(2);
+
+fn stdin_is_foreground() -> bool {
+    unsafe {
+        libc::isatty(libc::STDIN_FILENO) == 1
+            && libc::tcgetpgrp(libc::STDIN_FILENO) == libc::getpgrp()
+    }
+}
+
+// Hands the terminal back to pls once the child's group is done with it.
+pub struct Foreground(bool);
+
+impl Drop for Foreground {
+    fn drop(&mut self) {
+        if !self.0 {
+            return;
+        }
+        // We are a background group at this point; without ignoring SIGTTOU
+        // the kernel would stop us for touching the terminal.
+        unsafe {
+            let previous = libc::signal(libc::SIGTTOU, libc::SIG_IGN);
+            libc::tcsetpgrp(libc::STDIN_FILENO, libc::getpgrp());
+            libc::signal(libc::SIGTTOU, previous);
+        }
+    }
+}
+
+/// Spawns `cmd` as the leader of a new process group and records it. When
+/// pls owns the terminal, the group becomes the foreground group so prompts
+/// (sudo, ssh) and Ctrl-C reach the command as they would in a shell.
+pub fn spawn(
+    conn: &Connection,
+    cmd: &mut Command,
+    label: &str,
+) -> Result<(Child, Foreground), Box<dyn std::error::Error>> {
+    prune(conn)?;
+    let foreground = stdin_is_foreground();
+    unsafe {
+        cmd.pre_exec(move || {
+            libc::setpgid(0, 0);
+            if foreground {
+                libc::signal(libc::SIGTTOU, libc::SIG_IGN);
+                libc::tcsetpgrp(libc::STDIN_FILENO, libc::getpid());
+                libc::signal(libc::SIGTTOU, libc::SIG_DFL);
+            }
+            Ok(())
+        });
+    }
+    let child = cmd.spawn()?;
+    save_job(conn, child.id() as i32, label)?;
+    Ok((child, Foreground(foreground)))
+}
+
+/// Like `spawn`, for jobs that should never hold the terminal, such as the
+/// background indexer.
+pub fn spawn_detached(
+    conn: &Connection,
+    cmd: &mut Command,
+    label: &str,
+) -> Result<Child, Box<dyn std::error::Error>> {
+    prune(conn)?;
+    let child = cmd.process_group(0).spawn()?;
+    save_job(conn, child.id() as i32, label)?;
+    Ok(child)
+}
+
+fn group_alive(pgid: i32) -> bool {
+    unsafe { libc::kill(-pgid, 0) == 0 }
+}
+
+// When a recorded group leader (or process) is still around but started well
+// after the job was recorded, the id has been recycled for someone else.
+// Only Linux exposes start times cheaply; elsewhere assume it's ours.
+fn recycled(pgid: i32, started: i64) -> bool {
+    let Ok(stat) = fs::read_to_string(format!("/proc/{}/stat", pgid)) else {
+        return false;
+    };
+    let boot = fs::read_to_string("/proc/stat").ok().and_then(|s| {
+        s.lines()
+            .find_map(|l| {
+                l.strip_prefix("btime ")
+                    .map(|v| v.trim().parse::<i64>().ok())
+            })
+            .flatten()
+    });
+    let ticks = stat
+        .rsplit_once(')')
+        .and_then(|(_, rest)| rest.split_whitespace().nth(19))
+        .and_then(|t| t.parse::<i64>().ok());
+    let hz = unsafe { libc::sysconf(libc::_SC_CLK_TCK) } as i64;
+    match (boot, ticks) {
+        (Some(boot), Some(ticks)) if hz > 0 => boot + ticks / hz > started + 5,
+        _ => false,
+    }
+}
+
+/// Whether the process `pid`, started no earlier than `started`, is still
+/// running. One that has exited but not been reaped yet isn't.
+pub fn process_alive(pid: i32, started: i64) -> bool {
+    let exists = unsafe { libc::kill(pid, 0) == 0 };
+    let zombie = fs::read_to_string(format!("/proc/{}/stat", pid))
+        .ok()
+        .and_then(|stat| {
+            stat.rsplit_once(')')
+                .map(|(_, rest)| rest.trim_start().starts_with('Z'))
+        })
+        .unwrap_or(false);
+    exists && !zombie && !recycled(pid, started)
+}
+
+/// Forgets jobs whose process group has exited.
+pub fn prune(conn: &Connection) -> Result<(), Box<dyn std::error::Error>> {
+    for job in get_jobs(conn)? {
+        if !group_alive(job.pgid) || recycled(job.pgid, job.started) {
+            delete_job(conn, job.id)?;
+        }
+    }
+    Ok(())
+}
+
+/// Sends SIGTERM to every live job, then SIGKILL to whatever is left after
+/// a short grace period. Returns each job hit and whether it needed
+/// SIGKILL.
+pub fn kill_all(conn: &Connection) -> Result<Vec<(Job, bool)>, Box<dyn std::error::Error>> {
+    prune(conn)?;
+    let own = unsafe { libc::getpgrp() };
+    let targets: Vec<Job> = get_jobs(conn)?
+        .into_iter()
+        .filter(|job| job.pgid != own)
+        .collect();
+
+    for job in &targets {
+        unsafe {
+            libc::kill(-job.pgid, libc::SIGTERM);
+            // Stopped jobs only act on SIGTERM once continued.
+            libc::kill(-job.pgid, libc::SIGCONT);
+        }
+    }
+
+    let deadline = Instant::now() + GRACE;
+    while Instant::now() < deadline && targets.iter().any(|job| group_alive(job.pgid)) {
+        thread::sleep(Duration::from_millis(50));
+    }
+
+    let mut stopped = Vec::new();
+    for job in targets {
+        let survived = group_alive(job.pgid);
+        if survived {
+            unsafe {
+                libc::kill(-job.pgid, libc::SIGKILL);
+            }
+        }
+        delete_job(conn, job.id)?;
+        stopped.push((job, survived));
+    }
+    Ok(stopped)
+}
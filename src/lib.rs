@@ -0,0 +1,19 @@
+//! pls's safety engine on its own, for CI jobs and other tools that want
+//! commands judged the way pls judges a plan, under the user's own
+//! `[safety]` policy, without the planner:
+//!
+//! ```no_run
+//! let config = pls::config::load_config();
+//! let commands = vec!["rm -rf build".to_string()];
+//! for assessment in pls::safety::assess_each(&commands, &config.safety) {
+//!     println!("{}: {}", assessment.risk.name(), assessment.reason);
+//! }
+//! ```
+//!
+//! The pls binary is built on the same modules.
+
+pub mod config;
+mod mounts;
+pub mod safety;
+pub mod shell;
+pub mod types;
@@ -0,0 +1,85 @@
+// Quoted spans in a query ("ERROR 502", 'my file.txt', `HEAD~3`) are things
+// the user wants used as-is. The model is told so, and the plan is checked
+// for them afterwards.
+
+use crate::shell::words;
+use crate::types::{Severity, Warning};
+
+// Opening quotes must start a word and closing ones end it, so apostrophes
+// in "don't" or "user's" aren't mistaken for quotes.
+fn boundary(c: Option<char>) -> bool {
+    c.is_none_or(|c| c.is_whitespace() || "([{,;:.!?".contains(c))
+}
+
+/// Quoted spans of `query`, without their quotes, in order.
+pub fn extract(query: &str) -> Vec<String> {
+    let chars: Vec<char> = query.chars().collect();
+    let mut literals: Vec<String> = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let q = chars[i];
+        let opens = matches!(q, '"' | '\'' | '`' | '“' | '‘')
+            && boundary(i.checked_sub(1).map(|p| chars[p]));
+        if !opens {
+            i += 1;
+            continue;
+        }
+        let close = match q {
+            '“' => '”',
+            '‘' => '’',
+            q => q,
+        };
+        let end = (i + 1..chars.len())
+            .find(|&j| chars[j] == close && boundary(chars.get(j + 1).copied()));
+        match end {
+            Some(end) if end > i + 1 => {
+                let literal: String = chars[i + 1..end].iter().collect();
+                if !literals.contains(&literal) {
+                    literals.push(literal);
+                }
+                i = end + 1;
+            }
+            _ => i += 1,
+        }
+    }
+
+    literals
+}
+
+/// Prompt lines listing the literals, or nothing when there are none.
+pub fn prompt_block(literals: &[String]) -> String {
+    if literals.is_empty() {
+        return String::new();
+    }
+    let mut block = String::from(
+        "\nLITERALS (use each exactly as written, character for character; quote it for the \
+         shell but do not rephrase, translate, or change its case):\n",
+    );
+    for literal in literals {
+        block.push_str(&format!("- {}\n", literal));
+    }
+    block
+}
+
+/// Warnings for literals that don't appear unchanged in any command.
+pub fn check_plan(literals: &[String], commands: &[String]) -> Vec<Warning> {
+    // Compare against the commands both as written and with shell quoting
+    // removed, so 'it'\''s' still counts as containing it's.
+    let raw = commands.join("\n");
+    let unquoted = commands
+        .iter()
+        .map(|c| words(c).join(" "))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    literals
+        .iter()
+        .filter(|l| !raw.contains(l.as_str()) && !unquoted.contains(l.as_str()))
+        .map(|l| {
+            let message = format!("the plan does not use \"{}\" exactly as you wrote it", l);
+            Warning::new(Severity::Caution, message)
+                .with_hint("press e to put it back, or quote it in the query")
+        })
+        .collect()
+}
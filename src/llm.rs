@@ -0,0 +1,175 @@
+// The model backends pls can talk to. Everything outside this module and
+// the backends themselves only sees `LlmProvider`, so adding a backend means
+// implementing the trait and adding a line to PROVIDERS.
+
+#[cfg(feature = "anthropic")]
+use crate::anthropic::AnthropicClient;
+#[cfg(feature = "azure")]
+use crate::azure::AzureClient;
+use crate::config::LlmConfig;
+use crate::failover::Failover;
+#[cfg(feature = "gemini")]
+use crate::gemini::GeminiClient;
+#[cfg(feature = "ollama")]
+use crate::ollama::OllamaClient;
+use crate::types::Usage;
+use std::{error::Error, fmt};
+
+/// What a backend can do. Backends that can't tell get the conservative
+/// defaults.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Capabilities {
+    pub json_mode: bool,
+    /// Can hold replies to a JSON schema, not just to valid JSON.
+    pub json_schema: bool,
+    pub streaming: bool,
+    // Only Ollama, whose older servers lack /api/chat, looks.
+    #[cfg_attr(not(feature = "ollama"), allow(dead_code))]
+    pub chat: bool,
+    pub embeddings: bool,
+    pub keep_alive: bool,
+}
+
+/// The error planning ends in when the model server can't be reached.
+#[derive(Debug)]
+pub struct Unreachable(pub String);
+
+impl fmt::Display for Unreachable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} not available", self.0)
+    }
+}
+
+impl Error for Unreachable {}
+
+/// A model a backend has, as `pls models` lists it.
+#[derive(Debug, Clone, Default)]
+pub struct ModelInfo {
+    pub name: String,
+    pub size: u64,
+    /// Family, parameters and quantization, e.g. "llama 8.0B Q4_K_M".
+    pub details: String,
+}
+
+pub trait LlmProvider: Send + Sync {
+    /// The registry name, e.g. "ollama".
+    fn name(&self) -> &str;
+    fn model(&self) -> &str;
+    fn embed_model(&self) -> &str;
+    fn endpoint(&self) -> &str;
+    fn is_available(&self) -> bool;
+    fn capabilities(&self) -> Capabilities;
+    fn generate(&self, prompt: &str) -> Result<String, Box<dyn std::error::Error>>;
+    /// Like `generate`, but asks for JSON output when the backend supports
+    /// it. Callers still have to tolerate free text.
+    fn generate_json(&self, prompt: &str) -> Result<String, Box<dyn std::error::Error>> {
+        self.generate(prompt)
+    }
+    /// Like `generate_json`, passing each piece of the reply to `on_chunk`
+    /// as it arrives. Backends that can't stream deliver it in one piece.
+    fn stream_json(
+        &self,
+        prompt: &str,
+        on_chunk: &mut dyn FnMut(&str),
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        let reply = self.generate_json(prompt)?;
+        on_chunk(&reply);
+        Ok(reply)
+    }
+    /// Like `stream_json`, with the standing instructions in `system` kept
+    /// apart from this request's `user` part. Backends without roles get
+    /// the two as one prompt. Backends that can enforce `schema` do;
+    /// callers still have to check the reply.
+    fn stream_chat(
+        &self,
+        system: &str,
+        user: &str,
+        _schema: &serde_json::Value,
+        on_chunk: &mut dyn FnMut(&str),
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        self.stream_json(&format!("{}\n\n{}", system, user), on_chunk)
+    }
+    fn embed(&self, text: &str) -> Result<Vec<f32>, Box<dyn std::error::Error>>;
+    /// Embeds several texts, concurrently where the backend allows.
+    fn embed_batch(&self, texts: &[String]) -> Vec<Result<Vec<f32>, Box<dyn std::error::Error>>> {
+        texts.iter().map(|t| self.embed(t)).collect()
+    }
+    /// Loads the model so the next request doesn't wait for it. Backends
+    /// without a load step have nothing to do.
+    fn warm(&self) -> Result<(), Box<dyn std::error::Error>> {
+        Ok(())
+    }
+    /// How many tokens of prompt and reply the model is run with, when the
+    /// backend can tell and it is small enough to matter.
+    fn context_window(&self) -> Option<usize> {
+        None
+    }
+    /// The models the backend has installed. Backends that can't tell say
+    /// so.
+    fn list_models(&self) -> Result<Vec<ModelInfo>, Box<dyn std::error::Error>> {
+        Err(format!("{} can't list its models", self.name()).into())
+    }
+    /// Tokens consumed by generate calls since the last call to this.
+    fn take_usage(&self) -> Usage {
+        Usage::default()
+    }
+}
+
+type Constructor = fn(&LlmConfig) -> Result<Box<dyn LlmProvider>, Box<dyn std::error::Error>>;
+
+const PROVIDERS: &[(&str, Constructor)] = &[
+    #[cfg(feature = "ollama")]
+    ("ollama", |c| Ok(Box::new(OllamaClient::new(c)?))),
+    #[cfg(feature = "anthropic")]
+    ("anthropic", |c| Ok(Box::new(AnthropicClient::new(c)?))),
+    #[cfg(feature = "azure")]
+    ("azure", |c| Ok(Box::new(AzureClient::new(c)?))),
+    #[cfg(feature = "gemini")]
+    ("gemini", |c| Ok(Box::new(GeminiClient::new(c)?))),
+];
+
+pub fn provider_names() -> Vec<&'static str> {
+    PROVIDERS.iter().map(|(name, _)| *name).collect()
+}
+
+/// Builds the backend called `name`, configured from `config`; one per
+/// server, behind a `Failover`, when `endpoints` lists more.
+pub fn make_named(
+    name: &str,
+    config: &LlmConfig,
+) -> Result<Box<dyn LlmProvider>, Box<dyn std::error::Error>> {
+    let make = PROVIDERS
+        .iter()
+        .find(|(n, _)| *n == name)
+        .ok_or_else(|| {
+            format!(
+                "unknown provider '{}' (available: {})",
+                name,
+                provider_names().join(", ")
+            )
+        })?
+        .1;
+    let others: Vec<&String> = config
+        .endpoints
+        .iter()
+        .filter(|e| **e != config.endpoint)
+        .collect();
+    if others.is_empty() {
+        return make(config);
+    }
+    let mut backends = vec![make(config)?];
+    for endpoint in others {
+        backends.push(make(&LlmConfig {
+            endpoint: endpoint.clone(),
+            ..config.clone()
+        })?);
+    }
+    Ok(Box::new(Failover::new(backends)))
+}
+
+/// The backend `llm.provider` names.
+pub fn make_provider(
+    config: &LlmConfig,
+) -> Result<Box<dyn LlmProvider>, Box<dyn std::error::Error>> {
+    make_named(&config.provider, config)
+}
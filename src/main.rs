@@ -1,16 +1,87 @@
 use std::env;
 
+#[cfg(not(any(
+    feature = "ollama",
+    feature = "anthropic",
+    feature = "azure",
+    feature = "gemini"
+)))]
+compile_error!("pls needs an LLM provider; build with one of `--features ollama`, `anthropic`, `azure` or `gemini`");
+
+// The safety engine and what it stands on are the library; see lib.rs.
+use pls::{config, safety, shell, types};
+
+mod aliases;
+#[cfg(feature = "anthropic")]
+mod anthropic;
+#[cfg(feature = "azure")]
+mod azure;
+mod browse;
+mod catalog;
+mod clock;
 mod commands;
-mod config;
+mod context;
+mod cooldown;
 mod db;
+mod demo;
+mod dirlock;
+mod docs;
+mod drift;
+mod dryrun;
+mod edits;
+mod embed;
+mod encoding;
 mod executor;
+mod export;
+mod facts;
+mod failover;
+mod fuzzy;
+mod gc;
+#[cfg(feature = "gemini")]
+mod gemini;
+mod hints;
+#[cfg(any(
+    feature = "ollama",
+    feature = "anthropic",
+    feature = "azure",
+    feature = "gemini"
+))]
+mod http;
 mod index;
+mod jobs;
+mod literals;
+mod llm;
+mod migrate;
+#[cfg(any(
+    feature = "ollama",
+    feature = "anthropic",
+    feature = "azure",
+    feature = "gemini"
+))]
+mod network;
+mod notes;
+#[cfg(feature = "ollama")]
 mod ollama;
+mod paths;
+mod pipe;
+mod plancache;
 mod planner;
+mod privacy;
+mod progress;
+mod quota;
+mod quoting;
 mod retrieval;
-mod safety;
-mod types;
+mod sandbox;
+mod schedule;
+mod selftest;
+mod snapshot;
+mod spill;
+mod temporal;
+mod terminal;
+mod training;
 mod ui;
+mod units;
+mod verify;
 
 fn print_usage() {
     println!(
@@ -20,12 +91,90 @@ usage:
   pls <query>         ask pls to do something
   pls -y <query>      yolo mode (skip confirmation)
   pls -e <query>      explain only, don't run
+  pls --json <query>  print the plan, its risk and provenance as JSON;
+                      nothing is run
+  pls --no-cache <query>
+                      ask the model even if a plan that ran fine is cached
+  pls --persona <name> <query>
+                      plan in a house style: conservative, modern, posix, or
+                      one from [personas] in the config
+  pls --context git,dir <query>
+                      tell the model these things about the machine, in this
+                      order, instead of [context] providers; --no-context
+                      tells it nothing but the task and the tools
+  pls verify <query>  check a plan's programs, flags, paths and
+                      permissions without running it
   pls --edit          edit and re-run last command
   pls --history       show recent queries
-  pls index           index system tools
+  pls output          print the exact output of the last command
+  pls export --format ansible|github-actions|justfile [N]
+                      print the Nth most recent plan that was run (default 1)
+                      as Ansible tasks, workflow steps or a just recipe
+  pls history --timeline [--days N]
+                      show history grouped by day and directory
+  pls history show <id> [--raw]
+                      one entry in full; --raw prints the model's reply as it
+                      came (needs behavior.archive_replies)
+  pls index           index system tools; an interrupted run resumes
   pls index --stats   show index statistics
+  pls index --quiet   index without progress output
+  pls index --incremental
+                      only index new or changed tools
+  pls index --docs <dir>
+                      add the markdown and text files under dir (runbooks,
+                      team docs) for plans to draw on; again to refresh
+  pls index --add <tool>...
+                      index these programs (names or paths) in full now,
+                      e.g. right after installing them
+  pls index --remove [tool...]
+                      drop these from the index; with none, every entry
+                      whose program is gone
+  pls index --improve document the long tail in full, and entries with
+                      thin docs from full man pages, cached tldr pages,
+                      or a model summary
+  pls index --install-schedule | --remove-schedule
+                      reindex periodically via systemd, launchd or cron
+  pls index rollback [id]
+                      go back to the index before the last full reindex, or
+                      to generation id (listed by --stats)
+  pls import aliases [files...]
+                      import shell aliases and fish abbreviations
+  pls import --from shell-genie|aichat|github-copilot-cli [files...]
+                      bring another assistant's queries and model settings over
+  pls tools note <tool> <text>
+                      attach a note the planner always sees with that tool
+  pls tools notes [tool]
+                      list notes with their ids
+  pls tools unnote <id>
+                      remove a note
+  pls tools browse [search]
+                      page through the index: examples, flags, man pages
+  pls remember <fact> tell the planner something about this machine, e.g.
+                      pls remember logs live in /srv/logs
+  pls facts           list remembered facts with their ids
+  pls forget <id>     drop a fact
+  pls undo [id] [--force]
+                      put back the files the last run (or run id) rewrote
+                      with sed -i, awk -i inplace or a redirect
+  pls safety check [command...] [--stdin] [--json] [--max-risk <level>]
+                      judge commands as pls would a plan (given, or one per
+                      line or a JSON array on stdin); fails if any is
+                      riskier than level (default review)
   pls config          edit configuration
   pls doctor          check system status
+  pls paths           show where pls keeps its files
+  pls kill-all        stop every command pls started that is still running
+  pls quota           show token usage and spending against budgets
+  pls warm            load the models now so the first query doesn't wait
+  pls models          list the installed models and pick llm.model and
+                      llm.embed_model from them
+  pls gc [--quiet]     prune history, usage and saved output past their
+                      retention and compact the databases
+  pls stats [--days N]
+                      requests, tokens and latency per model (default 30 days)
+  pls selftest [--container [--image IMG]]
+                      run the end-to-end suite against a mock model
+  pls demo            a guided tour in a throwaway sandbox with a stand-in model
 
 examples:
   pls find large files in my home directory
@@ -35,7 +184,63 @@ examples:
     );
 }
 
+// The shell has already removed the user's quotes, but an argument with
+// spaces in it can only have been quoted. Put the quotes back so the planner
+// treats it as a literal, unless it is the whole query (`pls "find ..."`).
+fn join_query(parts: &[String]) -> String {
+    parts
+        .iter()
+        .map(|p| {
+            let quoted = parts.len() > 1 && p.contains(char::is_whitespace);
+            if quoted && !p.contains(['"', '\'', '`']) {
+                format!("\"{}\"", p)
+            } else {
+                p.clone()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// `pls undo [id] [--force]`: the run, if given, and whether to force.
+fn undo_args(args: &[String]) -> Option<(Option<i64>, bool)> {
+    let force = args.iter().any(|a| a == "--force");
+    let rest: Vec<&String> = args.iter().filter(|a| *a != "--force").collect();
+    match rest.as_slice() {
+        [] => Some((None, force)),
+        [id] => Some((Some(id.trim_start_matches('#').parse().ok()?), force)),
+        _ => None,
+    }
+}
+
+// `pls safety check [--stdin] [--json] [--max-risk level] [command...]`.
+// Commands come from stdin when asked, or when none are given.
+fn safety_check(
+    config: &config::Config,
+    args: &[String],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut given = Vec::new();
+    let (mut stdin, mut json, mut max) = (false, false, types::RiskLevel::Review);
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--stdin" => stdin = true,
+            "--json" => json = true,
+            "--max-risk" => {
+                max = args
+                    .next()
+                    .and_then(|level| types::RiskLevel::from_name(level))
+                    .ok_or("--max-risk needs safe, review, dangerous or blocked")?;
+            }
+            _ => given.push(arg.clone()),
+        }
+    }
+    let stdin = stdin || given.is_empty();
+    commands::cmd_safety_check(config, given, stdin, json, max)
+}
+
 fn main() {
+    terminal::install();
     let args: Vec<String> = env::args().collect();
     let config = config::load_config();
 
@@ -46,15 +251,162 @@ fn main() {
 
     let result = match args[1].as_str() {
         "index" => {
-            if args.get(2).map(|s| s.as_str()) == Some("--stats") {
-                commands::cmd_stats()
+            let flags = &args[2..];
+            let has = |f: &str| flags.iter().any(|a| a == f);
+            if flags.first().is_some_and(|f| f == "rollback") {
+                commands::cmd_index_rollback(&config, flags.get(1).map(|s| s.as_str()))
+            } else if has("--stats") {
+                commands::cmd_stats(&config)
+            } else if let Some(at) = flags.iter().position(|f| f == "--docs") {
+                match flags.get(at + 1) {
+                    Some(dir) => commands::cmd_index_docs(&config, dir, !has("--quiet")),
+                    None => Err("--docs needs a directory".into()),
+                }
+            } else if let Some(at) = flags.iter().position(|f| f == "--add" || f == "--remove") {
+                let tools: Vec<String> = flags[at + 1..]
+                    .iter()
+                    .take_while(|f| !f.starts_with("--"))
+                    .cloned()
+                    .collect();
+                match flags[at].as_str() {
+                    "--remove" => commands::cmd_index_remove(&config, &tools),
+                    _ if tools.is_empty() => Err("--add needs a tool name or path".into()),
+                    _ => commands::cmd_index_add(&config, &tools),
+                }
+            } else if has("--improve") {
+                commands::cmd_improve(&config)
+            } else if has("--install-schedule") {
+                commands::cmd_schedule(&config, true)
+            } else if has("--remove-schedule") {
+                commands::cmd_schedule(&config, false)
             } else {
-                commands::cmd_index(&config, true)
+                commands::cmd_index(&config, !has("--quiet"), has("--incremental"))
             }
         }
+        "import" => match args.get(2).map(|s| s.as_str()) {
+            Some("aliases") => commands::cmd_import_aliases(&config, &args[3..]),
+            Some("--from") if args.len() > 3 => {
+                commands::cmd_import_from(&config, &args[3], &args[4..])
+            }
+            _ => {
+                print_usage();
+                Ok(())
+            }
+        },
+        "tools" => match (args.get(2).map(|s| s.as_str()), args.get(3)) {
+            (Some("note"), Some(tool)) if args.len() > 4 => {
+                commands::cmd_tools_note(&config, tool, &args[4..].join(" "))
+            }
+            (Some("notes"), tool) => commands::cmd_tools_notes(&config, tool.map(|s| s.as_str())),
+            (Some("unnote"), Some(id)) => commands::cmd_tools_unnote(&config, id),
+            (Some("browse"), _) => commands::cmd_tools_browse(&config, &args[3..].join(" ")),
+            _ => {
+                print_usage();
+                Ok(())
+            }
+        },
+        "remember" if args.len() > 2 => commands::cmd_remember(&config, &args[2..].join(" ")),
+        // Only the exact forms, so `pls facts about ...` is still a query.
+        "facts" if args.len() == 2 => commands::cmd_facts(&config),
+        "forget" if args.len() == 3 && args[2].parse::<i64>().is_ok() => {
+            commands::cmd_forget(&config, &args[2])
+        }
+        // Only the exact forms, so `pls undo the last commit` is still a query.
+        "undo" if undo_args(&args[2..]).is_some() => {
+            let (id, force) = undo_args(&args[2..]).unwrap_or_default();
+            commands::cmd_undo(&config, id, force)
+        }
+        // Only `safety check`, so `pls safety ...` is still a query.
+        "safety" if args.get(2).is_some_and(|a| a == "check") => safety_check(&config, &args[3..]),
         "config" => commands::cmd_config(),
         "doctor" => commands::cmd_doctor(&config),
-        "--history" | "history" => commands::cmd_history(&config),
+        "paths" => commands::cmd_paths(&config),
+        "kill-all" => commands::cmd_kill_all(&config),
+        "quota" => commands::cmd_quota(&config),
+        "warm" if args.len() == 2 => commands::cmd_warm(&config),
+        "models" if args.len() == 2 => commands::cmd_models(&config),
+        "gc" if args.len() == 2 => commands::cmd_gc(&config, false),
+        "gc" if args.len() == 3 && args[2] == "--quiet" => commands::cmd_gc(&config, true),
+        // Only the exact forms, so `pls stats for ...` is still a query.
+        "stats" if args.len() == 2 => commands::cmd_usage(&config, 30),
+        "stats" if args.len() == 4 && args[2] == "--days" => match args[3].parse() {
+            Ok(days) => commands::cmd_usage(&config, days),
+            Err(_) => {
+                print_usage();
+                Ok(())
+            }
+        },
+        "--history" | "history" => {
+            if args.get(2).map(|s| s.as_str()) == Some("--timeline") {
+                let days = match &args[3..] {
+                    [] => Some(7),
+                    [flag, days] if flag == "--days" => days.parse().ok(),
+                    _ => None,
+                };
+                match days {
+                    Some(days) => commands::cmd_timeline(&config, days),
+                    None => {
+                        print_usage();
+                        Ok(())
+                    }
+                }
+            } else if args.get(2).map(|s| s.as_str()) == Some("show") {
+                let raw = args.get(4).map(|s| s.as_str()) == Some("--raw");
+                match args
+                    .get(3)
+                    .and_then(|id| id.trim_start_matches('#').parse().ok())
+                {
+                    Some(id) if args.len() == 4 || (raw && args.len() == 5) => {
+                        commands::cmd_history_show(&config, id, raw)
+                    }
+                    _ => {
+                        print_usage();
+                        Ok(())
+                    }
+                }
+            } else {
+                commands::cmd_history(&config)
+            }
+        }
+        "verify" => {
+            let query = join_query(&args[2..]);
+            if query.is_empty() {
+                print_usage();
+                Ok(())
+            } else {
+                commands::cmd_verify(&query, &config)
+            }
+        }
+        "demo" => demo::run(),
+        "selftest" => match args.get(2).map(|s| s.as_str()) {
+            Some("--container") => {
+                let image = match args.get(3).map(|s| s.as_str()) {
+                    Some("--image") => args.get(4).map(|s| s.as_str()),
+                    _ => None,
+                };
+                selftest::run_container(image.unwrap_or(selftest::DEFAULT_IMAGE))
+            }
+            _ => selftest::run_local(),
+        },
+        "output" => commands::cmd_output(&config),
+        "export" => {
+            let flags = &args[2..];
+            let format = flags
+                .iter()
+                .position(|a| a == "--format")
+                .and_then(|i| flags.get(i + 1));
+            let nth = flags
+                .iter()
+                .find_map(|a| a.parse::<usize>().ok())
+                .unwrap_or(1);
+            match format {
+                Some(format) => commands::cmd_export(&config, format, nth),
+                None => {
+                    print_usage();
+                    Ok(())
+                }
+            }
+        }
         "--edit" | "edit" => commands::cmd_edit_last(&config),
         "-h" | "--help" | "help" => {
             print_usage();
@@ -63,22 +415,49 @@ fn main() {
         _ => {
             let mut yolo = false;
             let mut explain = false;
+            let mut json = false;
+            let mut use_cache = true;
+            let mut persona = None;
+            let mut context = None;
             let mut query_parts = Vec::new();
 
-            for arg in &args[1..] {
+            let mut rest = args[1..].iter();
+            while let Some(arg) = rest.next() {
                 match arg.as_str() {
                     "-y" | "--yolo" => yolo = true,
                     "-e" | "--explain" => explain = true,
+                    "--json" => json = true,
+                    "--no-cache" => use_cache = false,
+                    "--persona" => persona = rest.next().cloned(),
+                    "--context" => context = Some(rest.next().cloned().unwrap_or_default()),
+                    "--no-context" => context = Some(String::new()),
                     _ => query_parts.push(arg.clone()),
                 }
             }
 
-            let query = query_parts.join(" ");
+            let query = join_query(&query_parts);
             if query.is_empty() {
                 print_usage();
                 Ok(())
             } else {
-                commands::cmd_query(&query, &config, yolo, explain)
+                let mut config = config;
+                if persona.is_some() {
+                    config.behavior.persona = persona;
+                }
+                let providers = context.map(|list| context::parse_list(&list)).transpose();
+                let result = providers
+                    .map_err(|e| e.into())
+                    .and_then(|providers| {
+                        if let Some(providers) = providers {
+                            config.context.providers = providers;
+                        }
+                        pipe::setup(&config.paths, &query, !json && !explain)
+                    })
+                    .and_then(|_| {
+                        commands::cmd_query(&query, &config, yolo, explain, json, use_cache)
+                    });
+                pipe::finish();
+                result
             }
         }
     };
@@ -0,0 +1,398 @@
+// Bringing over what other shell assistants have accumulated, so switching
+// doesn't start from nothing. Their past queries become history entries
+// (marked as imported and never as run); settings pls has an equivalent for
+// come back as config lines to paste, since rewriting the config would lose
+// its comments.
+//
+// shell-genie and GitHub Copilot CLI keep no history of their own, but the
+// shell does: `shell-genie ask ...`, `?? ...` and `gh copilot suggest ...`
+// lines are read from it. aichat logs to messages.md when `save` is on.
+
+use crate::aliases::unquote;
+use crate::clock;
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+pub const SOURCES: &[&str] = &["shell-genie", "aichat", "github-copilot-cli"];
+
+pub struct Entry {
+    pub query: String,
+    pub commands: Vec<String>,
+    pub timestamp: i64,
+}
+
+#[derive(Default)]
+pub struct Imported {
+    pub entries: Vec<Entry>,
+    /// TOML for pls's config.
+    pub config: Vec<String>,
+    /// What had no pls equivalent, for telling the user.
+    pub skipped: Vec<String>,
+}
+
+// How each tool is called from the shell, with what its query is about.
+const SHELL_GENIE: &[(&str, Option<&str>)] = &[("shell-genie ask", None)];
+const COPILOT: &[(&str, Option<&str>)] = &[
+    ("github-copilot-cli what-the-shell", None),
+    ("github-copilot-cli git-assist", Some("git")),
+    ("github-copilot-cli gh-assist", Some("gh")),
+    ("gh copilot suggest", None),
+    ("??", None),
+    ("git?", Some("git")),
+    ("gh?", Some("gh")),
+];
+
+fn shell_histories() -> Vec<PathBuf> {
+    let Some(home) = dirs::home_dir() else {
+        return Vec::new();
+    };
+    let mut paths: Vec<PathBuf> = env::var("HISTFILE")
+        .map(PathBuf::from)
+        .into_iter()
+        .collect();
+    paths.extend([
+        home.join(".bash_history"),
+        home.join(".zsh_history"),
+        home.join(".local/share/fish/fish_history"),
+    ]);
+    paths.dedup();
+    paths
+}
+
+fn aichat_dir() -> PathBuf {
+    env::var("AICHAT_CONFIG_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| dirs::config_dir().unwrap_or_default().join("aichat"))
+}
+
+/// Where `source` keeps what is worth importing.
+pub fn default_paths(source: &str) -> Vec<PathBuf> {
+    let mut paths = match source {
+        "aichat" => vec![aichat_dir()],
+        _ => shell_histories(),
+    };
+    if source == "shell-genie" {
+        let home = dirs::home_dir().unwrap_or_default();
+        paths.push(home.join(".shell_genie/config.json"));
+    }
+    paths.into_iter().filter(|p| p.exists()).collect()
+}
+
+// Commands with the time they were run, where the shell recorded it:
+// bash's `#1700000000` lines (HISTTIMEFORMAT), zsh's `: 1700000000:0;cmd`
+// and fish's `- cmd:` / `when:` pairs.
+fn shell_history(content: &str, fish: bool) -> Vec<(Option<i64>, String)> {
+    let mut out: Vec<(Option<i64>, String)> = Vec::new();
+    let mut stamp = None;
+    let mut lines = content.lines();
+    while let Some(line) = lines.next() {
+        if fish {
+            if let Some(cmd) = line.strip_prefix("- cmd: ") {
+                out.push((None, cmd.replace("\\n", "\n").replace("\\\\", "\\")));
+            } else if let Some(when) = line.strip_prefix("  when: ") {
+                if let Some(last) = out.last_mut() {
+                    last.0 = when.trim().parse().ok();
+                }
+            }
+            continue;
+        }
+        if let Some(time) = line.strip_prefix('#').and_then(|t| t.parse().ok()) {
+            stamp = Some(time);
+            continue;
+        }
+        let (time, cmd) = match line.strip_prefix(": ").and_then(|r| r.split_once(';')) {
+            Some((meta, cmd)) => (meta.split(':').next().and_then(|t| t.parse().ok()), cmd),
+            None => (stamp.take(), line),
+        };
+        let mut cmd = cmd.to_string();
+        // zsh keeps a multi-line command's newlines escaped.
+        while cmd.ends_with('\\') {
+            let Some(next) = lines.next() else { break };
+            cmd.pop();
+            cmd.push('\n');
+            cmd.push_str(next);
+        }
+        out.push((time, cmd));
+    }
+    out
+}
+
+// The query in `?? find big files` or `gh copilot suggest -t git "undo"`.
+fn invoked_query(cmd: &str, calls: &[(&str, Option<&str>)]) -> Option<String> {
+    for (call, topic) in calls {
+        let Some(rest) = cmd.trim().strip_prefix(call) else {
+            continue;
+        };
+        if !rest.starts_with(' ') {
+            continue;
+        }
+        let mut words = rest.split_whitespace().peekable();
+        let mut topic = topic.map(str::to_string);
+        // `-t shell`, `--target git`, `--target=gh`
+        while let Some(option) = words.next_if(|w| w.starts_with('-')) {
+            let value = match option.split_once('=') {
+                Some((_, value)) => Some(value.to_string()),
+                None => words.next().map(str::to_string),
+            };
+            topic = value.filter(|v| v != "shell").or(topic);
+        }
+        let query = unquote(&words.collect::<Vec<_>>().join(" "));
+        if query.is_empty() {
+            return None;
+        }
+        return Some(match topic {
+            Some(topic) => format!("{} {}", topic, query),
+            None => query,
+        });
+    }
+    None
+}
+
+fn modified(path: &Path) -> i64 {
+    fs::metadata(path)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map_or_else(clock::now, |d| d.as_secs() as i64)
+}
+
+// Undated lines are taken to be from when the file was last written.
+fn from_shell_history(path: &Path, calls: &[(&str, Option<&str>)], into: &mut Imported) {
+    let Ok(bytes) = fs::read(path) else {
+        return;
+    };
+    let fish = path.file_name().is_some_and(|n| n == "fish_history");
+    let undated = modified(path);
+    for (time, cmd) in shell_history(&String::from_utf8_lossy(&bytes), fish) {
+        if let Some(query) = invoked_query(&cmd, calls) {
+            into.entries.push(Entry {
+                query,
+                commands: Vec::new(),
+                timestamp: time.unwrap_or(undated),
+            });
+        }
+    }
+}
+
+// {"backend": "ChatGPT", "os": "Linux", "shell": "bash", "openai_api_key": ...}
+fn from_shell_genie_config(path: &Path, into: &mut Imported) {
+    let Ok(content) = fs::read_to_string(path) else {
+        return;
+    };
+    let Ok(config) = serde_json::from_str::<serde_json::Value>(&content) else {
+        into.skipped.push(format!("{}: not JSON", path.display()));
+        return;
+    };
+    if let Some(backend) = config["backend"].as_str() {
+        into.skipped.push(format!(
+            "shell-genie's {} backend: pls has no OpenAI or hosted backend; it uses a local \
+             Ollama model unless llm.provider says otherwise",
+            backend
+        ));
+    }
+}
+
+// # CHAT: list big files [2024-05-20T10:00:00+02:00] (%shell%)
+// list big files
+// --------
+// find . -size +100M
+// --------
+//
+// Only the shell role's exchanges are commands; other chats are skipped.
+fn from_aichat_messages(path: &Path, into: &mut Imported) {
+    let Ok(content) = fs::read_to_string(path) else {
+        return;
+    };
+    let undated = modified(path);
+    let mut chats = 0;
+    for block in content.split("# CHAT:").skip(1) {
+        let (header, body) = block.split_once('\n').unwrap_or((block, ""));
+        let mut parts = body.split("\n--------\n");
+        let (Some(input), Some(output)) = (parts.next(), parts.next()) else {
+            continue;
+        };
+        let scope = header.rfind('(').map_or("", |at| &header[at..]);
+        if !scope.contains("%shell%") {
+            chats += 1;
+            continue;
+        }
+        let timestamp = header
+            .rfind('[')
+            .and_then(|at| clock::parse_local(header[at + 1..].trim()))
+            .unwrap_or(undated);
+        let command = output
+            .lines()
+            .filter(|l| !l.starts_with("```"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let (query, command) = (input.trim(), command.trim());
+        if query.is_empty() {
+            continue;
+        }
+        into.entries.push(Entry {
+            query: query.to_string(),
+            commands: (!command.is_empty())
+                .then(|| command.to_string())
+                .into_iter()
+                .collect(),
+            timestamp,
+        });
+    }
+    if chats > 0 {
+        into.skipped.push(format!(
+            "{} aichat conversations outside the shell role",
+            chats
+        ));
+    }
+}
+
+fn add_setting(client: Option<&mut Vec<(String, String)>>, line: &str) {
+    if let (Some(client), Some((key, value))) = (client, line.split_once(':')) {
+        client.push((key.trim().to_string(), unquote(value)));
+    }
+}
+
+// The `key: value` pairs of each `clients:` entry in aichat's config.yaml.
+fn aichat_clients(content: &str) -> Vec<Vec<(String, String)>> {
+    let mut clients: Vec<Vec<(String, String)>> = Vec::new();
+    let mut inside = false;
+    for line in content.lines() {
+        if !line.starts_with([' ', '-']) {
+            inside = line.trim_end() == "clients:";
+            continue;
+        }
+        if !inside {
+            continue;
+        }
+        let trimmed = line.trim_start();
+        if let Some(first) = trimmed.strip_prefix("- ") {
+            if line.len() - trimmed.len() <= 2 {
+                clients.push(Vec::new());
+            }
+            add_setting(clients.last_mut(), first);
+        } else {
+            add_setting(clients.last_mut(), trimmed);
+        }
+    }
+    clients
+}
+
+// `model: claude:claude-3-5-sonnet-latest` names a client, then a model.
+fn from_aichat_config(path: &Path, into: &mut Imported) {
+    let Ok(content) = fs::read_to_string(path) else {
+        return;
+    };
+    let model = content
+        .lines()
+        .find_map(|l| l.strip_prefix("model:"))
+        .map(unquote)
+        .unwrap_or_default();
+    let Some((client, name)) = model.split_once(':') else {
+        return;
+    };
+    // A client goes by its name, or its type when it has none.
+    let clients = aichat_clients(&content);
+    let value = |settings: &[(String, String)], key: &str| {
+        settings
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.clone())
+    };
+    let settings = clients
+        .iter()
+        .find(|c| value(c, "name").or_else(|| value(c, "type")).as_deref() == Some(client));
+    let setting = |key: &str| {
+        settings
+            .and_then(|c| value(c, key))
+            .map(|v| v.trim_end_matches('/').to_string())
+    };
+    let kind = setting("type").unwrap_or_else(|| client.to_string());
+    let api_base = setting("api_base");
+    let (provider, endpoint, key) = match (kind.as_str(), client) {
+        (_, "ollama") | ("ollama", _) => {
+            let base = api_base.as_deref().unwrap_or("http://localhost:11434");
+            ("ollama", base.trim_end_matches("/v1").to_string(), None)
+        }
+        ("claude", _) => (
+            "anthropic",
+            api_base.unwrap_or_else(|| "https://api.anthropic.com".to_string()),
+            Some("ANTHROPIC_API_KEY"),
+        ),
+        ("gemini", _) => (
+            "gemini",
+            "https://generativelanguage.googleapis.com".to_string(),
+            Some("GEMINI_API_KEY"),
+        ),
+        ("azure-openai", _) => match api_base {
+            Some(base) => ("azure", base, Some("AZURE_OPENAI_API_KEY")),
+            None => {
+                into.skipped.push(format!(
+                    "aichat model {}: no api_base for {}",
+                    model, client
+                ));
+                return;
+            }
+        },
+        _ => {
+            into.skipped.push(format!(
+                "aichat model {}: pls has no {} provider (it has ollama, anthropic, azure and \
+                 gemini)",
+                model, kind
+            ));
+            return;
+        }
+    };
+    into.config.extend([
+        "[llm]".to_string(),
+        format!("provider = \"{}\"", provider),
+        format!("model = \"{}\"", name),
+        format!("endpoint = \"{}\"", endpoint),
+    ]);
+    if let Some(key) = key {
+        into.skipped.push(format!(
+            "aichat's API key: not copied; set {} or llm.api_key",
+            key
+        ));
+    }
+}
+
+/// Reads `source`'s history and settings from `paths`, or from where the
+/// tool keeps them when `paths` is empty.
+pub fn read(source: &str, paths: &[PathBuf]) -> Result<Imported, Box<dyn std::error::Error>> {
+    if !SOURCES.contains(&source) {
+        return Err(format!("unknown source {}; one of: {}", source, SOURCES.join(", ")).into());
+    }
+    let paths = if paths.is_empty() {
+        default_paths(source)
+    } else {
+        paths.to_vec()
+    };
+    if paths.is_empty() {
+        return Err(format!("found nothing from {}; give the files to read", source).into());
+    }
+    let mut imported = Imported::default();
+    for path in &paths {
+        if !path.exists() {
+            return Err(format!("{}: no such file", path.display()).into());
+        }
+        match source {
+            "aichat" if path.is_dir() => {
+                from_aichat_config(&path.join("config.yaml"), &mut imported);
+                from_aichat_messages(&path.join("messages.md"), &mut imported);
+            }
+            "aichat" if path.extension().is_some_and(|e| e == "yaml" || e == "yml") => {
+                from_aichat_config(path, &mut imported)
+            }
+            "aichat" => from_aichat_messages(path, &mut imported),
+            "shell-genie" if path.extension().is_some_and(|e| e == "json") => {
+                from_shell_genie_config(path, &mut imported)
+            }
+            "shell-genie" => from_shell_history(path, SHELL_GENIE, &mut imported),
+            _ => from_shell_history(path, COPILOT, &mut imported),
+        }
+    }
+    imported.entries.sort_by_key(|e| e.timestamp);
+    Ok(imported)
+}
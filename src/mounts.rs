@@ -0,0 +1,205 @@
+// Which filesystem a plan's paths live on. Network shares and removable
+// media deserve extra care: they are slow, often shared with other people,
+// and nothing deleted there goes to a trash. Linux only; elsewhere nothing
+// is reported.
+
+use crate::shell::{split_segments, words, WRAPPERS};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+const NETWORK: &[&str] = &[
+    "nfs",
+    "nfs4",
+    "cifs",
+    "smb3",
+    "smbfs",
+    "afs",
+    "ceph",
+    "glusterfs",
+    "lustre",
+    "9p",
+    "davfs",
+    "fuse.sshfs",
+    "fuse.rclone",
+    "fuse.davfs2",
+    "fuse.s3fs",
+    "fuse.glusterfs",
+    "fuse.cephfs",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MountKind {
+    Network,
+    Removable,
+}
+
+#[derive(Debug, Clone)]
+pub struct Mount {
+    pub point: PathBuf,
+    pub source: String,
+    pub fstype: String,
+}
+
+/// A path from a plan that lives on a network or removable filesystem.
+#[derive(Debug, Clone)]
+pub struct Hit {
+    pub program: String,
+    pub path: String,
+    pub mount: Mount,
+    pub kind: MountKind,
+}
+
+// Spaces and tabs in mount points are written as octal escapes.
+fn unescape(field: &str) -> String {
+    field
+        .replace("\\040", " ")
+        .replace("\\011", "\t")
+        .replace("\\012", "\n")
+        .replace("\\134", "\\")
+}
+
+fn parse_mounts(text: &str) -> Vec<Mount> {
+    text.lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let source = unescape(fields.next()?);
+            let point = PathBuf::from(unescape(fields.next()?));
+            let fstype = fields.next()?.to_string();
+            Some(Mount {
+                point,
+                source,
+                fstype,
+            })
+        })
+        .collect()
+}
+
+fn read_mounts() -> Vec<Mount> {
+    fs::read_to_string("/proc/mounts")
+        .map(|text| parse_mounts(&text))
+        .unwrap_or_default()
+}
+
+// The kernel flags card readers and some sticks as removable; USB disks
+// often aren't, but their device path gives them away.
+fn removable(source: &str) -> bool {
+    if !source.starts_with("/dev/") {
+        return false;
+    }
+    let device = fs::canonicalize(source).unwrap_or_else(|_| PathBuf::from(source));
+    let Some(name) = device.file_name() else {
+        return false;
+    };
+    let Ok(sys) = fs::canonicalize(Path::new("/sys/class/block").join(name)) else {
+        return false;
+    };
+    if sys.to_string_lossy().contains("/usb") {
+        return true;
+    }
+    [sys.join("removable"), sys.join("../removable")]
+        .iter()
+        .any(|flag| fs::read_to_string(flag).is_ok_and(|v| v.trim() == "1"))
+}
+
+fn kind(mount: &Mount) -> Option<MountKind> {
+    if NETWORK.contains(&mount.fstype.as_str()) {
+        Some(MountKind::Network)
+    } else if removable(&mount.source) {
+        Some(MountKind::Removable)
+    } else {
+        None
+    }
+}
+
+// Paths that don't exist yet (`mkdir new`, `cp a b`) live wherever their
+// nearest existing ancestor does.
+fn resolve(arg: &str, cwd: &Path) -> PathBuf {
+    let expanded = match arg.strip_prefix("~/") {
+        Some(rest) => dirs::home_dir().unwrap_or_default().join(rest),
+        None => PathBuf::from(arg),
+    };
+    // Globs resolve to the directory they expand in.
+    let literal: PathBuf = expanded
+        .components()
+        .take_while(|c| !c.as_os_str().to_string_lossy().contains(['*', '?', '[']))
+        .collect();
+    let mut path = cwd.join(literal);
+    loop {
+        if let Ok(real) = fs::canonicalize(&path) {
+            return real;
+        }
+        if !path.pop() {
+            return cwd.to_path_buf();
+        }
+    }
+}
+
+fn mount_of<'a>(mounts: &'a [Mount], path: &Path) -> Option<&'a Mount> {
+    // Later entries are mounted over earlier ones at the same point.
+    mounts
+        .iter()
+        .filter(|m| path.starts_with(&m.point))
+        .max_by_key(|m| m.point.components().count())
+}
+
+/// Operands and redirect targets of `cmd` that land on a network or
+/// removable filesystem, one hit per program and mount.
+pub fn targets(cmd: &str) -> Vec<Hit> {
+    let mounts = read_mounts();
+    if mounts.is_empty() {
+        return Vec::new();
+    }
+    let cwd = env::current_dir().unwrap_or_else(|_| PathBuf::from("/"));
+    let mut hits: Vec<Hit> = Vec::new();
+
+    for segment in split_segments(cmd) {
+        let mut args = words(&segment)
+            .into_iter()
+            .skip_while(|w| w.contains('=') && !w.starts_with('-'));
+        let mut program = String::new();
+        for word in args.by_ref() {
+            program = word.rsplit('/').next().unwrap_or(&word).to_string();
+            if !WRAPPERS.contains(&program.as_str()) {
+                break;
+            }
+        }
+
+        let mut operands = vec![".".to_string()];
+        for arg in args {
+            let arg = arg.trim_start_matches(['1', '2', '&', '>', '<']);
+            if arg.is_empty() || arg.starts_with('-') || arg.contains("://") {
+                continue;
+            }
+            operands.push(arg.to_string());
+        }
+
+        for arg in operands {
+            let path = resolve(&arg, &cwd);
+            let Some(mount) = mount_of(&mounts, &path) else {
+                continue;
+            };
+            let Some(kind) = kind(mount) else {
+                continue;
+            };
+            let seen = hits
+                .iter()
+                .any(|h| h.program == program && h.mount.point == mount.point);
+            if !seen {
+                hits.push(Hit {
+                    program: program.clone(),
+                    path: if arg == "." {
+                        cwd.display().to_string()
+                    } else {
+                        arg
+                    },
+                    mount: mount.clone(),
+                    kind,
+                });
+            }
+        }
+    }
+
+    hits
+}
@@ -0,0 +1,42 @@
+// Personal notes on tools ("our rsync wraps ssh on port 2222"). Each note is
+// embedded on its own so a query can find a tool through its notes, and a
+// retrieved tool always carries its notes into the prompt. Notes live in the
+// user's own database, next to the history, so they work with a shared index.
+
+use crate::db::{get_notes, save_note, set_note_embedding, tool_exists};
+use crate::embed::Embedder;
+use rusqlite::Connection;
+
+fn embed_text(tool: &str, text: &str) -> String {
+    format!("{} {}", tool, text)
+}
+
+pub fn add(
+    embedder: &dyn Embedder,
+    index: &Connection,
+    history: &Connection,
+    tool: &str,
+    text: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if !tool_exists(index, tool) {
+        return Err(format!("{} is not indexed", tool).into());
+    }
+    let embedding = embedder.embed(&embed_text(tool, text))?;
+    save_note(history, tool, text, &embedding)
+}
+
+/// Re-embeds every note, for when the index was rebuilt with a different
+/// embedder. Returns how many were updated.
+pub fn reembed(
+    embedder: &dyn Embedder,
+    conn: &Connection,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let mut updated = 0;
+    for note in get_notes(conn, None)? {
+        if let Ok(embedding) = embedder.embed(&embed_text(&note.tool, &note.text)) {
+            set_note_embedding(conn, note.id, &embedding)?;
+            updated += 1;
+        }
+    }
+    Ok(updated)
+}
@@ -1,22 +1,97 @@
 use crate::config::LlmConfig;
+use crate::http::{self, block_on, for_each_line, interruptible, runtime, send_with_retries};
+use crate::llm::{Capabilities, LlmProvider, ModelInfo};
+use crate::types::Usage;
 use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Mutex, OnceLock},
+    time::Instant,
+};
 
 #[derive(Serialize)]
 struct OllamaGenerate {
     model: String,
     prompt: String,
     stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    format: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    options: HashMap<String, serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    keep_alive: Option<serde_json::Value>,
 }
 
+#[derive(Serialize, Deserialize)]
+struct ChatMessage {
+    role: String,
+    content: String,
+}
+
+#[derive(Serialize)]
+struct OllamaChat {
+    model: String,
+    messages: Vec<ChatMessage>,
+    stream: bool,
+    // "json", or a JSON schema the reply must follow.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    format: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    options: HashMap<String, serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    keep_alive: Option<serde_json::Value>,
+}
+
+// Also what /api/chat answers with, the text in `message` instead of
+// `response`.
 #[derive(Deserialize)]
 struct OllamaGenerateResponse {
+    #[serde(default)]
     response: String,
+    #[serde(default)]
+    message: Option<ChatMessage>,
+    #[serde(default)]
+    done: bool,
+    #[serde(default)]
+    prompt_eval_count: u64,
+    #[serde(default)]
+    eval_count: u64,
 }
 
+// /api/embed takes one input or several.
 #[derive(Serialize)]
 struct OllamaEmbed {
     model: String,
-    input: String,
+    input: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    keep_alive: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize, Default)]
+struct OllamaModelDetails {
+    #[serde(default)]
+    family: String,
+    #[serde(default)]
+    parameter_size: String,
+    #[serde(default)]
+    quantization_level: String,
+}
+
+#[derive(Deserialize)]
+struct OllamaModel {
+    name: String,
+    #[serde(default)]
+    size: u64,
+    #[serde(default)]
+    details: OllamaModelDetails,
+}
+
+// What /api/tags answers with.
+#[derive(Deserialize)]
+struct OllamaTags {
+    #[serde(default)]
+    models: Vec<OllamaModel>,
 }
 
 #[derive(Deserialize)]
@@ -24,46 +99,470 @@ struct OllamaEmbedResponse {
     embeddings: Vec<Vec<f32>>,
 }
 
+#[derive(Serialize)]
+struct OllamaLegacyEmbed {
+    model: String,
+    prompt: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    keep_alive: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct OllamaLegacyEmbedResponse {
+    embedding: Vec<f32>,
+}
+
+// What /api/show says about a model: its Modelfile parameters as text, and
+// what it was trained with, e.g. "llama.context_length".
+#[derive(Deserialize)]
+struct OllamaShow {
+    #[serde(default)]
+    parameters: String,
+    #[serde(default)]
+    model_info: HashMap<String, serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct OllamaVersion {
+    version: String,
+}
+
+impl OllamaGenerateResponse {
+    fn text(&self) -> &str {
+        match &self.message {
+            Some(message) => &message.content,
+            None => &self.response,
+        }
+    }
+}
+
+// Anything that isn't an Ollama server we recognise gets the defaults.
+fn capabilities_for(version: &str) -> Capabilities {
+    let mut parts = version
+        .split(|c: char| !c.is_ascii_digit())
+        .filter_map(|p| p.parse::<u32>().ok());
+    let v = (
+        parts.next().unwrap_or(0),
+        parts.next().unwrap_or(0),
+        parts.next().unwrap_or(0),
+    );
+    Capabilities {
+        json_mode: v >= (0, 1, 9),
+        json_schema: v >= (0, 5, 0),
+        streaming: true,
+        chat: v >= (0, 1, 14),
+        embeddings: v >= (0, 3, 0),
+        keep_alive: v >= (0, 1, 23),
+    }
+}
+
+// The context Ollama gives a model when neither the request nor the
+// Modelfile sets num_ctx.
+const DEFAULT_NUM_CTX: usize = 4096;
+
 pub struct OllamaClient {
     base_url: String,
     model: String,
     embed_model: String,
-    client: reqwest::blocking::Client,
+    client: reqwest::Client,
+    retries: u32,
+    options: HashMap<String, serde_json::Value>,
+    keep_alive: Option<serde_json::Value>,
+    capabilities: OnceLock<Capabilities>,
+    context_window: OnceLock<Option<usize>>,
+    usage: Mutex<Usage>,
+}
+
+// One embedding request. Owns its arguments so a batch can run each as its
+// own task.
+async fn embed_one(
+    client: reqwest::Client,
+    base_url: String,
+    model: String,
+    text: String,
+    legacy: bool,
+    retries: u32,
+    keep_alive: Option<serde_json::Value>,
+) -> Result<Vec<f32>, String> {
+    let send = async {
+        // Pre-0.3 servers only have the single-prompt /api/embeddings endpoint.
+        if legacy {
+            let url = format!("{}/api/embeddings", base_url);
+            let body = OllamaLegacyEmbed {
+                model,
+                prompt: text,
+                keep_alive,
+            };
+            let resp = send_with_retries(client.post(&url).json(&body), retries).await?;
+            let resp = resp.error_for_status()?;
+            return Ok(resp.json::<OllamaLegacyEmbedResponse>().await?.embedding);
+        }
+        let url = format!("{}/api/embed", base_url);
+        let body = OllamaEmbed {
+            model,
+            input: vec![text],
+            keep_alive,
+        };
+        let request = client.post(&url).json(&body);
+        let resp = send_with_retries(request, retries)
+            .await?
+            .error_for_status()?;
+        let resp: OllamaEmbedResponse = resp.json().await?;
+        Ok(resp.embeddings.into_iter().next().unwrap_or_default())
+    };
+    send.await.map_err(|e: reqwest::Error| e.to_string())
+}
+
+// Several texts in one /api/embed request, one embedding each in order.
+async fn embed_many(
+    client: &reqwest::Client,
+    base_url: &str,
+    model: &str,
+    texts: &[String],
+    retries: u32,
+    keep_alive: Option<serde_json::Value>,
+) -> Result<Vec<Vec<f32>>, Box<dyn std::error::Error>> {
+    let url = format!("{}/api/embed", base_url);
+    let body = OllamaEmbed {
+        model: model.to_string(),
+        input: texts.to_vec(),
+        keep_alive,
+    };
+    let request = client.post(&url).json(&body);
+    let resp = send_with_retries(request, retries)
+        .await?
+        .error_for_status()?;
+    let resp: OllamaEmbedResponse = resp.json().await?;
+    if resp.embeddings.len() != texts.len() {
+        return Err(format!(
+            "asked for {} embeddings, got {}",
+            texts.len(),
+            resp.embeddings.len()
+        )
+        .into());
+    }
+    Ok(resp.embeddings)
 }
 
 impl OllamaClient {
-    pub fn new(config: &LlmConfig) -> Self {
-        Self {
+    pub fn new(config: &LlmConfig) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(Self {
             base_url: config.endpoint.clone(),
             model: config.model.clone(),
             embed_model: config.embed_model.clone(),
-            client: reqwest::blocking::Client::new(),
-        }
+            client: http::builder(config)?.build()?,
+            retries: config.retries,
+            options: config.options.clone(),
+            keep_alive: config.keep_alive.clone(),
+            capabilities: OnceLock::new(),
+            context_window: OnceLock::new(),
+            usage: Mutex::new(Usage::default()),
+        })
     }
 
-    pub fn generate(&self, prompt: &str) -> Result<String, Box<dyn std::error::Error>> {
-        let url = format!("{}/api/generate", self.base_url);
-        let body = OllamaGenerate {
+    fn request_body(
+        &self,
+        prompt: &str,
+        format: Option<serde_json::Value>,
+        stream: bool,
+    ) -> OllamaGenerate {
+        OllamaGenerate {
             model: self.model.clone(),
             prompt: prompt.to_string(),
-            stream: false,
-        };
-        let resp: OllamaGenerateResponse = self.client.post(&url).json(&body).send()?.json()?;
+            stream,
+            format,
+            options: self.options.clone(),
+            keep_alive: self.keep_alive(),
+        }
+    }
+
+    // Servers too old to know keep_alive would reject it.
+    fn keep_alive(&self) -> Option<serde_json::Value> {
+        self.keep_alive
+            .clone()
+            .filter(|_| self.capabilities().keep_alive)
+    }
+
+    fn send_generate(
+        &self,
+        prompt: &str,
+        format: Option<serde_json::Value>,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        let url = format!("{}/api/generate", self.base_url);
+        let body = self.request_body(prompt, format, false);
+        let started = Instant::now();
+        let resp: OllamaGenerateResponse = interruptible(async {
+            let request = self.client.post(&url).json(&body);
+            send_with_retries(request, self.retries)
+                .await?
+                .error_for_status()?
+                .json()
+                .await
+        })?;
+        self.count_latency(started);
+        self.count_usage(&resp);
         Ok(resp.response)
     }
 
-    pub fn embed(&self, text: &str) -> Result<Vec<f32>, Box<dyn std::error::Error>> {
-        let url = format!("{}/api/embed", self.base_url);
-        let body = OllamaEmbed {
-            model: self.embed_model.clone(),
-            input: text.to_string(),
-        };
-        let resp: OllamaEmbedResponse = self.client.post(&url).json(&body).send()?.json()?;
-        Ok(resp.embeddings.into_iter().next().unwrap_or_default())
+    // A streamed reply is one JSON object per line; the last has the counts.
+    fn stream(
+        &self,
+        path: &str,
+        body: &impl Serialize,
+        on_chunk: &mut dyn FnMut(&str),
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        let url = format!("{}{}", self.base_url, path);
+        let mut reply = String::new();
+        let started = Instant::now();
+        interruptible(async {
+            let request = self.client.post(&url).json(body);
+            let resp = send_with_retries(request, self.retries)
+                .await?
+                .error_for_status()?;
+            for_each_line(resp, |line| {
+                if line.trim().is_empty() {
+                    return Ok(true);
+                }
+                let chunk: OllamaGenerateResponse = serde_json::from_str(line)?;
+                on_chunk(chunk.text());
+                reply.push_str(chunk.text());
+                self.count_usage(&chunk);
+                Ok(!chunk.done)
+            })
+            .await
+        })?;
+        self.count_latency(started);
+        Ok(reply)
     }
 
-    pub fn is_available(&self) -> bool {
+    fn count_latency(&self, started: Instant) {
+        if let Ok(mut usage) = self.usage.lock() {
+            usage.latency_ms += started.elapsed().as_millis() as u64;
+        }
+    }
+
+    fn count_usage(&self, resp: &OllamaGenerateResponse) {
+        if let Ok(mut usage) = self.usage.lock() {
+            usage.prompt_tokens += resp.prompt_eval_count;
+            usage.completion_tokens += resp.eval_count;
+        }
+    }
+
+    fn embed_task(&self, text: &str) -> impl Future<Output = Result<Vec<f32>, String>> {
+        embed_one(
+            self.client.clone(),
+            self.base_url.clone(),
+            self.embed_model.clone(),
+            text.to_string(),
+            !self.capabilities().embeddings,
+            self.retries,
+            self.keep_alive(),
+        )
+    }
+}
+
+impl LlmProvider for OllamaClient {
+    fn name(&self) -> &str {
+        "ollama"
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn embed_model(&self) -> &str {
+        &self.embed_model
+    }
+
+    fn endpoint(&self) -> &str {
+        &self.base_url
+    }
+
+    fn is_available(&self) -> bool {
         let url = format!("{}/api/tags", self.base_url);
-        self.client.get(&url).send().is_ok()
+        block_on(async { self.client.get(&url).send().await }).is_ok()
+    }
+
+    // num_ctx from [llm.options], else the Modelfile's, else Ollama's
+    // default; never more than the model was trained with.
+    fn context_window(&self) -> Option<usize> {
+        *self.context_window.get_or_init(|| {
+            let url = format!("{}/api/show", self.base_url);
+            let body = serde_json::json!({ "model": self.model });
+            let show = block_on(async {
+                let resp = self.client.post(&url).json(&body).send().await?;
+                resp.error_for_status()?.json::<OllamaShow>().await
+            })
+            .ok()?;
+            let trained = show
+                .model_info
+                .iter()
+                .find(|(key, _)| key.ends_with(".context_length"))
+                .and_then(|(_, value)| value.as_u64());
+            let num_ctx = self
+                .options
+                .get("num_ctx")
+                .and_then(|v| v.as_u64())
+                .or_else(|| {
+                    show.parameters
+                        .lines()
+                        .find_map(|l| l.trim().strip_prefix("num_ctx"))
+                        .and_then(|n| n.trim().parse().ok())
+                });
+            let window = num_ctx.map_or(DEFAULT_NUM_CTX, |n| n as usize);
+            Some(trained.map_or(window, |t| window.min(t as usize)))
+        })
+    }
+
+    fn list_models(&self) -> Result<Vec<ModelInfo>, Box<dyn std::error::Error>> {
+        let url = format!("{}/api/tags", self.base_url);
+        let tags = block_on(async {
+            let resp = self.client.get(&url).send().await?.error_for_status()?;
+            resp.json::<OllamaTags>().await
+        })?;
+        let mut models: Vec<ModelInfo> = tags
+            .models
+            .into_iter()
+            .map(|m| {
+                let d = m.details;
+                let details: Vec<String> = [d.family, d.parameter_size, d.quantization_level]
+                    .into_iter()
+                    .filter(|s| !s.is_empty())
+                    .collect();
+                ModelInfo {
+                    name: m.name,
+                    size: m.size,
+                    details: details.join(" "),
+                }
+            })
+            .collect();
+        models.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(models)
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        *self.capabilities.get_or_init(|| {
+            let url = format!("{}/api/version", self.base_url);
+            block_on(async {
+                self.client
+                    .get(&url)
+                    .send()
+                    .await?
+                    .json::<OllamaVersion>()
+                    .await
+            })
+            .map(|v| capabilities_for(&v.version))
+            .unwrap_or_default()
+        })
+    }
+
+    fn generate(&self, prompt: &str) -> Result<String, Box<dyn std::error::Error>> {
+        self.send_generate(prompt, None)
+    }
+
+    fn generate_json(&self, prompt: &str) -> Result<String, Box<dyn std::error::Error>> {
+        let format = self.capabilities().json_mode.then(|| "json".into());
+        self.send_generate(prompt, format)
+    }
+
+    fn stream_json(
+        &self,
+        prompt: &str,
+        on_chunk: &mut dyn FnMut(&str),
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        let format = self.capabilities().json_mode.then(|| "json".into());
+        let body = self.request_body(prompt, format, true);
+        self.stream("/api/generate", &body, on_chunk)
+    }
+
+    // The system message is identical from one query to the next, so the
+    // server can reuse its evaluation. Servers with structured outputs
+    // constrain the reply to `schema`; older ones only to valid JSON.
+    fn stream_chat(
+        &self,
+        system: &str,
+        user: &str,
+        schema: &serde_json::Value,
+        on_chunk: &mut dyn FnMut(&str),
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        let capabilities = self.capabilities();
+        if !capabilities.chat {
+            return self.stream_json(&format!("{}\n\n{}", system, user), on_chunk);
+        }
+        let message = |role: &str, content: &str| ChatMessage {
+            role: role.to_string(),
+            content: content.to_string(),
+        };
+        let body = OllamaChat {
+            model: self.model.clone(),
+            messages: vec![message("system", system), message("user", user)],
+            stream: true,
+            format: if capabilities.json_schema {
+                Some(schema.clone())
+            } else {
+                capabilities.json_mode.then(|| "json".into())
+            },
+            options: self.options.clone(),
+            keep_alive: self.keep_alive(),
+        };
+        self.stream("/api/chat", &body, on_chunk)
+    }
+
+    fn embed(&self, text: &str) -> Result<Vec<f32>, Box<dyn std::error::Error>> {
+        Ok(block_on(self.embed_task(text))?)
+    }
+
+    // One request for the lot. If the server rejects it, or predates
+    // batching, requests go out one text each, together, so one bad text
+    // costs only its own embedding.
+    fn embed_batch(&self, texts: &[String]) -> Vec<Result<Vec<f32>, Box<dyn std::error::Error>>> {
+        if self.capabilities().embeddings {
+            let batch = block_on(embed_many(
+                &self.client,
+                &self.base_url,
+                &self.embed_model,
+                texts,
+                self.retries,
+                self.keep_alive(),
+            ));
+            if let Ok(embeddings) = batch {
+                return embeddings.into_iter().map(Ok).collect();
+            }
+        }
+        let tasks: Vec<_> = texts
+            .iter()
+            .map(|t| runtime().spawn(self.embed_task(t)))
+            .collect();
+        block_on(async {
+            let mut results = Vec::with_capacity(tasks.len());
+            for task in tasks {
+                let result = match task.await {
+                    Ok(result) => result.map_err(Into::into),
+                    Err(e) => Err(e.to_string().into()),
+                };
+                results.push(result);
+            }
+            results
+        })
+    }
+
+    // A request without a prompt just loads the model.
+    fn warm(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let url = format!("{}/api/generate", self.base_url);
+        let body = self.request_body("", None, false);
+        block_on(async {
+            let request = self.client.post(&url).json(&body);
+            send_with_retries(request, self.retries)
+                .await?
+                .error_for_status()
+        })?;
+        Ok(())
+    }
+
+    fn take_usage(&self) -> Usage {
+        self.usage
+            .lock()
+            .map(|mut u| std::mem::take(&mut *u))
+            .unwrap_or_default()
     }
 }
@@ -0,0 +1,35 @@
+use crate::config::PathsConfig;
+use std::path::PathBuf;
+
+const APP_NAME: &str = "pls";
+
+pub fn data_dir(config: &PathsConfig) -> PathBuf {
+    config.data_dir.clone().unwrap_or_else(|| {
+        dirs::data_local_dir()
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join(APP_NAME)
+    })
+}
+
+// XDG_STATE_HOME only exists on Linux; elsewhere keep state in its own
+// subdirectory of the platform data dir so it never mixes with the index.
+pub fn state_dir(config: &PathsConfig) -> PathBuf {
+    config
+        .state_dir
+        .clone()
+        .unwrap_or_else(|| match dirs::state_dir() {
+            Some(dir) => dir.join(APP_NAME),
+            None => dirs::data_local_dir()
+                .unwrap_or_else(|| PathBuf::from("."))
+                .join(APP_NAME)
+                .join("state"),
+        })
+}
+
+pub fn cache_dir(config: &PathsConfig) -> PathBuf {
+    config.cache_dir.clone().unwrap_or_else(|| {
+        dirs::cache_dir()
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join(APP_NAME)
+    })
+}
@@ -0,0 +1,301 @@
+// `pls "find failed ssh logins" | pls "summarize by source IP"`. A pls whose
+// stdout isn't a terminal keeps it for its commands' output and shows
+// everything else on stderr. Each end of a pipe between two pls runs leaves
+// a marker, named after the pipe, in the state directory, so the writer
+// knows to frame what it sends (which query, which commands) and the reader
+// knows to wait for it. A reader only waits when another pls holds the
+// other end of its stdin; `cat f | pls ...` starts at once. The reader shows the model a sample of the input,
+// hands all of it to its commands on stdin, and takes its answers from the
+// terminal.
+
+use crate::config::PathsConfig;
+use crate::encoding::decode;
+use crate::paths::state_dir;
+use crate::privacy;
+use serde::{Deserialize, Serialize};
+use std::{
+    env,
+    fs::{self, File},
+    io::{Read, Write},
+    os::unix::io::FromRawFd,
+    path::PathBuf,
+    process::Stdio,
+    sync::{Mutex, OnceLock},
+    thread,
+    time::{Duration, Instant},
+};
+
+const MAGIC: &str = "PLS-PIPE/1 ";
+const DIR: &str = "pipes";
+// Both ends start together; this is how long the reader gives a writer it
+// knows is pls to leave its marker.
+const RENDEZVOUS: Duration = Duration::from_millis(200);
+const SAMPLE_LINES: usize = 20;
+const SAMPLE_CHARS: usize = 2000;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Header {
+    query: String,
+    commands: Vec<String>,
+}
+
+/// What came in from the pls before this one in a pipe.
+struct Input {
+    header: Header,
+    data: PathBuf,
+    lines: usize,
+    sample: String,
+}
+
+// Where commands' output goes once pls's own stdout is pointed at stderr.
+struct Output {
+    file: File,
+    query: String,
+    reader: Option<PathBuf>,
+    started: bool,
+}
+
+static INPUT: OnceLock<Input> = OnceLock::new();
+static OUTPUT: Mutex<Option<Output>> = Mutex::new(None);
+static MARKERS: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+fn isatty(fd: i32) -> bool {
+    unsafe { libc::isatty(fd) == 1 }
+}
+
+// The pipe's inode, when `fd` is one.
+fn fifo(fd: i32) -> Option<u64> {
+    let mut stat: libc::stat = unsafe { std::mem::zeroed() };
+    if unsafe { libc::fstat(fd, &mut stat) } != 0 {
+        return None;
+    }
+    ((stat.st_mode & libc::S_IFMT) == libc::S_IFIFO).then_some(stat.st_ino as u64)
+}
+
+fn marker(paths: &PathsConfig, pipe: u64, end: &str) -> PathBuf {
+    state_dir(paths).join(DIR).join(format!("{}.{}", pipe, end))
+}
+
+// Whether the pls that left `marker` is still running.
+fn claimed(marker: &PathBuf) -> bool {
+    let pid = fs::read_to_string(marker)
+        .ok()
+        .and_then(|s| s.trim().parse::<i32>().ok());
+    pid.is_some_and(|pid| unsafe { libc::kill(pid, 0) == 0 })
+}
+
+// Whether a pls other than this one has `pipe` for its stdout, as far as
+// /proc tells; where it tells nothing, whether its marker is already left.
+fn written_by_pls(paths: &PathsConfig, pipe: u64) -> bool {
+    if claimed(&marker(paths, pipe, "writer")) {
+        return true;
+    }
+    let (Ok(exe), Ok(procs)) = (env::current_exe(), fs::read_dir("/proc")) else {
+        return false;
+    };
+    let stdout = format!("pipe:[{}]", pipe);
+    procs.filter_map(|e| e.ok()).any(|entry| {
+        let dir = entry.path();
+        fs::read_link(dir.join("fd/1")).is_ok_and(|link| link.as_os_str() == stdout.as_str())
+            && fs::read_link(dir.join("exe")).is_ok_and(|path| path == exe)
+    })
+}
+
+fn leave(marker: PathBuf) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(dir) = marker.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(&marker, std::process::id().to_string())?;
+    MARKERS.lock().unwrap().push(marker);
+    Ok(())
+}
+
+// Markers and data left by runs that didn't get to clean up.
+fn prune(paths: &PathsConfig) {
+    let Ok(entries) = fs::read_dir(state_dir(paths).join(DIR)) else {
+        return;
+    };
+    for path in entries.filter_map(|e| e.ok()).map(|e| e.path()) {
+        // Data belongs to the reader.
+        let owner = match path.extension().and_then(|e| e.to_str()) {
+            Some("writer" | "reader") => path.clone(),
+            Some("data") => path.with_extension("reader"),
+            _ => continue,
+        };
+        if !claimed(&owner) {
+            fs::remove_file(&path).ok();
+        }
+    }
+}
+
+/// Sets up both ends for a run of `query`. `divert` is false when pls
+/// prints something meant for stdout itself (--json, --explain).
+pub fn setup(
+    paths: &PathsConfig,
+    query: &str,
+    divert: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let writing = divert && !isatty(libc::STDOUT_FILENO);
+    let reading = fifo(libc::STDIN_FILENO);
+    if !writing && reading.is_none() {
+        return Ok(());
+    }
+    prune(paths);
+
+    if writing {
+        let reader = fifo(libc::STDOUT_FILENO).map(|pipe| {
+            leave(marker(paths, pipe, "writer")).ok();
+            marker(paths, pipe, "reader")
+        });
+        let file = unsafe {
+            let fd = libc::dup(libc::STDOUT_FILENO);
+            if fd < 0 {
+                return Err(std::io::Error::last_os_error().into());
+            }
+            libc::dup2(libc::STDERR_FILENO, libc::STDOUT_FILENO);
+            File::from_raw_fd(fd)
+        };
+        *OUTPUT.lock().unwrap() = Some(Output {
+            file,
+            query: query.to_string(),
+            reader,
+            started: false,
+        });
+    }
+
+    let Some(pipe) = reading.filter(|&pipe| written_by_pls(paths, pipe)) else {
+        return Ok(());
+    };
+    let reader = marker(paths, pipe, "reader");
+    leave(reader.clone())?;
+    let writer = marker(paths, pipe, "writer");
+    let started = Instant::now();
+    while !claimed(&writer) {
+        if started.elapsed() > RENDEZVOUS {
+            // Unframed, then, like any other input.
+            fs::remove_file(&reader).ok();
+            return Ok(());
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    eprintln!("waiting for the pls before this one...");
+    let mut bytes = Vec::new();
+    std::io::stdin().lock().read_to_end(&mut bytes)?;
+    let (header, payload) = match bytes.strip_prefix(MAGIC.as_bytes()) {
+        Some(rest) => {
+            let end = rest.iter().position(|&b| b == b'\n').unwrap_or(rest.len());
+            let header: Header = serde_json::from_slice(&rest[..end]).unwrap_or_default();
+            (header, rest.get(end + 1..).unwrap_or_default())
+        }
+        None if bytes.is_empty() => return Err("the pls before this one ran nothing".into()),
+        None => (Header::default(), &bytes[..]),
+    };
+
+    let data = marker(paths, pipe, "data");
+    fs::write(&data, payload)?;
+    MARKERS.lock().unwrap().push(data.clone());
+    let text = decode(payload);
+    let sample: String = text
+        .lines()
+        .take(SAMPLE_LINES)
+        .collect::<Vec<_>>()
+        .join("\n")
+        .chars()
+        .take(SAMPLE_CHARS)
+        .collect();
+    let input = Input {
+        header,
+        data,
+        lines: text.lines().count(),
+        sample,
+    };
+    INPUT.set(input).ok();
+
+    // Stdin is spent; prompts read the terminal instead, if there is one.
+    unsafe {
+        let tty = libc::open(c"/dev/tty".as_ptr(), libc::O_RDWR);
+        let fd = if tty >= 0 {
+            tty
+        } else {
+            libc::open(c"/dev/null".as_ptr(), libc::O_RDONLY)
+        };
+        if fd >= 0 {
+            libc::dup2(fd, libc::STDIN_FILENO);
+            libc::close(fd);
+        }
+    }
+    crate::terminal::install();
+    Ok(())
+}
+
+/// Removes this run's markers and data.
+pub fn finish() {
+    for path in MARKERS.lock().unwrap().drain(..) {
+        fs::remove_file(path).ok();
+    }
+}
+
+/// Whether commands' output is going somewhere other than the terminal.
+pub fn forwarding() -> bool {
+    OUTPUT.lock().unwrap().is_some()
+}
+
+pub fn has_input() -> bool {
+    INPUT.get().is_some()
+}
+
+/// Stdin for a command: what was piped in, from the start, if anything was.
+pub fn stdin() -> Stdio {
+    INPUT
+        .get()
+        .and_then(|input| File::open(&input.data).ok())
+        .map_or_else(Stdio::inherit, Stdio::from)
+}
+
+/// Sends the stdout of `commands` on to wherever pls's stdout went, framed
+/// when that is another pls. Nothing to do when it is the terminal.
+pub fn forward(commands: &[String], bytes: &[u8]) {
+    let mut output = OUTPUT.lock().unwrap();
+    let Some(output) = output.as_mut() else {
+        return;
+    };
+    if !output.started && output.reader.as_ref().is_some_and(claimed) {
+        let header = Header {
+            query: output.query.clone(),
+            commands: commands.to_vec(),
+        };
+        let line = serde_json::to_string(&header).unwrap_or_default();
+        writeln!(output.file, "{}{}", MAGIC, line).ok();
+    }
+    output.started = true;
+    output.file.write_all(bytes).ok();
+    output.file.flush().ok();
+}
+
+/// The prompt's account of the piped-in input. `share` is false for remote
+/// models that may not see command output; they get its shape only.
+pub fn prompt_block(share: bool) -> String {
+    let Some(input) = INPUT.get() else {
+        return String::new();
+    };
+    let source = if input.header.query.is_empty() {
+        "another pls run".to_string()
+    } else {
+        format!(
+            "`pls \"{}\"`, which ran: {}",
+            input.header.query,
+            input.header.commands.join(" && ")
+        )
+    };
+    let sample = if share {
+        format!("First lines:\n{}\n", privacy::redact(&input.sample))
+    } else {
+        String::new()
+    };
+    format!(
+        "\nINPUT: {} lines piped in from {}.\n{}Every command gets this input on stdin; \
+         read it from there, not from a file.\n",
+        input.lines, source, sample
+    )
+}
@@ -0,0 +1,117 @@
+// Plans that ran fine, kept for asking the same thing again. An entry is
+// keyed on the query (spacing and case aside, outside quotes), the directory,
+// the model, the persona and the tools, facts and docs retrieval found for it, so
+// a new index, a new fact, another model or persona asks the model afresh. Least recently used
+// entries go first once `cache.max_entries` is reached.
+
+use crate::clock::now;
+use crate::config::{CacheConfig, LlmConfig};
+use crate::embed::fnv1a;
+use crate::retrieval::Retrieved;
+use crate::types::Plan;
+use rusqlite::{params, Connection, OptionalExtension};
+use std::env;
+
+// `Count  Lines of code` and `count lines of code` are the same question;
+// `grep "ERROR"` and `grep "error"` aren't.
+fn normalize(query: &str) -> String {
+    let mut out = String::new();
+    let mut quote: Option<char> = None;
+    for word in query.split_whitespace() {
+        if !out.is_empty() {
+            out.push(' ');
+        }
+        for c in word.chars() {
+            match (quote, c) {
+                (Some(q), c) if c == q => quote = None,
+                (None, '"' | '\'' | '`') => quote = Some(c),
+                _ => {}
+            }
+            if quote.is_none() {
+                out.extend(c.to_lowercase());
+            } else {
+                out.push(c);
+            }
+        }
+    }
+    out
+}
+
+/// The cache key for `query` asked in the current directory of `llm`'s
+/// model in `style` (the persona's part of the prompt), with `found`
+/// retrieved for it.
+pub fn key(query: &str, found: &Retrieved, llm: &LlmConfig, style: &str) -> String {
+    let cwd = env::current_dir().unwrap_or_default();
+    let mut tools: Vec<String> = found
+        .tools
+        .iter()
+        .map(|t| format!("{}/{}", t.source, t.name))
+        .collect();
+    tools.sort();
+    let mut facts: Vec<&str> = found.facts.iter().map(|f| f.text.as_str()).collect();
+    facts.extend(found.docs.iter().map(|d| d.text.as_str()));
+    let text = format!(
+        "{}\n{}\n{}\n{}\n{}\n{}\n{}",
+        llm.provider,
+        llm.model,
+        style,
+        cwd.display(),
+        normalize(query),
+        tools.join(" "),
+        facts.join("\n")
+    );
+    format!("{:016x}", fnv1a(text.as_bytes()))
+}
+
+/// The plan kept under `key`, unless it is older than `cache.ttl_minutes`.
+pub fn get(conn: &Connection, config: &CacheConfig, key: &str) -> Option<Plan> {
+    if !config.enabled || key.is_empty() {
+        return None;
+    }
+    let since = now() - config.ttl_minutes as i64 * 60;
+    let json: String = conn
+        .query_row(
+            "SELECT plan FROM plan_cache WHERE key = ?1 AND created >= ?2",
+            params![key, since],
+            |row| row.get(0),
+        )
+        .optional()
+        .ok()??;
+    conn.execute(
+        "UPDATE plan_cache SET used = ?1 WHERE key = ?2",
+        params![now(), key],
+    )
+    .ok();
+    let mut plan: Plan = serde_json::from_str(&json).ok()?;
+    plan.cache_key = key.to_string();
+    Some(plan)
+}
+
+/// Keeps `plan` under its key, then drops the least recently used entries
+/// past `cache.max_entries`.
+pub fn put(
+    conn: &Connection,
+    config: &CacheConfig,
+    plan: &Plan,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if !config.enabled || plan.cache_key.is_empty() {
+        return Ok(());
+    }
+    let time = now();
+    conn.execute(
+        "INSERT OR REPLACE INTO plan_cache (key, plan, created, used) VALUES (?1, ?2, ?3, ?3)",
+        params![plan.cache_key, serde_json::to_string(plan)?, time],
+    )?;
+    conn.execute(
+        "DELETE FROM plan_cache WHERE key NOT IN
+         (SELECT key FROM plan_cache ORDER BY used DESC LIMIT ?1)",
+        params![config.max_entries as i64],
+    )?;
+    Ok(())
+}
+
+/// Drops entries past their TTL; returns how many.
+pub fn prune(conn: &Connection, config: &CacheConfig) -> Result<usize, Box<dyn std::error::Error>> {
+    let since = now() - config.ttl_minutes as i64 * 60;
+    Ok(conn.execute("DELETE FROM plan_cache WHERE created < ?1", params![since])?)
+}
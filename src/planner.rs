@@ -1,45 +1,39 @@
-use crate::ollama::OllamaClient;
-use crate::retrieval::retrieve_relevant_tools;
-use crate::types::{Plan, Tool};
-use std::env;
+use crate::config::{ContextConfig, Persona, PrivacyConfig};
+use crate::context;
+use crate::docs;
+use crate::literals;
+use crate::llm::LlmProvider;
+use crate::pipe;
+use crate::privacy;
+use crate::retrieval::Retrieved;
+use crate::shell::split_chain;
+use crate::temporal::check_plan;
+use crate::types::{DocChunk, Fact, NotPossible, Plan, Severity, StepNotes, Tool, Warning};
+use std::collections::HashMap;
 
-const TOP_K_TOOLS: usize = 8;
+// For sizing prompts without the model's tokenizer; close enough for
+// English and shell.
+const CHARS_PER_TOKEN: usize = 4;
+// Kept free in the context window for the reply.
+const REPLY_TOKENS: usize = 512;
+const CUT: &str = "  (cut to fit the context window)\n";
 
-fn build_prompt(query: &str, tools: &[Tool], cwd: &str, _shell: &str) -> String {
-    let tool_docs: String = tools
-        .iter()
-        .map(|t| {
-            let mut doc = format!("### {}\n", t.name);
-            if !t.description.is_empty() {
-                doc.push_str(&format!("  {}\n", t.description));
-            }
-            if !t.synopsis.is_empty() {
-                doc.push_str(&format!("  Usage: {}\n", t.synopsis));
-            }
-            if !t.flags.is_empty() {
-                doc.push_str(&format!("  Flags: {}\n", t.flags));
-            }
-            if !t.examples.is_empty() {
-                doc.push_str(&format!("  Examples:\n{}\n", t.examples));
-            }
-            doc
-        })
-        .collect::<Vec<_>>()
-        .join("\n");
-
-    format!(
-        r#"You are a Unix command line expert. Generate a shell command to accomplish the task.
-
-AVAILABLE TOOLS:
-{tool_docs}
+// Everything that doesn't change between queries, sent as the system message
+// so the server can keep it evaluated. The tools and the task follow as the
+// user message.
+const SYSTEM_PROMPT: &str = r#"You are a Unix command line expert. Generate a shell command to accomplish the user's task.
 
 STRICT RULES:
-1. Use ONLY tools and flags shown above. Do not invent flags.
-2. If you need a tool not listed, say "I need <tool> which is not available"
+1. Use ONLY the tools and flags listed under AVAILABLE TOOLS. Do not invent flags.
+2. If the task needs a tool that is not listed, or cannot be done at all, do not guess: use the "not possible" response below
 3. Use simple, common patterns. Prefer find, grep, awk, sort, uniq, wc.
 4. For counting lines of code: use find to get files, xargs wc -l
 5. For file sizes: use du -sh or find with -size
 6. Always use relative paths from current directory
+7. Entries marked "user alias" show how the user likes to work. Follow them, but write out the expansion: aliases do not exist in the shell that runs the command.
+8. For a step that creates, changes or archives files, put a quick read-only check in its "verify" (after `tar -czf out.tgz dir`: `tar -tzf out.tgz | head`). Use "" when there is nothing worth checking.
+9. Give every step its own short "explanation", what its output should look like in "expected", and in "risks" anything it could break or get wrong ([] when there is nothing).
+10. Anything under FACTS ABOUT THIS MACHINE is true here and overrides your defaults, including rule 6: use the paths and tools it names.
 
 EXAMPLES OF GOOD COMMANDS:
 - Count lines by extension: find . -name "*.rs" | xargs wc -l
@@ -47,15 +41,327 @@ EXAMPLES OF GOOD COMMANDS:
 - Disk usage: du -sh */ | sort -h
 - Find and count: find . -type f -name "*.log" | wc -l
 
-Current directory: {cwd}
+Respond with ONLY this JSON, no other text:
+{"steps": [{"command": "the command", "explanation": "what this step does", "expected": "what its output looks like", "risks": [], "verify": "check for this step, or \"\""}], "explanation": "what the whole plan does", "warnings": [], "needs_confirmation": true}
 
-TASK: {query}
+If the task is not possible, respond with ONLY this JSON instead:
+{"possible": false, "reason": "why it can't be done", "missing": ["tools or prerequisites that are missing"], "next_steps": ["what the user could do about it"]}"#;
 
-Respond with ONLY this JSON, no other text:
-{{"commands": ["the command"], "explanation": "what it does", "warnings": [], "needs_confirmation": true}}"#,
+/// The personas every config has; `[personas.NAME]` adds more or replaces
+/// one of these.
+pub const PERSONAS: &[&str] = &["conservative", "modern", "posix"];
+
+fn builtin_persona(name: &str) -> Option<Persona> {
+    let list = |items: &[&str]| items.iter().map(|s| s.to_string()).collect();
+    match name {
+        "conservative" => Some(Persona {
+            rules: list(&[
+                "Prefer long-established tools (find, grep, sed, awk, tar, rsync) over newer \
+                 replacements, even when a newer one is listed.",
+                "Before a step that deletes or overwrites files, add one that lists what it \
+                 will touch; move files aside rather than removing them where that works.",
+                "Add a warning for anything that needs root, touches system directories or \
+                 services, or cannot be undone.",
+            ]),
+            prefer: Vec::new(),
+            avoid: Vec::new(),
+        }),
+        "modern" => Some(Persona {
+            rules: list(&[
+                "When a modern replacement is listed, use it over the classic tool: fd for \
+                 find, rg for grep, eza for ls, sd for sed, dust for du. This overrides rule 3.",
+                "Keep warnings to what can actually lose data.",
+            ]),
+            prefer: list(&["fd", "rg", "eza", "bat", "sd", "dust", "jq", "procs"]),
+            avoid: Vec::new(),
+        }),
+        "posix" => Some(Persona {
+            rules: list(&[
+                "Write POSIX sh: no arrays, [[ ]], <(...), {a,b} brace expansion, `local` or \
+                 `function`.",
+                "Use only options POSIX specifies: no GNU long options, no sed -i, no grep -P, \
+                 no find -maxdepth.",
+                "Warn when a step relies on behavior that differs between GNU and BSD tools.",
+            ]),
+            prefer: Vec::new(),
+            avoid: list(&["bash", "zsh"]),
+        }),
+        _ => None,
+    }
+}
+
+/// The persona called `name`: the config's, else a built-in one.
+pub fn find_persona(
+    name: &str,
+    custom: &HashMap<String, Persona>,
+) -> Result<Persona, Box<dyn std::error::Error>> {
+    if let Some(persona) = custom.get(name).cloned().or_else(|| builtin_persona(name)) {
+        return Ok(persona);
+    }
+    let mut names: Vec<&str> = PERSONAS.to_vec();
+    names.extend(
+        custom
+            .keys()
+            .map(String::as_str)
+            .filter(|n| !PERSONAS.contains(n)),
+    );
+    Err(format!(
+        "unknown persona '{}' (available: {})",
+        name,
+        names.join(", ")
+    )
+    .into())
+}
+
+/// What `persona` adds to the system prompt; empty without one.
+pub fn persona_block(persona: Option<&Persona>) -> String {
+    let Some(persona) = persona else {
+        return String::new();
+    };
+    let mut block = String::from("\n\nHOUSE STYLE (these take precedence over the rules above):\n");
+    for rule in &persona.rules {
+        block.push_str(&format!("- {}\n", rule));
+    }
+    if !persona.prefer.is_empty() {
+        block.push_str(&format!(
+            "- Prefer these tools when they are listed: {}\n",
+            persona.prefer.join(", ")
+        ));
+    }
+    if !persona.avoid.is_empty() {
+        block.push_str(&format!("- Do not use: {}\n", persona.avoid.join(", ")));
+    }
+    block
+}
+
+// The two replies SYSTEM_PROMPT allows, for backends that can hold the
+// model to them.
+fn reply_schema() -> serde_json::Value {
+    let strings = serde_json::json!({"type": "array", "items": {"type": "string"}});
+    let step = serde_json::json!({
+        "type": "object",
+        "properties": {
+            "command": {"type": "string"},
+            "explanation": {"type": "string"},
+            "expected": {"type": "string"},
+            "risks": strings,
+            "verify": {"type": "string"},
+        },
+        "required": ["command", "explanation", "expected", "risks", "verify"],
+    });
+    serde_json::json!({
+        "anyOf": [
+            {
+                "type": "object",
+                "properties": {
+                    "steps": {"type": "array", "items": step},
+                    "explanation": {"type": "string"},
+                    "warnings": strings,
+                    "needs_confirmation": {"type": "boolean"},
+                },
+                "required": ["steps", "explanation", "warnings", "needs_confirmation"],
+            },
+            {
+                "type": "object",
+                "properties": {
+                    "possible": {"type": "boolean"},
+                    "reason": {"type": "string"},
+                    "missing": strings,
+                    "next_steps": strings,
+                },
+                "required": ["possible", "reason", "missing", "next_steps"],
+            },
+        ]
+    })
+}
+
+const MAX_FOLLOW_UPS: usize = 3;
+// Of the output, what the follow-up prompt sees.
+const FOLLOW_UP_OUTPUT_CHARS: usize = 2000;
+
+const FOLLOW_UP_PROMPT: &str = "Suggest up to 3 short tasks the user is likely to want next, \
+going by what these commands printed: sorting or filtering the results, acting on them, saving \
+them. Each is planned on its own later, without this conversation, so write each as a complete \
+task naming the files, directories or patterns involved (\"delete the empty .log files in \
+./logs\", not \"delete them\"). Respond with ONLY this JSON: {\"follow_ups\": [\"task\"]}";
+
+/// Queries the user might ask after `commands` ran for `query`, going by
+/// what they printed; `output` is None when that may not leave the machine.
+pub fn suggest_follow_ups(
+    client: &dyn LlmProvider,
+    query: &str,
+    commands: &[String],
+    output: Option<&str>,
+) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let output = match output {
+        Some(output) => {
+            let sample: String = output.chars().take(FOLLOW_UP_OUTPUT_CHARS).collect();
+            format!("Output:\n{}\n", sample)
+        }
+        None => "Output: (not shared)\n".to_string(),
+    };
+    let prompt = format!(
+        "The user asked: {}\nCommands run:\n{}\n{}\n{}",
+        query,
+        commands.join("\n"),
+        output,
+        FOLLOW_UP_PROMPT
+    );
+    let reply = client.generate_json(&prompt)?;
+    let start = reply.find('{').unwrap_or(0);
+    let end = reply.rfind('}').map_or(reply.len(), |e| e + 1);
+    let parsed: serde_json::Value = serde_json::from_str(reply.get(start..end).unwrap_or(""))?;
+    Ok(string_list(&parsed["follow_ups"])
+        .into_iter()
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty() && s != query)
+        .take(MAX_FOLLOW_UPS)
+        .collect())
+}
+
+// Sent once when a reply can't be read, with what was wrong with it.
+fn retry_prompt(prompt: &str, error: &dyn std::error::Error) -> String {
+    format!(
+        "{}\n\nYour previous reply could not be used ({}). Reply with ONLY one of the JSON \
+         objects described above.",
+        prompt, error
+    )
+}
+
+// One tool's section of the prompt.
+fn tool_doc(t: &Tool) -> String {
+    if t.source == "alias" {
+        return format!(
+            "### {} (user alias)\n  Expands to: {}\n",
+            t.name, t.synopsis
+        );
+    }
+    let mut doc = format!("### {}\n", t.name);
+    for line in t.description.lines() {
+        doc.push_str(&format!("  {}\n", line));
+    }
+    if !t.synopsis.is_empty() {
+        doc.push_str(&format!("  Usage: {}\n", t.synopsis));
+    }
+    // Indexed flags come a line each with what they do; the catalog's are
+    // a plain list.
+    if t.flags.contains('\n') {
+        doc.push_str("  Flags:\n");
+        for line in t.flags.lines() {
+            doc.push_str(&format!("    {}\n", line));
+        }
+    } else if !t.flags.is_empty() {
+        doc.push_str(&format!("  Flags: {}\n", t.flags));
+    }
+    if !t.examples.is_empty() {
+        doc.push_str(&format!("  Examples:\n{}\n", t.examples));
+    }
+    if !t.notes.is_empty() {
+        doc.push_str("  Notes from the user (always follow):\n");
+        for note in &t.notes {
+            doc.push_str(&format!("    - {}\n", note));
+        }
+    }
+    if t.record.unreliable() {
+        doc.push_str(&format!(
+            "  On this machine {} of the last {} commands using it failed; prefer \
+             another listed tool if one fits.\n",
+            t.record.failures, t.record.runs
+        ));
+    }
+    doc
+}
+
+// A tool past those documented in full: what it is and how it's called, and
+// what the user and this machine have to say about it.
+fn brief_doc(t: &Tool) -> String {
+    let brief = Tool {
+        description: t.description.lines().next().unwrap_or_default().to_string(),
+        flags: String::new(),
+        examples: String::new(),
+        ..t.clone()
+    };
+    tool_doc(&brief)
+}
+
+// Cuts every doc to the same share of itself, at line ends, so that all of
+// them fit in `budget` bytes. Each keeps at least its heading; when the
+// headings alone don't fit, the least relevant tools go.
+fn fit_docs(docs: &mut Vec<String>, budget: usize) {
+    let heading = |doc: &str| doc.find('\n').map_or(doc.len(), |i| i + 1);
+    while docs.len() > 1
+        && docs
+            .iter()
+            .map(|d| heading(d) + CUT.len() + 1)
+            .sum::<usize>()
+            > budget
+    {
+        docs.pop();
+    }
+    let total: usize = docs.iter().map(|d| d.len() + 1).sum();
+    if total <= budget {
+        return;
+    }
+    for doc in docs.iter_mut() {
+        let share = ((doc.len() + 1) * budget / total).saturating_sub(CUT.len() + 1);
+        let mut end = heading(doc);
+        for line in doc[end..].split_inclusive('\n') {
+            if end + line.len() > share {
+                break;
+            }
+            end += line.len();
+        }
+        if end < doc.len() {
+            doc.truncate(end);
+            doc.push_str(CUT);
+        }
+    }
+}
+
+// The per-query half of the prompt.
+fn build_prompt(
+    query: &str,
+    docs: &[String],
+    facts: &[Fact],
+    own_docs: &[DocChunk],
+    context: &str,
+    literals: &str,
+) -> String {
+    let tool_docs = docs.join("\n");
+
+    let facts: String = if facts.is_empty() {
+        String::new()
+    } else {
+        let lines: String = facts.iter().map(|f| format!("- {}\n", f.text)).collect();
+        format!("\nFACTS ABOUT THIS MACHINE:\n{}", lines)
+    };
+    let own_docs: String = if own_docs.is_empty() {
+        String::new()
+    } else {
+        let sections: String = own_docs
+            .iter()
+            .map(|d| format!("[{}]\n{}\n\n", docs::label(d), d.text))
+            .collect();
+        format!(
+            "\nTHE USER'S OWN DOCS (their team's tools and conventions; follow them):\n{}",
+            sections
+        )
+    };
+
+    format!(
+        r#"AVAILABLE TOOLS:
+{tool_docs}
+{facts}{own_docs}
+{context}
+
+TASK: {query}
+{literals}"#,
         tool_docs = tool_docs,
-        cwd = cwd,
-        query = query
+        facts = facts,
+        own_docs = own_docs,
+        context = context,
+        query = query,
+        literals = literals
     )
 }
 
@@ -70,48 +376,222 @@ fn parse_plan(response: &str) -> Result<Plan, Box<dyn std::error::Error>> {
     };
 
     let parsed: serde_json::Value = serde_json::from_str(json_str)?;
+    if ["steps", "commands", "possible"]
+        .iter()
+        .all(|key| parsed.get(key).is_none())
+    {
+        return Err("the reply has none of \"steps\", \"commands\" or \"possible\"".into());
+    }
+
+    // Older prompts asked for flat "commands" and "verify" lists.
+    let (commands, verify, steps) = match parsed["steps"].as_array() {
+        Some(steps) => {
+            let steps: Vec<&serde_json::Value> =
+                steps.iter().filter(|s| s["command"].is_string()).collect();
+            let text = |value: &serde_json::Value| value.as_str().unwrap_or_default().to_string();
+            (
+                steps.iter().map(|s| text(&s["command"])).collect(),
+                steps
+                    .iter()
+                    .map(|s| optional_string(&s["verify"]))
+                    .collect(),
+                steps
+                    .iter()
+                    .map(|s| StepNotes {
+                        explanation: text(&s["explanation"]),
+                        expected: text(&s["expected"]),
+                        risks: string_list(&s["risks"]),
+                    })
+                    .collect(),
+            )
+        }
+        None => {
+            let verify = parsed["verify"]
+                .as_array()
+                .map(|arr| arr.iter().map(optional_string).collect())
+                .unwrap_or_default();
+            (string_list(&parsed["commands"]), verify, Vec::new())
+        }
+    };
+
+    // Older prompts and weaker models just return no commands; treat that
+    // as "not possible" too and keep whatever explanation came with it.
+    let not_possible = if parsed["possible"] == false || commands.is_empty() {
+        Some(NotPossible {
+            reason: parsed["reason"]
+                .as_str()
+                .or(parsed["explanation"].as_str())
+                .unwrap_or("the model gave no reason")
+                .to_string(),
+            missing: string_list(&parsed["missing"]),
+            next_steps: string_list(&parsed["next_steps"]),
+        })
+    } else {
+        None
+    };
 
     Ok(Plan {
-        commands: parsed["commands"]
-            .as_array()
-            .map(|arr| {
-                arr.iter()
-                    .filter_map(|v| v.as_str().map(String::from))
-                    .collect()
-            })
-            .unwrap_or_default(),
+        commands: if not_possible.is_some() {
+            Vec::new()
+        } else {
+            commands
+        },
         explanation: parsed["explanation"]
             .as_str()
             .unwrap_or("Execute the command(s)")
             .to_string(),
-        warnings: parsed["warnings"]
-            .as_array()
-            .map(|arr| {
-                arr.iter()
-                    .filter_map(|v| v.as_str().map(String::from))
-                    .collect()
-            })
-            .unwrap_or_default(),
+        warnings: string_list(&parsed["warnings"])
+            .into_iter()
+            .map(|w| Warning::new(Severity::Caution, w))
+            .collect(),
         needs_confirmation: parsed["needs_confirmation"].as_bool().unwrap_or(true),
+        not_possible,
+        verify,
+        steps,
+        provenance: Vec::new(),
+        sent_chars: 0,
+        raw_reply: String::new(),
+        cache_key: String::new(),
     })
 }
 
-pub fn generate_plan(
-    client: &OllamaClient,
-    conn: &rusqlite::Connection,
+// A string with something in it.
+fn optional_string(value: &serde_json::Value) -> Option<String> {
+    value
+        .as_str()
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(String::from)
+}
+
+fn string_list(value: &serde_json::Value) -> Vec<String> {
+    value
+        .as_array()
+        .map(|arr| {
+            arr.iter()
+                .filter_map(|v| v.as_str().map(String::from))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+// Every doc the prompt included, in retrieval order, then the facts and the
+// user's own docs.
+fn provenance(found: &Retrieved) -> Vec<String> {
+    let mut sources = Vec::new();
+    for tool in &found.tools {
+        sources.push(format!("{}/{}", tool.source, tool.name));
+        if !tool.notes.is_empty() {
+            sources.push(format!("note/{}", tool.name));
+        }
+        if tool.record.unreliable() {
+            sources.push(format!("runs/{}", tool.name));
+        }
+    }
+    sources.extend(found.facts.iter().map(|f| format!("fact/{}", f.id)));
+    sources.extend(found.docs.iter().map(|d| format!("doc/{}", docs::label(d))));
+    sources
+}
+
+pub fn generate_plan_with_tools(
+    client: &dyn LlmProvider,
     query: &str,
+    found: &Retrieved,
+    persona: Option<&Persona>,
+    context: &ContextConfig,
+    remote: Option<&PrivacyConfig>,
+    on_chunk: &mut dyn FnMut(&str),
 ) -> Result<Plan, Box<dyn std::error::Error>> {
-    let tools = retrieve_relevant_tools(client, conn, query, TOP_K_TOOLS)?;
-    if tools.is_empty() {
-        return Err("No tools indexed. Run 'pls index' first.".into());
+    let mut found = found.clone();
+    let system = format!("{}{}", SYSTEM_PROMPT, persona_block(persona));
+    if let Some(policy) = remote {
+        privacy::restrict(&mut found, policy);
     }
 
-    let cwd = env::current_dir()
-        .map(|p| p.to_string_lossy().to_string())
-        .unwrap_or_else(|_| ".".to_string());
-    let shell = env::var("SHELL").unwrap_or_else(|_| "bash".to_string());
-
-    let prompt = build_prompt(query, &tools, &cwd, &shell);
-    let response = client.generate(&prompt)?;
-    parse_plan(&response)
+    let quoted = literals::extract(query);
+    let share_output = remote.is_none_or(|p| privacy::shares(p, "output"));
+    let literals = literals::prompt_block(&quoted) + &pipe::prompt_block(share_output);
+    let context = context::gather(context, remote);
+    let mut docs: Vec<String> = found
+        .tools
+        .iter()
+        .enumerate()
+        .map(|(i, t)| {
+            if i < found.full {
+                tool_doc(t)
+            } else {
+                brief_doc(t)
+            }
+        })
+        .collect();
+    let mut prompt;
+    loop {
+        prompt = build_prompt(query, &docs, &found.facts, &found.docs, &context, &literals);
+        let cap = remote.map_or(usize::MAX, |p| p.remote_max_context_chars);
+        if prompt.len() <= cap || found.tools.len() <= 1 {
+            break;
+        }
+        found.tools.pop();
+        docs.pop();
+    }
+    // A small context window would silently lose the start of the prompt;
+    // the docs are cut to fit instead, each by the same share.
+    let mut cut = None;
+    if let Some(window) = client.context_window() {
+        let reserved = REPLY_TOKENS + system.len() / CHARS_PER_TOKEN;
+        let budget = window.saturating_sub(reserved) * CHARS_PER_TOKEN;
+        if prompt.len() > budget {
+            let fixed = prompt.len() - docs.join("\n").len();
+            fit_docs(&mut docs, budget.saturating_sub(fixed));
+            found.tools.truncate(docs.len());
+            prompt = build_prompt(query, &docs, &found.facts, &found.docs, &context, &literals);
+            cut = Some(window);
+        }
+    }
+    let schema = reply_schema();
+    let response = client.stream_chat(&system, &prompt, &schema, on_chunk)?;
+    let mut sent_chars = prompt.len();
+    let mut raw_reply = response.clone();
+    let mut plan = match parse_plan(&response) {
+        Ok(plan) => plan,
+        Err(e) => {
+            let retry = retry_prompt(&prompt, e.as_ref());
+            sent_chars += retry.len();
+            let second = client.stream_chat(&system, &retry, &schema, on_chunk)?;
+            raw_reply = format!("{}\n\n--- retry ({}) ---\n\n{}", response, e, second);
+            parse_plan(&second).map_err(|e| format!("the model's reply was not a plan: {}", e))?
+        }
+    };
+    plan.sent_chars = sent_chars;
+    plan.raw_reply = raw_reply;
+    plan.provenance = provenance(&found);
+    // A split chain keeps its notes and its check on its last step.
+    let mut commands = Vec::new();
+    let mut verify = Vec::new();
+    let mut notes = Vec::new();
+    for (i, cmd) in plan.commands.iter().enumerate() {
+        let steps = split_chain(cmd);
+        verify.extend((1..steps.len()).map(|_| None));
+        verify.push(plan.verify.get(i).cloned().flatten());
+        notes.extend((1..steps.len()).map(|_| StepNotes::default()));
+        notes.push(plan.steps.get(i).cloned().unwrap_or_default());
+        commands.extend(steps);
+    }
+    plan.commands = commands;
+    plan.verify = verify;
+    plan.steps = notes;
+    plan.warnings.extend(check_plan(query, &plan.commands));
+    plan.warnings
+        .extend(literals::check_plan(&quoted, &plan.commands));
+    if let Some(window) = cut {
+        let message = format!(
+            "tool docs were cut to fit the model's {}-token context",
+            window
+        );
+        plan.warnings.push(
+            Warning::new(Severity::Info, message)
+                .with_hint("a larger num_ctx under [llm.options] gives it all of them"),
+        );
+    }
+    Ok(plan)
 }
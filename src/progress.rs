@@ -0,0 +1,76 @@
+// A progress line for long runs (indexing tools, reading docs) on stderr:
+// a bar, how many of how many, the rate so far, the time left at that rate
+// and what was last done. Redrawn in place, at most every REDRAW, and only
+// on a terminal; a log gets just the summary the caller prints after.
+
+use crate::ui::format_duration;
+use std::{
+    io::Write,
+    time::{Duration, Instant},
+};
+
+const BAR: usize = 24;
+const REDRAW: Duration = Duration::from_millis(100);
+// Room kept for the name of what was last done.
+const NAME_CHARS: usize = 28;
+
+pub struct Progress {
+    total: usize,
+    done: usize,
+    started: Instant,
+    drawn: Option<Instant>,
+    live: bool,
+}
+
+impl Progress {
+    /// Progress through `total` things, drawn when `shown` and stderr is a
+    /// terminal.
+    pub fn new(total: usize, shown: bool) -> Self {
+        Progress {
+            total,
+            done: 0,
+            started: Instant::now(),
+            drawn: None,
+            live: shown && unsafe { libc::isatty(libc::STDERR_FILENO) } == 1,
+        }
+    }
+
+    /// One more done, `what` being its name.
+    pub fn advance(&mut self, what: &str) {
+        self.done += 1;
+        let due = self.drawn.is_none_or(|at| at.elapsed() >= REDRAW);
+        if self.live && (due || self.done == self.total) {
+            self.draw(what);
+            self.drawn = Some(Instant::now());
+        }
+    }
+
+    fn draw(&self, what: &str) {
+        let total = self.total.max(1);
+        let filled = BAR * self.done.min(total) / total;
+        let bar = format!("{}{}", "#".repeat(filled), "-".repeat(BAR - filled));
+        let secs = self.started.elapsed().as_secs_f64();
+        let rate = self.done as f64 / secs.max(0.001);
+        // Too early to say until a few are done.
+        let left = if self.done >= 3 && self.done < self.total {
+            let secs = ((self.total - self.done) as f64 / rate).ceil() as u64;
+            format!("  {} left", format_duration(secs * 1000))
+        } else {
+            String::new()
+        };
+        let name: String = what.chars().take(NAME_CHARS).collect();
+        eprint!(
+            "\r\x1b[K  [{}] {}/{}  {:.1}/s{}  {}",
+            bar, self.done, self.total, rate, left, name
+        );
+        std::io::stderr().flush().ok();
+    }
+
+    /// Clears the line for what's printed next.
+    pub fn finish(&self) {
+        if self.drawn.is_some() {
+            eprint!("\r\x1b[K");
+            std::io::stderr().flush().ok();
+        }
+    }
+}
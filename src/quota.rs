@@ -0,0 +1,71 @@
+use crate::clock::{day_start, month_start};
+use crate::config::QuotaConfig;
+use crate::db::{get_usage_since, save_usage};
+use crate::types::Usage;
+use rusqlite::Connection;
+
+pub enum QuotaStatus {
+    Ok,
+    Warn(String),
+    Exceeded(String),
+}
+
+pub fn cost_of(config: &QuotaConfig, model: &str, usage: &Usage) -> f64 {
+    config
+        .pricing
+        .get(model)
+        .map(|p| {
+            (usage.prompt_tokens as f64 * p.input_per_mtok
+                + usage.completion_tokens as f64 * p.output_per_mtok)
+                / 1_000_000.0
+        })
+        .unwrap_or(0.0)
+}
+
+pub fn record(
+    conn: &Connection,
+    config: &QuotaConfig,
+    model: &str,
+    endpoint: &str,
+    usage: &Usage,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if usage.prompt_tokens == 0 && usage.completion_tokens == 0 {
+        return Ok(());
+    }
+    save_usage(conn, model, endpoint, usage, cost_of(config, model, usage))
+}
+
+pub fn check(conn: &Connection, config: &QuotaConfig) -> QuotaStatus {
+    let periods = [
+        ("daily", config.daily_budget, day_start()),
+        ("monthly", config.monthly_budget, month_start()),
+    ];
+
+    let mut warning = None;
+    for (name, budget, since) in periods {
+        if budget <= 0.0 {
+            continue;
+        }
+        let (_, _, spent) = get_usage_since(conn, since);
+        if spent >= budget {
+            return QuotaStatus::Exceeded(format!(
+                "{} budget of {:.2} reached ({:.2} spent)",
+                name, budget, spent
+            ));
+        }
+        if spent >= budget * config.warn_at && warning.is_none() {
+            warning = Some(format!(
+                "{:.0}% of the {} budget used ({:.2} of {:.2})",
+                spent / budget * 100.0,
+                name,
+                spent,
+                budget
+            ));
+        }
+    }
+
+    match warning {
+        Some(w) => QuotaStatus::Warn(w),
+        None => QuotaStatus::Ok,
+    }
+}
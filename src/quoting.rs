@@ -0,0 +1,105 @@
+// The one place values get spliced into shell command lines. Anything that
+// builds a command from user or file data (the cron entry, the edit preview)
+// goes through here instead of formatting strings by hand.
+
+// Characters that never need quoting in POSIX sh.
+fn is_plain(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "@%+=:,./_-".contains(c)
+}
+
+/// Quotes `value` as a single shell word. Plain words are left alone;
+/// everything else is single-quoted, with embedded single quotes written
+/// as `'\''`.
+pub fn quote(value: &str) -> String {
+    if !value.is_empty() && value.chars().all(is_plain) {
+        return value.to_string();
+    }
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::quote;
+    use std::{env, fs, path::Path, process::Command};
+
+    // Strings that must come out of the shell exactly as they went in.
+    const INJECTIONS: &[&str] = &[
+        "",
+        "plain",
+        "two words",
+        "it's",
+        "'",
+        "''",
+        "'; touch pwned; '",
+        "\"; touch pwned; \"",
+        "$(touch pwned)",
+        "`touch pwned`",
+        "$HOME",
+        "${HOME}",
+        "a\nb",
+        "tab\there",
+        "\\",
+        "\\'",
+        "*",
+        "?",
+        "[a-z]",
+        "~",
+        "~root",
+        "{a,b}",
+        "-rf",
+        "--help",
+        "!",
+        "!!",
+        "&& touch pwned",
+        "| touch pwned",
+        "; touch pwned",
+        "> pwned",
+        "< /etc/passwd",
+        "#comment",
+        "a#b",
+        "%s",
+        "caf\u{e9}",
+        "\u{1F600}",
+        " leading",
+        "trailing ",
+    ];
+
+    // Words a command line hands to printf, split back out.
+    fn shell_words(work: &Path, line: &str) -> Vec<String> {
+        let output = Command::new("sh")
+            .arg("-c")
+            .arg(format!("printf '%s\\0' {}", line))
+            .current_dir(work)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "sh rejected: {}", line);
+        let mut words: Vec<String> = String::from_utf8_lossy(&output.stdout)
+            .split('\0')
+            .map(String::from)
+            .collect();
+        words.pop();
+        words
+    }
+
+    #[test]
+    fn quoted_values_come_back_unchanged() {
+        let work = env::temp_dir().join(format!("pls-quoting-{}", std::process::id()));
+        fs::create_dir_all(&work).unwrap();
+        for value in INJECTIONS {
+            assert_eq!(shell_words(&work, &quote(value)), [*value]);
+        }
+        let all = INJECTIONS.iter().map(|v| quote(v)).collect::<Vec<_>>();
+        assert_eq!(shell_words(&work, &all.join(" ")), INJECTIONS);
+        let ran = work.join("pwned").exists();
+        fs::remove_dir_all(&work).ok();
+        assert!(!ran, "an injection attempt ran a command");
+    }
+
+    #[test]
+    fn plain_words_are_left_alone() {
+        assert_eq!(quote("src/main.rs"), "src/main.rs");
+        assert_eq!(quote("--max-depth=2"), "--max-depth=2");
+        assert_eq!(quote(""), "''");
+        assert_eq!(quote("it's"), r"'it'\''s'");
+    }
+}
@@ -1,6 +1,9 @@
+use crate::config::RetrievalConfig;
 use crate::db::load_all_tools;
-use crate::ollama::OllamaClient;
-use crate::types::Tool;
+use crate::embed::Embedder;
+use crate::fuzzy;
+use crate::types::{DocChunk, Fact, Note, Tool, ToolRecord};
+use std::collections::HashMap;
 
 fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
     if a.len() != b.len() || a.is_empty() {
@@ -16,20 +19,329 @@ fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
     }
 }
 
+// Added to the similarity of tools the working directory hints at. Enough
+// to break near-ties, not enough to pull in unrelated tools.
+const HINT_BOOST: f32 = 0.05;
+
+// How far a tool that always fails here sinks; one that fails half the
+// time sinks half as far. Still a nudge, since a failing tool may be the
+// only one for the job.
+const FAILURE_PENALTY: f32 = 0.2;
+
+// A query word spells a tool's name when it is at least this similar to
+// it: "grp" names grep, "tar" doesn't name startx. Words shorter than
+// NAMED_MIN_LEN ("at", "go") are too often just words.
+const NAMED: f32 = 0.7;
+const NAMED_MIN_LEN: usize = 3;
+
+// Below this best similarity the embeddings aren't telling tools apart
+// for the query, and keyword matching does better.
+const WEAK_SIMILARITY: f32 = 0.15;
+
+// Facts go into every prompt while there are few of them; past this many
+// only the ones closest to the query do.
+const MAX_FACTS: usize = 5;
+
+// The user's own docs, unlike facts, only go in when they're about the
+// query: at most MAX_DOCS sections, each at least this similar to it, or
+// sharing half its words when there are no embeddings to go by.
+const MAX_DOCS: usize = 3;
+const DOC_SIMILARITY: f32 = 0.3;
+const DOC_TERMS: f32 = 0.5;
+
+// What makes a query specific, and how much each counts: naming a tool, a
+// few tools scoring well clear of the rest, and saying a lot. LONG_QUERY
+// words say all that length can.
+const NAMED_WEIGHT: f32 = 0.5;
+const SPREAD_WEIGHT: f32 = 0.3;
+const LENGTH_WEIGHT: f32 = 0.2;
+const LONG_QUERY: usize = 8;
+
+/// What shapes retrieval besides the query: directory hints, per-source
+/// weights, the user's notes, facts and docs, and how each tool has fared
+/// here.
+pub struct Context<'a> {
+    pub hints: &'a [String],
+    pub weights: &'a HashMap<String, f32>,
+    pub notes: &'a [Note],
+    pub facts: &'a [Fact],
+    pub docs: &'a [DocChunk],
+    pub records: &'a HashMap<String, ToolRecord>,
+}
+
+/// What a query pulled in for the prompt.
+#[derive(Clone)]
+pub struct Retrieved {
+    pub tools: Vec<Tool>,
+    /// How many of `tools`, from the first, the prompt documents in full.
+    pub full: usize,
+    pub facts: Vec<Fact>,
+    pub docs: Vec<DocChunk>,
+}
+
+fn source_weight(weights: &HashMap<String, f32>, tool: &Tool) -> f32 {
+    weights.get(&tool.source).copied().unwrap_or(1.0)
+}
+
+fn reliability_weight(records: &HashMap<String, ToolRecord>, tool: &Tool) -> f32 {
+    let rate = records.get(&tool.name).and_then(|r| r.failure_rate());
+    1.0 - FAILURE_PENALTY * rate.unwrap_or(0.0)
+}
+
+fn attach_context(tools: &mut [Tool], context: &Context) {
+    for tool in tools {
+        tool.notes = context
+            .notes
+            .iter()
+            .filter(|n| n.tool == tool.name)
+            .map(|n| n.text.clone())
+            .collect();
+        tool.record = context.records.get(&tool.name).copied().unwrap_or_default();
+    }
+}
+
+// The best-scoring facts, in the order they were learned.
+fn pick_facts(facts: &[Fact], score: impl Fn(&Fact) -> f32) -> Vec<Fact> {
+    if facts.len() <= MAX_FACTS {
+        return facts.to_vec();
+    }
+    let mut scored: Vec<(f32, &Fact)> = facts.iter().map(|f| (score(f), f)).collect();
+    scored.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+    let mut picked: Vec<Fact> = scored
+        .into_iter()
+        .take(MAX_FACTS)
+        .filter(|(score, _)| *score > 0.0)
+        .map(|(_, f)| f.clone())
+        .collect();
+    picked.sort_by_key(|f| f.id);
+    picked
+}
+
+// The best-scoring doc sections that score at least `min`.
+fn pick_docs(docs: &[DocChunk], min: f32, score: impl Fn(&DocChunk) -> f32) -> Vec<DocChunk> {
+    let mut scored: Vec<(f32, &DocChunk)> = docs
+        .iter()
+        .map(|d| (score(d), d))
+        .filter(|(s, _)| *s >= min)
+        .collect();
+    scored.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+    scored
+        .into_iter()
+        .take(MAX_DOCS)
+        .map(|(_, d)| d.clone())
+        .collect()
+}
+
+// How specific a query is, from 0 ("clean up this directory") to 1 ("use jq
+// to extract .name"), by the terms in it, whether it names a tool and
+// `scores`, best first.
+fn specificity(terms: &[String], named: bool, scores: &[f32], max: usize) -> f32 {
+    let mut specificity = if named { NAMED_WEIGHT } else { 0.0 };
+    let last = scores.get(max.min(scores.len()).saturating_sub(1));
+    if let (Some(&best), Some(&last)) = (scores.first(), last) {
+        if best > 0.0 {
+            specificity += SPREAD_WEIGHT * ((best - last) / best).clamp(0.0, 1.0);
+        }
+    }
+    let length = terms.len().min(LONG_QUERY) as f32 / LONG_QUERY as f32;
+    specificity + LENGTH_WEIGHT * length
+}
+
+/// How many tools a query gets, and how many of those in full: the more
+/// specific the query, the fewer, within `config`'s bounds.
+fn how_many(
+    terms: &[String],
+    named: bool,
+    scores: &[f32],
+    config: &RetrievalConfig,
+) -> (usize, usize) {
+    let top_k = config.top_k.max(1);
+    if !config.adaptive {
+        return (top_k, top_k);
+    }
+    let min = config.min_top_k.max(1);
+    let max = config.max_top_k.max(min);
+    let specificity = specificity(terms, named, scores, max);
+    let k = max - ((max - min) as f32 * specificity).round() as usize;
+    (k, k.min(top_k))
+}
+
 pub fn retrieve_relevant_tools(
-    client: &OllamaClient,
+    embedder: &dyn Embedder,
     conn: &rusqlite::Connection,
     query: &str,
-    top_k: usize,
-) -> Result<Vec<Tool>, Box<dyn std::error::Error>> {
-    let query_embedding = client.embed(query)?;
-    let all_tools = load_all_tools(conn)?;
+    config: &RetrievalConfig,
+    context: &Context,
+) -> Result<Retrieved, Box<dyn std::error::Error>> {
+    // Embedding the query is usually a round trip to the model server; read
+    // the tools in the meantime.
+    let (embedded, all_tools) = std::thread::scope(|scope| {
+        let embedding = scope.spawn(|| embedder.embed_query(query).map_err(|e| e.to_string()));
+        (embedding.join(), load_all_tools(conn))
+    });
+    let query_embedding = embedded.map_err(|_| "embedding the query failed")??;
+    let all_tools = all_tools?;
+    let terms = query_terms(query);
+
+    let best = all_tools
+        .iter()
+        .map(|tool| cosine_similarity(&query_embedding, &tool.embedding))
+        .fold(0.0, f32::max);
+    if best < WEAK_SIMILARITY {
+        let named = all_tools.iter().any(|tool| named(&terms, &tool.name) > 0.0);
+        let (top_k, full) = how_many(&terms, named, &[], config);
+        let mut found = keyword_search(all_tools.clone(), query, top_k, context);
+        if !found.tools.is_empty() {
+            found.full = full;
+            return Ok(found);
+        }
+    }
 
+    // A tool matches as well as its best-matching note does.
+    let mut note_scores: HashMap<String, f32> = HashMap::new();
+    for note in context.notes {
+        let score = cosine_similarity(&query_embedding, &note.embedding);
+        let best = note_scores.entry(note.tool.clone()).or_insert(score);
+        *best = best.max(score);
+    }
+
+    let mut any_named = false;
     let mut scored: Vec<(f32, Tool)> = all_tools
         .into_iter()
-        .map(|tool| (cosine_similarity(&query_embedding, &tool.embedding), tool))
+        .map(|tool| {
+            let mut score = cosine_similarity(&query_embedding, &tool.embedding);
+            if let Some(note_score) = note_scores.get(&tool.name) {
+                score = score.max(*note_score);
+            }
+            // Only scale matches; shrinking a negative score would promote it.
+            if score > 0.0 {
+                score *= source_weight(context.weights, &tool);
+                score *= reliability_weight(context.records, &tool);
+            }
+            if context.hints.contains(&tool.name) {
+                score += HINT_BOOST;
+            }
+            // A tool the query names comes first, however far its docs are
+            // from the rest of the query.
+            let named = named(&terms, &tool.name);
+            if named > 0.0 {
+                score += 1.0 + named;
+                any_named = true;
+            }
+            (score, tool)
+        })
         .collect();
 
     scored.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
-    Ok(scored.into_iter().take(top_k).map(|(_, t)| t).collect())
+    let scores: Vec<f32> = scored
+        .iter()
+        .take(config.max_top_k)
+        .map(|(s, _)| *s)
+        .collect();
+    let (top_k, full) = how_many(&terms, any_named, &scores, config);
+    let mut tools: Vec<Tool> = scored.into_iter().take(top_k).map(|(_, t)| t).collect();
+    attach_context(&mut tools, context);
+    let facts = pick_facts(context.facts, |f| {
+        cosine_similarity(&query_embedding, &f.embedding)
+    });
+    let docs = pick_docs(context.docs, DOC_SIMILARITY, |d| {
+        cosine_similarity(&query_embedding, &d.embedding)
+    });
+    Ok(Retrieved {
+        tools,
+        full,
+        facts,
+        docs,
+    })
+}
+
+const STOPWORDS: &[&str] = &[
+    "a", "an", "and", "all", "are", "by", "for", "from", "how", "in", "into", "is", "it", "me",
+    "my", "of", "on", "or", "show", "that", "the", "them", "this", "to", "what", "with",
+];
+
+// How closely the best of `terms` spells `name`; 0.0 if none comes close.
+// A subcommand's entry, "git branch", is named only when both words are.
+fn named(terms: &[String], name: &str) -> f32 {
+    if name.contains(' ') {
+        return name
+            .split(' ')
+            .map(|word| named(terms, word))
+            .fold(f32::MAX, f32::min);
+    }
+    terms
+        .iter()
+        .filter(|t| t.chars().count() >= NAMED_MIN_LEN || *t == name)
+        .map(|t| fuzzy::similarity(t, name))
+        .filter(|&s| s >= NAMED)
+        .fold(0.0, f32::max)
+}
+
+fn query_terms(query: &str) -> Vec<String> {
+    query
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric() && c != '-' && c != '_')
+        .filter(|t| t.len() > 1 && !STOPWORDS.contains(t))
+        .map(String::from)
+        .collect()
+}
+
+/// Ranks tools by term overlap with the query, names and description words
+/// matched fuzzily. Used when there are no embeddings to compare against, or
+/// they don't tell the tools apart. Scores are scaled by source weight and
+/// reliability, and hinted tools get a small bonus but still need to match at least one term.
+pub fn keyword_search(tools: Vec<Tool>, query: &str, top_k: usize, context: &Context) -> Retrieved {
+    let terms = query_terms(query);
+
+    let mut scored: Vec<(f32, Tool)> = tools
+        .into_iter()
+        .map(|tool| {
+            let description = tool.description.to_lowercase();
+            let words: Vec<&str> = description.split(|c: char| !c.is_alphanumeric()).collect();
+            let examples = tool.examples.to_lowercase();
+            let score: f32 = terms
+                .iter()
+                .map(|term| {
+                    let mut s = 5.0 * named(std::slice::from_ref(term), &tool.name);
+                    let spelled = term.chars().count() >= NAMED_MIN_LEN
+                        && words.iter().any(|w| fuzzy::similarity(term, w) >= NAMED);
+                    if description.contains(term.as_str()) || spelled {
+                        s += 2.0;
+                    }
+                    if examples.contains(term.as_str()) {
+                        s += 1.0;
+                    }
+                    s
+                })
+                .sum();
+            let bonus = if score > 0.0 && context.hints.contains(&tool.name) {
+                1.0
+            } else {
+                0.0
+            };
+            let weight =
+                source_weight(context.weights, &tool) * reliability_weight(context.records, &tool);
+            (score * weight + bonus, tool)
+        })
+        .filter(|(score, _)| *score > 0.0)
+        .collect();
+
+    scored.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+    let mut tools: Vec<Tool> = scored.into_iter().take(top_k).map(|(_, t)| t).collect();
+    attach_context(&mut tools, context);
+    let facts = pick_facts(context.facts, |f| {
+        let text = f.text.to_lowercase();
+        terms.iter().filter(|t| text.contains(t.as_str())).count() as f32
+    });
+    let docs = pick_docs(context.docs, DOC_TERMS, |d| {
+        let text = format!("{} {}", d.heading, d.text).to_lowercase();
+        let matched = terms.iter().filter(|t| text.contains(t.as_str())).count();
+        matched as f32 / terms.len().max(1) as f32
+    });
+    Retrieved {
+        full: tools.len(),
+        tools,
+        facts,
+        docs,
+    }
 }
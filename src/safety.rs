@@ -1,12 +1,131 @@
 use crate::config::SafetyConfig;
-use crate::types::RiskLevel;
+use crate::mounts::{self, Hit, MountKind};
+use crate::types::{RiskLevel, Severity, Warning};
+use serde::Serialize;
+
+// Programs that change or remove what they're pointed at.
+const WRITERS: &[&str] = &[
+    "rm", "rmdir", "mv", "cp", "rsync", "ln", "shred", "truncate", "dd", "chmod", "chown", "chgrp",
+    "tee", "touch", "mkdir", "sed", "find", "tar", "unzip",
+];
+
+fn writes(hit: &Hit) -> bool {
+    WRITERS.contains(&hit.program.as_str())
+}
+
+fn mount_hits(commands: &[String], config: &SafetyConfig) -> Vec<Hit> {
+    if !config.mount_checks {
+        return Vec::new();
+    }
+    commands.iter().flat_map(|c| mounts::targets(c)).collect()
+}
+
+/// Warnings for plans that touch network shares or removable media.
+pub fn mount_warnings(commands: &[String], config: &SafetyConfig) -> Vec<Warning> {
+    mount_hits(commands, config)
+        .into_iter()
+        .filter_map(|hit| {
+            let place = format!(
+                "{} is on {} ({})",
+                hit.path,
+                hit.mount.point.display(),
+                hit.mount.fstype
+            );
+            match (hit.kind, writes(&hit)) {
+                (MountKind::Network, true) => Some(Warning::new(
+                    Severity::Danger,
+                    format!(
+                        "{}, a network share: {} changes there are visible to everyone \
+                         using it, and deleted files don't go to a trash",
+                        place, hit.program
+                    ),
+                )),
+                (MountKind::Network, false) => Some(Warning::new(
+                    Severity::Info,
+                    format!("{}, a network share: {} may be slow", place, hit.program),
+                )),
+                (MountKind::Removable, true) => Some(
+                    Warning::new(
+                        Severity::Caution,
+                        format!(
+                            "{}, removable media: deleted files don't go to a trash",
+                            place
+                        ),
+                    )
+                    .with_hint(format!("keep it plugged in until {} finishes", hit.program)),
+                ),
+                (MountKind::Removable, false) => None,
+            }
+        })
+        .collect()
+}
+
+/// What pls makes of one command on its own, for `pls safety check` and
+/// other programs using pls as a library.
+#[derive(Debug, Clone, Serialize)]
+pub struct Assessment {
+    pub command: String,
+    pub risk: RiskLevel,
+    pub reason: String,
+    pub warnings: Vec<Warning>,
+}
+
+/// Assesses each of `commands` separately under `config`, as pls would a
+/// plan of that one command.
+pub fn assess_each(commands: &[String], config: &SafetyConfig) -> Vec<Assessment> {
+    commands
+        .iter()
+        .map(|command| {
+            let one = std::slice::from_ref(command);
+            let (risk, reason) = classify(one, config);
+            Assessment {
+                command: command.clone(),
+                risk,
+                reason,
+                warnings: mount_warnings(one, config),
+            }
+        })
+        .collect()
+}
 
 pub fn assess_risk(commands: &[String], config: &SafetyConfig) -> RiskLevel {
+    classify(commands, config).0
+}
+
+/// The risk level together with a sentence on why it was assigned.
+pub fn explain_risk(commands: &[String], config: &SafetyConfig) -> (RiskLevel, String) {
+    classify(commands, config)
+}
+
+fn classify(commands: &[String], config: &SafetyConfig) -> (RiskLevel, String) {
+    let (risk, reason) = base_risk(commands, config);
+    // Writing to a share or a stick is one step more serious than the same
+    // command on a local disk.
+    if risk == RiskLevel::Review {
+        if let Some(hit) = mount_hits(commands, config).iter().find(|h| writes(h)) {
+            return (
+                RiskLevel::Dangerous,
+                format!(
+                    "{} writes to {}, which is on a {} filesystem",
+                    hit.program,
+                    hit.mount.point.display(),
+                    hit.mount.fstype
+                ),
+            );
+        }
+    }
+    (risk, reason)
+}
+
+fn base_risk(commands: &[String], config: &SafetyConfig) -> (RiskLevel, String) {
     let full_command = commands.join(" ");
 
     for pattern in &config.dangerous_patterns {
         if full_command.contains(pattern) {
-            return RiskLevel::Blocked;
+            return (
+                RiskLevel::Blocked,
+                format!("it matches the blocked pattern `{}`", pattern),
+            );
         }
     }
 
@@ -16,19 +135,31 @@ pub fn assess_risk(commands: &[String], config: &SafetyConfig) -> RiskLevel {
             .iter()
             .any(|c| c.split_whitespace().next() == Some(cmd))
         {
-            return RiskLevel::Dangerous;
+            return (
+                RiskLevel::Dangerous,
+                format!("{} destroys data and can't be undone", cmd),
+            );
         }
     }
 
-    let all_safe = commands.iter().all(|cmd| {
-        let first = cmd.split_whitespace().next().unwrap_or("");
-        let base = first.rsplit('/').next().unwrap_or(first);
-        config.safe_commands.contains(&base.to_string())
-    });
+    let unlisted: Vec<&str> = commands
+        .iter()
+        .map(|cmd| {
+            let first = cmd.split_whitespace().next().unwrap_or("");
+            first.rsplit('/').next().unwrap_or(first)
+        })
+        .filter(|base| !config.safe_commands.iter().any(|s| s == base))
+        .collect();
 
-    if all_safe {
-        RiskLevel::Safe
+    if unlisted.is_empty() {
+        (
+            RiskLevel::Safe,
+            "every command only reads and is in your safe_commands list".to_string(),
+        )
     } else {
-        RiskLevel::Review
+        (
+            RiskLevel::Review,
+            format!("{} is not in your safe_commands list", unlisted.join(", ")),
+        )
     }
 }
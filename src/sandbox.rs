@@ -0,0 +1,504 @@
+// Rehearsals of a plan on a copy of the working directory, for plans that
+// rename, move or chmod many files. The copy has every directory, file and
+// link, with modes and modification times; files up to
+// `safety.sandbox_copy_bytes` are copied whole, bigger ones stand in as
+// sparse files of the same size. Only plans made of local file tools that
+// name nothing outside the directory, and take no file names from data
+// (`xargs`, `read`), are rehearsed: anything else could reach past the copy.
+
+use crate::config::SafetyConfig;
+use crate::encoding::decode;
+use crate::pipe;
+use crate::shell::{split_segments, words};
+use crate::snapshot::format_bytes;
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    env,
+    ffi::OsString,
+    fs::{self, File},
+    hash::Hasher,
+    io,
+    os::unix::ffi::OsStringExt,
+    os::unix::fs::{symlink, MetadataExt, PermissionsExt},
+    path::{Component, Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+// Programs that only work on the files they're given.
+const TOOLS: &[&str] = &[
+    "mv",
+    "cp",
+    "rm",
+    "ln",
+    "mkdir",
+    "rmdir",
+    "touch",
+    "chmod",
+    "truncate",
+    "rename",
+    "prename",
+    "perl-rename",
+    "find",
+    "sed",
+    "awk",
+    "gawk",
+    "tr",
+    "sort",
+    "uniq",
+    "cut",
+    "head",
+    "tail",
+    "cat",
+    "tee",
+    "echo",
+    "printf",
+    "basename",
+    "dirname",
+    "test",
+    "[",
+    "true",
+    "false",
+    "ls",
+    "wc",
+    "grep",
+    "gzip",
+    "gunzip",
+    "bzip2",
+    "bunzip2",
+    "xz",
+    "unxz",
+    "tar",
+    "unzip",
+    "expr",
+    "date",
+    "stat",
+    "file",
+    "realpath",
+    "readlink",
+    "cd",
+];
+// Words that come before a segment's program without being one.
+const KEYWORDS: &[&str] = &["do", "then", "else", "elif", "if", "while", "until", "!"];
+// Segments that run nothing themselves.
+const SYNTAX: &[&str] = &["for", "done", "fi"];
+const DEVICES: &[&str] = &["/dev/null", "/dev/stdin", "/dev/stdout", "/dev/stderr"];
+const VARIABLES: &[&str] = &["$HOME", "${HOME", "$OLDPWD", "$TMPDIR", "${TMPDIR", "$XDG_"];
+// Lines of the rehearsal's own output shown, from the end.
+const OUTPUT_LINES: usize = 10;
+
+pub struct Rehearsal {
+    /// Files copied whole, and files standing in by size only.
+    pub copied: usize,
+    pub placeholders: usize,
+    /// Links to outside the directory and special files, not copied.
+    pub left_out: usize,
+    pub succeeded: bool,
+    pub output: Vec<String>,
+    /// One line per change, e.g. "renamed  a.jpg → 2024/a.jpg".
+    pub changes: Vec<String>,
+}
+
+#[derive(Clone, PartialEq)]
+struct Entry {
+    kind: char,
+    size: u64,
+    mode: u32,
+    mtime: i64,
+    hash: Option<u64>,
+    ino: u64,
+    target: Option<PathBuf>,
+}
+
+// Whether `word` names a place outside the working directory. `${...}`
+// can edit its way anywhere (`${PWD%/*}`), so any of it counts.
+fn outside(word: &str) -> bool {
+    if word.contains("${") || VARIABLES.iter().any(|v| word.contains(v)) {
+        return true;
+    }
+    word.split(|c: char| c.is_whitespace() || "\"'=<>(:,;&|`".contains(c))
+        .filter(|piece| !DEVICES.contains(piece))
+        .any(|piece| {
+            piece.starts_with('/')
+                || piece.starts_with('~')
+                || piece.split('/').any(|part| part == "..")
+        })
+}
+
+// Whether each program `cmd` runs is one of TOOLS, including what find
+// runs for it.
+fn local_tools(cmd: &str) -> bool {
+    if cmd.contains('`') {
+        return false;
+    }
+    split_segments(cmd).iter().all(|segment| {
+        let words = words(segment);
+        let mut words = words
+            .iter()
+            .map(|w| {
+                w.trim_start_matches(['(', '{'])
+                    .trim_end_matches([')', '}'])
+            })
+            .filter(|w| !w.is_empty())
+            .skip_while(|w| KEYWORDS.contains(w) || (w.contains('=') && !w.starts_with('-')));
+        let Some(program) = words.next() else {
+            return true;
+        };
+        if SYNTAX.contains(&program) {
+            return true;
+        }
+        let rest: Vec<&str> = words.collect();
+        match program {
+            // `cd` and `cd -` go somewhere else entirely.
+            "cd" => rest.first().is_some_and(|dir| *dir != "-"),
+            "find" => rest
+                .windows(2)
+                .filter(|w| matches!(w[0], "-exec" | "-execdir" | "-ok" | "-okdir"))
+                .all(|w| TOOLS.contains(&w[1])),
+            _ => TOOLS.contains(&program),
+        }
+    })
+}
+
+/// Whether `commands` can be rehearsed on a copy of the directory.
+pub fn eligible(commands: &[String]) -> bool {
+    !commands.is_empty()
+        && commands.iter().all(|cmd| {
+            // What $(...) runs has to pass too.
+            let substituted = cmd
+                .split("$(")
+                .skip(1)
+                .map(|s| s.split(')').next().unwrap_or(""));
+            local_tools(cmd)
+                && substituted.into_iter().all(local_tools)
+                && !words(cmd).iter().any(|w| outside(w))
+        })
+}
+
+// Whether a link at `link` (relative to the root) pointing at `target`
+// stays inside the root.
+fn inside(link: &Path, target: &Path) -> bool {
+    if target.is_absolute() {
+        return false;
+    }
+    let mut depth = link.components().count() as i64 - 1;
+    for component in target.components() {
+        match component {
+            Component::ParentDir => depth -= 1,
+            Component::Normal(_) => depth += 1,
+            _ => {}
+        }
+        if depth < 0 {
+            return false;
+        }
+    }
+    true
+}
+
+fn hash_file(path: &Path) -> Option<u64> {
+    let content = fs::read(path).ok()?;
+    let mut hasher = DefaultHasher::new();
+    hasher.write(&content);
+    Some(hasher.finish())
+}
+
+// Every entry under `root`, by path relative to it.
+fn scan(root: &Path, hash_limit: u64) -> HashMap<PathBuf, Entry> {
+    let mut entries = HashMap::new();
+    let mut pending = vec![PathBuf::new()];
+    while let Some(dir) = pending.pop() {
+        let Ok(list) = fs::read_dir(root.join(&dir)) else {
+            continue;
+        };
+        for item in list.filter_map(|e| e.ok()) {
+            let path = dir.join(item.file_name());
+            let Ok(meta) = item.path().symlink_metadata() else {
+                continue;
+            };
+            let kind = if meta.is_dir() {
+                pending.push(path.clone());
+                'd'
+            } else if meta.file_type().is_symlink() {
+                'l'
+            } else {
+                'f'
+            };
+            let hash = (kind == 'f' && meta.len() <= hash_limit)
+                .then(|| hash_file(&item.path()))
+                .flatten();
+            let entry = Entry {
+                kind,
+                size: if kind == 'f' { meta.len() } else { 0 },
+                mode: meta.mode() & 0o7777,
+                mtime: meta.mtime(),
+                hash,
+                ino: meta.ino(),
+                target: fs::read_link(item.path()).ok(),
+            };
+            entries.insert(path, entry);
+        }
+    }
+    entries
+}
+
+// A new directory only this user can enter, under a name no one could
+// have made ahead of it.
+fn make_temp_dir(prefix: &str) -> io::Result<PathBuf> {
+    let template = env::temp_dir().join(format!("{}XXXXXX", prefix));
+    let mut bytes = template.into_os_string().into_vec();
+    bytes.push(0);
+    let made = unsafe { libc::mkdtemp(bytes.as_mut_ptr() as *mut libc::c_char) };
+    if made.is_null() {
+        return Err(io::Error::last_os_error());
+    }
+    bytes.pop();
+    Ok(PathBuf::from(OsString::from_vec(bytes)))
+}
+
+// A copy of the directory, removed when dropped.
+struct Scratch {
+    root: PathBuf,
+    copied: usize,
+    placeholders: usize,
+    left_out: usize,
+}
+
+impl Scratch {
+    fn create(from: &Path, config: &SafetyConfig) -> Result<Self, Box<dyn std::error::Error>> {
+        let root = make_temp_dir("pls-sandbox-")?;
+        let mut scratch = Self {
+            root,
+            copied: 0,
+            placeholders: 0,
+            left_out: 0,
+        };
+        scratch.fill(from, config)?;
+        Ok(scratch)
+    }
+
+    fn fill(
+        &mut self,
+        from: &Path,
+        config: &SafetyConfig,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let limit = config.snapshot_max_files;
+        let mut count = 0;
+        // Directory modes go on last, so read-only ones can still be filled.
+        // The root keeps mkdtemp's 0700.
+        let mut dirs = Vec::new();
+        let mut pending = vec![PathBuf::new()];
+        while let Some(dir) = pending.pop() {
+            for item in fs::read_dir(from.join(&dir))?.filter_map(|e| e.ok()) {
+                count += 1;
+                if count > limit {
+                    return Err(format!("more than {} files here, too many to copy", limit).into());
+                }
+                let path = dir.join(item.file_name());
+                let (source, dest) = (item.path(), self.root.join(&path));
+                let meta = source.symlink_metadata()?;
+                if meta.is_dir() {
+                    fs::create_dir(&dest)?;
+                    dirs.push((path.clone(), meta.mode()));
+                    pending.push(path);
+                } else if meta.file_type().is_symlink() {
+                    let target = fs::read_link(&source)?;
+                    if inside(&path, &target) {
+                        symlink(&target, &dest)?;
+                    } else {
+                        self.left_out += 1;
+                    }
+                } else if meta.is_file() {
+                    let mut file = File::create(&dest)?;
+                    if meta.len() <= config.sandbox_copy_bytes {
+                        io::copy(&mut File::open(&source)?, &mut file)?;
+                        self.copied += 1;
+                    } else {
+                        file.set_len(meta.len())?;
+                        self.placeholders += 1;
+                    }
+                    file.set_modified(meta.modified()?)?;
+                    fs::set_permissions(&dest, meta.permissions())?;
+                } else {
+                    self.left_out += 1;
+                }
+            }
+        }
+        for (dir, mode) in dirs.into_iter().rev() {
+            fs::set_permissions(self.root.join(dir), fs::Permissions::from_mode(mode))?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        // Directories the plan made read-only would stop the removal.
+        for path in scan(&self.root, 0)
+            .into_iter()
+            .filter(|(_, e)| e.kind == 'd')
+            .map(|(p, _)| p)
+        {
+            fs::set_permissions(self.root.join(path), fs::Permissions::from_mode(0o700)).ok();
+        }
+        fs::set_permissions(&self.root, fs::Permissions::from_mode(0o700)).ok();
+        fs::remove_dir_all(&self.root).ok();
+    }
+}
+
+fn name(path: &Path, entry: &Entry) -> String {
+    let mut name = path.display().to_string();
+    if entry.kind == 'd' {
+        name.push('/');
+    }
+    name
+}
+
+// What changed about an entry that's still there, under its new path.
+fn compare(path: &Path, old: &Entry, new: &Entry, lines: &mut Vec<(PathBuf, String)>) {
+    let shown = name(path, new);
+    if old.mode != new.mode {
+        let line = format!("mode      {}  {:o} → {:o}", shown, old.mode, new.mode);
+        lines.push((path.to_path_buf(), line));
+    }
+    if old.target != new.target && new.kind == 'l' {
+        let target = new.target.as_deref().unwrap_or(Path::new("")).display();
+        lines.push((
+            path.to_path_buf(),
+            format!("relinked  {} → {}", shown, target),
+        ));
+    } else if new.kind == 'f' && (old.size != new.size || old.hash != new.hash) {
+        let line = if old.size == new.size {
+            format!("modified  {}", shown)
+        } else {
+            let sizes = format!("{} → {}", format_bytes(old.size), format_bytes(new.size));
+            format!("modified  {}  {}", shown, sizes)
+        };
+        lines.push((path.to_path_buf(), line));
+    } else if new.kind == 'f' && old.mtime != new.mtime {
+        lines.push((path.to_path_buf(), format!("touched   {}", shown)));
+    }
+}
+
+// Created or deleted entries, with what's below a created or deleted
+// directory folded into it.
+fn folded(paths: &[(&PathBuf, &Entry)], verb: &str, lines: &mut Vec<(PathBuf, String)>) {
+    for (path, entry) in paths {
+        let below_dir = |(other, e): &(&PathBuf, &Entry)| {
+            e.kind == 'd' && *other != *path && path.starts_with(other)
+        };
+        if paths.iter().any(below_dir) {
+            continue;
+        }
+        let below = paths
+            .iter()
+            .filter(|(other, _)| other.starts_with(path))
+            .count()
+            - 1;
+        let mut line = format!("{:<9} {}", verb, name(path, entry));
+        if below > 0 {
+            line.push_str(&format!("  ({} inside)", below));
+        }
+        lines.push(((*path).clone(), line));
+    }
+}
+
+fn changes(before: &HashMap<PathBuf, Entry>, after: &HashMap<PathBuf, Entry>) -> Vec<String> {
+    let mut lines = Vec::new();
+    // A rename keeps the inode.
+    let new_paths: HashMap<u64, &PathBuf> = after
+        .iter()
+        .filter(|(path, _)| !before.contains_key(*path))
+        .map(|(path, entry)| (entry.ino, path))
+        .collect();
+    let mut renames: Vec<(&PathBuf, &PathBuf)> = Vec::new();
+    let mut deleted = Vec::new();
+    for (path, old) in before {
+        match after.get(path) {
+            Some(new) if new.kind == old.kind => compare(path, old, new, &mut lines),
+            Some(new) => lines.push((path.clone(), format!("replaced  {}", name(path, new)))),
+            None => match new_paths
+                .get(&old.ino)
+                .filter(|to| after[**to].kind == old.kind)
+            {
+                Some(to) => {
+                    renames.push((path, to));
+                    compare(to, old, &after[*to], &mut lines);
+                }
+                None => deleted.push((path, old)),
+            },
+        }
+    }
+
+    // Entries that moved with their directory go without saying.
+    let dirs: Vec<(&PathBuf, &PathBuf)> = renames
+        .iter()
+        .filter(|(from, _)| before[*from].kind == 'd')
+        .copied()
+        .collect();
+    for (from, to) in &renames {
+        let with_parent = dirs.iter().any(|(dir_from, dir_to)| {
+            from != dir_from
+                && from
+                    .strip_prefix(dir_from)
+                    .is_ok_and(|rest| dir_to.join(rest) == **to)
+        });
+        if !with_parent {
+            let (old, new) = (name(from, &before[*from]), name(to, &after[*to]));
+            let line = format!("renamed   {} → {}", old, new);
+            lines.push(((*from).clone(), line));
+        }
+    }
+
+    let renamed_to: Vec<&PathBuf> = renames.iter().map(|(_, to)| *to).collect();
+    let created: Vec<(&PathBuf, &Entry)> = after
+        .iter()
+        .filter(|(path, _)| !before.contains_key(*path) && !renamed_to.contains(path))
+        .collect();
+    folded(&created, "created", &mut lines);
+    folded(&deleted, "deleted", &mut lines);
+
+    lines.sort();
+    lines.into_iter().map(|(_, line)| line).collect()
+}
+
+/// Runs `commands` on a copy of the working directory and reports what
+/// they changed there.
+pub fn rehearse(
+    commands: &[String],
+    config: &SafetyConfig,
+) -> Result<Rehearsal, Box<dyn std::error::Error>> {
+    let cwd = env::current_dir()?;
+    let scratch = Scratch::create(&cwd, config)?;
+    let before = scan(&scratch.root, config.sandbox_copy_bytes);
+
+    let mut output = Vec::new();
+    let mut succeeded = true;
+    for cmd in commands {
+        let result = Command::new("sh")
+            .arg("-c")
+            .arg(cmd)
+            .current_dir(&scratch.root)
+            .env("PWD", &scratch.root)
+            .stdin(pipe::stdin())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()?;
+        let text = format!("{}{}", decode(&result.stdout), decode(&result.stderr));
+        let root = scratch.root.display().to_string();
+        output.extend(text.lines().map(|l| l.replace(&root, ".")));
+        if !result.status.success() {
+            succeeded = false;
+            break;
+        }
+    }
+    output.drain(..output.len().saturating_sub(OUTPUT_LINES));
+
+    let after = scan(&scratch.root, config.sandbox_copy_bytes);
+    Ok(Rehearsal {
+        copied: scratch.copied,
+        placeholders: scratch.placeholders,
+        left_out: scratch.left_out,
+        succeeded,
+        output,
+        changes: changes(&before, &after),
+    })
+}
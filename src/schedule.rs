@@ -0,0 +1,237 @@
+// Periodic `pls index --quiet --incremental` through whatever scheduler the
+// system already runs: a systemd user timer, a launchd agent, or cron.
+
+use crate::index::find_in_path;
+use crate::quoting;
+use std::{
+    env, fs,
+    io::Write,
+    path::PathBuf,
+    process::{Command, Stdio},
+};
+
+const UNIT: &str = "pls-index";
+const LAUNCHD_LABEL: &str = "dev.pls.index";
+const CRON_MARKER: &str = "# pls-index";
+
+enum Scheduler {
+    Systemd,
+    Launchd,
+    Cron,
+}
+
+fn runs_ok(program: &str, args: &[&str]) -> bool {
+    Command::new(program)
+        .args(args)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|s| s.success())
+}
+
+fn detect() -> Result<Scheduler, Box<dyn std::error::Error>> {
+    if cfg!(target_os = "macos") {
+        return Ok(Scheduler::Launchd);
+    }
+    if runs_ok("systemctl", &["--user", "show-environment"]) {
+        return Ok(Scheduler::Systemd);
+    }
+    if runs_ok("crontab", &["-l"]) || find_in_path("crontab").is_some() {
+        return Ok(Scheduler::Cron);
+    }
+    Err("no scheduler found (need systemd, launchd or cron)".into())
+}
+
+fn home() -> Result<PathBuf, Box<dyn std::error::Error>> {
+    dirs::home_dir().ok_or_else(|| "cannot find home directory".into())
+}
+
+fn systemd_dir() -> Result<PathBuf, Box<dyn std::error::Error>> {
+    Ok(dirs::config_dir()
+        .ok_or("cannot find config directory")?
+        .join("systemd/user"))
+}
+
+fn launchd_plist() -> Result<PathBuf, Box<dyn std::error::Error>> {
+    Ok(home()?
+        .join("Library/LaunchAgents")
+        .join(format!("{}.plist", LAUNCHD_LABEL)))
+}
+
+fn read_crontab() -> String {
+    Command::new("crontab")
+        .arg("-l")
+        .output()
+        .ok()
+        .filter(|o| o.status.success())
+        .map(|o| String::from_utf8_lossy(&o.stdout).to_string())
+        .unwrap_or_default()
+}
+
+fn write_crontab(content: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let mut child = Command::new("crontab")
+        .arg("-")
+        .stdin(Stdio::piped())
+        .spawn()?;
+    child
+        .stdin
+        .take()
+        .ok_or("cannot write to crontab")?
+        .write_all(content.as_bytes())?;
+    if !child.wait()?.success() {
+        return Err("crontab rejected the new table".into());
+    }
+    Ok(())
+}
+
+// cron turns a bare % into a newline, even inside quotes.
+fn cron_word(value: &str) -> String {
+    quoting::quote(value).replace('%', "\\%")
+}
+
+fn without_pls_lines(crontab: &str) -> String {
+    crontab
+        .lines()
+        .filter(|l| !l.contains(CRON_MARKER))
+        .map(|l| format!("{}\n", l))
+        .collect()
+}
+
+/// Installs a job that runs an incremental index every `days` days and
+/// returns where it was put.
+pub fn install(days: u32) -> Result<String, Box<dyn std::error::Error>> {
+    let days = days.max(1);
+    let exe = env::current_exe()?;
+    let exe = exe.to_string_lossy();
+    // Keep using the same config file if it was moved.
+    let config = env::var("PLS_CONFIG").ok();
+
+    match detect()? {
+        Scheduler::Systemd => {
+            let dir = systemd_dir()?;
+            fs::create_dir_all(&dir)?;
+            let environment = config
+                .map(|c| format!("Environment=PLS_CONFIG={}\n", c))
+                .unwrap_or_default();
+            fs::write(
+                dir.join(format!("{}.service", UNIT)),
+                format!(
+                    "[Unit]\nDescription=Refresh the pls tool index\n\n\
+                     [Service]\nType=oneshot\n{}ExecStart={} index --quiet --incremental\n",
+                    environment, exe
+                ),
+            )?;
+            fs::write(
+                dir.join(format!("{}.timer", UNIT)),
+                format!(
+                    "[Unit]\nDescription=Refresh the pls tool index every {} days\n\n\
+                     [Timer]\nOnBootSec=15min\nOnUnitActiveSec={}d\nPersistent=true\n\n\
+                     [Install]\nWantedBy=timers.target\n",
+                    days, days
+                ),
+            )?;
+            let timer = format!("{}.timer", UNIT);
+            if !runs_ok("systemctl", &["--user", "daemon-reload"])
+                || !runs_ok("systemctl", &["--user", "enable", "--now", &timer])
+            {
+                return Err("systemctl could not enable the timer".into());
+            }
+            Ok(format!("systemd user timer {}.timer", UNIT))
+        }
+        Scheduler::Launchd => {
+            let plist = launchd_plist()?;
+            if let Some(parent) = plist.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let environment = config
+                .map(|c| {
+                    format!(
+                        "  <key>EnvironmentVariables</key>\n  <dict>\n    \
+                         <key>PLS_CONFIG</key><string>{}</string>\n  </dict>\n",
+                        c
+                    )
+                })
+                .unwrap_or_default();
+            fs::write(
+                &plist,
+                format!(
+                    "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+                     <!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" \
+                     \"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">\n\
+                     <plist version=\"1.0\">\n<dict>\n  \
+                     <key>Label</key><string>{}</string>\n  \
+                     <key>ProgramArguments</key>\n  <array>\n    \
+                     <string>{}</string>\n    <string>index</string>\n    \
+                     <string>--quiet</string>\n    <string>--incremental</string>\n  </array>\n  \
+                     <key>StartInterval</key><integer>{}</integer>\n{}\
+                     </dict>\n</plist>\n",
+                    LAUNCHD_LABEL,
+                    exe,
+                    days as u64 * 86_400,
+                    environment
+                ),
+            )?;
+            let plist = plist.to_string_lossy();
+            runs_ok("launchctl", &["unload", &plist]);
+            if !runs_ok("launchctl", &["load", "-w", &plist]) {
+                return Err("launchctl could not load the agent".into());
+            }
+            Ok(format!("launchd agent {}", plist))
+        }
+        Scheduler::Cron => {
+            let prefix = config
+                .map(|c| format!("PLS_CONFIG={} ", cron_word(&c)))
+                .unwrap_or_default();
+            let when = if days == 1 {
+                "0 3 * * *".to_string()
+            } else {
+                format!("0 3 */{} * *", days)
+            };
+            let mut crontab = without_pls_lines(&read_crontab());
+            crontab.push_str(&format!(
+                "{} {}{} index --quiet --incremental {}\n",
+                when,
+                prefix,
+                cron_word(&exe),
+                CRON_MARKER
+            ));
+            write_crontab(&crontab)?;
+            Ok("crontab entry".to_string())
+        }
+    }
+}
+
+/// Removes whatever `install` set up. Returns what was removed.
+pub fn remove() -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let mut removed = Vec::new();
+
+    if let Ok(dir) = systemd_dir() {
+        let timer = dir.join(format!("{}.timer", UNIT));
+        if timer.exists() {
+            runs_ok(
+                "systemctl",
+                &["--user", "disable", "--now", &format!("{}.timer", UNIT)],
+            );
+            fs::remove_file(&timer)?;
+            fs::remove_file(dir.join(format!("{}.service", UNIT))).ok();
+            runs_ok("systemctl", &["--user", "daemon-reload"]);
+            removed.push(format!("systemd user timer {}.timer", UNIT));
+        }
+    }
+
+    if let Ok(plist) = launchd_plist() {
+        if plist.exists() {
+            runs_ok("launchctl", &["unload", "-w", &plist.to_string_lossy()]);
+            fs::remove_file(&plist)?;
+            removed.push(format!("launchd agent {}", plist.display()));
+        }
+    }
+
+    let crontab = read_crontab();
+    if crontab.contains(CRON_MARKER) {
+        write_crontab(&without_pls_lines(&crontab))?;
+        removed.push("crontab entry".to_string());
+    }
+
+    Ok(removed)
+}
@@ -0,0 +1,393 @@
+// End-to-end check of index -> plan -> execute against a throwaway fixture.
+// A tiny HTTP server stands in for Ollama and answers each known task with
+// a canned plan, so the suite needs neither a model nor network access.
+
+use crate::config::Config;
+use crate::index::find_in_path;
+use std::{
+    env, fs,
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    os::unix::fs::{symlink, PermissionsExt},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    thread,
+};
+
+pub const DEFAULT_IMAGE: &str = "debian:stable-slim";
+
+// Real binaries the fixture PATH links to. Anything else is invisible.
+const HOST_TOOLS: &[&str] = &[
+    "sh", "mkdir", "touch", "cat", "ls", "false", "true", "printf",
+];
+
+const FIXTURE_TOOL: &str = r#"#!/bin/sh
+case "$1" in
+  --help)
+    echo "usage: fixturetool [--shout] WORD"
+    echo "  --shout   print WORD followed by an exclamation mark"
+    ;;
+  --shout) echo "$2!" ;;
+  *) echo "$1" ;;
+esac
+"#;
+
+struct Case {
+    query: &'static str,
+    commands: &'static [&'static str],
+    verify: &'static [&'static str],
+    // The prompt must mention this, or retrieval didn't do its job.
+    expects_tool: Option<&'static str>,
+    output_contains: &'static str,
+    check: fn(&Path) -> Result<(), String>,
+}
+
+const CASES: &[Case] = &[
+    Case {
+        query: "make a directory called out",
+        commands: &["mkdir out"],
+        verify: &[],
+        expects_tool: Some("mkdir"),
+        output_contains: "",
+        check: |work| {
+            work.join("out")
+                .is_dir()
+                .then_some(())
+                .ok_or("out/ was not created".into())
+        },
+    },
+    Case {
+        query: "write hello into out/greeting.txt",
+        commands: &["printf hello > out/greeting.txt"],
+        verify: &[],
+        expects_tool: None,
+        output_contains: "",
+        check: |work| match fs::read_to_string(work.join("out/greeting.txt")) {
+            Ok(text) if text == "hello" => Ok(()),
+            Ok(text) => Err(format!("greeting.txt contains {:?}", text)),
+            Err(e) => Err(format!("greeting.txt: {}", e)),
+        },
+    },
+    Case {
+        query: "shout the word fixture",
+        commands: &["fixturetool --shout fixture"],
+        verify: &[],
+        expects_tool: Some("fixturetool"),
+        output_contains: "fixture!",
+        check: |_| Ok(()),
+    },
+    Case {
+        query: "stop a chain at the first failure",
+        commands: &["false && touch out/never"],
+        verify: &[],
+        expects_tool: None,
+        output_contains: "step 1 failed",
+        check: |work| {
+            (!work.join("out/never").exists())
+                .then_some(())
+                .ok_or("a step ran after a failed one".into())
+        },
+    },
+    Case {
+        query: "copy the greeting to out/copy.txt",
+        commands: &["cat out/greeting.txt > out/copy.txt"],
+        verify: &["cat out/copy.txt"],
+        expects_tool: Some("cat"),
+        output_contains: "verified step 1",
+        check: |_| Ok(()),
+    },
+    Case {
+        query: "run a step whose check fails",
+        commands: &["true"],
+        verify: &["false"],
+        expects_tool: None,
+        output_contains: "did not verify",
+        check: |_| Ok(()),
+    },
+    Case {
+        query: "wipe the whole disk",
+        commands: &["rm -rf /"],
+        verify: &[],
+        expects_tool: None,
+        output_contains: "refused",
+        check: |_| Ok(()),
+    },
+];
+
+/// The query a planner prompt asks about.
+pub fn task(prompt: &str) -> &str {
+    prompt
+        .lines()
+        .find_map(|l| l.strip_prefix("TASK: "))
+        .unwrap_or("")
+}
+
+fn plan_for(prompt: &str) -> serde_json::Value {
+    let task = task(prompt);
+    let case = CASES.iter().find(|c| c.query == task);
+    let (commands, verify): (Vec<&str>, Vec<&str>) = match case {
+        Some(c) if c.expects_tool.is_none_or(|t| prompt.contains(t)) => {
+            (c.commands.to_vec(), c.verify.to_vec())
+        }
+        _ => (Vec::new(), Vec::new()),
+    };
+    serde_json::json!({
+        "commands": commands,
+        "verify": verify,
+        "explanation": "selftest",
+        "warnings": [],
+        "needs_confirmation": true,
+    })
+}
+
+// One piece of a reply the way /api/generate or /api/chat puts it.
+fn reply_line(text: &str, chat: bool, done: bool) -> serde_json::Value {
+    if chat {
+        serde_json::json!({ "message": { "role": "assistant", "content": text }, "done": done })
+    } else {
+        serde_json::json!({ "response": text, "done": done })
+    }
+}
+
+// A streamed reply, a few characters per line the way Ollama sends tokens.
+fn stream_lines(text: &str, chat: bool) -> String {
+    let text: Vec<char> = text.chars().collect();
+    let mut lines: Vec<String> = text
+        .chunks(8)
+        .map(|piece| reply_line(&piece.iter().collect::<String>(), chat, false).to_string())
+        .collect();
+    lines.push(reply_line("", chat, true).to_string());
+    lines.join("\n")
+}
+
+fn handle(mut stream: TcpStream, answer: fn(&str) -> serde_json::Value) -> std::io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+
+    let mut length = 0;
+    loop {
+        let mut header = String::new();
+        reader.read_line(&mut header)?;
+        let header = header.trim();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse().unwrap_or(0);
+            }
+        }
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+
+    let path = request_line.split_whitespace().nth(1).unwrap_or("");
+    let reply = match path {
+        "/api/version" => serde_json::json!({ "version": "0.5.0" }).to_string(),
+        "/api/tags" => serde_json::json!({ "models": [] }).to_string(),
+        "/api/generate" | "/api/chat" => {
+            let request: serde_json::Value = serde_json::from_slice(&body).unwrap_or_default();
+            let chat = path == "/api/chat";
+            let prompt = match request["messages"].as_array() {
+                Some(messages) => messages
+                    .iter()
+                    .filter_map(|m| m["content"].as_str())
+                    .collect::<Vec<_>>()
+                    .join("\n\n"),
+                None => request["prompt"].as_str().unwrap_or("").to_string(),
+            };
+            let text = answer(&prompt).to_string();
+            if request["stream"].as_bool() == Some(true) {
+                stream_lines(&text, chat)
+            } else {
+                reply_line(&text, chat, true).to_string()
+            }
+        }
+        _ => serde_json::json!({ "error": "not found" }).to_string(),
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        reply.len(),
+        reply
+    )
+}
+
+/// Serves Ollama's API on a free local port until pls exits, with `answer`
+/// writing the model's reply to each prompt. Returns the endpoint.
+pub fn start_mock(
+    answer: fn(&str) -> serde_json::Value,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let addr = listener.local_addr()?;
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            handle(stream, answer).ok();
+        }
+    });
+    Ok(format!("http://{}", addr))
+}
+
+struct Fixture {
+    root: PathBuf,
+    exe: PathBuf,
+}
+
+impl Fixture {
+    fn create(endpoint: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let root = env::temp_dir().join(format!("pls-selftest-{}", std::process::id()));
+        let bin = root.join("bin");
+        fs::create_dir_all(&bin)?;
+        fs::create_dir_all(root.join("work"))?;
+
+        for tool in HOST_TOOLS {
+            let path = find_in_path(tool).ok_or(format!("{} not found on this system", tool))?;
+            symlink(path, bin.join(tool))?;
+        }
+        let fake = bin.join("fixturetool");
+        fs::write(&fake, FIXTURE_TOOL)?;
+        fs::set_permissions(&fake, fs::Permissions::from_mode(0o755))?;
+
+        let mut config = Config::default();
+        config.llm.endpoint = endpoint.to_string();
+        config.llm.embed_provider = "local".to_string();
+        config.paths.data_dir = Some(root.join("data"));
+        config.paths.state_dir = Some(root.join("state"));
+        config.paths.cache_dir = Some(root.join("cache"));
+        fs::write(root.join("config.toml"), toml::to_string(&config)?)?;
+
+        Ok(Self {
+            root,
+            exe: env::current_exe()?,
+        })
+    }
+
+    fn work(&self) -> PathBuf {
+        self.root.join("work")
+    }
+
+    // Runs pls inside the fixture, answering every prompt with enter.
+    fn pls(&self, args: &[&str]) -> Result<String, Box<dyn std::error::Error>> {
+        let mut child = Command::new(&self.exe)
+            .args(args)
+            .current_dir(self.work())
+            .env("PATH", self.root.join("bin"))
+            .env("HOME", &self.root)
+            .env("PLS_CONFIG", self.root.join("config.toml"))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(b"\n").ok();
+        }
+        let output = child.wait_with_output()?;
+        Ok(format!(
+            "{}{}",
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        ))
+    }
+}
+
+impl Drop for Fixture {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.root).ok();
+    }
+}
+
+fn report(name: &str, result: Result<(), String>, failed: &mut usize) {
+    match result {
+        Ok(()) => println!("  ok    {}", name),
+        Err(e) => {
+            println!("  FAIL  {}: {}", name, e);
+            *failed += 1;
+        }
+    }
+}
+
+/// Runs the suite on this machine, inside a temporary directory.
+pub fn run_local() -> Result<(), Box<dyn std::error::Error>> {
+    let endpoint = start_mock(plan_for)?;
+    let fixture = Fixture::create(&endpoint)?;
+    let mut failed = 0;
+
+    println!("selftest:");
+
+    let stats = fixture
+        .pls(&["index", "--quiet"])
+        .and_then(|_| fixture.pls(&["index", "--stats"]))?;
+    let expected = HOST_TOOLS.len() + 1;
+    report(
+        "index fake PATH",
+        if stats.contains(&format!("tools: {}", expected)) {
+            Ok(())
+        } else {
+            Err(format!(
+                "expected {} tools, got: {}",
+                expected,
+                stats.trim()
+            ))
+        },
+        &mut failed,
+    );
+
+    for case in CASES {
+        let output = fixture.pls(&[case.query])?;
+        let result = if !output.contains(case.output_contains) {
+            Err(format!(
+                "output lacks {:?}: {}",
+                case.output_contains,
+                output.trim()
+            ))
+        } else if output.contains("not possible:") {
+            Err("no plan (tool missing from the prompt?)".to_string())
+        } else {
+            (case.check)(&fixture.work())
+        };
+        report(case.query, result, &mut failed);
+    }
+
+    let history = fixture.pls(&["history"])?;
+    report(
+        "history records queries",
+        if history.contains(CASES[0].query) {
+            Ok(())
+        } else {
+            Err(format!("missing from: {}", history.trim()))
+        },
+        &mut failed,
+    );
+
+    println!();
+    let total = CASES.len() + 3;
+    println!("{} passed, {} failed", total - failed, failed);
+    if failed > 0 {
+        return Err("selftest failed".into());
+    }
+    Ok(())
+}
+
+/// Runs the suite in a throwaway docker or podman container, with this
+/// binary mounted read-only. The image needs a compatible libc.
+pub fn run_container(image: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let engine = ["docker", "podman"]
+        .into_iter()
+        .find(|e| find_in_path(e).is_some())
+        .ok_or("neither docker nor podman is installed")?;
+    let exe = env::current_exe()?;
+
+    println!("running selftest in {} ({})", image, engine);
+    let status = Command::new(engine)
+        .args(["run", "--rm", "--network", "none", "-v"])
+        .arg(format!("{}:/usr/local/bin/pls:ro", exe.display()))
+        .args([image, "/usr/local/bin/pls", "selftest"])
+        .status()?;
+
+    if !status.success() {
+        return Err("selftest failed in container".into());
+    }
+    Ok(())
+}
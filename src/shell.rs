@@ -0,0 +1,313 @@
+// Lightweight shell-line inspection. This is not a full parser; it only
+// understands enough quoting to find where one command ends and the next
+// begins.
+
+pub const WRAPPERS: &[&str] = &[
+    "sudo", "doas", "env", "nohup", "time", "nice", "ionice", "timeout", "xargs", "exec",
+    "command", "watch",
+];
+
+// Walks a command line and cuts it at the top-level operators for which
+// `split` returns true. Other operators stay part of the segment text.
+fn split_top_level(cmd: &str, split: impl Fn(&str) -> bool) -> Vec<String> {
+    split_with_operators(cmd, split)
+        .into_iter()
+        .map(|(_, s)| s)
+        .collect()
+}
+
+// As split_top_level, each segment with the operator before it ("" for the
+// first).
+fn split_with_operators(cmd: &str, split: impl Fn(&str) -> bool) -> Vec<(String, String)> {
+    let mut segments = Vec::new();
+    let mut op_before = String::new();
+    let mut current = String::new();
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    let mut chars = cmd.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if escaped {
+            current.push(c);
+            escaped = false;
+            continue;
+        }
+        match (quote, c) {
+            (_, '\\') if quote != Some('\'') => {
+                current.push(c);
+                escaped = true;
+            }
+            (Some(q), c) if c == q => {
+                quote = None;
+                current.push(c);
+            }
+            (Some(_), c) => current.push(c),
+            (None, '\'' | '"') => {
+                quote = Some(c);
+                current.push(c);
+            }
+            (None, '|' | '&' | ';' | '\n') => {
+                // `2>&1` and `&>` are redirections, not separators.
+                if c == '&' && (current.ends_with('>') || chars.peek() == Some(&'>')) {
+                    current.push(c);
+                    continue;
+                }
+                let mut op = c.to_string();
+                if (c == '|' || c == '&') && chars.peek() == Some(&c) {
+                    chars.next();
+                    op.push(c);
+                }
+                if split(&op) {
+                    let before = std::mem::replace(&mut op_before, op);
+                    segments.push((before, std::mem::take(&mut current)));
+                } else {
+                    current.push_str(&op);
+                }
+            }
+            (None, c) => current.push(c),
+        }
+    }
+    segments.push((op_before, current));
+
+    segments
+        .into_iter()
+        .map(|(op, s)| (op, s.trim().to_string()))
+        .filter(|(_, s)| !s.is_empty())
+        .collect()
+}
+
+/// Splits a command line at `|`, `||`, `&&`, `;`, `&` and newlines that are
+/// not inside quotes.
+pub fn split_segments(cmd: &str) -> Vec<String> {
+    split_top_level(cmd, |_| true)
+}
+
+/// Splits a command line into pipelines: at `||`, `&&`, `;`, `&` and
+/// newlines, but not `|`.
+pub fn split_pipelines(cmd: &str) -> Vec<String> {
+    split_top_level(cmd, |op| op != "|")
+}
+
+/// A command longer than `width` laid out one pipeline stage per line: a
+/// break before each top-level `|`, `&&` and `||`, continued with `\` and
+/// the operators lined up under each other. Shorter commands, those already
+/// spanning lines or piping stderr with `|&`, and a `width` of 0 are left
+/// as they are.
+pub fn fold(cmd: &str, width: usize) -> String {
+    if width == 0 || cmd.chars().count() <= width || cmd.contains('\n') || cmd.contains("|&") {
+        return cmd.to_string();
+    }
+    let stages = split_with_operators(cmd, |op| matches!(op, "|" | "&&" | "||"));
+    if stages.len() < 2 {
+        return cmd.to_string();
+    }
+    let op_width = stages.iter().map(|(op, _)| op.len()).max().unwrap_or(0);
+    stages
+        .iter()
+        .map(|(op, stage)| match op.as_str() {
+            "" => stage.clone(),
+            op => format!("  {:<w$} {}", op, stage, w = op_width),
+        })
+        .collect::<Vec<_>>()
+        .join(" \\\n")
+}
+
+/// Undoes `fold`, or any other `\`-continued lines: the command back on
+/// one line. Lines that aren't continued stay as they are.
+pub fn unfold(text: &str) -> String {
+    let mut out = String::new();
+    let mut continued = false;
+    for line in text.trim().lines() {
+        if continued {
+            out.push(' ');
+            out.push_str(line.trim_start());
+        } else {
+            if !out.is_empty() {
+                out.push('\n');
+            }
+            out.push_str(line);
+        }
+        continued = out.ends_with('\\') && !out.ends_with("\\\\");
+        if continued {
+            out.pop();
+            out.truncate(out.trim_end().len());
+        }
+    }
+    out
+}
+
+// Builtins whose effect has to carry over to the next command, so a chain
+// containing them only works as a single shell invocation.
+const STATEFUL: &[&str] = &[
+    "cd", "pushd", "popd", "export", "source", ".", "set", "unset", "alias", "shopt", "umask",
+];
+
+// Whether every `(` and `{` in `step` is closed in it, outside quotes. A
+// `&&` inside `( ... )`, `{ ...; }` or `$( ... )` leaves both halves
+// unbalanced.
+fn balanced(step: &str) -> bool {
+    let mut depth = 0i32;
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    for c in step.chars() {
+        match (quote, c) {
+            _ if escaped => escaped = false,
+            (q, '\\') if q != Some('\'') => escaped = true,
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '\'' | '"') => quote = Some(c),
+            (None, '(' | '{') => depth += 1,
+            (None, ')' | '}') => {
+                depth -= 1;
+                if depth < 0 {
+                    return false;
+                }
+            }
+            _ => {}
+        }
+    }
+    depth == 0
+}
+
+/// Splits `a && b && c` into separate steps, which the executor runs with
+/// the same stop-on-failure semantics. Chains that also use `||`, `;` or
+/// `&` are left whole, as is any chain whose steps depend on shell state
+/// (`cd`, `export`, bare `VAR=value`) and any with a `&&` inside a
+/// subshell, group or `$(...)`.
+pub fn split_chain(cmd: &str) -> Vec<String> {
+    let steps = split_top_level(cmd, |op| op == "&&");
+    if steps.len() < 2 || !steps.iter().all(|step| balanced(step)) {
+        return vec![cmd.trim().to_string()];
+    }
+    let mixed = split_top_level(cmd, |op| matches!(op, "||" | ";" | "&" | "\n")).len() > 1;
+    if mixed {
+        return vec![cmd.trim().to_string()];
+    }
+
+    let stateful = steps.iter().any(|step| {
+        let first = step.split_whitespace().next().unwrap_or("");
+        STATEFUL.contains(&first.trim_start_matches(['(', '{']))
+            || step
+                .split_whitespace()
+                .all(|t| t.contains('=') && !t.starts_with('-'))
+    });
+    if stateful {
+        vec![cmd.trim().to_string()]
+    } else {
+        steps
+    }
+}
+
+/// Splits a command into words the way the shell would, removing quotes.
+/// Operators are not treated specially.
+pub fn words(cmd: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut current = String::new();
+    let mut in_word = false;
+    let mut quote: Option<char> = None;
+    let mut chars = cmd.chars();
+
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some('"'), '\\') => {
+                if let Some(next) = chars.next() {
+                    current.push(next);
+                }
+            }
+            (Some(_), c) => current.push(c),
+            (None, '\'' | '"') => {
+                quote = Some(c);
+                in_word = true;
+            }
+            (None, '\\') => {
+                if let Some(next) = chars.next() {
+                    current.push(next);
+                }
+                in_word = true;
+            }
+            (None, c) if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut current));
+                    in_word = false;
+                }
+            }
+            (None, c) => {
+                current.push(c);
+                in_word = true;
+            }
+        }
+    }
+    if in_word {
+        words.push(current);
+    }
+
+    words
+}
+
+/// Names of the programs a command line invokes, in order of appearance.
+/// Wrappers like `sudo` or `xargs` are reported along with the command they
+/// run.
+pub fn command_names(cmd: &str) -> Vec<String> {
+    let mut names = Vec::new();
+
+    for segment in split_segments(cmd) {
+        let mut tokens = segment
+            .split_whitespace()
+            .skip_while(|t| t.contains('=') && !t.starts_with('-'))
+            .map(|t| t.trim_start_matches(['(', '{']));
+
+        while let Some(token) = tokens.next() {
+            if token.is_empty() || token.starts_with('-') {
+                continue;
+            }
+            let name = token.rsplit('/').next().unwrap_or(token).to_string();
+            let wrapper = WRAPPERS.contains(&name.as_str());
+            names.push(name);
+            if !wrapper {
+                break;
+            }
+            // `timeout 10s cmd`: skip the duration argument.
+            if names.last().map(|n| n.as_str()) == Some("timeout") {
+                tokens.next();
+            }
+        }
+    }
+
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::split_chain;
+
+    #[test]
+    fn chains_split_into_steps() {
+        assert_eq!(
+            split_chain("mkdir out && cp a out/"),
+            ["mkdir out", "cp a out/"]
+        );
+        assert_eq!(
+            split_chain("make && echo 'a && b'"),
+            ["make", "echo 'a && b'"]
+        );
+    }
+
+    #[test]
+    fn nested_chains_stay_whole() {
+        for cmd in [
+            "( cd build && make )",
+            "(cd build && make) && ls",
+            "{ make && make install; }",
+            "echo $(make && echo ok) && ls",
+        ] {
+            assert_eq!(split_chain(cmd), [cmd]);
+        }
+    }
+
+    #[test]
+    fn stateful_chains_stay_whole() {
+        assert_eq!(split_chain("cd build && make"), ["cd build && make"]);
+        assert_eq!(split_chain("(cd build) && make"), ["(cd build) && make"]);
+    }
+}
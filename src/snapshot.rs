@@ -0,0 +1,158 @@
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    fs,
+    hash::Hasher,
+    path::{Path, PathBuf},
+};
+
+// Files at or under this size get content-hashed so in-place edits show up.
+const HASH_LIMIT: u64 = 64 * 1024;
+
+#[derive(Debug, Clone, PartialEq)]
+struct FileState {
+    size: u64,
+    hash: Option<u64>,
+}
+
+/// File list of a directory tree at one point in time.
+pub struct Snapshot {
+    files: HashMap<PathBuf, FileState>,
+    truncated: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct SnapshotDiff {
+    pub created: usize,
+    pub deleted: usize,
+    pub modified: usize,
+    pub bytes_before: u64,
+    pub bytes_after: u64,
+    pub truncated: bool,
+}
+
+fn hash_file(path: &Path) -> Option<u64> {
+    let content = fs::read(path).ok()?;
+    let mut hasher = DefaultHasher::new();
+    hasher.write(&content);
+    Some(hasher.finish())
+}
+
+pub fn take(root: &Path, max_files: usize) -> Snapshot {
+    let mut files = HashMap::new();
+    let mut pending = vec![root.to_path_buf()];
+    let mut truncated = false;
+
+    while let Some(dir) = pending.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.filter_map(|e| e.ok()) {
+            let Ok(meta) = entry.path().symlink_metadata() else {
+                continue;
+            };
+            if meta.is_dir() {
+                pending.push(entry.path());
+                continue;
+            }
+            if files.len() >= max_files {
+                truncated = true;
+                break;
+            }
+            let hash = if meta.is_file() && meta.len() <= HASH_LIMIT {
+                hash_file(&entry.path())
+            } else {
+                None
+            };
+            files.insert(
+                entry.path(),
+                FileState {
+                    size: meta.len(),
+                    hash,
+                },
+            );
+        }
+    }
+
+    Snapshot { files, truncated }
+}
+
+pub fn diff(before: &Snapshot, after: &Snapshot) -> SnapshotDiff {
+    let mut result = SnapshotDiff {
+        truncated: before.truncated || after.truncated,
+        ..Default::default()
+    };
+
+    for (path, state) in &before.files {
+        result.bytes_before += state.size;
+        match after.files.get(path) {
+            None => result.deleted += 1,
+            Some(now) if now != state => result.modified += 1,
+            _ => {}
+        }
+    }
+    for (path, state) in &after.files {
+        result.bytes_after += state.size;
+        if !before.files.contains_key(path) {
+            result.created += 1;
+        }
+    }
+
+    result
+}
+
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+impl SnapshotDiff {
+    /// One line like "deleted 42 files, 1.2 GB freed".
+    pub fn summary(&self) -> String {
+        let mut parts = Vec::new();
+        let files = |n: usize| if n == 1 { "file" } else { "files" };
+        if self.created > 0 {
+            parts.push(format!("created {} {}", self.created, files(self.created)));
+        }
+        if self.deleted > 0 {
+            parts.push(format!("deleted {} {}", self.deleted, files(self.deleted)));
+        }
+        if self.modified > 0 {
+            parts.push(format!(
+                "modified {} {}",
+                self.modified,
+                files(self.modified)
+            ));
+        }
+        if parts.is_empty() {
+            parts.push("no file changes".to_string());
+        }
+
+        if self.bytes_after < self.bytes_before {
+            parts.push(format!(
+                "{} freed",
+                format_bytes(self.bytes_before - self.bytes_after)
+            ));
+        } else if self.bytes_after > self.bytes_before {
+            parts.push(format!(
+                "{} added",
+                format_bytes(self.bytes_after - self.bytes_before)
+            ));
+        }
+
+        let mut line = parts.join(", ");
+        if self.truncated {
+            line.push_str(" (partial: file limit reached)");
+        }
+        line
+    }
+}
@@ -0,0 +1,83 @@
+// Output of a run as it is produced, kept in the state directory until the
+// run is filed. The history entry goes in as pending, with the pid of the
+// pls running it, before the first command starts; if pls is killed or the
+// machine goes down before it finishes, the next start files the entry as
+// interrupted, with whatever output reached the spill files.
+
+use crate::config::PathsConfig;
+use crate::db::{get_pending_history, interrupt_history};
+use crate::encoding::decode;
+use crate::jobs;
+use crate::paths::state_dir;
+use rusqlite::Connection;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+const DIR: &str = "running";
+// Lines of an interrupted run's output kept with its entry, from the end.
+const KEPT_LINES: usize = 100;
+
+fn path(paths: &PathsConfig, id: i64) -> PathBuf {
+    state_dir(paths).join(DIR).join(format!("{}.out", id))
+}
+
+/// Where the run filed as entry `id` writes its stdout. Stderr goes beside
+/// it, see `stderr_path`.
+pub fn create(paths: &PathsConfig, id: i64) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    fs::create_dir_all(state_dir(paths).join(DIR))?;
+    Ok(path(paths, id))
+}
+
+pub fn stderr_path(stdout: &Path) -> PathBuf {
+    stdout.with_extension("err")
+}
+
+/// Where a step's pipeline stages leave their exit statuses.
+pub fn stages_path(stdout: &Path) -> PathBuf {
+    stdout.with_extension("stages")
+}
+
+pub fn remove(stdout: &Path) {
+    fs::remove_file(stdout).ok();
+    fs::remove_file(stderr_path(stdout)).ok();
+    fs::remove_file(stages_path(stdout)).ok();
+}
+
+/// Files entries whose pls has gone away as interrupted. Returns how many.
+pub fn recover(
+    conn: &Connection,
+    paths: &PathsConfig,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let mut recovered = 0;
+    for run in get_pending_history(conn)? {
+        if jobs::process_alive(run.pid, run.started) {
+            continue;
+        }
+        let (id, started) = (run.id, run.started);
+        let out = path(paths, id);
+        let stdout = fs::read(&out).unwrap_or_default();
+        let stderr = fs::read(stderr_path(&out)).unwrap_or_default();
+
+        let text = format!("{}\n{}", decode(&stdout), decode(&stderr));
+        let mut lines: Vec<&str> = text.lines().filter(|l| !l.is_empty()).collect();
+        lines.drain(..lines.len().saturating_sub(KEPT_LINES));
+        lines.push("[interrupted: pls exited before the run finished]");
+
+        // The last write is as close as we get to when it stopped.
+        let stopped = [&out, &stderr_path(&out)]
+            .iter()
+            .filter_map(|p| fs::metadata(p).and_then(|m| m.modified()).ok())
+            .filter_map(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_millis() as i64)
+            .max();
+        let duration_ms = stopped.map_or(0, |ms| (ms - started * 1000).max(0) as u64);
+
+        interrupt_history(conn, id, &lines.join("\n"), &stdout, duration_ms)?;
+        remove(&out);
+        recovered += 1;
+    }
+    Ok(recovered)
+}
@@ -0,0 +1,259 @@
+use crate::clock;
+use crate::shell::{split_segments, words};
+use crate::types::{Severity, Warning};
+use std::env;
+
+const MINUTE: f64 = 1.0;
+const HOUR: f64 = 60.0;
+const DAY: f64 = 1440.0;
+const WEEK: f64 = 7.0 * DAY;
+const MONTH: f64 = 30.0 * DAY;
+const QUARTER: f64 = 91.0 * DAY;
+const YEAR: f64 = 365.0 * DAY;
+
+/// How far back a query or command reaches, in minutes.
+struct Window {
+    minutes: f64,
+    phrase: String,
+    // -mtime style flags can only count whole days.
+    day_granular: bool,
+}
+
+/// Date, time, timezone and locale for the prompt, so relative phrases like
+/// "since Monday" can be resolved.
+pub fn context_block() -> String {
+    let tm = clock::local(clock::now());
+    let zone = unsafe {
+        if tm.tm_zone.is_null() {
+            String::new()
+        } else {
+            std::ffi::CStr::from_ptr(tm.tm_zone)
+                .to_string_lossy()
+                .to_string()
+        }
+    };
+    let offset = tm.tm_gmtoff / 60;
+    let locale = ["LC_ALL", "LC_TIME", "LANG"]
+        .iter()
+        .find_map(|v| env::var(v).ok().filter(|s| !s.is_empty()))
+        .unwrap_or_else(|| "C".to_string());
+
+    format!(
+        "Current date: {} ({}), local time {:02}:{:02}, timezone {} (UTC{}{:02}:{:02}), locale {}",
+        clock::format_date(&tm),
+        clock::weekday(&tm),
+        tm.tm_hour,
+        tm.tm_min,
+        zone,
+        if offset < 0 { '-' } else { '+' },
+        offset.abs() / 60,
+        offset.abs() % 60,
+        locale
+    )
+}
+
+fn unit_minutes(word: &str) -> Option<f64> {
+    let word = word.trim_end_matches('s');
+    match word {
+        "minute" | "min" => Some(MINUTE),
+        "hour" | "hr" => Some(HOUR),
+        "day" => Some(DAY),
+        "week" => Some(WEEK),
+        "month" => Some(MONTH),
+        "quarter" => Some(QUARTER),
+        "year" => Some(YEAR),
+        _ => None,
+    }
+}
+
+fn minutes_since_midnight(tm: &libc::tm) -> f64 {
+    (tm.tm_hour * 60 + tm.tm_min) as f64
+}
+
+fn days_since_weekday(tm: &libc::tm, name: &str) -> Option<f64> {
+    let target = clock::WEEKDAYS
+        .iter()
+        .position(|d| d.to_lowercase().starts_with(name) && name.len() >= 3)?;
+    Some(((tm.tm_wday as usize + 7 - target) % 7) as f64)
+}
+
+// Resolves the phrases people use in queries and in `date -d` style
+// expressions to a look-back window.
+fn parse_relative(text: &str, tm: &libc::tm) -> Option<Window> {
+    let lower = text.to_lowercase();
+    let tokens: Vec<&str> = lower
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|t| !t.is_empty())
+        .collect();
+    let window = |minutes: f64, phrase: String| {
+        Some(Window {
+            minutes,
+            phrase,
+            day_granular: false,
+        })
+    };
+
+    for (i, token) in tokens.iter().enumerate() {
+        let next = tokens.get(i + 1).copied().unwrap_or("");
+        let after = tokens.get(i + 2).copied().unwrap_or("");
+        match *token {
+            "today" => return window(minutes_since_midnight(tm), "today".into()),
+            "yesterday" => {
+                return window(minutes_since_midnight(tm) + DAY, "since yesterday".into())
+            }
+            "last" | "past" => {
+                if let (Ok(n), Some(unit)) = (next.parse::<f64>(), unit_minutes(after)) {
+                    return window(n * unit, format!("the last {} {}", next, after));
+                }
+                if let Some(unit) = unit_minutes(next) {
+                    return window(unit, format!("the last {}", next));
+                }
+                if let Some(days) = days_since_weekday(tm, next) {
+                    let days = if days == 0.0 { 7.0 } else { days };
+                    return window(
+                        days * DAY + minutes_since_midnight(tm),
+                        format!("since last {}", next),
+                    );
+                }
+            }
+            "since" => {
+                if let Some(days) = days_since_weekday(tm, next) {
+                    return window(
+                        days * DAY + minutes_since_midnight(tm),
+                        format!("since {}", next),
+                    );
+                }
+            }
+            "this" if next == "week" => {
+                let days = ((tm.tm_wday + 6) % 7) as f64;
+                return window(days * DAY + minutes_since_midnight(tm), "this week".into());
+            }
+            "this" if next == "month" => {
+                let days = (tm.tm_mday - 1) as f64;
+                return window(days * DAY + minutes_since_midnight(tm), "this month".into());
+            }
+            _ => {}
+        }
+        if next.starts_with("ago") || after == "ago" {
+            if let (Ok(n), Some(unit)) = (token.parse::<f64>(), unit_minutes(next)) {
+                return window(n * unit, format!("{} {} ago", token, next));
+            }
+        }
+    }
+
+    None
+}
+
+fn parse_absolute_date(text: &str) -> Option<f64> {
+    let mut parts = text.trim().splitn(3, '-');
+    let year: i32 = parts.next()?.parse().ok()?;
+    let month: i32 = parts.next()?.parse().ok()?;
+    let day: i32 = parts.next()?.get(..2)?.parse().ok()?;
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    tm.tm_year = year - 1900;
+    tm.tm_mon = month - 1;
+    tm.tm_mday = day;
+    tm.tm_isdst = -1;
+    let then = unsafe { libc::mktime(&mut tm) } as i64;
+    Some((clock::now() - then) as f64 / 60.0)
+}
+
+// Windows expressed by find/date/journalctl arguments in one command.
+fn command_windows(cmd: &str, tm: &libc::tm) -> Vec<Window> {
+    let mut windows = Vec::new();
+
+    for segment in split_segments(cmd) {
+        let args = words(&segment);
+        let program = args.first().map(|p| p.rsplit('/').next().unwrap_or(p));
+        for (i, arg) in args.iter().enumerate() {
+            let Some(value) = args.get(i + 1) else {
+                continue;
+            };
+            let date_arg = match program {
+                Some("date") => matches!(arg.as_str(), "-d" | "--date"),
+                Some("journalctl") => matches!(arg.as_str(), "-S" | "--since"),
+                _ => arg == "-newermt",
+            };
+            if date_arg {
+                let resolved = parse_relative(value, tm)
+                    .map(|w| w.minutes)
+                    .or_else(|| parse_absolute_date(value));
+                if let Some(minutes) = resolved {
+                    windows.push(Window {
+                        minutes,
+                        phrase: format!("{} '{}'", arg, value),
+                        day_granular: false,
+                    });
+                }
+                continue;
+            }
+            match arg.as_str() {
+                "-mtime" | "-ctime" | "-atime" => {
+                    if let Some(n) = value.strip_prefix('-').and_then(|n| n.parse::<f64>().ok()) {
+                        windows.push(Window {
+                            minutes: n * DAY,
+                            phrase: format!("{} {}", arg, value),
+                            day_granular: true,
+                        });
+                    }
+                }
+                "-mmin" | "-cmin" | "-amin" => {
+                    if let Some(n) = value.strip_prefix('-').and_then(|n| n.parse::<f64>().ok()) {
+                        windows.push(Window {
+                            minutes: n,
+                            phrase: format!("{} {}", arg, value),
+                            day_granular: false,
+                        });
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    windows
+}
+
+fn describe(minutes: f64) -> String {
+    if minutes < 2.0 * HOUR {
+        format!("{:.0} minutes", minutes)
+    } else if minutes < 2.0 * DAY {
+        format!("{:.0} hours", minutes / HOUR)
+    } else {
+        format!("{:.0} days", minutes / DAY)
+    }
+}
+
+/// Warnings for commands whose date arithmetic doesn't match the time range
+/// the query asked for.
+pub fn check_plan(query: &str, commands: &[String]) -> Vec<Warning> {
+    let tm = clock::local(clock::now());
+    let Some(requested) = parse_relative(query, &tm) else {
+        return Vec::new();
+    };
+
+    let mut warnings = Vec::new();
+    for cmd in commands {
+        for found in command_windows(cmd, &tm) {
+            let mut tolerance = requested.minutes * 0.25;
+            if found.day_granular && requested.minutes >= DAY {
+                tolerance = tolerance.max(DAY);
+            }
+            if (found.minutes - requested.minutes).abs() > tolerance {
+                let message = format!(
+                    "query asks for {} (~{}) but `{}` covers ~{}",
+                    requested.phrase,
+                    describe(requested.minutes),
+                    found.phrase,
+                    describe(found.minutes)
+                );
+                warnings.push(
+                    Warning::new(Severity::Caution, message)
+                        .with_hint("press e to fix the range before running it"),
+                );
+            }
+        }
+    }
+
+    warnings
+}
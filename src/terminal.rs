@@ -0,0 +1,108 @@
+#[cfg(feature = "terminal-guard")]
+use std::sync::OnceLock;
+
+// Terminal modes as they were when pls started. Restored on panic, on fatal
+// signals, and whenever a TerminalGuard is dropped.
+#[cfg(feature = "terminal-guard")]
+static ORIGINAL: OnceLock<libc::termios> = OnceLock::new();
+
+#[cfg(feature = "terminal-guard")]
+const FATAL_SIGNALS: [libc::c_int; 4] = [libc::SIGINT, libc::SIGTERM, libc::SIGHUP, libc::SIGQUIT];
+
+#[cfg(feature = "terminal-guard")]
+fn snapshot() -> Option<libc::termios> {
+    unsafe {
+        if libc::isatty(libc::STDIN_FILENO) != 1 {
+            return None;
+        }
+        let mut term: libc::termios = std::mem::zeroed();
+        if libc::tcgetattr(libc::STDIN_FILENO, &mut term) != 0 {
+            return None;
+        }
+        Some(term)
+    }
+}
+
+#[cfg(feature = "terminal-guard")]
+fn apply(term: &libc::termios) {
+    unsafe {
+        libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, term);
+    }
+}
+
+#[cfg(feature = "terminal-guard")]
+pub fn restore() {
+    if let Some(term) = ORIGINAL.get() {
+        apply(term);
+    }
+}
+
+#[cfg(feature = "terminal-guard")]
+extern "C" fn on_signal(sig: libc::c_int) {
+    // tcsetattr is async-signal-safe; after restoring, fall back to the
+    // default disposition so the exit status still reflects the signal.
+    restore();
+    unsafe {
+        libc::signal(sig, libc::SIG_DFL);
+        libc::raise(sig);
+    }
+}
+
+#[cfg(feature = "terminal-guard")]
+pub fn install() {
+    let Some(term) = snapshot() else {
+        return;
+    };
+    if ORIGINAL.set(term).is_err() {
+        return;
+    }
+
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        restore();
+        previous(info);
+    }));
+
+    for sig in FATAL_SIGNALS {
+        unsafe {
+            libc::signal(sig, on_signal as *const () as libc::sighandler_t);
+        }
+    }
+}
+
+/// Snapshots the terminal on creation and puts it back on drop, so anything
+/// run in between (an editor, a child command) can't leave it in raw mode.
+#[cfg(feature = "terminal-guard")]
+pub struct TerminalGuard {
+    saved: Option<libc::termios>,
+}
+
+#[cfg(feature = "terminal-guard")]
+impl TerminalGuard {
+    pub fn new() -> Self {
+        Self { saved: snapshot() }
+    }
+}
+
+#[cfg(feature = "terminal-guard")]
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        if let Some(term) = &self.saved {
+            apply(term);
+        }
+    }
+}
+
+// Without the guard, terminal handling is left entirely to the children.
+#[cfg(not(feature = "terminal-guard"))]
+pub fn install() {}
+
+#[cfg(not(feature = "terminal-guard"))]
+pub struct TerminalGuard;
+
+#[cfg(not(feature = "terminal-guard"))]
+impl TerminalGuard {
+    pub fn new() -> Self {
+        Self
+    }
+}
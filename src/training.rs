@@ -0,0 +1,161 @@
+// Training wheels for the first runs: alongside each plan, say in plain
+// words what every command touches and why it got its risk level, so new
+// users learn what to look for before they reach for -y.
+
+use crate::shell::{split_segments, words, WRAPPERS};
+
+const DELETES: &[&str] = &["rm", "rmdir", "unlink", "shred"];
+const CREATES: &[&str] = &["mkdir", "touch"];
+const PERMISSIONS: &[&str] = &["chmod", "chown", "chgrp"];
+const NETWORK: &[&str] = &[
+    "curl", "wget", "ssh", "scp", "sftp", "ftp", "nc", "ping", "dig",
+];
+const INSTALLERS: &[&str] = &[
+    "apt", "apt-get", "dnf", "yum", "pacman", "brew", "pip", "pip3", "npm",
+];
+const SIGNALS: &[&str] = &["kill", "pkill", "killall"];
+
+// Arguments that aren't flags or redirections.
+fn operands(args: &[String]) -> Vec<&str> {
+    let mut operands = Vec::new();
+    let mut redirect_target = false;
+    for arg in args {
+        if redirect_target {
+            redirect_target = false;
+            continue;
+        }
+        let redirect = arg.trim_start_matches(['1', '2', '&']);
+        if redirect.starts_with(['>', '<']) {
+            redirect_target = redirect.trim_start_matches(['>', '<']).is_empty();
+        } else if !arg.starts_with('-') {
+            operands.push(arg.as_str());
+        }
+    }
+    operands
+}
+
+fn list(items: &[&str]) -> String {
+    if items.is_empty() {
+        "its arguments".to_string()
+    } else {
+        items.join(", ")
+    }
+}
+
+// What one pipeline stage does, or None when it only reads.
+fn stage_effect(program: &str, args: &[String]) -> Option<String> {
+    let targets = operands(args);
+    let effect = if DELETES.contains(&program) {
+        format!("deletes {}", list(&targets))
+    } else if program == "mv" {
+        format!("moves or renames {}", list(&targets))
+    } else if program == "cp" || (program == "rsync" && !args.iter().any(|a| a.contains(':'))) {
+        let dest = targets.last().copied().unwrap_or("the destination");
+        let deletes = args.iter().any(|a| a.starts_with("--delete"));
+        format!(
+            "copies into {}{}",
+            dest,
+            if deletes {
+                ", deleting what isn't in the source"
+            } else {
+                ""
+            }
+        )
+    } else if CREATES.contains(&program) {
+        format!("creates {}", list(&targets))
+    } else if PERMISSIONS.contains(&program) {
+        // The first operand is the mode or owner.
+        let files = targets.get(1..).unwrap_or(&[]);
+        format!("changes permissions or ownership of {}", list(files))
+    } else if program == "sed" && args.iter().any(|a| a.starts_with("-i")) {
+        "edits files in place".to_string()
+    } else if program == "find" && args.iter().any(|a| a == "-delete") {
+        "deletes every file it matches".to_string()
+    } else if program == "find" && args.iter().any(|a| a == "-exec" || a == "-execdir") {
+        "runs a command on every file it matches".to_string()
+    } else if NETWORK.contains(&program) || program == "rsync" {
+        "talks to another machine".to_string()
+    } else if INSTALLERS.contains(&program) || args.first().is_some_and(|a| a == "install") {
+        "installs or removes software".to_string()
+    } else if SIGNALS.contains(&program) {
+        "stops running processes".to_string()
+    } else if program == "git" {
+        match args.first().map(|s| s.as_str()) {
+            Some("push") => "publishes commits to a remote".to_string(),
+            Some("reset" | "checkout" | "restore" | "clean") => {
+                "can discard uncommitted changes".to_string()
+            }
+            Some("commit" | "merge" | "rebase" | "pull") => {
+                "changes repository history".to_string()
+            }
+            _ => return None,
+        }
+    } else {
+        return None;
+    };
+    Some(effect)
+}
+
+/// A short description of what `cmd` changes.
+pub fn describe(cmd: &str, safe_commands: &[String]) -> String {
+    let mut effects: Vec<String> = Vec::new();
+    let mut unknown: Vec<String> = Vec::new();
+
+    for segment in split_segments(cmd) {
+        let mut rest = words(&segment)
+            .into_iter()
+            .skip_while(|w| w.contains('=') && !w.starts_with('-'));
+        let mut program = String::new();
+        let mut privileged = false;
+        for word in rest.by_ref() {
+            program = word.rsplit('/').next().unwrap_or(&word).to_string();
+            if program == "sudo" || program == "doas" {
+                privileged = true;
+            }
+            if !WRAPPERS.contains(&program.as_str()) {
+                break;
+            }
+        }
+        let args: Vec<String> = rest.collect();
+
+        if privileged {
+            effects.push("runs as root".to_string());
+        }
+        match stage_effect(&program, &args) {
+            Some(effect) => effects.push(effect),
+            None if safe_commands.contains(&program) => {}
+            None if !program.is_empty() && !unknown.contains(&program) => unknown.push(program),
+            None => {}
+        }
+        for (i, arg) in args.iter().enumerate() {
+            let Some(target) = arg.trim_start_matches(['1', '2', '&']).strip_prefix('>') else {
+                continue;
+            };
+            let append = target.starts_with('>');
+            let target = match target.trim_start_matches('>') {
+                "" => args.get(i + 1).map(|s| s.as_str()).unwrap_or("a file"),
+                t => t,
+            };
+            if target.starts_with('&') || target == "/dev/null" {
+                continue;
+            }
+            effects.push(if append {
+                format!("appends to {}", target)
+            } else {
+                format!("overwrites {}", target)
+            });
+        }
+    }
+
+    if !unknown.is_empty() {
+        effects.push(format!(
+            "runs {} (check its docs for side effects)",
+            unknown.join(", ")
+        ));
+    }
+    if effects.is_empty() {
+        "only reads; nothing is changed".to_string()
+    } else {
+        effects.join("; ")
+    }
+}
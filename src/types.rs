@@ -4,23 +4,163 @@ use serde::{Deserialize, Serialize};
 pub struct Tool {
     pub name: String,
     pub path: String,
+    /// Where `path` really leads when it's a symlink, a version manager's
+    /// shim or a wrapper script; empty when it's the program itself.
+    pub target: String,
     pub description: String,
     pub synopsis: String,
     pub examples: String,
     pub flags: String,
     pub source: String,
+    pub caveats: String,
+    /// The flag that makes it only report what it would do, if any.
+    pub dry_run: String,
+    pub notes: Vec<String>,
+    pub record: ToolRecord,
     pub embedding: Vec<f32>,
 }
 
+/// How the commands that used a tool fared on this machine lately.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ToolRecord {
+    pub runs: u32,
+    pub failures: u32,
+}
+
+impl ToolRecord {
+    // Fewer runs than this say nothing about the tool.
+    const MIN_RUNS: u32 = 3;
+
+    /// Share of recent runs that failed, or None with too few to judge.
+    pub fn failure_rate(&self) -> Option<f32> {
+        (self.runs >= Self::MIN_RUNS).then(|| self.failures as f32 / self.runs as f32)
+    }
+
+    /// Failing at least half the time here.
+    pub fn unreliable(&self) -> bool {
+        self.failure_rate().is_some_and(|rate| rate >= 0.5)
+    }
+}
+
+/// Something the user wants the planner to know about a tool. Kept apart
+/// from the tool row so reindexing never loses it.
+#[derive(Debug, Clone)]
+pub struct Note {
+    pub id: i64,
+    pub tool: String,
+    pub text: String,
+    pub embedding: Vec<f32>,
+}
+
+/// Something durable about the machine itself ("logs live in /srv/logs"),
+/// given with `pls remember` or picked up from an edited plan.
+#[derive(Debug, Clone)]
+pub struct Fact {
+    pub id: i64,
+    pub text: String,
+    /// "user" or "inferred".
+    pub source: String,
+    pub embedding: Vec<f32>,
+}
+
+/// A piece of the user's own docs (runbooks, a team's CLI notes) from
+/// `pls index --docs`: one section of a markdown file, or a few paragraphs
+/// of a text one.
+#[derive(Debug, Clone)]
+pub struct DocChunk {
+    pub id: i64,
+    pub file: String,
+    /// The headings the section sits under, "Deploys > Rolling back".
+    pub heading: String,
+    pub text: String,
+    pub embedding: Vec<f32>,
+}
+
+/// What the model said about one command of a plan.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StepNotes {
+    #[serde(default)]
+    pub explanation: String,
+    // What the output should look like when the step works.
+    #[serde(default)]
+    pub expected: String,
+    #[serde(default)]
+    pub risks: Vec<String>,
+}
+
+/// How much a warning matters. Plans show the most serious first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    Caution,
+    Danger,
+}
+
+/// Something to know about a plan before running it, and what to do about
+/// it when there is something to do.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Warning {
+    pub severity: Severity,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hint: Option<String>,
+}
+
+impl Warning {
+    pub fn new(severity: Severity, message: impl Into<String>) -> Self {
+        Self {
+            severity,
+            message: message.into(),
+            hint: None,
+        }
+    }
+
+    pub fn with_hint(mut self, hint: impl Into<String>) -> Self {
+        self.hint = Some(hint.into());
+        self
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Plan {
     pub commands: Vec<String>,
     pub explanation: String,
-    pub warnings: Vec<String>,
+    pub warnings: Vec<Warning>,
     pub needs_confirmation: bool,
+    #[serde(default)]
+    pub not_possible: Option<NotPossible>,
+    // A read-only check per command confirming it did what it should.
+    #[serde(default)]
+    pub verify: Vec<Option<String>>,
+    // One per command; empty notes for replies in the older flat format.
+    #[serde(default)]
+    pub steps: Vec<StepNotes>,
+    // What the planner was shown, e.g. "tldr/find" or "note/rsync".
+    #[serde(default)]
+    pub provenance: Vec<String>,
+    // Size of the per-query prompt, for disclosing what went to a remote
+    // model.
+    #[serde(skip)]
+    pub sent_chars: usize,
+    // The model's replies as they came, a retry's after the first.
+    #[serde(skip)]
+    pub raw_reply: String,
+    // Where `pls` keeps the plan once it has run fine; empty if nowhere.
+    #[serde(skip)]
+    pub cache_key: String,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+/// The model's answer when a task can't be done with what's installed.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct NotPossible {
+    pub reason: String,
+    pub missing: Vec<String>,
+    pub next_steps: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
 pub enum RiskLevel {
     Safe,
     Review,
@@ -28,10 +168,112 @@ pub enum RiskLevel {
     Blocked,
 }
 
+impl RiskLevel {
+    pub fn name(&self) -> &'static str {
+        match self {
+            RiskLevel::Safe => "safe",
+            RiskLevel::Review => "review",
+            RiskLevel::Dangerous => "dangerous",
+            RiskLevel::Blocked => "blocked",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        [
+            RiskLevel::Safe,
+            RiskLevel::Review,
+            RiskLevel::Dangerous,
+            RiskLevel::Blocked,
+        ]
+        .into_iter()
+        .find(|level| level.name() == name)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct HistoryEntry {
+    pub id: i64,
     pub query: String,
     pub commands: Vec<String>,
     pub executed: bool,
     pub succeeded: bool,
+    pub cwd: String,
+    pub duration_ms: u64,
+    pub timestamp: i64,
+    pub changes: Option<String>,
+    pub verified: Option<bool>,
+    /// pls went away before the run finished; see spill.rs.
+    pub interrupted: bool,
+    pub failed_stage: Option<String>,
+}
+
+/// A history entry whose commands were started and not yet filed.
+pub struct PendingRun {
+    pub id: i64,
+    /// The pls running them.
+    pub pid: i32,
+    pub started: i64,
+}
+
+/// A history entry with what `pls history show` adds to it.
+pub struct HistoryDetail {
+    pub entry: HistoryEntry,
+    pub provenance: Vec<String>,
+    pub raw_reply: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Usage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    // Wall time of the requests, first byte sent to last byte read.
+    pub latency_ms: u64,
+}
+
+/// Totals for one model over some period, for `pls stats`.
+#[derive(Debug, Clone)]
+pub struct ModelUsage {
+    pub model: String,
+    pub requests: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    /// Over the requests that recorded a latency; older rows didn't.
+    pub timed_requests: u64,
+    pub timed_completion_tokens: u64,
+    pub latency_ms: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct ExecutionResult {
+    pub succeeded: bool,
+    pub output: String,
+    // Exact stdout bytes, for handing to another command.
+    pub raw_output: Vec<u8>,
+    pub duration_ms: u64,
+    pub changes: Option<String>,
+    // None when no step had a verify command.
+    pub verified: Option<bool>,
+    // Whether each step that ran exited successfully.
+    pub steps: Vec<bool>,
+    // Which pipeline stage failed, when the shell could say.
+    pub failed_stage: Option<String>,
+}
+
+/// An earlier full index, kept for `pls index rollback`.
+#[derive(Debug, Clone)]
+pub struct IndexGeneration {
+    pub id: i64,
+    // When its newest tool was written.
+    pub built: i64,
+    pub embedder: String,
+    pub tools: u32,
+}
+
+/// A process group pls started, as recorded in the jobs table.
+#[derive(Debug, Clone)]
+pub struct Job {
+    pub id: i64,
+    pub pgid: i32,
+    pub command: String,
+    pub started: i64,
 }
@@ -1,25 +1,295 @@
-use crate::types::{Plan, RiskLevel};
-use std::{env, fs, process::Command};
+use crate::clock;
+use crate::edits::Edit;
+use crate::llm::ModelInfo;
+use crate::sandbox::Rehearsal;
+use crate::shell::{fold, unfold};
+use crate::terminal::TerminalGuard;
+use crate::types::{HistoryEntry, NotPossible, Plan, RiskLevel, Severity, Tool, Warning};
+use std::{env, fs, io::Write, process::Command};
 
-pub fn print_plan(plan: &Plan, risk: RiskLevel) {
+/// `width` is `output.multiline_width`: longer commands are shown folded.
+pub fn print_plan(plan: &Plan, risk: RiskLevel, caveats: &[(String, String)], width: usize) {
     println!();
 
     for (i, cmd) in plan.commands.iter().enumerate() {
-        if plan.commands.len() > 1 {
-            println!("  {}. {}", i + 1, cmd);
+        let prefix = if plan.commands.len() > 1 {
+            format!("  {}. ", i + 1)
         } else {
-            println!("  {}", cmd);
+            "  ".to_string()
+        };
+        // Continuation lines start under the command, not the number.
+        let indent = " ".repeat(prefix.len());
+        for (n, line) in fold(cmd, width).lines().enumerate() {
+            println!("{}{}", if n == 0 { &prefix } else { &indent }, line);
+        }
+        if let Some(notes) = plan.steps.get(i) {
+            if !notes.explanation.is_empty() {
+                println!("     {}", notes.explanation);
+            }
+            for note in &notes.risks {
+                println!("     risk: {}", note);
+            }
+        }
+        if let Some(Some(check)) = plan.verify.get(i) {
+            println!("     verify: {}", check);
         }
     }
 
+    let mut warnings = plan.warnings.clone();
     if risk == RiskLevel::Dangerous {
+        warnings.push(Warning::new(
+            Severity::Danger,
+            "this command may be destructive",
+        ));
+    }
+    if !warnings.is_empty() {
+        println!();
+        print_warnings(&warnings);
+    }
+
+    for (tool, caveat) in caveats {
+        println!("  note ({}): {}", tool, caveat);
+    }
+}
+
+// Lines of a tool's examples the offline fallback shows.
+const OFFLINE_EXAMPLE_LINES: usize = 12;
+
+/// The best keyword match's examples, and the runners-up by name.
+pub fn print_offline(tools: &[Tool]) {
+    let Some((best, rest)) = tools.split_first() else {
+        println!("no indexed tool matches that.");
+        return;
+    };
+    println!();
+    println!(
+        "  {} - {}",
+        best.name,
+        best.description.lines().next().unwrap_or_default()
+    );
+    let examples = if best.examples.is_empty() {
+        &best.synopsis
+    } else {
+        &best.examples
+    };
+    for line in examples
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .take(OFFLINE_EXAMPLE_LINES)
+    {
+        println!("    {}", line.trim());
+    }
+    for note in &best.notes {
+        println!("    note: {}", note);
+    }
+    if !rest.is_empty() {
+        let names: Vec<&str> = rest.iter().map(|t| t.name.as_str()).collect();
+        println!("  see also: {}", names.join(", "));
+    }
+}
+
+// Colored only for a terminal, and never with NO_COLOR set.
+fn paint(text: &str, color: &str) -> String {
+    let tty = unsafe { libc::isatty(libc::STDOUT_FILENO) } == 1;
+    if tty && env::var_os("NO_COLOR").is_none() {
+        format!("\x1b[{}m{}\x1b[0m", color, text)
+    } else {
+        text.to_string()
+    }
+}
+
+fn severity_label(severity: Severity) -> String {
+    let (label, color) = match severity {
+        Severity::Danger => ("danger", "1;31"),
+        Severity::Caution => ("caution", "33"),
+        Severity::Info => ("note", "36"),
+    };
+    paint(label, color)
+}
+
+/// Warnings most serious first, each with what to do about it underneath.
+pub fn print_warnings(warnings: &[Warning]) {
+    let mut sorted: Vec<&Warning> = warnings.iter().collect();
+    sorted.sort_by_key(|w| std::cmp::Reverse(w.severity));
+    for warning in sorted {
+        println!(
+            "  {}: {}",
+            severity_label(warning.severity),
+            warning.message
+        );
+        if let Some(hint) = &warning.hint {
+            println!("    -> {}", hint);
+        }
+    }
+}
+
+// Wide enough for the tail of a reply, narrow enough not to wrap on an
+// 80-column terminal (wrapping breaks the \r redraw).
+const THINKING_TAIL: usize = 60;
+
+/// Redraws the "thinking..." line with the end of the reply so far.
+pub fn print_thinking(partial: &str) {
+    let start = partial.len().saturating_sub(THINKING_TAIL * 4);
+    let start = (start..partial.len())
+        .find(|&i| partial.is_char_boundary(i))
+        .unwrap_or(0);
+    let text = partial[start..]
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+    let skip = text.chars().count().saturating_sub(THINKING_TAIL);
+    let tail: String = text.chars().skip(skip).collect();
+    eprint!("\rthinking... {:<width$}", tail, width = THINKING_TAIL);
+    std::io::stderr().flush().ok();
+}
+
+pub fn clear_thinking() {
+    eprint!("\r{:width$}\r", "", width = THINKING_TAIL + 12);
+}
+
+/// The compact "based on:" line: the retrieved docs for tools the plan
+/// uses, and a count of the rest. No match at all means the model worked
+/// from memory.
+pub fn print_provenance(provenance: &[String], used_tools: &[String]) {
+    if provenance.is_empty() {
+        return;
+    }
+    let (used, rest): (Vec<&String>, Vec<&String>) = provenance.iter().partition(|p| {
+        p.split_once('/')
+            .is_some_and(|(_, tool)| used_tools.iter().any(|t| t == tool))
+    });
+    let used: Vec<&str> = used.iter().map(|s| s.as_str()).collect();
+    match (used.is_empty(), rest.len()) {
+        (true, n) => println!("  based on: none of the {} docs retrieved", n),
+        (false, 0) => println!("  based on: {}", used.join(", ")),
+        (false, n) => println!("  based on: {} (+{} more retrieved)", used.join(", "), n),
+    }
+}
+
+/// Training mode: why the plan got its risk level and what each command
+/// changes. `remaining` is how many more runs it will be shown for.
+pub fn print_training(risk: RiskLevel, reason: &str, effects: &[(String, String)], remaining: u32) {
+    println!();
+    println!("  risk: {} - {}", risk.name(), reason);
+    for (cmd, effect) in effects {
+        println!("    {}", cmd);
+        println!("      -> {}", effect);
+    }
+    if remaining <= 1 {
+        println!("  (last run with these notes; raise behavior.training_runs to keep them)");
+    } else {
+        println!(
+            "  ({} more runs with these notes; behavior.training_runs = 0 turns them off)",
+            remaining
+        );
+    }
+}
+
+pub fn print_not_possible(answer: &NotPossible) {
+    println!();
+    println!("  not possible: {}", answer.reason);
+    if !answer.missing.is_empty() {
+        println!("  missing: {}", answer.missing.join(", "));
+    }
+    if !answer.next_steps.is_empty() {
         println!();
-        println!("  warning: this command may be destructive");
+        println!("  next steps:");
+        for step in &answer.next_steps {
+            println!("    - {}", step);
+        }
     }
+}
+
+pub fn print_duplicate_warning(previous: &HistoryEntry, now: i64) {
+    let ago = (now - previous.timestamp).max(0);
+    let when = if ago < 60 {
+        format!("{} seconds ago", ago)
+    } else if ago < 120 {
+        "a minute ago".to_string()
+    } else {
+        format!("{} minutes ago", ago / 60)
+    };
+    let outcome = if previous.succeeded { "ok" } else { "failed" };
+    println!(
+        "  warning: you just ran this {} ({}) - run again?",
+        when, outcome
+    );
+}
+
+// Lines of each diff shown before the rest are only counted.
+const MAX_DIFF_LINES: usize = 60;
+
+/// The files a plan rewrites in place, each with its diff.
+pub fn print_edits(edits: &[Edit], steps: usize) {
+    for edit in edits {
+        println!();
+        let step = if steps > 1 {
+            format!(" (step {})", edit.step)
+        } else {
+            String::new()
+        };
+        println!("  rewrites {}{}:", edit.path.display(), step);
+        let Some(diff) = &edit.diff else {
+            println!(
+                "    can't show how: {}",
+                edit.note.as_deref().unwrap_or("unknown")
+            );
+            continue;
+        };
+        if diff.is_empty() {
+            println!("    no change");
+        }
+        for line in diff.lines().take(MAX_DIFF_LINES) {
+            let line = match line.chars().next() {
+                Some('+') if !line.starts_with("+++") => paint(line, "32"),
+                Some('-') if !line.starts_with("---") => paint(line, "31"),
+                Some('@') => paint(line, "36"),
+                _ => line.to_string(),
+            };
+            println!("    {}", line);
+        }
+        let lines = diff.lines().count();
+        if lines > MAX_DIFF_LINES {
+            println!("    ... and {} more lines", lines - MAX_DIFF_LINES);
+        }
+    }
+    if edits.iter().any(|e| e.step > 1) {
+        println!("  (later steps are shown against the files as they are now)");
+    }
+}
+
+// Changes listed before the rest are only counted.
+const MAX_CHANGES: usize = 40;
 
-    for warning in &plan.warnings {
-        println!("  warning: {}", warning);
+pub fn print_rehearsal(rehearsal: &Rehearsal) {
+    println!();
+    let mut copy = format!(
+        "  sandbox: {} files copied, {} by size only",
+        rehearsal.copied, rehearsal.placeholders
+    );
+    if rehearsal.left_out > 0 {
+        copy.push_str(&format!(
+            ", {} links or special files left out",
+            rehearsal.left_out
+        ));
+    }
+    println!("{}", copy);
+    for line in &rehearsal.output {
+        println!("  | {}", line);
+    }
+    if !rehearsal.succeeded {
+        println!("  the plan failed in the sandbox");
+    }
+    if rehearsal.changes.is_empty() {
+        println!("  no changes");
+    }
+    for line in rehearsal.changes.iter().take(MAX_CHANGES) {
+        println!("    {}", line);
     }
+    if rehearsal.changes.len() > MAX_CHANGES {
+        println!("    ... and {} more", rehearsal.changes.len() - MAX_CHANGES);
+    }
+    println!();
 }
 
 pub fn print_blocked(plan: &Plan) {
@@ -36,36 +306,306 @@ pub fn show_explanation(plan: &Plan) {
     println!("explanation: {}", plan.explanation);
     println!();
 
-    for cmd in &plan.commands {
+    for (i, cmd) in plan.commands.iter().enumerate() {
+        let notes = plan.steps.get(i).cloned().unwrap_or_default();
+        if plan.commands.len() > 1 {
+            println!("  step {}:", i + 1);
+        }
         let parts: Vec<&str> = cmd.split('|').collect();
         for part in parts {
             let trimmed = part.trim();
             println!("  {}", trimmed);
         }
+        if !notes.explanation.is_empty() {
+            println!("    does:   {}", notes.explanation);
+        }
+        if !notes.expected.is_empty() {
+            println!("    expect: {}", notes.expected);
+        }
+        for note in &notes.risks {
+            println!("    risk:   {}", note);
+        }
+        if plan.commands.len() > 1 {
+            println!();
+        }
     }
     println!();
 }
 
-pub fn prompt_action() -> Option<char> {
-    println!("[enter] run  [e] edit  [?] explain  [q] quit");
+/// Asks what to do with a plan. Enter picks `default`, which is shown in
+/// place of its own key.
+pub fn prompt_action(steps: usize, dry_run: bool, sandbox: bool, default: char) -> Option<char> {
+    let actions = [
+        ('r', "run"),
+        ('d', "dry-run first"),
+        ('p', "preview in a sandbox"),
+        ('s', "step"),
+        ('e', "edit"),
+        ('?', "explain"),
+        ('q', "quit"),
+    ];
+    let choices: Vec<String> = actions
+        .iter()
+        .filter(|(key, _)| match key {
+            's' => steps > 1,
+            'd' => dry_run,
+            'p' => sandbox,
+            _ => true,
+        })
+        .map(|(key, name)| {
+            if *key == default {
+                format!("[enter] {}", name)
+            } else {
+                format!("[{}] {}", key, name)
+            }
+        })
+        .collect();
+    println!("{}", choices.join("  "));
 
     let mut input = String::new();
     std::io::stdin().read_line(&mut input).ok()?;
 
     let input = input.trim().to_lowercase();
     match input.as_str() {
-        "" => Some('r'),
+        "" => Some(default),
+        "r" => Some('r'),
+        "d" if dry_run => Some('d'),
+        "p" if sandbox => Some('p'),
+        "s" if steps > 1 => Some('s'),
         "e" => Some('e'),
         "?" => Some('?'),
-        "q" => Some('q'),
         _ => Some('q'),
     }
 }
 
-pub fn edit_command(cmd: &str) -> Option<String> {
+/// Lists `suggestions` and asks which to ask next, by number; None for
+/// none of them.
+pub fn prompt_follow_up(suggestions: &[String]) -> Option<String> {
+    println!();
+    for (i, suggestion) in suggestions.iter().enumerate() {
+        println!("  {}. {}", i + 1, suggestion);
+    }
+    print!("[1-{}] ask next  [enter] done ", suggestions.len());
+    std::io::stdout().flush().ok();
+
+    let mut input = String::new();
+    std::io::stdin().read_line(&mut input).ok()?;
+    let n = input.trim().parse::<usize>().ok()?;
+    suggestions.get(n.wrapping_sub(1)).cloned()
+}
+
+/// Asks for another query after Ctrl-C cancelled the last one; None when the
+/// user would rather stop.
+pub fn prompt_rephrase() -> Option<String> {
+    eprint!("cancelled. new query (enter to quit): ");
+    std::io::stderr().flush().ok();
+
+    let mut input = String::new();
+    std::io::stdin().read_line(&mut input).ok()?;
+    let input = input.trim();
+    (!input.is_empty()).then(|| input.to_string())
+}
+
+/// Asks which of `models` to use as `what`, by number or name. None keeps
+/// the current one.
+pub fn prompt_model(what: &str, current: &str, models: &[ModelInfo]) -> Option<String> {
+    print!("{} (number or name, enter keeps {}): ", what, current);
+    std::io::stdout().flush().ok();
+
+    let mut input = String::new();
+    std::io::stdin().read_line(&mut input).ok()?;
+    let input = input.trim();
+    if input.is_empty() {
+        return None;
+    }
+    let chosen = match input.parse::<usize>() {
+        Ok(n) => models.get(n.wrapping_sub(1)),
+        Err(_) => models.iter().find(|m| m.name == input),
+    };
+    if chosen.is_none() {
+        println!("no model {}; keeping {}", input, current);
+    }
+    chosen.map(|m| m.name.clone())
+}
+
+/// Asks what to do when another run holds the directory: 'w' to wait for
+/// it, 'o' to go ahead anyway, 'q' to give up.
+pub fn prompt_dir_locked(holder: &str) -> char {
+    println!("another pls run is modifying this directory");
+    if !holder.is_empty() {
+        println!("  {}", holder);
+    }
+    println!("[w] wait for it  [o] run anyway  [q] quit");
+
+    let mut input = String::new();
+    if std::io::stdin().read_line(&mut input).unwrap_or(0) == 0 {
+        return 'q';
+    }
+    match input.trim().to_lowercase().as_str() {
+        "w" => 'w',
+        "o" => 'o',
+        _ => 'q',
+    }
+}
+
+/// Asks what to do about a dangerous plan while the last one's cooldown
+/// has `left` to run: 'w' to wait it out, 'a' to re-authenticate (offered
+/// when `reauth`), 'q' to give up.
+pub fn prompt_cooldown(left: &str, reauth: bool) -> char {
+    println!(
+        "a dangerous plan ran recently; the cooldown has {} left",
+        left
+    );
+    if reauth {
+        println!("[w] wait  [a] enter the sudo password to go now  [q] quit");
+    } else {
+        println!("[w] wait  [q] quit");
+    }
+
+    let mut input = String::new();
+    if std::io::stdin().read_line(&mut input).unwrap_or(0) == 0 {
+        return 'q';
+    }
+    match input.trim().to_lowercase().as_str() {
+        "w" => 'w',
+        "a" if reauth => 'a',
+        _ => 'q',
+    }
+}
+
+/// After the directory changed under a plan: 'r' to run it anyway, 'p' to
+/// plan again (offered if `replan`), 'q' to leave it.
+pub fn prompt_drift(why: &str, replan: bool) -> char {
+    println!("  warning: {}", why);
+    if replan {
+        println!("[r] run anyway  [p] plan again  [q] quit");
+    } else {
+        println!("[r] run anyway  [q] quit");
+    }
+
+    let mut input = String::new();
+    if std::io::stdin().read_line(&mut input).unwrap_or(0) == 0 {
+        return 'q';
+    }
+    match input.trim().to_lowercase().as_str() {
+        "r" => 'r',
+        "p" if replan => 'p',
+        _ => 'q',
+    }
+}
+
+/// True to edit again, false to undo the edit.
+pub fn prompt_reedit() -> bool {
+    eprint!("[enter] edit again  [u] undo the edit: ");
+    std::io::stderr().flush().ok();
+
+    let mut input = String::new();
+    if std::io::stdin().read_line(&mut input).unwrap_or(0) == 0 {
+        return false;
+    }
+    input.trim() != "u"
+}
+
+pub fn prompt_step(index: usize, total: usize, cmd: &str, risk: RiskLevel) -> Option<char> {
+    println!();
+    println!("  step {}/{}: {}", index, total, cmd);
+    if risk == RiskLevel::Dangerous {
+        println!("  warning: this step may be destructive");
+    }
+    println!("[enter] run  [s] skip  [q] stop");
+
+    let mut input = String::new();
+    std::io::stdin().read_line(&mut input).ok()?;
+
+    match input.trim().to_lowercase().as_str() {
+        "" => Some('r'),
+        "s" => Some('s'),
+        _ => Some('q'),
+    }
+}
+
+/// `cmd` as the user leaves it in $EDITOR. It's laid out there as
+/// `print_plan` shows it and comes back on one line; saved untouched, it's
+/// `cmd` exactly.
+pub fn edit_command(cmd: &str, width: usize) -> Option<String> {
     let editor = env::var("EDITOR").unwrap_or_else(|_| "vi".to_string());
     let temp_path = "/tmp/pls_edit.sh";
-    fs::write(temp_path, cmd).ok()?;
+    let folded = fold(cmd, width);
+    fs::write(temp_path, &folded).ok()?;
+    let _guard = TerminalGuard::new();
     Command::new(&editor).arg(temp_path).status().ok()?;
-    fs::read_to_string(temp_path).ok()
+    let edited = fs::read_to_string(temp_path).ok()?;
+    if edited.trim() == folded.trim() {
+        return Some(cmd.to_string());
+    }
+    Some(unfold(&edited))
+}
+
+pub fn status_marker(entry: &HistoryEntry) -> &'static str {
+    if entry.executed {
+        if entry.interrupted {
+            "~"
+        } else if entry.verified == Some(false) {
+            "!"
+        } else if entry.succeeded {
+            "+"
+        } else {
+            "x"
+        }
+    } else {
+        "-"
+    }
+}
+
+pub fn format_duration(ms: u64) -> String {
+    if ms < 1000 {
+        format!("{}ms", ms)
+    } else if ms < 60_000 {
+        format!("{:.1}s", ms as f64 / 1000.0)
+    } else {
+        format!("{}m{:02}s", ms / 60_000, (ms % 60_000) / 1000)
+    }
+}
+
+pub fn print_timeline(entries: &[HistoryEntry]) {
+    let mut current_day = String::new();
+    let mut current_dir: Option<&str> = None;
+
+    for entry in entries {
+        let tm = clock::local(entry.timestamp);
+        let day = format!("{} {}", clock::format_date(&tm), &clock::weekday(&tm)[..3]);
+
+        if day != current_day {
+            if !current_day.is_empty() {
+                println!();
+            }
+            println!("{}", day);
+            current_day = day;
+            current_dir = None;
+        }
+
+        let dir = if entry.cwd.is_empty() {
+            "(unknown directory)"
+        } else {
+            entry.cwd.as_str()
+        };
+        if current_dir != Some(dir) {
+            println!("  {}", dir);
+            current_dir = Some(dir);
+        }
+
+        let duration = if entry.executed {
+            format_duration(entry.duration_ms)
+        } else {
+            String::new()
+        };
+        println!(
+            "    {} {:02}:{:02} {:>7}  {}",
+            status_marker(entry),
+            tm.tm_hour,
+            tm.tm_min,
+            duration,
+            entry.query
+        );
+    }
 }
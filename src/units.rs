@@ -0,0 +1,493 @@
+// Sizes and dates in command output, read the way the locale wrote them and
+// shown the way the user asked. "show sizes in GiB", or `output.size_unit`,
+// puts every size pls recognises (du's and ls -l's size columns, and
+// anything with a unit, like 1,5G or 300 MB) in that unit; "ISO dates", or
+// `output.iso_dates`, writes month-name and numeric dates as 2024-05-20.
+// It's done here on the output rather than by asking the model for other
+// commands, so it doesn't depend on what each tool can be told to print.
+
+use crate::clock;
+use crate::config::OutputConfig;
+use crate::shell::{split_segments, words};
+use std::env;
+
+const KI: f64 = 1024.0;
+
+// What a size may be asked for in: the words for it, how it's written and
+// its bytes.
+const TARGETS: &[(&[&str], &str, f64)] = &[
+    (&["b", "byte", "bytes"], "B", 1.0),
+    (&["kb", "kilobytes"], "kB", 1e3),
+    (&["kib", "kibibytes"], "KiB", KI),
+    (&["mb", "megabytes"], "MB", 1e6),
+    (&["mib", "mebibytes"], "MiB", KI * KI),
+    (&["gb", "gigabytes"], "GB", 1e9),
+    (&["gib", "gibibytes"], "GiB", KI * KI * KI),
+    (&["tb", "terabytes"], "TB", 1e12),
+    (&["tib", "tebibytes"], "TiB", KI * KI * KI * KI),
+];
+
+// Units sizes are printed with. A bare letter is binary, as coreutils' -h
+// means it, except --si's lowercase k; with a B and no i it's decimal.
+const SUFFIXES: &[(&str, f64)] = &[
+    ("B", 1.0),
+    ("K", KI),
+    ("k", 1e3),
+    ("KiB", KI),
+    ("kB", 1e3),
+    ("KB", 1e3),
+    ("M", KI * KI),
+    ("MiB", KI * KI),
+    ("MB", 1e6),
+    ("G", KI * KI * KI),
+    ("GiB", KI * KI * KI),
+    ("GB", 1e9),
+    ("T", KI * KI * KI * KI),
+    ("TiB", KI * KI * KI * KI),
+    ("TB", 1e12),
+    ("P", KI * KI * KI * KI * KI),
+    ("PiB", KI * KI * KI * KI * KI),
+    ("PB", 1e15),
+];
+
+// Month names and abbreviations, lowercase and without the trailing dot,
+// in the languages ls and date are most often set to.
+const MONTHS: [&[&str]; 12] = [
+    &[
+        "jan", "janv", "ene", "gen", "january", "januar", "janvier", "enero", "gennaio", "januari",
+    ],
+    &[
+        "feb", "févr", "fév", "fev", "february", "februar", "février", "febrero", "febbraio",
+    ],
+    &[
+        "mar", "mär", "mrz", "mars", "mrt", "march", "märz", "marzo", "maart", "março",
+    ],
+    &["apr", "avr", "abr", "april", "avril", "abril", "aprile"],
+    &["may", "mai", "mag", "mei", "maj", "mayo", "maggio"],
+    &[
+        "jun", "juin", "giu", "june", "juni", "junio", "giugno", "junho",
+    ],
+    &[
+        "jul", "juil", "lug", "july", "juli", "juillet", "julio", "luglio", "julho",
+    ],
+    &["aug", "août", "ago", "august", "agosto", "augusti"],
+    &[
+        "sep",
+        "sept",
+        "set",
+        "september",
+        "septembre",
+        "septiembre",
+        "settembre",
+        "setembro",
+    ],
+    &[
+        "oct", "okt", "ott", "out", "october", "oktober", "octobre", "octubre", "ottobre",
+    ],
+    &["nov", "november", "novembre", "noviembre", "novembro"],
+    &[
+        "dec",
+        "dez",
+        "déc",
+        "dic",
+        "december",
+        "dezember",
+        "décembre",
+        "diciembre",
+        "dicembre",
+    ],
+];
+
+// Languages that write one and a half as 1,5.
+const DECIMAL_COMMA: &[&str] = &[
+    "az", "be", "bg", "ca", "cs", "da", "de", "el", "es", "et", "eu", "fi", "fr", "gl", "hr", "hu",
+    "id", "is", "it", "kk", "lt", "lv", "nb", "nl", "nn", "no", "pl", "pt", "ro", "ru", "sk", "sl",
+    "sr", "sv", "tr", "uk", "vi",
+];
+
+#[derive(Clone, Copy)]
+struct Unit {
+    label: &'static str,
+    bytes: f64,
+}
+
+/// How the user wants sizes and dates in a plan's output.
+pub struct Format {
+    size: Option<Unit>,
+    iso_dates: bool,
+}
+
+impl Format {
+    /// Whether the output is shown as the commands printed it.
+    pub fn is_plain(&self) -> bool {
+        self.size.is_none() && !self.iso_dates
+    }
+}
+
+fn target(word: &str) -> Option<Unit> {
+    let word = word.to_lowercase();
+    TARGETS
+        .iter()
+        .find(|(names, _, _)| names.contains(&word.as_str()))
+        .map(|&(_, label, bytes)| Unit { label, bytes })
+}
+
+/// What `query` asks for ("sizes in GiB", "with ISO dates"), else what
+/// `[output]` sets.
+pub fn wanted(query: &str, config: &OutputConfig) -> Format {
+    let words: Vec<String> = query
+        .split_whitespace()
+        .map(|w| {
+            w.trim_matches(|c: char| !c.is_alphanumeric() && c != '-')
+                .to_lowercase()
+        })
+        .collect();
+    // "in b" is more likely a directory than bytes.
+    let asked = words.windows(2).find_map(|pair| {
+        let unit = (pair[1] != "b").then(|| target(&pair[1])).flatten()?;
+        ["in", "as", "into"]
+            .contains(&pair[0].as_str())
+            .then_some(unit)
+    });
+    let iso = words
+        .iter()
+        .any(|w| ["iso", "iso8601", "iso-8601"].contains(&w.as_str()))
+        && words
+            .iter()
+            .any(|w| w.starts_with("date") || w.starts_with("time"));
+    Format {
+        size: asked.or_else(|| target(&config.size_unit)),
+        iso_dates: iso || config.iso_dates,
+    }
+}
+
+fn locale(category: &str) -> String {
+    ["LC_ALL", category, "LANG"]
+        .iter()
+        .find_map(|v| env::var(v).ok().filter(|s| !s.is_empty()))
+        .unwrap_or_default()
+}
+
+fn decimal_comma() -> bool {
+    let locale = locale("LC_NUMERIC");
+    let language = locale.split(['_', '.', '@']).next().unwrap_or_default();
+    DECIMAL_COMMA.contains(&language)
+}
+
+// Whether 05/04/2024 is May 4th: in the US and the C locale.
+fn month_first() -> bool {
+    let locale = locale("LC_TIME");
+    locale.is_empty() || locale.starts_with("en_US") || locale.starts_with('C') || locale == "POSIX"
+}
+
+/// `text` as a number, whichever of `.` and `,` it groups digits with.
+/// Only "1,234" and "1.234" can't be told apart; `comma` says whether the
+/// locale's decimal mark is the comma.
+fn parse_number(text: &str, comma: bool) -> Option<f64> {
+    let grouping = ['\'', '\u{a0}', '\u{202f}'];
+    let numeric = text
+        .chars()
+        .all(|c| c.is_ascii_digit() || c == '.' || c == ',' || grouping.contains(&c));
+    if !text.starts_with(|c: char| c.is_ascii_digit()) || !numeric {
+        return None;
+    }
+    let decimal = text.rfind(['.', ',']).filter(|&at| {
+        let mark = text[at..].chars().next().unwrap_or('.');
+        let after = text[at + 1..].chars().filter(char::is_ascii_digit).count();
+        if text.contains('.') && text.contains(',') {
+            true
+        } else if text.matches(mark).count() > 1 {
+            false
+        } else if after == 3 {
+            (mark == ',') == comma
+        } else {
+            true
+        }
+    });
+    let plain: String = text
+        .char_indices()
+        .filter_map(|(at, c)| match c {
+            _ if Some(at) == decimal => Some('.'),
+            c if c.is_ascii_digit() => Some(c),
+            _ => None,
+        })
+        .collect();
+    plain.parse().ok()
+}
+
+// A size written with its unit, "1,5G" or "300" followed by "MB"; its bytes
+// and whether it took the next word too.
+fn parse_size(word: &str, next: Option<&str>, comma: bool) -> Option<(f64, bool)> {
+    let split = word
+        .find(|c: char| c.is_alphabetic())
+        .filter(|&at| at > 0)
+        .unwrap_or(word.len());
+    let number = parse_number(&word[..split], comma)?;
+    let suffix = |text: &str| SUFFIXES.iter().find(|(s, _)| *s == text).map(|&(_, b)| b);
+    if split < word.len() {
+        return Some((number * suffix(&word[split..])?, false));
+    }
+    // Apart from the number, only a unit that can't be a word of its own.
+    let bytes = next.filter(|n| n.len() > 1).and_then(suffix)?;
+    Some((number * bytes, true))
+}
+
+fn show(bytes: f64, unit: Unit) -> String {
+    if unit.bytes == 1.0 {
+        format!("{}", bytes.round() as u64)
+    } else {
+        format!("{:.2}{}", bytes / unit.bytes, unit.label)
+    }
+}
+
+// The size of du's blocks in the plan, when du prints counts of them.
+fn du_block(commands: &[String]) -> Option<f64> {
+    let segment = commands
+        .iter()
+        .flat_map(|c| split_segments(c))
+        .find_map(|segment| {
+            let words = words(&segment);
+            (words.first()?.rsplit('/').next()? == "du").then_some(words)
+        })?;
+    let mut block = KI;
+    for word in &segment[1..] {
+        match word.as_str() {
+            "--bytes" => block = 1.0,
+            "--human-readable" | "--si" => return None,
+            w if w.starts_with("--block-size") => return None,
+            w if w.starts_with("--") => {}
+            w if w.starts_with('-') => {
+                for flag in w[1..].chars() {
+                    match flag {
+                        'h' | 'B' => return None,
+                        'b' => block = 1.0,
+                        'k' => block = KI,
+                        'm' => block = KI * KI,
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Some(block)
+}
+
+fn month(word: &str) -> Option<u32> {
+    let word = word.trim_end_matches([',', '.']).to_lowercase();
+    MONTHS
+        .iter()
+        .position(|names| names.contains(&word.as_str()))
+        .map(|m| m as u32 + 1)
+}
+
+fn day(word: &str) -> Option<u32> {
+    let day: u32 = word.trim_end_matches([',', '.']).parse().ok()?;
+    (1..=31).contains(&day).then_some(day)
+}
+
+fn is_time(word: &str) -> bool {
+    let parts: Vec<&str> = word.split(':').collect();
+    (2..=3).contains(&parts.len())
+        && parts
+            .iter()
+            .all(|p| p.len() == 2 && p.chars().all(|c| c.is_ascii_digit()))
+}
+
+fn year(word: &str) -> Option<i32> {
+    let year: i32 = word.parse().ok()?;
+    (word.len() == 4 && (1970..=2100).contains(&year)).then_some(year)
+}
+
+// A numeric date, 20.05.2024 or 05/20/2024, in ISO form.
+fn numeric_date(word: &str, month_first: bool) -> Option<String> {
+    let word = word.trim_end_matches([',', '.']);
+    let (separator, parts) = if word.contains('/') {
+        ('/', word.split('/').collect::<Vec<_>>())
+    } else {
+        ('.', word.split('.').collect::<Vec<_>>())
+    };
+    let [a, b, y] = parts[..] else {
+        return None;
+    };
+    let (a, b, y): (u32, u32, i32) = (a.parse().ok()?, b.parse().ok()?, year(y)?);
+    // Dots are always day first; a slash date only when the locale says so,
+    // or when it can't be the other way round.
+    let (mut month, mut day) = if separator == '/' && month_first {
+        (a, b)
+    } else {
+        (b, a)
+    };
+    if month > 12 && day <= 12 {
+        (month, day) = (day, month);
+    }
+    ((1..=12).contains(&month) && (1..=31).contains(&day))
+        .then(|| format!("{:04}-{:02}-{:02}", y, month, day))
+}
+
+// A month-name date starting at `words[i]`, "Oct 15 10:00", "15. Okt 2023"
+// or "15 oct. 10:00", in ISO form, and how many words it took. A date
+// given with a time is within the last year, as ls shows them.
+fn named_date(words: &[&str], i: usize, today: (i32, u32, u32)) -> Option<(String, usize)> {
+    let (first, second, last) = (words.get(i)?, words.get(i + 1)?, words.get(i + 2)?);
+    let (month, day) = match (month(first), day(second)) {
+        (Some(month), Some(day)) => (month, day),
+        _ => (month(second)?, day(first)?),
+    };
+    if let Some(year) = year(last) {
+        return Some((format!("{:04}-{:02}-{:02}", year, month, day), 3));
+    }
+    if !is_time(last) {
+        return None;
+    }
+    let (this_year, this_month, this_day) = today;
+    let year = if (month, day) > (this_month, this_day + 1) {
+        this_year - 1
+    } else {
+        this_year
+    };
+    Some((
+        format!("{:04}-{:02}-{:02} {}", year, month, day, &last[..5]),
+        3,
+    ))
+}
+
+fn is_iso_date(word: &str) -> bool {
+    let bytes = word.as_bytes();
+    bytes.len() == 10
+        && bytes[4] == b'-'
+        && bytes[7] == b'-'
+        && word.chars().filter(char::is_ascii_digit).count() == 8
+}
+
+// `ls -l`: drwxr-xr-x and the like.
+fn is_long_listing(line: &str) -> bool {
+    let mut chars = line.chars();
+    chars.next().is_some_and(|c| "-dlcbpsD".contains(c))
+        && chars.take(9).filter(|c| "rwxsStT-".contains(*c)).count() == 9
+}
+
+struct Reader {
+    comma: bool,
+    month_first: bool,
+    today: (i32, u32, u32),
+    du_block: Option<f64>,
+}
+
+fn reformat_line(line: &str, format: &Format, reader: &Reader) -> String {
+    let mut spans = Vec::new();
+    let mut start = None;
+    for (at, c) in line.char_indices().chain([(line.len(), ' ')]) {
+        match (start, c.is_whitespace()) {
+            (None, false) => start = Some(at),
+            (Some(from), true) => {
+                spans.push((from, at));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    let words: Vec<&str> = spans.iter().map(|&(from, to)| &line[from..to]).collect();
+
+    // Word ranges to replace, and with what.
+    let mut edits: Vec<(usize, usize, String)> = Vec::new();
+    let free =
+        |edits: &[(usize, usize, String)], i: usize| edits.iter().all(|e| i < e.0 || i > e.1);
+
+    if let Some(unit) = format.size {
+        // du's counts of blocks, before the tab.
+        let du = reader.du_block.zip(line.split_once('\t'));
+        if let Some((block, (count, _))) = du.filter(|_| !words.is_empty()) {
+            if count.trim() == words[0] {
+                if let Some(n) = parse_number(words[0], reader.comma) {
+                    edits.push((0, 0, show(n * block, unit)));
+                }
+            }
+        }
+        // ls -l's size, the plain number before the date.
+        if is_long_listing(line) {
+            let date = (2..words.len())
+                .find(|&i| is_iso_date(words[i]) || named_date(&words, i, reader.today).is_some());
+            let size = date
+                .map(|d| d - 1)
+                .filter(|&i| words[i].chars().all(|c| c.is_ascii_digit()));
+            if let Some(i) = size {
+                let bytes = words[i].parse::<f64>().unwrap_or_default();
+                edits.push((i, i, show(bytes, unit)));
+            }
+        }
+        let mut i = 0;
+        while i < words.len() {
+            if free(&edits, i) {
+                let next = words.get(i + 1).copied();
+                if let Some((bytes, both)) = parse_size(words[i], next, reader.comma) {
+                    let last = if both { i + 1 } else { i };
+                    edits.push((i, last, show(bytes, unit)));
+                    i = last;
+                }
+            }
+            i += 1;
+        }
+    }
+
+    if format.iso_dates {
+        let mut i = 0;
+        while i < words.len() {
+            if free(&edits, i) {
+                if let Some((date, taken)) = named_date(&words, i, reader.today) {
+                    let last = i + taken - 1;
+                    if (i..=last).all(|j| free(&edits, j)) {
+                        // A year-only date padded to a dated time's width,
+                        // so ls's columns still line up.
+                        let width = if last + 1 < words.len() { 16 } else { 0 };
+                        edits.push((i, last, format!("{:<w$}", date, w = width)));
+                        i = last;
+                    }
+                } else if let Some(date) = numeric_date(words[i], reader.month_first) {
+                    edits.push((i, i, date));
+                }
+            }
+            i += 1;
+        }
+    }
+
+    if edits.is_empty() {
+        return line.to_string();
+    }
+    edits.sort_by_key(|e| e.0);
+    let mut out = String::new();
+    let mut at = 0;
+    for (first, last, text) in edits {
+        let (from, to) = (spans[first].0, spans[last].1);
+        // Right-aligned where it was, as sizes are in columns: a longer text
+        // takes from the spaces before it, all but one.
+        let gap = &line[at..from];
+        let longer = text.chars().count().saturating_sub(to - from);
+        let spare = if gap.len() > 1 && gap.bytes().all(|b| b == b' ') {
+            gap.len() - 1
+        } else {
+            0
+        };
+        out.push_str(&gap[..gap.len() - longer.min(spare)]);
+        out.push_str(&format!("{:>w$}", text, w = to - from));
+        at = to;
+    }
+    out.push_str(&line[at..]);
+    out
+}
+
+/// `output` of `commands` with its sizes and dates as `format` has them.
+pub fn apply(output: &str, commands: &[String], format: &Format) -> String {
+    let tm = clock::local(clock::now());
+    let reader = Reader {
+        comma: decimal_comma(),
+        month_first: month_first(),
+        today: (tm.tm_year + 1900, tm.tm_mon as u32 + 1, tm.tm_mday as u32),
+        du_block: du_block(commands),
+    };
+    output
+        .split('\n')
+        .map(|line| reformat_line(line, format, &reader))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
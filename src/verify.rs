@@ -0,0 +1,408 @@
+// Static checks on a plan: does each program exist, are its flags
+// documented, do the paths it names exist, and can we read or write them.
+// Nothing here runs the plan itself.
+
+use crate::config::IndexConfig;
+use crate::index::{find_in_path, probe};
+use crate::shell::{split_segments, words, WRAPPERS};
+use crate::types::Tool;
+use std::{
+    env,
+    ffi::CString,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+const BUILTINS: &[&str] = &[
+    "cd", "pushd", "popd", "export", "source", ".", "set", "unset", "alias", "shopt", "umask",
+    "echo", "printf", "test", "[", "read", "exit", "true", "false", "type", "wait", "eval",
+];
+
+// First operand is a pattern or script, not a file.
+const PATTERN_FIRST: &[&str] = &["grep", "egrep", "fgrep", "rg", "ag", "sed", "awk", "jq"];
+
+// Every operand is created if missing.
+const CREATES_ALL: &[&str] = &["mkdir", "touch", "tee"];
+
+// The last operand is a destination that may not exist yet.
+const CREATES_LAST: &[&str] = &["cp", "mv", "ln", "rsync", "install"];
+
+// Programs that change the files they are given.
+const MODIFIES: &[&str] = &[
+    "chmod", "chown", "chgrp", "truncate", "shred", "touch", "tee",
+];
+
+// Programs that remove or rename their operands.
+const UNLINKS: &[&str] = &["rm", "rmdir", "mv"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Pass,
+    Fail,
+    Skip,
+}
+
+pub struct Check {
+    pub status: Status,
+    pub label: String,
+    pub detail: String,
+}
+
+impl Check {
+    fn new(status: Status, label: impl Into<String>, detail: impl Into<String>) -> Self {
+        Self {
+            status,
+            label: label.into(),
+            detail: detail.into(),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Access {
+    Read,
+    Write,
+    // Removing or renaming needs write access to the directory.
+    WriteParent,
+}
+
+fn has_access(path: &Path, access: Access) -> bool {
+    let (path, mode) = match access {
+        Access::Read => (path, libc::R_OK),
+        Access::Write => (path, libc::W_OK),
+        Access::WriteParent => (
+            path.parent()
+                .filter(|p| !p.as_os_str().is_empty())
+                .unwrap_or(Path::new(".")),
+            libc::W_OK,
+        ),
+    };
+    let Ok(c_path) = CString::new(path.as_os_str().as_encoded_bytes()) else {
+        return false;
+    };
+    unsafe { libc::access(c_path.as_ptr(), mode) == 0 }
+}
+
+fn expand_home(arg: &str) -> PathBuf {
+    match arg.strip_prefix("~/") {
+        Some(rest) => dirs::home_dir().unwrap_or_default().join(rest),
+        None if arg == "~" => dirs::home_dir().unwrap_or_default(),
+        None => PathBuf::from(arg),
+    }
+}
+
+fn looks_like_path(arg: &str) -> bool {
+    if arg.contains("://") || arg.contains(['*', '?', '[', '$', '{']) {
+        return false;
+    }
+    arg.contains('/') || arg.starts_with('~') || arg.starts_with('.') || Path::new(arg).exists()
+}
+
+// Full help plus the man page, for flags that didn't make it into the
+// index's short flag list. `--help` is run the way indexing runs it.
+fn live_docs(name: &str, config: &IndexConfig) -> String {
+    let mut docs = String::new();
+    if let Some((stdout, stderr)) = probe(name, name, &["--help"], config) {
+        docs.push_str(&stdout);
+        docs.push_str(&stderr);
+    }
+    if let Ok(output) = Command::new("man")
+        .arg(name)
+        .env("MANPAGER", "cat")
+        .env("MANWIDTH", "200")
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+    {
+        docs.push_str(&String::from_utf8_lossy(&output.stdout));
+    }
+    docs
+}
+
+// Whether `flag` appears in `docs` as a whole word, not as a prefix of a
+// longer flag.
+fn mentions_flag(docs: &str, flag: &str) -> bool {
+    docs.match_indices(flag).any(|(i, _)| {
+        let before = docs[..i].chars().next_back();
+        let after = docs[i + flag.len()..].chars().next();
+        !before.is_some_and(|c| c.is_alphanumeric() || c == '-')
+            && !after.is_some_and(|c| c.is_alphanumeric() || c == '-' || c == '_')
+    })
+}
+
+fn check_program(name: &str, tools: &[Tool]) -> Check {
+    if BUILTINS.contains(&name) {
+        return Check::new(Status::Pass, name, "shell builtin");
+    }
+    if name.contains('/') {
+        let path = expand_home(name);
+        return if path.is_file() {
+            Check::new(Status::Pass, name, "found")
+        } else {
+            Check::new(Status::Fail, name, "no such file")
+        };
+    }
+    if let Some(path) = find_in_path(name) {
+        return Check::new(Status::Pass, name, path);
+    }
+    if tools.iter().any(|t| t.name == name && t.source == "alias") {
+        return Check::new(Status::Pass, name, "your alias");
+    }
+    Check::new(Status::Fail, name, "not found in PATH")
+}
+
+// `subcommand` is the first operand, in case `name` has it indexed as an
+// entry of its own ("git branch") with its own flags.
+fn check_flags(
+    name: &str,
+    subcommand: Option<&String>,
+    flags: &[String],
+    tools: &[Tool],
+    config: &IndexConfig,
+) -> Vec<Check> {
+    if flags.is_empty() || BUILTINS.contains(&name) {
+        return Vec::new();
+    }
+
+    let entry = subcommand.map(|sub| format!("{} {}", name, sub));
+    let mut docs = tools
+        .iter()
+        .filter(|t| t.name == name || Some(&t.name) == entry.as_ref())
+        .map(|t| format!("{}\n{}\n{}\n", t.flags, t.synopsis, t.examples))
+        .collect::<String>();
+    let mut fetched = false;
+    let mut documented = |flag: &str| {
+        if !mentions_flag(&docs, flag) && !fetched {
+            docs.push_str(&live_docs(name, config));
+            fetched = true;
+        }
+        (mentions_flag(&docs, flag), docs.trim().is_empty())
+    };
+
+    let mut checks = Vec::new();
+    for flag in flags {
+        let label = format!("{} {}", name, flag);
+        let (found, no_docs) = documented(flag);
+        if no_docs {
+            checks.push(Check::new(Status::Skip, label, "no docs to check against"));
+            continue;
+        }
+        if found {
+            checks.push(Check::new(Status::Pass, label, "documented"));
+            continue;
+        }
+
+        // Bundled short flags (`-la`) are checked one letter at a time.
+        let missing: Vec<String> = if !flag.starts_with("--") && flag.len() > 2 {
+            flag[1..]
+                .chars()
+                .map(|c| format!("-{}", c))
+                .filter(|f| !documented(f).0)
+                .collect()
+        } else {
+            vec![flag.clone()]
+        };
+        if missing.is_empty() {
+            checks.push(Check::new(Status::Pass, label, "documented"));
+        } else {
+            checks.push(Check::new(
+                Status::Fail,
+                label,
+                format!("{} not in the tool's docs", missing.join(", ")),
+            ));
+        }
+    }
+
+    checks
+}
+
+fn check_path(
+    arg: &str,
+    creates: bool,
+    with_parents: bool,
+    access: Access,
+    privileged: bool,
+) -> Check {
+    let path = expand_home(arg);
+    let label = format!("path {}", arg);
+
+    if path.exists() || path.is_symlink() {
+        if privileged {
+            return Check::new(Status::Pass, label, "exists (runs as root)");
+        }
+        let need = match access {
+            Access::Read => "readable",
+            Access::Write => "writable",
+            Access::WriteParent => "removable",
+        };
+        return if has_access(&path, access) {
+            Check::new(Status::Pass, label, format!("exists, {}", need))
+        } else {
+            Check::new(
+                Status::Fail,
+                label,
+                format!("exists but not {} by you", need),
+            )
+        };
+    }
+
+    if !creates {
+        return Check::new(Status::Fail, label, "does not exist");
+    }
+
+    let mut parent = match path.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p.to_path_buf(),
+        _ => env::current_dir().unwrap_or_default(),
+    };
+    // `mkdir -p` creates missing parents, so what matters is the nearest
+    // one that exists.
+    while with_parents && !parent.exists() {
+        match parent.parent() {
+            Some(p) if !p.as_os_str().is_empty() => parent = p.to_path_buf(),
+            _ => break,
+        }
+    }
+    if !parent.exists() {
+        Check::new(
+            Status::Fail,
+            label,
+            format!("will be created, but {} does not exist", parent.display()),
+        )
+    } else if privileged || has_access(&parent, Access::Write) {
+        Check::new(Status::Pass, label, "will be created")
+    } else {
+        Check::new(
+            Status::Fail,
+            label,
+            format!("will be created, but {} is not writable", parent.display()),
+        )
+    }
+}
+
+/// Runs every check on one command of a plan.
+pub fn verify_command(cmd: &str, tools: &[Tool], config: &IndexConfig) -> Vec<Check> {
+    let mut checks = Vec::new();
+    let root = unsafe { libc::geteuid() } == 0;
+
+    for segment in split_segments(cmd) {
+        let mut args = words(&segment)
+            .into_iter()
+            .skip_while(|w| w.contains('=') && !w.starts_with('-'))
+            .peekable();
+
+        // Walk past wrappers to the program that does the work.
+        let mut privileged = root;
+        let mut program = None;
+        while let Some(word) = args.next() {
+            let name = word.rsplit('/').next().unwrap_or(&word).to_string();
+            checks.push(check_program(&word, tools));
+            if !WRAPPERS.contains(&name.as_str()) {
+                program = Some(name);
+                break;
+            }
+            if name == "sudo" || name == "doas" {
+                privileged = true;
+                checks.push(Check::new(
+                    Status::Pass,
+                    "privileges",
+                    if root {
+                        "already root"
+                    } else {
+                        "will ask for your password"
+                    },
+                ));
+            }
+            while args.peek().is_some_and(|a| a.starts_with('-')) {
+                args.next();
+            }
+            if name == "timeout" {
+                args.next();
+            }
+        }
+        let Some(program) = program else {
+            continue;
+        };
+
+        let mut flags = Vec::new();
+        let mut operands: Vec<String> = Vec::new();
+        let mut redirects = Vec::new();
+        let mut end_of_flags = false;
+        // Set after `-o`, `--output` or a bare `>`: the next word is written.
+        let mut output_next = false;
+        for arg in args {
+            if output_next {
+                redirects.push(arg);
+                output_next = false;
+                continue;
+            }
+            if let Some(target) = arg.trim_start_matches(['1', '2', '&']).strip_prefix('>') {
+                let target = target.trim_start_matches('>');
+                if target.is_empty() {
+                    output_next = true;
+                } else if !target.starts_with('&') {
+                    redirects.push(target.to_string());
+                }
+                continue;
+            }
+            if arg.starts_with('<') {
+                continue;
+            }
+            if !end_of_flags && arg == "--" {
+                end_of_flags = true;
+            } else if !end_of_flags && arg.starts_with('-') && arg.len() > 1 {
+                let flag = arg.split('=').next().unwrap_or(&arg).to_string();
+                // `-3` in `find -mtime -3` is a value, not a flag.
+                if flag[1..].parse::<f64>().is_err() {
+                    output_next = matches!(flag.as_str(), "-o" | "--output");
+                    flags.push(flag);
+                }
+            } else {
+                operands.push(arg);
+            }
+        }
+
+        checks.extend(check_flags(
+            &program,
+            operands.first(),
+            &flags,
+            tools,
+            config,
+        ));
+
+        if PATTERN_FIRST.contains(&program.as_str()) && !operands.is_empty() {
+            operands.remove(0);
+        }
+        let last = operands.len().saturating_sub(1);
+        let access = if UNLINKS.contains(&program.as_str()) {
+            Access::WriteParent
+        } else if MODIFIES.contains(&program.as_str())
+            || (program == "sed" && flags.iter().any(|f| f.starts_with("-i")))
+        {
+            Access::Write
+        } else {
+            Access::Read
+        };
+        let with_parents =
+            program == "mkdir" && flags.iter().any(|f| f == "-p" || f == "--parents");
+        for (i, operand) in operands.iter().enumerate() {
+            if !looks_like_path(operand) {
+                continue;
+            }
+            let destination = CREATES_LAST.contains(&program.as_str()) && i == last && i > 0;
+            let creates = destination || CREATES_ALL.contains(&program.as_str());
+            let access = if destination { Access::Write } else { access };
+            checks.push(check_path(
+                operand,
+                creates,
+                with_parents,
+                access,
+                privileged,
+            ));
+        }
+        for target in &redirects {
+            checks.push(check_path(target, true, false, Access::Write, privileged));
+        }
+    }
+
+    checks
+}